@@ -1,20 +1,136 @@
-// #[derive(Debug, Clone)]
-// pub struct Device {
-//     pub name: String,
-//     pub r#type: DeviceType,
-//     pub id: i32,
-// }
-
-// #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
-// #[repr(C)]
-// pub enum DeviceType {
-//     #[default]
-//     Host,
-//     X86,
-//     Cuda,
-//     Arm,
-//     OpenCL,
-//     Npu,
-//     // any target
-//     Any,
-// }
+use super::Context;
+use anyhow::{anyhow, Result};
+use bridge::nndevice::memory::{Buffer, DeviceMemory};
+use model::tensor::{Location, Tensor};
+use std::rc::Rc;
+
+/// 在设备上分配与tensor形状、类型一致的内存，数据未初始化
+pub fn alloc_device_tensor(memory: &Rc<dyn DeviceMemory>, tensor: &Tensor) -> Result<Tensor> {
+    let len = tensor.shape().len() * tensor.dtype().size_of();
+    let buffer = Buffer::alloc(memory, len)
+        .map_err(|e| anyhow!("为Tensor {}分配设备内存失败, {}", tensor.name(), e))?;
+    let mut device = empty_like(tensor);
+    device.set_device_buffer(Rc::new(buffer));
+    Ok(device)
+}
+
+/// 将Tensor复制到设备上，已在设备上时共享同一块设备内存
+pub fn to_device(ctx: &Context, tensor: &Tensor) -> Result<Tensor> {
+    upload(&ctx.memory, tensor)
+}
+
+/// 将设备上的Tensor复制回host，已在host上时直接复制
+pub fn to_host(ctx: &Context, tensor: &Tensor) -> Result<Tensor> {
+    download(&ctx.memory, tensor)
+}
+
+pub(crate) fn upload(memory: &Rc<dyn DeviceMemory>, tensor: &Tensor) -> Result<Tensor> {
+    if tensor.location() == Location::Device {
+        return Ok(tensor.clone());
+    }
+    let bytes = tensor
+        .as_bytes()
+        .ok_or_else(|| anyhow!("Tensor {}没有数据", tensor.name()))?;
+    let device = alloc_device_tensor(memory, tensor)?;
+    if bytes.len() != device.data_len() {
+        return Err(anyhow!(
+            "Tensor {}数据长度{}与形状不一致, 需要{}",
+            tensor.name(),
+            bytes.len(),
+            device.data_len()
+        ));
+    }
+    // device由alloc_device_tensor按bytes的长度分配
+    unsafe { memory.copy_to_device(device.data_ptr(), bytes) }
+        .map_err(|e| anyhow!("复制Tensor {}到设备失败, {}", tensor.name(), e))?;
+    Ok(device)
+}
+
+pub(crate) fn download(memory: &Rc<dyn DeviceMemory>, tensor: &Tensor) -> Result<Tensor> {
+    if tensor.location() == Location::Host {
+        return Ok(tensor.clone());
+    }
+    let mut bytes = vec![0_u8; tensor.data_len()];
+    // 设备上的Tensor数据长度为data_len
+    unsafe { memory.copy_to_host(&mut bytes, tensor.data_ptr()) }
+        .map_err(|e| anyhow!("复制Tensor {}到host失败, {}", tensor.name(), e))?;
+    let mut host = empty_like(tensor);
+    host.set_vec_u8(bytes, tensor.dtype());
+    Ok(host)
+}
+
+/// 按请求的位置放置推理输出，后端未按请求放置时补充一次复制
+pub(crate) fn place_outputs(
+    memory: &Rc<dyn DeviceMemory>,
+    outputs: Vec<Tensor>,
+    on_device: bool,
+) -> Result<Vec<Tensor>> {
+    outputs
+        .into_iter()
+        .map(|output| match (on_device, output.location()) {
+            (true, Location::Host) => upload(memory, &output),
+            (false, Location::Device) => download(memory, &output),
+            _ => Ok(output),
+        })
+        .collect()
+}
+
+fn empty_like(tensor: &Tensor) -> Tensor {
+    Tensor::new_with_shape(
+        tensor.name(),
+        tensor.shape().data(),
+        tensor.format(),
+        tensor.dtype(),
+        tensor.r#type(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bridge::nndevice::memory::HostMemory;
+
+    #[test]
+    fn transfer_works() {
+        let host = Rc::new(HostMemory::new());
+        let memory: Rc<dyn DeviceMemory> = host.clone();
+
+        let tensor = Tensor::from_vec("x", &[2, 2], vec![1.0_f32, 2.0, 3.0, 4.0]).unwrap();
+        let device = upload(&memory, &tensor).unwrap();
+        assert_eq!(Location::Device, device.location());
+        assert_eq!(16, device.data_len());
+        assert!(device.as_bytes().is_none());
+        assert!(device.to_vec::<f32>().is_err());
+        assert_eq!(1, host.allocated());
+
+        // 设备上的Tensor复制时共享设备内存
+        let shared = upload(&memory, &device).unwrap();
+        assert_eq!(device.data_ptr(), shared.data_ptr());
+        assert_eq!(1, host.allocated());
+
+        let back = download(&memory, &device).unwrap();
+        assert_eq!(Location::Host, back.location());
+        assert_eq!(vec![1.0_f32, 2.0, 3.0, 4.0], back.to_vec::<f32>().unwrap());
+
+        drop(device);
+        assert_eq!(1, host.allocated());
+        drop(shared);
+        assert_eq!(0, host.allocated());
+    }
+
+    #[test]
+    fn place_outputs_works() {
+        let host = Rc::new(HostMemory::new());
+        let memory: Rc<dyn DeviceMemory> = host.clone();
+
+        let outputs = vec![Tensor::from_vec("y", &[3], vec![1_i32, 2, 3]).unwrap()];
+        let outputs = place_outputs(&memory, outputs, true).unwrap();
+        assert_eq!(Location::Device, outputs[0].location());
+        assert_eq!(1, host.allocated());
+
+        let outputs = place_outputs(&memory, outputs, false).unwrap();
+        assert_eq!(Location::Host, outputs[0].location());
+        assert_eq!(vec![1_i32, 2, 3], outputs[0].to_vec::<i32>().unwrap());
+        assert_eq!(0, host.allocated());
+    }
+}
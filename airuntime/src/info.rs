@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+
+use model::tensor::DType;
+
+use crate::pb::{self, tensor_shape_proto::dimension, type_proto};
+
+/// 模型的描述信息
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelInfo {
+    /// ONNX IR版本
+    pub ir_version: i64,
+    /// 生成模型的工具名
+    pub producer_name: String,
+    /// 生成模型的工具版本
+    pub producer_version: String,
+    /// 模型的命名空间
+    pub domain: String,
+    /// 模型版本
+    pub model_version: i64,
+    pub doc_string: String,
+    /// 模型的附加信息，保持模型中的顺序
+    pub metadata_props: Vec<(String, String)>,
+    /// 模型输入，不包含有初始值的输入
+    pub inputs: Vec<Signature>,
+    /// 模型输出
+    pub outputs: Vec<Signature>,
+}
+
+/// 模型输入输出的描述
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    pub dtype: DType,
+    /// 形状，None表示维数未知
+    pub dims: Option<Vec<Dim>>,
+    pub doc_string: String,
+}
+
+/// 输入输出形状中的一维
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dim {
+    /// 固定大小
+    Value(i64),
+    /// 符号维度，如batch
+    Param(String),
+    /// 未知大小
+    Unknown,
+}
+
+impl ModelInfo {
+    pub(crate) fn from_proto(proto: &pb::ModelProto) -> Self {
+        let (inputs, outputs) = match &proto.graph {
+            Some(g) => {
+                let initializers: HashSet<&str> = g
+                    .initializer
+                    .iter()
+                    .map(|t| t.name.as_str())
+                    .chain(
+                        g.sparse_initializer
+                            .iter()
+                            .filter_map(|t| t.values.as_ref().map(|v| v.name.as_str())),
+                    )
+                    .collect();
+                let inputs = g
+                    .input
+                    .iter()
+                    .filter(|v| !initializers.contains(v.name.as_str()))
+                    .map(Signature::from_proto)
+                    .collect();
+                let outputs = g.output.iter().map(Signature::from_proto).collect();
+                (inputs, outputs)
+            }
+            None => (vec![], vec![]),
+        };
+
+        Self {
+            ir_version: proto.ir_version,
+            producer_name: proto.producer_name.clone(),
+            producer_version: proto.producer_version.clone(),
+            domain: proto.domain.clone(),
+            model_version: proto.model_version,
+            doc_string: proto.doc_string.clone(),
+            metadata_props: proto
+                .metadata_props
+                .iter()
+                .map(|e| (e.key.clone(), e.value.clone()))
+                .collect(),
+            inputs,
+            outputs,
+        }
+    }
+
+    /// 查找附加信息，有重复的key时返回第一个
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata_props
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Signature {
+    fn from_proto(v: &pb::ValueInfoProto) -> Self {
+        let mut dtype = DType::Undefined;
+        let mut dims = None;
+        if let Some(type_proto::Value::TensorType(tt)) =
+            v.r#type.as_ref().and_then(|t| t.value.as_ref())
+        {
+            dtype = DType::from_code(tt.elem_type as u32);
+            dims = tt.shape.as_ref().map(|s| {
+                s.dim
+                    .iter()
+                    .map(|d| match &d.value {
+                        Some(dimension::Value::DimValue(v)) => Dim::Value(*v),
+                        Some(dimension::Value::DimParam(p)) if !p.is_empty() => {
+                            Dim::Param(p.clone())
+                        }
+                        _ => Dim::Unknown,
+                    })
+                    .collect()
+            });
+        }
+
+        Self {
+            name: v.name.clone(),
+            dtype,
+            dims,
+            doc_string: v.doc_string.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::tensor_shape_proto::Dimension;
+
+    fn value_info(name: &str, dims: Option<Vec<dimension::Value>>) -> pb::ValueInfoProto {
+        pb::ValueInfoProto {
+            name: String::from(name),
+            r#type: Some(pb::TypeProto {
+                value: Some(type_proto::Value::TensorType(type_proto::Tensor {
+                    elem_type: pb::tensor_proto::DataType::Float as i32,
+                    shape: dims.map(|dims| pb::TensorShapeProto {
+                        dim: dims
+                            .into_iter()
+                            .map(|v| Dimension {
+                                value: Some(v),
+                                ..Default::default()
+                            })
+                            .collect(),
+                    }),
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn info_works() {
+        let proto = pb::ModelProto {
+            ir_version: 8,
+            producer_name: String::from("pytorch"),
+            producer_version: String::from("2.0"),
+            model_version: 3,
+            metadata_props: vec![pb::StringStringEntryProto {
+                key: String::from("labels"),
+                value: String::from("cat,dog"),
+            }],
+            graph: Some(pb::GraphProto {
+                input: vec![
+                    value_info(
+                        "x",
+                        Some(vec![
+                            dimension::Value::DimParam(String::from("batch")),
+                            dimension::Value::DimValue(3),
+                        ]),
+                    ),
+                    value_info("w", Some(vec![dimension::Value::DimValue(3)])),
+                ],
+                output: vec![value_info("y", None)],
+                initializer: vec![pb::TensorProto {
+                    name: String::from("w"),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let info = ModelInfo::from_proto(&proto);
+        assert_eq!(8, info.ir_version);
+        assert_eq!("pytorch", info.producer_name);
+        assert_eq!(3, info.model_version);
+        assert_eq!(Some("cat,dog"), info.metadata("labels"));
+        assert_eq!(None, info.metadata("other"));
+
+        assert_eq!(1, info.inputs.len());
+        let x = &info.inputs[0];
+        assert_eq!("x", x.name);
+        assert_eq!(DType::Float32, x.dtype);
+        assert_eq!(
+            Some(vec![Dim::Param(String::from("batch")), Dim::Value(3)]),
+            x.dims
+        );
+        assert_eq!(None, info.outputs[0].dims);
+    }
+}
@@ -1,12 +1,25 @@
 pub use anyhow::*;
 
+pub mod device;
+pub mod info;
 mod loader;
+pub mod partition;
+pub mod pass;
+pub mod schema;
+mod tflite;
 
+pub use loader::{ExternalDataResolver, LoadError};
+
+use bridge::nndevice::memory::DeviceMemory;
 use bridge::nndevice::{self, engine};
-use log::error;
+use info::ModelInfo;
+use log::{error, warn};
 use model::graph::Graph;
-use model::tensor::Tensor;
+use model::tensor::{Format, Tensor};
+use partition::{Capability, Placement, Plan};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 #[allow(clippy::all)]
 pub mod pb {
@@ -27,15 +40,52 @@ pub struct Config {
     /// 设备id
     pub device_id: i32,
     // pub device_type: DeviceType,
-    // /// 精度类型
-    // pub precision: PrecisionType,
+    /// 精度类型，Float16时常量和中间张量在编译前转换为float16
+    pub precision: PrecisionType,
+    /// 后端选项，以下key由运行时使用，其他的交给后端：
+    /// - `model_sha256`、`model_signature`、`model_public_key`：模型的校验参数，值为hex
+    /// - [`pass::fuse::FUSE_ACTIVATION_KEY`]：是否把激活函数融合进卷积
+    /// - [`pass::precision::BLOCK_LIST_KEY`]：追加的保持float32的算子
+    /// - [`pass::manager::PASSES_KEY`]：编译前执行的pass，默认只执行`precision`、`layout`和
+    ///   `target_opset`需要的pass
+    /// - [`pass::manager::VALIDATE_KEY`]：是否在pass之间检查计算图
+    /// - [`pass::version::TARGET_OPSET_KEY`]：编译前把模型升级到的ai.onnx算子集版本
     pub ops: HashMap<String, String>,
+    /// 稀疏权重保留COO格式交给后端，默认转换为稠密张量
+    pub keep_sparse: bool,
+    /// 后端要求的数据布局，4维和5维张量在编译前转换为该布局，None时保持模型的布局
+    pub layout: Option<Format>,
+}
+
+/// 计算精度
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PrecisionType {
+    /// 保持模型的精度
+    #[default]
+    Float32,
+    /// float32转换为float16，数值敏感的算子保持float32
+    Float16,
 }
 
 /// 模型推理上下文
 pub struct Context {
     pub graph: Graph,
-    pub bridge_ctx: engine::Context,
+    /// 模型的描述信息和输入输出
+    pub info: ModelInfo,
+    pub bridge_ctx: Rc<engine::Context>,
+    /// 设备内存操作，设备上的Tensor持有其引用
+    pub memory: Rc<dyn DeviceMemory>,
+    /// 后端不支持全部算子时切分后的执行计划，None时整个图在后端执行
+    pub plan: Option<Rc<Plan>>,
+    /// 各设备子图的后端上下文，第一个与`bridge_ctx`相同
+    devices: Option<Rc<Devices>>,
+}
+
+/// 推理选项
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    /// 输出保留在设备上，用于将结果直接传给下一个模型
+    pub outputs_on_device: bool,
 }
 
 pub fn get_candidate_backends() -> Result<Vec<String>> {
@@ -46,31 +96,221 @@ pub fn load<C>(config: &Config, cb: C) -> Result<Context>
 where
     C: FnOnce(Result<()>) + 'static,
 {
-    let graph = loader::load(config.model_dir.as_str())?;
+    let (graph, info) = load_graph(config)?;
 
-    let ctx: Context = Context {
-        graph,
-        bridge_ctx: engine::create_context(&config.backend, config.device_id, config.ops.clone())?,
+    compile(graph, info, config, cb)
+}
+
+/// 只加载模型不编译，用于在编译前变换计算图，如量化
+pub fn load_graph(config: &Config) -> Result<(Graph, ModelInfo)> {
+    let opts = load_options(config)?;
+    // 按文件标识选择TFLite或ONNX模型的加载
+    let loaded = if tflite::is_tflite_file(&config.model_dir) {
+        tflite::load(&config.model_dir, &opts)?
+    } else {
+        loader::load(config.model_dir.as_str(), &opts)?
     };
-    engine::compile_graph(&ctx.bridge_ctx, &ctx.graph, |r| {
-        match r {
-            std::result::Result::Ok(_) => cb(Ok(())),
-            std::result::Result::Err(e) => {
-                error!("模型编译失败, {}", e);
-                cb(Err(anyhow!("模型编译失败")));
-            }
+    Ok(loaded)
+}
+
+/// 从内存中加载模型，忽略`config.model_dir`
+///
+/// 模型的外部数据由`resolver`按location提供，没有外部数据时可以为None，TFLite模型不使用
+pub fn load_from_bytes<C>(
+    bytes: &[u8],
+    resolver: Option<&dyn ExternalDataResolver>,
+    config: &Config,
+    cb: C,
+) -> Result<Context>
+where
+    C: FnOnce(Result<()>) + 'static,
+{
+    let opts = load_options(config)?;
+    let (graph, info) = if tflite::is_tflite(bytes) {
+        tflite::load_from_bytes(bytes, &opts)?
+    } else {
+        loader::load_from_bytes(bytes, resolver, &opts)?
+    };
+
+    compile(graph, info, config, cb)
+}
+
+fn load_options(config: &Config) -> Result<loader::Options> {
+    let integrity = loader::Integrity::from_ops(&config.ops)
+        .map_err(|e| LoadError::Integrity {
+            path: config.model_dir.clone(),
+            reason: e.to_string(),
+        })?;
+    Ok(loader::Options {
+        keep_sparse: config.keep_sparse,
+        integrity,
+        target_opset: pass::version::target_opset(&config.ops)?,
+        ..Default::default()
+    })
+}
+
+/// 编译已加载或变换后的计算图，如[`pass::quantize::quantize`]量化后的图
+pub fn compile<C>(graph: Graph, info: ModelInfo, config: &Config, cb: C) -> Result<Context>
+where
+    C: FnOnce(Result<()>) + 'static,
+{
+    let passes = pass::PassManager::from_config(config)?;
+    compile_with_passes(graph, info, config, &passes, cb)
+}
+
+/// 按`passes`变换计算图后编译，用于加入自定义的pass
+pub fn compile_with_passes<C>(
+    graph: Graph,
+    info: ModelInfo,
+    config: &Config,
+    passes: &pass::PassManager,
+    cb: C,
+) -> Result<Context>
+where
+    C: FnOnce(Result<()>) + 'static,
+{
+    let (graph, _) = passes.run(graph)?;
+    let bridge_ctx = Rc::new(engine::create_context(
+        &config.backend,
+        config.device_id,
+        config.ops.clone(),
+    )?);
+    // 后端不支持的算子切分到host上计算
+    let partitions = partition::partition(&graph, &capability(&bridge_ctx))?;
+    if partitions.iter().all(|p| p.placement == Placement::Device) {
+        let ctx: Context = Context {
+            graph,
+            info,
+            memory: bridge_ctx.clone(),
+            bridge_ctx,
+            plan: None,
+            devices: None,
         };
-    })?;
+        engine::compile_graph(&ctx.bridge_ctx, &ctx.graph, |r| {
+            match r {
+                std::result::Result::Ok(_) => cb(Ok(())),
+                std::result::Result::Err(e) => {
+                    error!("模型编译失败, {}", e);
+                    cb(Err(anyhow!("模型编译失败")));
+                }
+            };
+        })?;
+        return Ok(ctx);
+    }
 
-    Ok(ctx)
+    let plan = Rc::new(Plan::new(&graph, partitions));
+    let mut contexts = vec![];
+    for i in 0..plan.device_graphs().count() {
+        contexts.push(match i {
+            0 => bridge_ctx.clone(),
+            _ => Rc::new(engine::create_context(
+                &config.backend,
+                config.device_id,
+                config.ops.clone(),
+            )?),
+        });
+    }
+    // 所有设备子图编译完成后回调一次
+    let pending = Rc::new(RefCell::new((contexts.len(), Some(cb))));
+    if contexts.is_empty() {
+        if let Some(cb) = pending.borrow_mut().1.take() {
+            cb(Ok(()));
+        }
+    }
+    for (ctx, sub) in contexts.iter().zip(plan.device_graphs()) {
+        let pending = pending.clone();
+        let name = sub.name().clone();
+        engine::compile_graph(ctx, sub, move |r| {
+            let mut pending = pending.borrow_mut();
+            pending.0 -= 1;
+            let r = match r {
+                std::result::Result::Ok(_) if pending.0 > 0 => return,
+                std::result::Result::Ok(_) => Ok(()),
+                std::result::Result::Err(e) => {
+                    error!("子图{}编译失败, {}", name, e);
+                    Err(anyhow!("模型编译失败"))
+                }
+            };
+            if let Some(cb) = pending.1.take() {
+                cb(r);
+            }
+        })?;
+    }
+
+    Ok(Context {
+        graph,
+        info,
+        memory: bridge_ctx.clone(),
+        bridge_ctx,
+        plan: Some(plan),
+        devices: Some(Rc::new(Devices { contexts })),
+    })
+}
+
+/// 后端支持的算子，后端不报告时认为支持全部算子
+fn capability(ctx: &engine::Context) -> Capability {
+    match engine::supported_ops(ctx) {
+        std::result::Result::Ok(ops) => ops.iter().fold(Capability::new(), |c, op| {
+            c.with_op(&op.domain, &op.op_type, &op.dtypes)
+        }),
+        std::result::Result::Err(e) => {
+            warn!("后端未报告支持的算子, 按支持全部算子编译, {}", e);
+            Capability::all()
+        }
+    }
+}
+
+/// 执行设备子图的后端上下文
+struct Devices {
+    contexts: Vec<Rc<engine::Context>>,
+}
+
+impl partition::Backend for Devices {
+    fn execute(&self, index: usize, inputs: &[&Tensor], cb: partition::ExecuteCallback) -> Result<()> {
+        let ctx = self
+            .contexts
+            .get(index)
+            .ok_or_else(|| anyhow!("设备子图{}不存在", index))?;
+        let memory: Rc<dyn DeviceMemory> = ctx.clone();
+        engine::excute_with_options(ctx, inputs, false, move |r| match r {
+            // 子图的输出交给后面的子图，需要在host上
+            std::result::Result::Ok(outputs) => cb(device::place_outputs(&memory, outputs, false)),
+            std::result::Result::Err(e) => {
+                error!("设备子图{}推理失败, {}", index, e);
+                cb(Err(anyhow!("模型推理失败")));
+            }
+        })?;
+        Ok(())
+    }
 }
 
 pub fn run<C>(ctx: &Context, inputs: &[&Tensor], cb: C) -> Result<()>
 where
     C: FnOnce(Result<Vec<Tensor>>) + 'static,
 {
-    engine::excute(&ctx.bridge_ctx, inputs, |r| match r {
-        std::result::Result::Ok(outputs) => cb(Ok(outputs)),
+    run_with_options(ctx, inputs, &RunOptions::default(), cb)
+}
+
+/// 执行推理，输入可以在host或设备上，输出位置由`opts`决定
+pub fn run_with_options<C>(ctx: &Context, inputs: &[&Tensor], opts: &RunOptions, cb: C) -> Result<()>
+where
+    C: FnOnce(Result<Vec<Tensor>>) + 'static,
+{
+    let memory = ctx.memory.clone();
+    let on_device = opts.outputs_on_device;
+    if let (Some(plan), Some(devices)) = (&ctx.plan, &ctx.devices) {
+        // host上的子图需要输入数据在host上
+        let inputs = inputs
+            .iter()
+            .map(|t| device::download(&memory, t))
+            .collect::<Result<Vec<_>>>()?;
+        let inputs: Vec<&Tensor> = inputs.iter().collect();
+        return plan.run(devices.clone(), &inputs, move |r| {
+            cb(r.and_then(|outputs| device::place_outputs(&memory, outputs, on_device)))
+        });
+    }
+    engine::excute_with_options(&ctx.bridge_ctx, inputs, on_device, move |r| match r {
+        std::result::Result::Ok(outputs) => cb(device::place_outputs(&memory, outputs, on_device)),
         std::result::Result::Err(e) => {
             error!("模型编译失败, {}", e);
             cb(Err(anyhow!("模型编译失败")));
@@ -80,8 +320,28 @@ where
     Ok(())
 }
 
+/// 销毁上下文，需要先释放所有设备上的Tensor
 pub fn destory_context(ctx: Context) -> Result<()> {
-    engine::destory_context(ctx.bridge_ctx)?;
+    let Context {
+        memory,
+        bridge_ctx,
+        devices,
+        ..
+    } = ctx;
+    drop(memory);
+    if let Some(devices) = devices {
+        let devices = Rc::try_unwrap(devices)
+            .map_err(|_| anyhow!("仍有推理未完成, 无法销毁上下文"))?;
+        // 第一个与bridge_ctx相同
+        for ctx in devices.contexts.into_iter().skip(1) {
+            let ctx = Rc::try_unwrap(ctx)
+                .map_err(|_| anyhow!("仍有设备上的Tensor未释放, 无法销毁上下文"))?;
+            engine::destory_context(ctx)?;
+        }
+    }
+    let bridge_ctx = Rc::try_unwrap(bridge_ctx)
+        .map_err(|_| anyhow!("仍有设备上的Tensor未释放, 无法销毁上下文"))?;
+    engine::destory_context(bridge_ctx)?;
 
     Ok(())
 }
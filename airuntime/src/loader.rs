@@ -1,72 +1,169 @@
+mod error;
+mod external;
+mod function;
+mod integrity;
 mod transform;
 
-use anyhow::*;
+pub use error::LoadError;
+pub use external::ExternalDataResolver;
+pub(crate) use external::MappedFile;
+pub use integrity::Integrity;
+
+use anyhow::{anyhow, Result};
 use log::*;
 
 use prost::Message;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::info::ModelInfo;
+use crate::pass;
 use crate::pb;
+use crate::schema;
 use model::graph::*;
+use model::tensor::{DType, Tensor};
+
+/// 模型解析选项
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// 稀疏张量保留COO格式，否则加载时转换为稠密张量
+    pub keep_sparse: bool,
+    /// 校验外部数据的checksum，需要读取全部外部数据
+    pub verify_checksum: bool,
+    /// 模型的摘要和签名，需要校验时外部数据必须有checksum并校验
+    pub integrity: Integrity,
+    /// 编译前升级到的ai.onnx算子集版本，设置后允许加载低于最低支持版本的模型
+    pub target_opset: Option<i64>,
+}
 
-pub fn load(model_file: &str) -> Result<Graph> {
+pub fn load(model_file: &str, opts: &Options) -> Result<(Graph, ModelInfo), LoadError> {
     let model_file = Path::new(model_file);
-    let graph = parser_model(model_file)?;
+    let model = parser_model(model_file, opts)?;
 
-    Ok(graph)
+    Ok(model)
 }
 
-fn parser_model(model_file: &Path) -> Result<Graph> {
+fn parser_model(model_file: &Path, opts: &Options) -> Result<(Graph, ModelInfo), LoadError> {
     if !model_file.exists() {
         warn!("{:?}模型文件不存在", model_file.to_str());
-        return Err(anyhow!("模型文件不存在"));
+        return Err(LoadError::FileNotFound {
+            path: model_file.display().to_string(),
+        });
     }
     let mut model_path = PathBuf::new();
     model_path.push(&model_file);
 
-    if let Some(dir) = model_path.parent() {
-        let proto = parser_proto(model_path.as_path())?;
-        let graph = parser(&proto, dir)?;
+    let dir = model_path.parent().unwrap_or_else(|| Path::new("."));
+    let integrity = model_integrity(model_path.as_path(), opts)?;
+    let proto = parser_proto(model_path.as_path(), &integrity)?;
+    let resolver = external::DirResolver::new(dir);
+    let files = external::ExternalFiles::new(&resolver, checksum_policy(opts, &integrity));
+    let graph = parser(&proto, Some(&files), opts)?;
 
-        return Ok(graph);
-    };
+    Ok((graph, ModelInfo::from_proto(&proto)))
+}
+
+/// 从内存中解析模型，外部数据由`resolver`提供
+pub fn load_from_bytes(
+    bytes: &[u8],
+    resolver: Option<&dyn ExternalDataResolver>,
+    opts: &Options,
+) -> Result<(Graph, ModelInfo), LoadError> {
+    verify_model(&opts.integrity, "", bytes)?;
+    let proto = pb::ModelProto::decode(bytes).map_err(|source| LoadError::Decode {
+        path: String::new(),
+        source,
+    })?;
+    let checksum = checksum_policy(opts, &opts.integrity);
+    let files = resolver.map(|r| external::ExternalFiles::new(r, checksum));
+    let graph = parser(&proto, files.as_ref(), opts)?;
+
+    Ok((graph, ModelInfo::from_proto(&proto)))
+}
+
+/// 需要完整性校验时每个外部数据都必须有checksum
+fn checksum_policy(opts: &Options, integrity: &Integrity) -> external::ChecksumPolicy {
+    if integrity.is_enabled() {
+        external::ChecksumPolicy::Required
+    } else if opts.verify_checksum {
+        external::ChecksumPolicy::IfPresent
+    } else {
+        external::ChecksumPolicy::Skip
+    }
+}
+
+/// 配置的校验参数，没有配置的摘要和签名从模型旁的文件读取
+pub(crate) fn model_integrity(model_file: &Path, opts: &Options) -> Result<Integrity, LoadError> {
+    opts.integrity
+        .clone()
+        .with_sidecar(model_file)
+        .map_err(|e| LoadError::Integrity {
+            path: model_file.display().to_string(),
+            reason: e.to_string(),
+        })
+}
 
-    Err(anyhow!("解析失败"))
+/// 解析之前校验模型数据，未配置校验时直接通过
+pub(crate) fn verify_model(integrity: &Integrity, path: &str, bytes: &[u8]) -> Result<(), LoadError> {
+    if !integrity.is_enabled() {
+        return Ok(());
+    }
+    integrity
+        .verify(bytes)
+        .map_err(|reason| LoadError::Integrity {
+            path: String::from(path),
+            reason,
+        })?;
+    info!("模型{}完整性校验通过", path);
+
+    Ok(())
 }
 
-fn parser_proto(model_file: &Path) -> Result<pb::ModelProto> {
-    let map = unsafe { memmap2::Mmap::map(&fs::File::open(model_file)?)? };
-    let pb = crate::pb::ModelProto::decode(&*map)?;
+fn parser_proto(model_file: &Path, integrity: &Integrity) -> Result<pb::ModelProto, LoadError> {
+    let map = fs::File::open(model_file)
+        .and_then(|f| unsafe { memmap2::Mmap::map(&f) })
+        .map_err(|e| anyhow!("模型文件{}读取失败, {}", model_file.display(), e))?;
+    verify_model(integrity, &model_file.display().to_string(), &map)?;
+    let pb = crate::pb::ModelProto::decode(&*map).map_err(|source| LoadError::Decode {
+        path: model_file.display().to_string(),
+        source,
+    })?;
     Ok(pb)
 }
 
-fn parser(proto: &pb::ModelProto, dir: &Path) -> Result<Graph> {
-    let onnx_operator_set_version = proto
-        .opset_import
-        .iter()
-        .find(|import| import.domain.is_empty() || import.domain == "ai.onnx")
-        .map(|op| op.version)
-        .unwrap_or(0);
+/// 低于该版本的ONNX算子集在广播等语义上与现行定义差异较大，只能转换到新版本后使用
+const MIN_ONNX_OPSET: i64 = 7;
+
+fn parser(
+    proto: &pb::ModelProto,
+    external_files: Option<&external::ExternalFiles>,
+    opts: &Options,
+) -> Result<Graph, LoadError> {
     let pbgraph = proto
         .graph
         .as_ref()
         .ok_or_else(|| anyhow!("model proto does not contain a graph"))?;
-    debug!("ONNX operator set version: {:?}", onnx_operator_set_version);
-    // if onnx_operator_set_version != 0 && !(9..19).contains(&onnx_operator_set_version) {
-    //     warn!("ONNX operator for your model is {}, tract is only tested against \
-    //           operator set 9 to 18 (included). Your model may still work so this is not a hard fail.",
-    //           onnx_operator_set_version);
-    // }
+    let opset_imports = parser_opset_imports(proto, opts)?;
+    if !opset_imports.contains_key(schema::ONNX_DOMAIN)
+        && pbgraph
+            .node
+            .iter()
+            .any(|n| schema::normalize_domain(&n.domain) == schema::ONNX_DOMAIN)
+    {
+        return Err(anyhow!("模型使用了{}算子, 但没有导入该算子集", schema::ONNX_DOMAIN).into());
+    }
+    let functions = function::Functions::new(&proto.functions)?;
     let ctx = ParsingContext {
         // framework: self,
         model: proto,
+        functions: &functions,
         parent_graphs: vec![],
 
         subgraph: vec![],
-        onnx_operator_set_version,
-        model_path: dir.to_str(),
+        opset_imports,
+        external_files,
+        keep_sparse: opts.keep_sparse,
         // device_type: self.device_type,
         // device_options: vec![],
         // symbol_table: symbol_table.clone(),
@@ -75,13 +172,54 @@ fn parser(proto: &pb::ModelProto, dir: &Path) -> Result<Graph> {
     ctx.parse_graph(pbgraph)
 }
 
+/// 解析模型导入的算子集，domain统一为非空形式
+fn parser_opset_imports(proto: &pb::ModelProto, opts: &Options) -> Result<HashMap<String, i64>> {
+    let registry = schema::Registry::global();
+    let mut imports = HashMap::new();
+    for import in proto.opset_import.iter() {
+        let domain = schema::normalize_domain(&import.domain);
+        if imports.insert(String::from(domain), import.version).is_some() {
+            return Err(anyhow!("算子集{}被重复导入", domain));
+        }
+        debug!("opset import: {} v{}", domain, import.version);
+        match registry.max_version(domain) {
+            None => info!("算子集{}没有内置的算子定义, 其中的算子不做检查", domain),
+            Some(max) if import.version > max => warn!(
+                "模型导入的算子集{}版本为{}, 高于已知的最高版本{}, 算子按版本{}的定义检查",
+                domain, import.version, max, max
+            ),
+            Some(_) => {}
+        }
+    }
+    if let Some(&version) = imports.get(schema::ONNX_DOMAIN) {
+        // 配置了目标版本时由pass::version在编译前升级
+        let upgraded = opts.target_opset.is_some_and(|t| t >= MIN_ONNX_OPSET);
+        if version < MIN_ONNX_OPSET && !upgraded {
+            return Err(anyhow!(
+                "模型的{}算子集版本为{}, 最低支持版本{}, 可以配置{}升级",
+                schema::ONNX_DOMAIN,
+                version,
+                MIN_ONNX_OPSET,
+                pass::version::TARGET_OPSET_KEY
+            ));
+        }
+    }
+    Ok(imports)
+}
+
 #[derive(Clone)]
 struct ParsingContext<'a> {
-    pub onnx_operator_set_version: i64,
+    /// 模型导入的算子集，domain到版本
+    pub opset_imports: HashMap<String, i64>,
+    /// 模型本地函数，解析时展开为基本算子
+    pub functions: &'a function::Functions<'a>,
     pub subgraph: Vec<&'a Graph>,
     pub model: &'a pb::ModelProto,
     pub parent_graphs: Vec<&'a Graph>,
-    pub model_path: Option<&'a str>,
+    /// 模型目录下的外部数据文件
+    pub external_files: Option<&'a external::ExternalFiles<'a>>,
+    /// 稀疏张量保留COO格式
+    pub keep_sparse: bool,
     // pub device_type: DeviceType,
     // 可选设备列表
     // pub device_options: Vec<i32>,
@@ -89,39 +227,107 @@ struct ParsingContext<'a> {
 }
 
 impl<'a> ParsingContext<'a> {
-    pub fn parse_graph(&self, pbgraph: &pb::GraphProto) -> Result<Graph> {
+    /// 模型中导入的指定domain的算子集版本
+    pub fn opset_version(&self, domain: &str) -> i64 {
+        self.opset_imports
+            .get(schema::normalize_domain(domain))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// 函数体的解析上下文，函数导入的算子集覆盖模型导入的版本
+    fn in_function(&self, function: &pb::FunctionProto) -> Self {
+        let mut ctx = self.clone();
+        for import in function.opset_import.iter() {
+            ctx.opset_imports.insert(
+                String::from(schema::normalize_domain(&import.domain)),
+                import.version,
+            );
+        }
+        ctx
+    }
+
+    pub fn parse_graph(&self, pbgraph: &pb::GraphProto) -> Result<Graph, LoadError> {
+        self.parse_graph_in_scope(pbgraph, HashMap::new())
+    }
+
+    /// 解析属性中的子图，外层图的张量对子图可见
+    pub fn parse_subgraph(
+        &self,
+        pbgraph: &pb::GraphProto,
+        initializers: &HashMap<String, Tensor>,
+        value_infos: &HashMap<String, Tensor>,
+    ) -> Result<Graph, LoadError> {
+        let mut outer_scope = value_infos.clone();
+        outer_scope.extend(initializers.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.parse_graph_in_scope(pbgraph, outer_scope)
+    }
+
+    fn parse_graph_in_scope(
+        &self,
+        pbgraph: &pb::GraphProto,
+        outer_scope: HashMap<String, Tensor>,
+    ) -> Result<Graph, LoadError> {
         // let ctx = self.clone();
 
         let mut graph = Graph::new(&pbgraph.name);
         // graph.name = pbgraph.name.clone();
+        for (domain, version) in self.opset_imports.iter() {
+            graph = graph.add_opset_import(domain, *version)?;
+        }
 
         //遍历构建所有初始化张量的Map
         let mut initializers = HashMap::new();
         for t in pbgraph.initializer.iter() {
-            let tensor = transform::trans_tensor(t, self.model_path)?;
+            let tensor = transform::trans_tensor(t, self.external_files)?;
             initializers.insert(t.name.clone(), tensor);
         }
+        for t in pbgraph.sparse_initializer.iter() {
+            let tensor = transform::trans_sparse_tensor(t, self.external_files, self.keep_sparse)?;
+            initializers.insert(tensor.name().clone(), tensor);
+        }
 
         // 获取张量形状信息
-        let mut value_infos = HashMap::new();
-        // 获取中间张量的形状信息
-        pbgraph.value_info.iter().for_each(|v| {
-            let vi = transform::trans_valueinfo(v);
-            value_infos.insert(v.name.clone(), vi);
-        });
-        // 获取输入的张量形状信息
-        pbgraph.input.iter().for_each(|v| {
-            let vi = transform::trans_valueinfo(v);
+        let mut value_infos = outer_scope;
+        // 获取中间张量、输入和输出的形状信息
+        for v in pbgraph
+            .value_info
+            .iter()
+            .chain(pbgraph.input.iter())
+            .chain(pbgraph.output.iter())
+        {
+            let vi = transform::trans_valueinfo(v)?;
             value_infos.insert(v.name.clone(), vi);
-        });
-        // 获取输出的张量形状信息
-        pbgraph.output.iter().for_each(|v| {
-            let vi = transform::trans_valueinfo(v);
-            value_infos.insert(v.name.clone(), vi);
-        });
+        }
+
+        // 展开模型本地函数调用
+        let nodes = self.functions.inline(&pbgraph.node)?;
+        // 没有value_info的中间张量先按未知类型登记，构建完成后由形状推导补全
+        for pbnode in nodes.iter().map(|n| &n.node) {
+            for output in pbnode.output.iter().filter(|o| !o.is_empty()) {
+                if !value_infos.contains_key(output) {
+                    trace!("tensor {} has no value info", output);
+                    let vi = transform::trans_valueinfo(&pb::ValueInfoProto {
+                        name: output.clone(),
+                        ..Default::default()
+                    })?;
+                    value_infos.insert(output.clone(), vi);
+                }
+            }
+        }
 
         //构建node
-        for (i, pbnode) in pbgraph.node.iter().enumerate() {
+        for (i, inlined) in nodes.iter().enumerate() {
+            let pbnode = &inlined.node;
+            // 函数体中的算子(包括其中的子图)按函数导入的算子集解析
+            let scoped;
+            let ctx = match inlined.function {
+                Some(f) => {
+                    scoped = self.in_function(f);
+                    &scoped
+                }
+                None => self,
+            };
             let name = if !pbnode.name.is_empty() {
                 // pbnode.name.to_string().replace("/", "_")
                 pbnode.name.to_string()
@@ -134,16 +340,463 @@ impl<'a> ParsingContext<'a> {
             trace!("Creating op {}", name);
 
             // graph.add_op(name, pbnode, &initializers, &value_infos)?;
-            let op = transform::build_op(name, pbnode, &mut initializers, &mut value_infos)?;
-            graph = graph.add_operator(op).unwrap();
+            let op = transform::build_op(ctx, name, pbnode, &mut initializers, &mut value_infos)?;
+            // 按算子定义检查，未定义的算子交由后端处理
+            let domain = schema::normalize_domain(&pbnode.domain);
+            let opset = ctx.opset_version(domain);
+            let since_version = match schema::Registry::global().check(domain, opset, &op) {
+                Result::Ok(schema) => schema.version,
+                Err(e @ schema::Error::UnknownOp { .. }) => {
+                    warn!("{}", e);
+                    0
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let op = op.set_opset(domain, since_version)?;
+            graph = graph.add_operator(op)?;
+        }
+
+        // 构建graph input和output，有初始值的输入不作为图的输入
+        let constants: HashSet<&str> = pbgraph
+            .initializer
+            .iter()
+            .map(|t| t.name.as_str())
+            .chain(pbgraph.sparse_initializer.iter().filter_map(|t| {
+                t.values.as_ref().map(|v| v.name.as_str())
+            }))
+            .collect();
+        for v in pbgraph.input.iter() {
+            if !constants.contains(v.name.as_str()) {
+                graph = graph.add_input(value_infos[&v.name].clone())?;
+            }
+        }
+        for v in pbgraph.output.iter() {
+            graph = graph.add_output(value_infos[&v.name].clone())?;
         }
 
-        // // 构建graph input
-        // let gi = Tensor::trans_valueinfo(&pbgraph.input.get(0).unwrap())?;
-        // // 构建graph output
-        // let go = Tensor::trans_valueinfo(&pbgraph.output.get(0).unwrap())?;
-        // graph.inputs.push(gi);
-        // graph.outputs.push(go);
+        let graph = pass::infer::infer_shapes(graph)?;
+        check_defined(&graph)?;
         Ok(graph)
     }
 }
+
+/// 推导后所有算子输出和图输出的数据类型都必须已知，否则后端无法编译
+fn check_defined(graph: &Graph) -> Result<(), LoadError> {
+    let undefined = |node: &str, t: &Tensor| {
+        if !t.name().is_empty() && t.dtype() == DType::Undefined {
+            return Err(LoadError::UndefinedTensor {
+                node: String::from(node),
+                tensor: t.name().clone(),
+            });
+        }
+        Ok(())
+    };
+    for op in graph.operators() {
+        for t in op.outputs().values() {
+            undefined(op.name(), t)?;
+        }
+    }
+    for t in graph.outputs() {
+        undefined("", t)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::tensor::SharedBytes;
+    use std::rc::Rc;
+
+    /// 形状未知的float张量
+    fn value_info(name: &str) -> pb::ValueInfoProto {
+        pb::ValueInfoProto {
+            name: String::from(name),
+            r#type: Some(pb::TypeProto {
+                value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                    elem_type: 1,
+                    shape: None,
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn model(imports: &[(&str, i64)], nodes: Vec<pb::NodeProto>) -> pb::ModelProto {
+        pb::ModelProto {
+            opset_import: imports
+                .iter()
+                .map(|(domain, version)| pb::OperatorSetIdProto {
+                    domain: String::from(*domain),
+                    version: *version,
+                })
+                .collect(),
+            graph: Some(pb::GraphProto {
+                name: String::from("graph"),
+                node: nodes,
+                input: vec![value_info("x")],
+                output: vec![value_info("y")],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn node(op_type: &str, domain: &str) -> pb::NodeProto {
+        pb::NodeProto {
+            name: String::from(op_type),
+            op_type: String::from(op_type),
+            domain: String::from(domain),
+            input: vec![String::from("x")],
+            output: vec![String::from("y")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn opset_works() {
+        let proto = model(
+            &[("", 13), ("com.example", 2)],
+            vec![node("Relu", ""), node("Custom", "com.example")],
+        );
+        let graph = parser(&proto, None, &Options::default()).unwrap();
+        assert_eq!(Some(13), graph.opset_version(schema::ONNX_DOMAIN));
+        assert_eq!(Some(2), graph.opset_version("com.example"));
+
+        let relu = graph.get_operator("Relu").unwrap();
+        assert_eq!(schema::ONNX_DOMAIN, relu.domain());
+        assert_eq!(13, relu.since_version());
+        let custom = graph.get_operator("Custom").unwrap();
+        assert_eq!("com.example", custom.domain());
+        assert_eq!(0, custom.since_version());
+    }
+
+    #[test]
+    fn opset_rejected() {
+        // 版本过低
+        let proto = model(&[("", 6)], vec![node("Relu", "")]);
+        assert!(parser(&proto, None, &Options::default()).is_err());
+        // 重复导入
+        let proto = model(&[("", 13), ("ai.onnx", 14)], vec![node("Relu", "")]);
+        assert!(parser(&proto, None, &Options::default()).is_err());
+        // 使用了未导入的算子集
+        let proto = model(&[("com.example", 1)], vec![node("Relu", "")]);
+        assert!(parser(&proto, None, &Options::default()).is_err());
+        // 高于已知版本时按已知的最高版本检查
+        let proto = model(&[("", 99)], vec![node("Relu", "")]);
+        let graph = parser(&proto, None, &Options::default()).unwrap();
+        assert_eq!(14, graph.get_operator("Relu").unwrap().since_version());
+    }
+
+    #[test]
+    fn old_opset_upgraded() {
+        let float = |name: &str, f: f32| pb::AttributeProto {
+            name: String::from(name),
+            r#type: pb::attribute_proto::AttributeType::Float as i32,
+            f,
+            ..Default::default()
+        };
+        let mut clip = node("Clip", "");
+        clip.attribute = vec![float("min", 0.0), float("max", 6.0)];
+        let proto = model(&[("", 6)], vec![clip]);
+        // 配置目标版本后可以加载，编译前升级
+        let mut config = crate::Config::default();
+        config.ops.insert(
+            String::from(pass::version::TARGET_OPSET_KEY),
+            String::from("13"),
+        );
+        let opts = crate::load_options(&config).unwrap();
+        let graph = parser(&proto, None, &opts).unwrap();
+        assert_eq!(Some(6), graph.opset_version(schema::ONNX_DOMAIN));
+        assert_eq!(6, graph.get_operator("Clip").unwrap().since_version());
+
+        let passes = pass::PassManager::from_config(&config).unwrap();
+        let (graph, reports) = passes.run(graph).unwrap();
+        assert_eq!("version", reports[0].name);
+        assert_eq!(vec![String::from("Clip")], reports[0].changes);
+        assert_eq!(Some(13), graph.opset_version(schema::ONNX_DOMAIN));
+        let clip = graph.get_operator("Clip").unwrap();
+        assert_eq!(13, clip.since_version());
+        assert!(clip.attributes().is_empty());
+        let value = |tag| clip.get_input(tag).unwrap().to_vec::<f32>().unwrap();
+        assert_eq!(vec![0.0_f32], value("1"));
+        assert_eq!(vec![6.0_f32], value("2"));
+
+        // 目标版本也低于最低支持版本时仍然拒绝
+        let opts = Options {
+            target_opset: Some(6),
+            ..Default::default()
+        };
+        assert!(parser(&proto, None, &opts).is_err());
+    }
+
+    #[test]
+    fn functions_inlined() {
+        let mut proto = model(&[("", 13), ("local", 1)], vec![node("Twice", "local")]);
+        proto.functions.push(pb::FunctionProto {
+            name: Some(String::from("Twice")),
+            domain: Some(String::from("local")),
+            input: vec![String::from("a")],
+            output: vec![String::from("b")],
+            opset_import: vec![pb::OperatorSetIdProto {
+                domain: String::new(),
+                version: 14,
+            }],
+            node: vec![
+                pb::NodeProto {
+                    op_type: String::from("Relu"),
+                    input: vec![String::from("a")],
+                    output: vec![String::from("t")],
+                    ..Default::default()
+                },
+                pb::NodeProto {
+                    op_type: String::from("Relu"),
+                    input: vec![String::from("t")],
+                    output: vec![String::from("b")],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+        let graph = parser(&proto, None, &Options::default()).unwrap();
+        let ops = graph.operators();
+        assert_eq!(2, ops.len());
+        assert!(ops.iter().all(|op| op.r#type() == "Relu"));
+        assert!(ops.iter().any(|op| op.get_input("0").unwrap().name() == "x"));
+        assert!(ops.iter().any(|op| op.get_output("0").unwrap().name() == "y"));
+        // 函数体中的算子按函数导入的算子集检查
+        assert!(ops.iter().all(|op| op.since_version() == 14));
+    }
+
+    #[test]
+    fn missing_value_info_inferred() {
+        use pb::tensor_shape_proto::{dimension, Dimension};
+        let dim = |value| Dimension {
+            value: Some(value),
+            ..Default::default()
+        };
+        let mut proto = model(&[("", 13)], vec![node("Relu", ""), node("Transpose", "")]);
+        let graph = proto.graph.as_mut().unwrap();
+        graph.node[0].output[0] = String::from("t");
+        graph.node[1].input[0] = String::from("t");
+        graph.input[0].r#type = Some(pb::TypeProto {
+            value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                elem_type: 1,
+                shape: Some(pb::TensorShapeProto {
+                    dim: vec![
+                        dim(dimension::Value::DimParam(String::from("batch"))),
+                        dim(dimension::Value::DimValue(3)),
+                    ],
+                }),
+            })),
+            ..Default::default()
+        });
+
+        let graph = parser(&proto, None, &Options::default()).unwrap();
+        let t = graph.get_operator("Relu").unwrap().get_output("0").unwrap();
+        assert_eq!(model::tensor::DType::Float32, t.dtype());
+        assert_eq!(&[0, 3], t.shape().data());
+        assert_eq!(Some("batch"), t.dim_param(0));
+        let y = graph.get_operator("Transpose").unwrap().get_output("0").unwrap();
+        assert_eq!(&[3, 0], y.shape().data());
+        assert_eq!(Some("batch"), y.dim_param(1));
+        assert_eq!("x", graph.inputs()[0].name());
+        assert_eq!(&[3, 0], graph.outputs()[0].shape().data());
+    }
+
+    #[test]
+    fn undefined_tensor_rejected() {
+        // 自定义算子的输出没有声明类型，无法推导
+        let mut proto = model(
+            &[("", 13), ("com.example", 1)],
+            vec![node("Custom", "com.example"), node("Relu", "")],
+        );
+        let graph = proto.graph.as_mut().unwrap();
+        graph.node[0].output[0] = String::from("t");
+        graph.node[1].input[0] = String::from("t");
+        let err = parser(&proto, None, &Options::default()).unwrap_err();
+        assert!(
+            matches!(&err, LoadError::UndefinedTensor { node, tensor } if node == "Custom" && tensor == "t"),
+            "{}",
+            err
+        );
+
+        // 输入形状不能广播
+        let mut proto = model(&[("", 13)], vec![node("Add", "")]);
+        let graph = proto.graph.as_mut().unwrap();
+        graph.node[0].input.push(String::from("w"));
+        graph.initializer.push(pb::TensorProto {
+            name: String::from("w"),
+            dims: vec![3],
+            data_type: pb::tensor_proto::DataType::Float as i32,
+            float_data: vec![1.0, 2.0, 3.0],
+            ..Default::default()
+        });
+        graph.input[0].r#type = Some(pb::TypeProto {
+            value: Some(pb::type_proto::Value::TensorType(pb::type_proto::Tensor {
+                elem_type: 1,
+                shape: Some(pb::TensorShapeProto {
+                    dim: vec![pb::tensor_shape_proto::Dimension {
+                        value: Some(pb::tensor_shape_proto::dimension::Value::DimValue(2)),
+                        ..Default::default()
+                    }],
+                }),
+            })),
+            ..Default::default()
+        });
+        let err = parser(&proto, None, &Options::default()).unwrap_err();
+        assert!(
+            matches!(&err, LoadError::ShapeInference { node, .. } if node == "Add"),
+            "{}",
+            err
+        );
+    }
+
+    fn sparse_model() -> pb::ModelProto {
+        let mut proto = model(&[("", 13)], vec![]);
+        let graph = proto.graph.as_mut().unwrap();
+        let mut add = node("Add", "");
+        add.input.push(String::from("w"));
+        graph.node.push(add);
+        graph.sparse_initializer.push(pb::SparseTensorProto {
+            values: Some(pb::TensorProto {
+                name: String::from("w"),
+                dims: vec![2],
+                data_type: pb::tensor_proto::DataType::Float as i32,
+                float_data: vec![5.0, 7.0],
+                ..Default::default()
+            }),
+            // [NNZ, rank]格式的下标
+            indices: Some(pb::TensorProto {
+                dims: vec![2, 2],
+                data_type: pb::tensor_proto::DataType::Int64 as i32,
+                int64_data: vec![0, 1, 1, 2],
+                ..Default::default()
+            }),
+            dims: vec![2, 3],
+        });
+        proto
+    }
+
+    #[test]
+    fn sparse_initializer_works() {
+        let proto = sparse_model();
+        let graph = parser(&proto, None, &Options::default()).unwrap();
+        let w = graph.get_operator("Add").unwrap().get_input("1").unwrap();
+        assert!(!w.is_sparse());
+        assert_eq!(&[2, 3], w.shape().data());
+        assert_eq!(
+            vec![0.0_f32, 5.0, 0.0, 0.0, 0.0, 7.0],
+            w.to_vec::<f32>().unwrap()
+        );
+
+        let opts = Options {
+            keep_sparse: true,
+            ..Default::default()
+        };
+        let graph = parser(&proto, None, &opts).unwrap();
+        let w = graph.get_operator("Add").unwrap().get_input("1").unwrap();
+        assert_eq!(Some(&[1_i64, 5] as &[i64]), w.sparse_indices());
+        assert_eq!(vec![5.0_f32, 7.0], w.to_vec::<f32>().unwrap());
+    }
+
+    #[test]
+    fn load_from_bytes_works() {
+        let mut proto = model(&[("", 13)], vec![]);
+        let graph = proto.graph.as_mut().unwrap();
+        let mut add = node("Add", "");
+        add.input.push(String::from("w"));
+        graph.node.push(add);
+        graph.initializer.push(pb::TensorProto {
+            name: String::from("w"),
+            dims: vec![2],
+            data_type: pb::tensor_proto::DataType::Float as i32,
+            data_location: Some(pb::tensor_proto::DataLocation::External as i32),
+            external_data: vec![pb::StringStringEntryProto {
+                key: String::from("location"),
+                value: String::from("weights"),
+            }],
+            ..Default::default()
+        });
+        let bytes = proto.encode_to_vec();
+
+        let data = model::element::to_bytes(&[1.0_f32, 2.0]);
+        let resolver = |location: &str| -> Result<Rc<dyn SharedBytes>> {
+            match location {
+                "weights" => Ok(Rc::new(Weights(data.clone()))),
+                _ => Err(anyhow!("unknown location {}", location)),
+            }
+        };
+        let (graph, _) = load_from_bytes(&bytes, Some(&resolver), &Options::default()).unwrap();
+        let w = graph.get_operator("Add").unwrap().get_input("1").unwrap();
+        assert_eq!(vec![1.0_f32, 2.0], w.to_vec::<f32>().unwrap());
+
+        // 没有resolver时无法加载外部数据
+        assert!(load_from_bytes(&bytes, None, &Options::default()).is_err());
+        assert!(load_from_bytes(&bytes[1..], Some(&resolver), &Options::default()).is_err());
+    }
+
+    #[derive(Debug)]
+    struct Weights(Vec<u8>);
+
+    impl SharedBytes for Weights {
+        fn bytes(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn load_errors_typed() {
+        let e = load("not_exists.onnx", &Options::default()).unwrap_err();
+        assert!(matches!(e, LoadError::FileNotFound { .. }));
+        let e = load_from_bytes(&[0xff, 0xff], None, &Options::default()).unwrap_err();
+        assert!(matches!(e, LoadError::Decode { .. }));
+        // 摘要不一致时不解析模型
+        let opts = Options {
+            integrity: Integrity {
+                sha256: Some([0; 32]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let e = load_from_bytes(&[0xff, 0xff], None, &opts).unwrap_err();
+        assert!(matches!(e, LoadError::Integrity { .. }));
+
+        // 未定义的输入
+        let mut proto = model(&[("", 13)], vec![node("Relu", "")]);
+        proto.graph.as_mut().unwrap().node[0].input[0] = String::from("z");
+        match parser(&proto, None, &Options::default()).unwrap_err() {
+            LoadError::UndefinedTensor { node, tensor } => {
+                assert_eq!("Relu", node);
+                assert_eq!("z", tensor);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+
+        // 属性中不支持的数据类型，错误中带有节点名
+        let mut proto = model(&[("", 13)], vec![node("Relu", "")]);
+        proto.graph.as_mut().unwrap().node[0]
+            .attribute
+            .push(pb::AttributeProto {
+                name: String::from("value"),
+                r#type: pb::attribute_proto::AttributeType::Tensor as i32,
+                t: Some(pb::TensorProto {
+                    name: String::from("c"),
+                    data_type: 99,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        match parser(&proto, None, &Options::default()).unwrap_err() {
+            LoadError::UnsupportedDType {
+                node, tensor, dtype, ..
+            } => {
+                assert_eq!("Relu", node);
+                assert_eq!("c", tensor);
+                assert_eq!(99, dtype);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::schema;
+
+/// 模型加载失败的原因
+///
+/// `node`为空表示图的初始值或输入输出，不属于某个节点
+#[derive(thiserror::Error, Debug)]
+pub enum LoadError {
+    #[error("模型文件{path}不存在")]
+    FileNotFound { path: String },
+    #[error("模型{path}解析失败, {source}")]
+    Decode {
+        path: String,
+        source: prost::DecodeError,
+    },
+    #[error("{}张量{tensor}的数据类型{dtype}不支持, {reason}", at(.node))]
+    UnsupportedDType {
+        node: String,
+        tensor: String,
+        dtype: i32,
+        reason: String,
+    },
+    #[error("{}属性{attr}不支持, {reason}", at(.node))]
+    UnsupportedAttribute {
+        node: String,
+        attr: String,
+        reason: String,
+    },
+    #[error("{}张量{tensor}未定义", at(.node))]
+    UndefinedTensor { node: String, tensor: String },
+    #[error("{}输出形状推导失败, {reason}", at(.node))]
+    ShapeInference { node: String, reason: String },
+    #[error("{}张量{tensor}的外部数据加载失败, {source}", at(.node))]
+    ExternalData {
+        node: String,
+        tensor: String,
+        source: anyhow::Error,
+    },
+    #[error("{}张量{tensor}的数据无效, {reason}", at(.node))]
+    InvalidTensor {
+        node: String,
+        tensor: String,
+        reason: String,
+    },
+    #[error("模型{path}完整性校验失败, {reason}")]
+    Integrity { path: String, reason: String },
+    #[error(transparent)]
+    InvalidOperator(#[from] schema::Error),
+    #[error("模型无效, {0}")]
+    InvalidModel(#[from] anyhow::Error),
+}
+
+impl LoadError {
+    /// 错误发生在节点`name`的属性中时，补充节点名
+    pub(crate) fn in_node(mut self, name: &str) -> Self {
+        match &mut self {
+            LoadError::UnsupportedDType { node, .. }
+            | LoadError::UnsupportedAttribute { node, .. }
+            | LoadError::UndefinedTensor { node, .. }
+            | LoadError::ShapeInference { node, .. }
+            | LoadError::ExternalData { node, .. }
+            | LoadError::InvalidTensor { node, .. }
+                if node.is_empty() =>
+            {
+                *node = String::from(name);
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+fn at(node: &str) -> String {
+    if node.is_empty() {
+        String::new()
+    } else {
+        format!("节点{}的", node)
+    }
+}
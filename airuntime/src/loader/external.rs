@@ -0,0 +1,400 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::*;
+use log::*;
+use memmap2::Mmap;
+use model::tensor::{DType, SharedBytes, Tensor};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::pb::StringStringEntryProto;
+
+/// TensorProto.external_data描述的外部数据
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalData {
+    /// 相对于模型文件所在目录的路径
+    pub location: String,
+    /// 数据在文件中的起始字节
+    pub offset: usize,
+    /// 数据长度，未设置时到文件末尾
+    pub length: Option<usize>,
+    /// 数据的SHA1摘要，十六进制字符串
+    pub checksum: Option<String>,
+}
+
+impl ExternalData {
+    /// 按ONNX的定义解析key/value，未知的key忽略
+    pub fn parse(entries: &[StringStringEntryProto]) -> Result<Self> {
+        let mut location = None;
+        let mut offset = 0;
+        let mut length = None;
+        let mut checksum = None;
+        for entry in entries {
+            match entry.key.as_str() {
+                "location" => location = Some(entry.value.clone()),
+                "offset" => offset = parse_usize(&entry.key, &entry.value)?,
+                "length" => length = Some(parse_usize(&entry.key, &entry.value)?),
+                "checksum" => checksum = Some(entry.value.to_lowercase()),
+                key => debug!("ignore external data key {}", key),
+            }
+        }
+        let location = location.ok_or_else(|| anyhow!("external data has no location"))?;
+
+        Ok(Self {
+            location,
+            offset,
+            length,
+            checksum,
+        })
+    }
+}
+
+fn parse_usize(key: &str, value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|e| anyhow!("external data {} {} is invalid, {}", key, value, e))
+}
+
+/// 外部数据与checksum不一致
+#[derive(thiserror::Error, Debug)]
+#[error("external data {location} of tensor {tensor} checksum not match, need {expected}, but {actual}")]
+pub struct ChecksumMismatch {
+    pub location: String,
+    pub tensor: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// 需要校验时外部数据没有checksum
+#[derive(thiserror::Error, Debug)]
+#[error("external data {location} of tensor {tensor} has no checksum")]
+pub struct MissingChecksum {
+    pub location: String,
+    pub tensor: String,
+}
+
+/// 外部数据checksum的校验方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumPolicy {
+    /// 不校验
+    Skip,
+    /// 有checksum时校验
+    IfPresent,
+    /// 必须有checksum，用于模型的完整性校验，签名只覆盖模型文件
+    Required,
+}
+
+/// 外部数据文件的内存映射，由引用它的Tensor共享
+pub struct MappedFile {
+    path: PathBuf,
+    map: Mmap,
+}
+
+impl Debug for MappedFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MappedFile {{ path: {:?}, len: {} }}", self.path, self.map.len())
+    }
+}
+
+impl MappedFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            fs::File::open(path).map_err(|e| anyhow!("open file {:?} failed, {}", path, e))?;
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            path: path.to_path_buf(),
+            map,
+        })
+    }
+}
+
+impl SharedBytes for MappedFile {
+    fn bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+/// 按location提供外部数据，返回的数据由引用它的Tensor共享
+pub trait ExternalDataResolver {
+    fn resolve(&self, location: &str) -> Result<Rc<dyn SharedBytes>>;
+}
+
+impl<F> ExternalDataResolver for F
+where
+    F: Fn(&str) -> Result<Rc<dyn SharedBytes>>,
+{
+    fn resolve(&self, location: &str) -> Result<Rc<dyn SharedBytes>> {
+        self(location)
+    }
+}
+
+/// 从模型目录中映射外部数据文件
+///
+/// 数据只在被访问时由操作系统按页读入，不会整体复制到内存中
+pub struct DirResolver {
+    dir: PathBuf,
+}
+
+impl DirResolver {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+}
+
+impl ExternalDataResolver for DirResolver {
+    fn resolve(&self, location: &str) -> Result<Rc<dyn SharedBytes>> {
+        // 只允许模型目录下的相对路径
+        let relative = Path::new(location);
+        if location.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("external data location {} is not under model directory", location);
+        }
+        let path = self.dir.join(relative);
+        let file = MappedFile::open(&path)
+            .map_err(|e| anyhow!("map external data failed, {}", e))?;
+        info!("external data file mapped: {:?}, {} bytes", path, file.map.len());
+        Ok(Rc::new(file))
+    }
+}
+
+/// 模型引用的外部数据，同一location只解析一次
+pub struct ExternalFiles<'a> {
+    resolver: &'a dyn ExternalDataResolver,
+    checksum: ChecksumPolicy,
+    files: RefCell<HashMap<String, Rc<dyn SharedBytes>>>,
+}
+
+impl<'a> ExternalFiles<'a> {
+    pub fn new(resolver: &'a dyn ExternalDataResolver, checksum: ChecksumPolicy) -> Self {
+        Self {
+            resolver,
+            checksum,
+            files: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn open(&self, location: &str) -> Result<Rc<dyn SharedBytes>> {
+        if let Some(file) = self.files.borrow().get(location) {
+            return Ok(file.clone());
+        }
+        let file = self.resolver.resolve(location)?;
+        self.files
+            .borrow_mut()
+            .insert(String::from(location), file.clone());
+        Ok(file)
+    }
+
+    /// 将外部数据设置为tensor的数据，tensor持有文件映射的引用
+    pub fn load(&self, tensor: &mut Tensor, data: &ExternalData) -> Result<()> {
+        let file = self.open(&data.location)?;
+        let file_len = file.bytes().len();
+        if data.offset > file_len {
+            bail!(
+                "external data offset {} of tensor {} exceeds file {} length {}",
+                data.offset,
+                tensor.name(),
+                data.location,
+                file_len
+            );
+        }
+        let dtype = tensor.dtype();
+        if dtype == DType::String || dtype == DType::Undefined {
+            bail!(
+                "tensor {} of type {} has no fixed element size",
+                tensor.name(),
+                dtype
+            );
+        }
+        let length = data.length.unwrap_or(file_len - data.offset);
+        let expected = tensor.shape().len() * dtype.size_of();
+        if length != expected {
+            bail!(
+                "external data length {} of tensor {} not match shape {}",
+                length,
+                tensor.name(),
+                tensor.shape()
+            );
+        }
+        if data.offset + length > file_len {
+            bail!(
+                "external data [{}, {}) of tensor {} exceeds file {} length {}",
+                data.offset,
+                data.offset + length,
+                tensor.name(),
+                data.location,
+                file_len
+            );
+        }
+        if self.checksum == ChecksumPolicy::Required && data.checksum.is_none() {
+            return Err(MissingChecksum {
+                location: data.location.clone(),
+                tensor: tensor.name().clone(),
+            }
+            .into());
+        }
+        if self.checksum != ChecksumPolicy::Skip {
+            if let Some(checksum) = &data.checksum {
+                let bytes = &file.bytes()[data.offset..data.offset + length];
+                // ONNX规定为SHA-1，也接受SHA-256
+                let actual = match checksum.len() {
+                    40 => format!("{:x}", Sha1::digest(bytes)),
+                    64 => format!("{:x}", Sha256::digest(bytes)),
+                    _ => bail!("external data checksum {} is neither SHA-1 nor SHA-256", checksum),
+                };
+                if &actual != checksum {
+                    return Err(ChecksumMismatch {
+                        location: data.location.clone(),
+                        tensor: tensor.name().clone(),
+                        expected: checksum.clone(),
+                        actual,
+                    }
+                    .into());
+                }
+            }
+        }
+        tensor.set_shared_data(file, data.offset, length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::element;
+    use model::tensor::{Format, Type};
+
+    #[derive(Debug)]
+    struct Bytes(Vec<u8>);
+
+    impl SharedBytes for Bytes {
+        fn bytes(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    fn entry(key: &str, value: &str) -> StringStringEntryProto {
+        StringStringEntryProto {
+            key: String::from(key),
+            value: String::from(value),
+        }
+    }
+
+    #[test]
+    fn parse_works() {
+        let data = ExternalData::parse(&[
+            entry("location", "model.onnx.data"),
+            entry("offset", "4096"),
+            entry("length", "16"),
+            entry("checksum", "ABC"),
+        ])
+        .unwrap();
+        assert_eq!("model.onnx.data", data.location);
+        assert_eq!(4096, data.offset);
+        assert_eq!(Some(16), data.length);
+        assert_eq!(Some(String::from("abc")), data.checksum);
+
+        assert!(ExternalData::parse(&[entry("offset", "0")]).is_err());
+        assert!(ExternalData::parse(&[entry("location", "a"), entry("offset", "-1")]).is_err());
+    }
+
+    #[test]
+    fn load_works() {
+        let dir = std::env::temp_dir().join(format!("airuntime-external-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let bytes = element::to_bytes(&[1.0_f32, 2.0, 3.0, 4.0]);
+        fs::write(dir.join("model.onnx.data"), &bytes).unwrap();
+        let checksum = format!("{:x}", Sha1::digest(&bytes[8..]));
+
+        let resolver = DirResolver::new(&dir);
+        let files = ExternalFiles::new(&resolver, ChecksumPolicy::IfPresent);
+        let tensor = || Tensor::new_with_shape("w", &[2], Format::NCHW, DType::Float32, Type::Constant);
+        let data = ExternalData {
+            location: String::from("model.onnx.data"),
+            offset: 8,
+            length: Some(8),
+            checksum: Some(checksum),
+        };
+        let mut t1 = tensor();
+        files.load(&mut t1, &data).unwrap();
+        assert_eq!(vec![3.0_f32, 4.0], t1.to_vec::<f32>().unwrap());
+        // 同一文件只映射一次
+        let mut t2 = tensor();
+        files.load(&mut t2, &data).unwrap();
+        assert_eq!(1, files.files.borrow().len());
+        // 未设置长度时到文件末尾
+        let mut t3 = tensor();
+        files
+            .load(&mut t3, &ExternalData { length: None, checksum: None, ..data.clone() })
+            .unwrap();
+        assert_eq!(vec![3.0_f32, 4.0], t3.to_vec::<f32>().unwrap());
+
+        let sha256 = ExternalData {
+            checksum: Some(format!("{:x}", Sha256::digest(&bytes[8..]))),
+            ..data.clone()
+        };
+        files.load(&mut tensor(), &sha256).unwrap();
+        let bad = ExternalData {
+            checksum: Some(format!("{:x}", Sha1::digest(&bytes))),
+            ..data.clone()
+        };
+        let err = files.load(&mut tensor(), &bad).unwrap_err();
+        assert!(err.is::<ChecksumMismatch>());
+        let bad = ExternalData {
+            checksum: Some(String::from("00")),
+            ..data.clone()
+        };
+        assert!(files.load(&mut tensor(), &bad).is_err());
+        // 完整性校验时必须有checksum
+        let required = ExternalFiles::new(&resolver, ChecksumPolicy::Required);
+        required.load(&mut tensor(), &sha256).unwrap();
+        let unchecked = ExternalData {
+            checksum: None,
+            ..data.clone()
+        };
+        let err = required.load(&mut tensor(), &unchecked).unwrap_err();
+        assert!(err.is::<MissingChecksum>());
+        let bad = ExternalData {
+            location: String::from("../model.onnx.data"),
+            ..data.clone()
+        };
+        assert!(files.load(&mut tensor(), &bad).is_err());
+        let bad = ExternalData {
+            offset: 12,
+            ..data.clone()
+        };
+        assert!(files.load(&mut tensor(), &bad).is_err());
+        let mut strings =
+            Tensor::new_with_shape("s", &[2], Format::NCHW, DType::String, Type::Constant);
+        assert!(files.load(&mut strings, &data).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolver_works() {
+        let bytes = element::to_bytes(&[1_i64, 2, 3]);
+        let resolver = |location: &str| -> Result<Rc<dyn SharedBytes>> {
+            match location {
+                "weights" => Ok(Rc::new(Bytes(bytes.clone()))),
+                _ => bail!("unknown location {}", location),
+            }
+        };
+        let files = ExternalFiles::new(&resolver, ChecksumPolicy::Skip);
+        let mut t = Tensor::new_with_shape("w", &[3], Format::NCHW, DType::Int64, Type::Constant);
+        let data = ExternalData::parse(&[entry("location", "weights")]).unwrap();
+        files.load(&mut t, &data).unwrap();
+        assert_eq!(vec![1_i64, 2, 3], t.to_vec::<i64>().unwrap());
+
+        let data = ExternalData::parse(&[entry("location", "other")]).unwrap();
+        assert!(files.load(&mut t, &data).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::*;
+use log::trace;
+
+use crate::pb::{AttributeProto, FunctionProto, GraphProto, NodeProto};
+use crate::schema;
+
+/// 函数调用展开的最大嵌套深度，超过时认为函数间存在递归调用
+const MAX_DEPTH: usize = 64;
+
+/// 展开后的基本算子节点
+pub struct Inlined<'a> {
+    pub node: NodeProto,
+    /// 节点所在的函数，算子按函数自己导入的算子集检查
+    pub function: Option<&'a FunctionProto>,
+}
+
+/// 模型本地函数表，按(domain, 函数名)索引
+pub struct Functions<'a> {
+    functions: HashMap<(String, String), &'a FunctionProto>,
+    // 展开次数，用于生成唯一的节点和张量名字
+    count: Cell<usize>,
+}
+
+impl<'a> Functions<'a> {
+    pub fn new(functions: &'a [FunctionProto]) -> Result<Self> {
+        let mut map = HashMap::new();
+        for f in functions {
+            let key = key(
+                f.domain.as_deref().unwrap_or(""),
+                f.name.as_deref().unwrap_or(""),
+            );
+            if map.insert(key.clone(), f).is_some() {
+                bail!("模型函数{}::{}重复定义", key.0, key.1);
+            }
+        }
+        Ok(Self {
+            functions: map,
+            count: Cell::new(0),
+        })
+    }
+
+    fn get(&self, node: &NodeProto) -> Option<&'a FunctionProto> {
+        self.functions
+            .get(&key(&node.domain, &node.op_type))
+            .copied()
+    }
+
+    /// 将节点中的函数调用展开为函数体中的节点，返回的节点只包含基本算子
+    pub fn inline(&self, nodes: &[NodeProto]) -> Result<Vec<Inlined<'a>>> {
+        let mut result = vec![];
+        self.inline_into(nodes, None, &mut vec![], &mut result)?;
+        Ok(result)
+    }
+
+    fn inline_into(
+        &self,
+        nodes: &[NodeProto],
+        caller: Option<&'a FunctionProto>,
+        stack: &mut Vec<String>,
+        result: &mut Vec<Inlined<'a>>,
+    ) -> Result<()> {
+        for node in nodes {
+            let function = match self.get(node) {
+                Some(f) => f,
+                None => {
+                    result.push(Inlined {
+                        node: node.clone(),
+                        function: caller,
+                    });
+                    continue;
+                }
+            };
+            let fname = format!(
+                "{}::{}",
+                schema::normalize_domain(&node.domain),
+                node.op_type
+            );
+            if stack.contains(&fname) || stack.len() >= MAX_DEPTH {
+                bail!("模型函数{}存在递归调用: {:?}", fname, stack);
+            }
+            trace!("inline function {} called by {}", fname, node.name);
+
+            let body = self.expand(node, function)?;
+            stack.push(fname);
+            self.inline_into(&body, Some(function), stack, result)?;
+            stack.pop();
+        }
+        Ok(())
+    }
+
+    /// 展开一次函数调用，形参替换为实参，内部张量和节点加上唯一前缀
+    fn expand(&self, call: &NodeProto, function: &FunctionProto) -> Result<Vec<NodeProto>> {
+        if call.input.len() > function.input.len() || call.output.len() > function.output.len() {
+            bail!(
+                "节点{}调用函数{}的输入输出数量({}, {})超过函数定义({}, {})",
+                call.name,
+                call.op_type,
+                call.input.len(),
+                call.output.len(),
+                function.input.len(),
+                function.output.len()
+            );
+        }
+        let n = self.count.get();
+        self.count.set(n + 1);
+        let prefix = format!("{}_{}", call.op_type, n);
+
+        // 未提供的可选输入为空名字，未使用的输出保留为内部张量
+        let mut renames: HashMap<String, String> = HashMap::new();
+        for (i, formal) in function.input.iter().enumerate() {
+            let actual = call.input.get(i).cloned().unwrap_or_default();
+            renames.insert(formal.clone(), actual);
+        }
+        for (i, formal) in function.output.iter().enumerate() {
+            match call.output.get(i) {
+                Some(actual) if !actual.is_empty() => {
+                    renames.insert(formal.clone(), actual.clone());
+                }
+                _ => {}
+            }
+        }
+        let rename = |name: &String| -> String {
+            if name.is_empty() {
+                return String::new();
+            }
+            match renames.get(name) {
+                Some(actual) => actual.clone(),
+                None => format!("{}/{}", prefix, name),
+            }
+        };
+
+        let attrs: HashMap<&str, &AttributeProto> = call
+            .attribute
+            .iter()
+            .map(|a| (a.name.as_str(), a))
+            .collect();
+
+        let mut body = vec![];
+        for (i, node) in function.node.iter().enumerate() {
+            let name = if node.name.is_empty() {
+                format!("{}/{}_{}", prefix, node.op_type, i)
+            } else {
+                format!("{}/{}", prefix, node.name)
+            };
+            let mut node = NodeProto {
+                name,
+                input: node.input.iter().map(rename).collect(),
+                output: node.output.iter().map(rename).collect(),
+                attribute: resolve_attributes(&node.attribute, &attrs),
+                ..node.clone()
+            };
+            for attr in node.attribute.iter_mut() {
+                if let Some(g) = attr.g.as_mut() {
+                    rename_captured(g, &rename, &attrs, &HashSet::new());
+                }
+                for g in attr.graphs.iter_mut() {
+                    rename_captured(g, &rename, &attrs, &HashSet::new());
+                }
+            }
+            body.push(node);
+        }
+        Ok(body)
+    }
+}
+
+fn key(domain: &str, name: &str) -> (String, String) {
+    (
+        String::from(schema::normalize_domain(domain)),
+        String::from(name),
+    )
+}
+
+/// 替换引用调用方属性的属性，调用方未设置时去掉该属性
+fn resolve_attributes(
+    attributes: &[AttributeProto],
+    caller: &HashMap<&str, &AttributeProto>,
+) -> Vec<AttributeProto> {
+    attributes
+        .iter()
+        .filter_map(|a| {
+            if a.ref_attr_name.is_empty() {
+                return Some(a.clone());
+            }
+            caller
+                .get(a.ref_attr_name.as_str())
+                .map(|v| AttributeProto {
+                    name: a.name.clone(),
+                    ref_attr_name: String::new(),
+                    ..(*v).clone()
+                })
+        })
+        .collect()
+}
+
+/// 子图中引用的外层张量按函数的映射改名，子图及外层子图(scope)内定义的张量保持不变
+fn rename_captured<F>(
+    graph: &mut GraphProto,
+    rename: &F,
+    caller: &HashMap<&str, &AttributeProto>,
+    scope: &HashSet<String>,
+) where
+    F: Fn(&String) -> String,
+{
+    let mut local = scope.clone();
+    local.extend(graph.input.iter().map(|v| v.name.clone()));
+    local.extend(graph.initializer.iter().map(|t| t.name.clone()));
+    local.extend(graph.node.iter().flat_map(|n| n.output.iter().cloned()));
+
+    for node in graph.node.iter_mut() {
+        for input in node.input.iter_mut() {
+            if !input.is_empty() && !local.contains(input) {
+                *input = rename(input);
+            }
+        }
+        node.attribute = resolve_attributes(&node.attribute, caller);
+        for attr in node.attribute.iter_mut() {
+            if let Some(g) = attr.g.as_mut() {
+                rename_captured(g, rename, caller, &local);
+            }
+            for g in attr.graphs.iter_mut() {
+                rename_captured(g, rename, caller, &local);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb;
+
+    fn node(op_type: &str, inputs: &[&str], outputs: &[&str]) -> NodeProto {
+        NodeProto {
+            op_type: String::from(op_type),
+            input: inputs.iter().map(|s| String::from(*s)).collect(),
+            output: outputs.iter().map(|s| String::from(*s)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn function(
+        name: &str,
+        inputs: &[&str],
+        outputs: &[&str],
+        nodes: Vec<NodeProto>,
+    ) -> FunctionProto {
+        FunctionProto {
+            name: Some(String::from(name)),
+            domain: Some(String::from("local")),
+            input: inputs.iter().map(|s| String::from(*s)).collect(),
+            output: outputs.iter().map(|s| String::from(*s)).collect(),
+            attribute: vec![String::from("alpha")],
+            node: nodes,
+            ..Default::default()
+        }
+    }
+
+    fn inline(functions: &Functions, nodes: &[NodeProto]) -> Vec<NodeProto> {
+        functions
+            .inline(nodes)
+            .unwrap()
+            .into_iter()
+            .map(|n| n.node)
+            .collect()
+    }
+
+    fn call(op_type: &str, inputs: &[&str], outputs: &[&str]) -> NodeProto {
+        NodeProto {
+            name: String::from("call"),
+            domain: String::from("local"),
+            ..node(op_type, inputs, outputs)
+        }
+    }
+
+    #[test]
+    fn inline_works() {
+        let mut leaky = node("LeakyRelu", &["t"], &["y"]);
+        leaky.attribute.push(AttributeProto {
+            name: String::from("alpha"),
+            ref_attr_name: String::from("alpha"),
+            r#type: pb::attribute_proto::AttributeType::Float as i32,
+            ..Default::default()
+        });
+        let inner = function(
+            "Inner",
+            &["x"],
+            &["y"],
+            vec![node("Neg", &["x"], &["t"]), leaky],
+        );
+        let outer = function(
+            "Outer",
+            &["a", "b"],
+            &["c"],
+            vec![
+                node("Add", &["a", "b"], &["s"]),
+                NodeProto {
+                    domain: String::from("local"),
+                    attribute: vec![AttributeProto {
+                        name: String::from("alpha"),
+                        ref_attr_name: String::from("alpha"),
+                        ..Default::default()
+                    }],
+                    ..node("Inner", &["s"], &["c"])
+                },
+            ],
+        );
+        let functions = [inner, outer];
+        let functions = Functions::new(&functions).unwrap();
+
+        let mut caller = call("Outer", &["x1", "x2"], &["out"]);
+        caller.attribute.push(AttributeProto {
+            name: String::from("alpha"),
+            f: 0.5,
+            r#type: pb::attribute_proto::AttributeType::Float as i32,
+            ..Default::default()
+        });
+        let nodes = inline(&functions, &[node("Relu", &["in"], &["x1"]), caller]);
+
+        let types: Vec<&str> = nodes.iter().map(|n| n.op_type.as_str()).collect();
+        assert_eq!(vec!["Relu", "Add", "Neg", "LeakyRelu"], types);
+        // 形参替换为实参，内部张量加上前缀
+        assert_eq!(vec!["x1", "x2"], nodes[1].input);
+        let s = &nodes[1].output[0];
+        assert_eq!(&vec![s.clone()], &nodes[2].input);
+        assert_eq!(vec!["out"], nodes[3].output);
+        assert_ne!("t", nodes[2].output[0]);
+        // 属性引用替换为调用方的值
+        let alpha = &nodes[3].attribute[0];
+        assert_eq!("alpha", alpha.name);
+        assert!(alpha.ref_attr_name.is_empty());
+        assert_eq!(0.5, alpha.f);
+
+        // 调用方未设置属性时去掉该属性
+        let nodes = inline(&functions, &[call("Outer", &["x1", "x2"], &["out"])]);
+        assert!(nodes[2].attribute.is_empty());
+    }
+
+    #[test]
+    fn nested_subgraph_scope() {
+        // 内层子图引用外层子图定义的张量和函数内部张量
+        let inner = GraphProto {
+            node: vec![node("Add", &["u", "t"], &["v"])],
+            ..Default::default()
+        };
+        let outer = GraphProto {
+            node: vec![NodeProto {
+                attribute: vec![AttributeProto {
+                    name: String::from("body"),
+                    g: Some(inner),
+                    ..Default::default()
+                }],
+                ..node("Neg", &["t"], &["u"])
+            }],
+            ..Default::default()
+        };
+        let f = function(
+            "F",
+            &["x"],
+            &["y"],
+            vec![
+                node("Relu", &["x"], &["t"]),
+                NodeProto {
+                    attribute: vec![AttributeProto {
+                        name: String::from("then_branch"),
+                        g: Some(outer),
+                        ..Default::default()
+                    }],
+                    ..node("If", &["t"], &["y"])
+                },
+            ],
+        );
+        let functions = [f];
+        let functions = Functions::new(&functions).unwrap();
+        let nodes = inline(&functions, &[call("F", &["a"], &["b"])]);
+
+        let t = &nodes[0].output[0];
+        let outer = nodes[1].attribute[0].g.as_ref().unwrap();
+        assert_eq!(&vec![t.clone()], &outer.node[0].input);
+        let inner = outer.node[0].attribute[0].g.as_ref().unwrap();
+        assert_eq!(vec![String::from("u"), t.clone()], inner.node[0].input);
+    }
+
+    #[test]
+    fn function_recorded() {
+        let f = function("F", &["x"], &["y"], vec![node("Relu", &["x"], &["y"])]);
+        let functions = [f];
+        let functions = Functions::new(&functions).unwrap();
+        let nodes = functions
+            .inline(&[node("Neg", &["in"], &["a"]), call("F", &["a"], &["b"])])
+            .unwrap();
+        assert!(nodes[0].function.is_none());
+        assert_eq!(Some("F"), nodes[1].function.and_then(|f| f.name.as_deref()));
+    }
+
+    #[test]
+    fn recursion_rejected() {
+        let f = function("F", &["x"], &["y"], vec![call("F", &["x"], &["y"])]);
+        let functions = [f];
+        let functions = Functions::new(&functions).unwrap();
+        assert!(functions.inline(&[call("F", &["a"], &["b"])]).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! 模型文件的完整性校验
+//!
+//! SHA-256摘要和Ed25519签名都针对模型文件的全部数据，在解析模型之前校验
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use ed25519_dalek::{Signature, VerifyingKey};
+use log::*;
+use sha2::{Digest, Sha256};
+
+/// `Config.ops`中模型SHA-256摘要的key，值为hex
+pub const SHA256_KEY: &str = "model_sha256";
+/// `Config.ops`中模型签名的key，值为hex
+pub const SIGNATURE_KEY: &str = "model_signature";
+/// `Config.ops`中验证签名的公钥的key，值为hex
+pub const PUBLIC_KEY_KEY: &str = "model_public_key";
+
+/// 模型的完整性校验参数
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Integrity {
+    /// 模型数据的SHA-256摘要
+    pub sha256: Option<[u8; 32]>,
+    /// 对模型数据的Ed25519签名
+    pub signature: Option<[u8; 64]>,
+    /// 验证签名的公钥，设置后模型必须有签名，模型有签名时也必须设置
+    pub public_key: Option<[u8; 32]>,
+}
+
+impl Integrity {
+    /// 从`Config.ops`读取校验参数，没有设置的参数为None
+    pub fn from_ops(ops: &HashMap<String, String>) -> Result<Self> {
+        let get = |key: &str| -> Result<Option<Vec<u8>>> {
+            ops.get(key)
+                .map(|v| decode_hex(v.trim()).ok_or_else(|| anyhow!("{} {} is not hex", key, v)))
+                .transpose()
+        };
+
+        let integrity = Self {
+            sha256: get(SHA256_KEY)?
+                .map(|v| to_array(SHA256_KEY, v))
+                .transpose()?,
+            signature: get(SIGNATURE_KEY)?
+                .map(|v| to_array(SIGNATURE_KEY, v))
+                .transpose()?,
+            public_key: get(PUBLIC_KEY_KEY)?
+                .map(|v| to_array(PUBLIC_KEY_KEY, v))
+                .transpose()?,
+        };
+        if let Some(key) = &integrity.public_key {
+            VerifyingKey::from_bytes(key)
+                .map_err(|e| anyhow!("{} is invalid, {}", PUBLIC_KEY_KEY, e))?;
+        }
+
+        Ok(integrity)
+    }
+
+    /// 没有设置的摘要和签名从模型旁的`<model>.sha256`和`<model>.sig`读取
+    ///
+    /// 摘要文件为hex，可以是sha256sum的输出格式；签名文件为64字节或hex
+    pub fn with_sidecar(mut self, model_file: &Path) -> Result<Self> {
+        if self.sha256.is_none() {
+            if let Some(text) = read_sidecar(model_file, "sha256")? {
+                let text = String::from_utf8_lossy(&text);
+                let hex = text.split_whitespace().next().unwrap_or_default();
+                let digest = decode_hex(hex).ok_or_else(|| anyhow!("sha256 file is not hex"))?;
+                self.sha256 = Some(to_array("sha256 file", digest)?);
+            }
+        }
+        if self.signature.is_none() {
+            if let Some(sig) = read_sidecar(model_file, "sig")? {
+                let sig = match sig.len() {
+                    64 => sig,
+                    _ => decode_hex(String::from_utf8_lossy(&sig).trim())
+                        .ok_or_else(|| anyhow!("signature file is neither 64 bytes nor hex"))?,
+                };
+                self.signature = Some(to_array("signature file", sig)?);
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// 是否需要校验
+    pub fn is_enabled(&self) -> bool {
+        self.sha256.is_some() || self.signature.is_some() || self.public_key.is_some()
+    }
+
+    /// 校验模型数据，失败时返回原因
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), String> {
+        if let Some(expected) = &self.sha256 {
+            let actual: [u8; 32] = Sha256::digest(bytes).into();
+            if &actual != expected {
+                return Err(format!(
+                    "SHA-256 need {}, but {}",
+                    encode_hex(expected),
+                    encode_hex(&actual)
+                ));
+            }
+        }
+        match (&self.public_key, &self.signature) {
+            (Some(key), Some(signature)) => {
+                let key = VerifyingKey::from_bytes(key)
+                    .map_err(|e| format!("public key is invalid, {}", e))?;
+                key.verify_strict(bytes, &Signature::from_bytes(signature))
+                    .map_err(|e| format!("Ed25519 signature not match, {}", e))?;
+            }
+            (Some(_), None) => return Err(String::from("model has no signature")),
+            (None, Some(_)) => {
+                return Err(format!(
+                    "model has signature, but {} is not configured",
+                    PUBLIC_KEY_KEY
+                ))
+            }
+            (None, None) => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// 读取`<model>.<ext>`，不存在时返回None
+fn read_sidecar(model_file: &Path, ext: &str) -> Result<Option<Vec<u8>>> {
+    let mut path = PathBuf::from(model_file).into_os_string();
+    path.push(".");
+    path.push(ext);
+    let path = PathBuf::from(path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    debug!("read model sidecar {:?}", path);
+    let data = fs::read(&path).map_err(|e| anyhow!("read {:?} failed, {}", path, e))?;
+    Ok(Some(data))
+}
+
+fn to_array<const N: usize>(name: &str, v: Vec<u8>) -> Result<[u8; N]> {
+    let len = v.len();
+    v.try_into()
+        .map_err(|_| anyhow!("{} need {} bytes, but {}", name, N, len))
+}
+
+pub(crate) fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn verify_works() {
+        let model = b"model data".to_vec();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signature = key.sign(&model).to_bytes();
+        let ops = HashMap::from([
+            (
+                String::from(SHA256_KEY),
+                encode_hex(&Sha256::digest(&model)),
+            ),
+            (
+                String::from(PUBLIC_KEY_KEY),
+                encode_hex(key.verifying_key().as_bytes()),
+            ),
+        ]);
+        let integrity = Integrity::from_ops(&ops).unwrap();
+        assert!(integrity.is_enabled());
+        // 没有签名
+        assert!(integrity.verify(&model).is_err());
+
+        let dir = std::env::temp_dir().join(format!("airuntime-integrity-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let model_file = dir.join("model.onnx");
+        fs::write(dir.join("model.onnx.sig"), encode_hex(&signature)).unwrap();
+        let integrity = integrity.with_sidecar(&model_file).unwrap();
+        assert_eq!(Some(signature), integrity.signature);
+        assert_eq!(Ok(()), integrity.verify(&model));
+
+        let mut tampered = model.clone();
+        tampered[0] ^= 1;
+        assert!(integrity.verify(&tampered).unwrap_err().contains("SHA-256"));
+        let signature_only = Integrity {
+            sha256: None,
+            ..integrity
+        };
+        assert!(signature_only
+            .verify(&tampered)
+            .unwrap_err()
+            .contains("signature"));
+        // 有签名但没有配置公钥
+        let unverifiable = Integrity {
+            public_key: None,
+            ..signature_only
+        };
+        assert!(unverifiable
+            .verify(&model)
+            .unwrap_err()
+            .contains(PUBLIC_KEY_KEY));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let ops = HashMap::from([(String::from(SHA256_KEY), String::from("abc"))]);
+        assert!(Integrity::from_ops(&ops).is_err());
+        assert!(!Integrity::default().is_enabled());
+    }
+}
@@ -1,25 +1,22 @@
 use std::collections::HashMap;
-use std::path::Path;
-use std::path::PathBuf;
 
 use log::trace;
-use log::{info, warn};
-use model::attribute::AttType;
 use model::attribute::Attribute;
+use model::element;
+use model::half::{bf16, f16};
 use model::operator::Operator;
 use model::tensor::*;
 
+use super::error::LoadError;
+use super::external::{ChecksumMismatch, ExternalData, ExternalFiles, MissingChecksum};
+use super::ParsingContext;
 use crate::pb::{self, type_proto::Value, *};
-use anyhow::*;
-
-pub fn trans_tensor(t: &TensorProto, path: Option<&str>) -> Result<Tensor> {
-    let dtype = DType::from_code(t.data_type as u32);
-    // 标量按一维处理
-    let mut shape: Vec<u32> = t.dims.iter().map(|&x| x as u32).collect();
-    if t.dims.len() == 0 {
-        shape = vec![1];
-    }
+use crate::schema::Registry;
+use anyhow::{anyhow, Result};
 
+pub fn trans_tensor(t: &TensorProto, files: Option<&ExternalFiles>) -> Result<Tensor, LoadError> {
+    let dtype = trans_dtype(&t.name, t.data_type)?;
+    let shape: Vec<u32> = t.dims.iter().map(|&x| x as u32).collect();
     let mut tensor = Tensor::new_with_shape(
         &t.name,
         shape.as_slice(),
@@ -27,101 +24,322 @@ pub fn trans_tensor(t: &TensorProto, path: Option<&str>) -> Result<Tensor> {
         dtype,
         Type::Constant,
     );
+    // 标量按一维保存，并标记为0维
+    if t.dims.is_empty() {
+        tensor = tensor.with_scalar();
+    }
     let is_external = t.data_location.is_some()
         && t.data_location == Some(tensor_proto::DataLocation::External.into());
     if t.raw_data.len() > 0 {
+        if dtype == DType::String || dtype == DType::Undefined {
+            return Err(LoadError::UnsupportedDType {
+                node: String::new(),
+                tensor: t.name.clone(),
+                dtype: t.data_type,
+                reason: String::from("can not be stored in raw_data"),
+            });
+        }
+        if t.raw_data.len() != tensor.shape().len() * dtype.size_of() {
+            return Err(invalid_tensor(
+                &t.name,
+                format!(
+                    "raw_data length {} not match shape {}",
+                    t.raw_data.len(),
+                    tensor.shape()
+                ),
+            ));
+        }
         tensor.set_vec_u8(t.raw_data.to_vec(), dtype);
     } else if is_external {
-        if let Some(model_path) = path {
-            // external files will be loaded and fed to the tensor if necessary
-            info!(
-                "number of external file needed for this tensor: {}",
-                t.external_data.len()
-            );
-            let mut tensor_data: Vec<u8> = Vec::new();
-            for external_data in t.external_data.iter()
-            // according to the onnx format, it is possible to have multiple files for one tensor
-            {
-                let p = PathBuf::from(format!("{}/{}", model_path, external_data.value));
-                info!("external file detected: {:?}", p);
-                extend_bytes_from_path(&mut tensor_data, p)?;
-                info!("external file loaded");
-            }
-            tensor.set_vec(tensor_data);
-        } else {
-            warn!("no model path was specified in the parsing context, yet external data was detected. aborting");
+        // 外部数据按固定的元素大小读取
+        if dtype == DType::String || dtype == DType::Undefined {
+            return Err(invalid_tensor(
+                &t.name,
+                format!("{} can not be stored as external data", dtype),
+            ));
         }
-    } else {
-        match dtype {
-            DType::Float16 => tensor.set_vec(t.float_data.to_vec()),
-            DType::Int64 => tensor.set_vec(t.int64_data.to_vec()),
-            DType::Int32 => tensor.set_vec(t.int32_data.to_vec()),
-            DType::Float32 => tensor.set_vec(t.float_data.to_vec()),
-            // TODO 待验证
-            // DType::Int16 => tensor.set_vec(t.int32_data),
-            // DType::Int8 => tensor.set_vec(t.int32_data),
-            // DType::Uint16 => tensor.set_vec(t.int32_data),
-            // DType::Uint32 => ptr = t.int32_data.as_ptr() as *mut u8,
-            // DType::Uint8 => ptr = t.int32_data.as_ptr() as *mut u8,
-            // DType::Uint64 => ptr = t.uint64_data.as_ptr() as *mut u8,
-            // DType::Float16 => ptr = t.float_data.as_ptr() as *mut u8,
-            // DType::String => ptr = t.string_data.as_ptr() as *mut u8,
-            // DType::Double => ptr = t.double_data.as_ptr() as *mut u8,
-            // DType::Bool => ptr = t.int32_data.as_ptr() as *mut u8,
-            _ => panic!("dtype {} is not exist", dtype),
+        let external_error = |source| LoadError::ExternalData {
+            node: String::new(),
+            tensor: t.name.clone(),
+            source,
         };
+        let files = files.ok_or_else(|| {
+            external_error(anyhow!("model has no external data resolver"))
+        })?;
+        let data = ExternalData::parse(&t.external_data).map_err(external_error)?;
+        trace!("tensor {} external data: {:?}", t.name, data);
+        files
+            .load(&mut tensor, &data)
+            .map_err(|e| {
+                let e = match e.downcast::<ChecksumMismatch>() {
+                    Ok(e) => {
+                        return LoadError::Integrity {
+                            path: e.location.clone(),
+                            reason: e.to_string(),
+                        }
+                    }
+                    Err(e) => e,
+                };
+                match e.downcast::<MissingChecksum>() {
+                    Ok(e) => LoadError::Integrity {
+                        path: e.location.clone(),
+                        reason: e.to_string(),
+                    },
+                    Err(e) => external_error(e),
+                }
+            })?;
+    } else {
+        trans_typed_data(t, &mut tensor)?;
     }
 
     Ok(tensor)
 }
 
-fn extend_bytes_from_path(buf: &mut Vec<u8>, p: impl AsRef<Path>) -> Result<()> {
-    use std::fs;
+/// 按ONNX的存储规则解析typed字段中的数据
+///
+/// 8位和16位整数、bool、float16和bfloat16按元素保存在int32_data中，
+/// uint32和uint64保存在uint64_data中，复数按实部、虚部交替保存
+fn trans_typed_data(t: &TensorProto, tensor: &mut Tensor) -> Result<(), LoadError> {
+    let dtype = tensor.dtype();
+    let count = tensor.shape().len();
+    let check = |len: usize, per_element: usize| -> Result<(), LoadError> {
+        if len != count * per_element {
+            return Err(invalid_tensor(
+                &t.name,
+                format!("has {} values for shape {}, dtype {:?}", len, tensor.shape(), dtype),
+            ));
+        }
+        Ok(())
+    };
+    let int32 = &t.int32_data;
+    match dtype {
+        DType::Float32 => {
+            check(t.float_data.len(), 1)?;
+            tensor.set_vec(t.float_data.to_vec());
+        }
+        DType::Complex64 => {
+            check(t.float_data.len(), 2)?;
+            tensor.set_vec_u8(element::to_bytes(&t.float_data), dtype);
+        }
+        DType::Int32 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.to_vec());
+        }
+        DType::Int16 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| v as i16).collect());
+        }
+        DType::Int8 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| v as i8).collect());
+        }
+        DType::Uint16 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| v as u16).collect());
+        }
+        DType::Uint8 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| v as u8).collect());
+        }
+        DType::Bool => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| v != 0).collect());
+        }
+        DType::Float16 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| f16::from_bits(v as u16)).collect());
+        }
+        DType::Bfloat16 => {
+            check(int32.len(), 1)?;
+            tensor.set_vec(int32.iter().map(|&v| bf16::from_bits(v as u16)).collect());
+        }
+        DType::Int64 => {
+            check(t.int64_data.len(), 1)?;
+            tensor.set_vec(t.int64_data.to_vec());
+        }
+        DType::Float64 => {
+            check(t.double_data.len(), 1)?;
+            tensor.set_vec(t.double_data.to_vec());
+        }
+        DType::Complex128 => {
+            check(t.double_data.len(), 2)?;
+            tensor.set_vec_u8(element::to_bytes(&t.double_data), dtype);
+        }
+        DType::Uint32 => {
+            check(t.uint64_data.len(), 1)?;
+            tensor.set_vec(t.uint64_data.iter().map(|&v| v as u32).collect());
+        }
+        DType::Uint64 => {
+            check(t.uint64_data.len(), 1)?;
+            tensor.set_vec(t.uint64_data.to_vec());
+        }
+        DType::String => {
+            check(t.string_data.len(), 1)?;
+            *tensor = Tensor::from_strings(&t.name, tensor.shape().data(), t.string_data.to_vec())
+                .map_err(|e| invalid_tensor(&t.name, e.to_string()))?
+                .with_type(tensor.r#type());
+        }
+        DType::Undefined => {
+            return Err(LoadError::UnsupportedDType {
+                node: String::new(),
+                tensor: t.name.clone(),
+                dtype: t.data_type,
+                reason: String::from("dtype is undefined"),
+            })
+        }
+    }
 
-    let file = fs::File::open(p)?;
-    let mmap = unsafe { memmap2::Mmap::map(&file)? };
-    buf.extend_from_slice(&mmap);
     Ok(())
 }
 
-pub fn trans_valueinfo(v: &ValueInfoProto) -> Tensor {
+/// 解析稀疏张量，`keep_sparse`为false时转换为稠密张量
+pub fn trans_sparse_tensor(
+    st: &SparseTensorProto,
+    files: Option<&ExternalFiles>,
+    keep_sparse: bool,
+) -> Result<Tensor, LoadError> {
+    let values = st
+        .values
+        .as_ref()
+        .ok_or_else(|| invalid_tensor("", String::from("sparse tensor has no values")))?;
+    let name = values.name.clone();
+    let dtype = trans_dtype(&name, values.data_type)?;
+    let nnz = values.dims.first().copied().unwrap_or(0) as usize;
+    let values = trans_tensor(values, files)?
+        .as_bytes()
+        .ok_or_else(|| invalid_tensor(&name, String::from("sparse tensor has no value data")))?
+        .to_vec();
+
+    let shape: Vec<u32> = st.dims.iter().map(|&d| d as u32).collect();
+    let indices = match st.indices.as_ref() {
+        Some(t) if t.data_type != tensor_proto::DataType::Int64 as i32 => {
+            return Err(LoadError::UnsupportedDType {
+                node: String::new(),
+                tensor: name,
+                dtype: t.data_type,
+                reason: String::from("sparse tensor indices must be int64"),
+            })
+        }
+        Some(t) => trans_tensor(t, files)?
+            .to_vec::<i64>()
+            .map_err(|e| invalid_tensor(&name, e.to_string()))?,
+        None => vec![],
+    };
+    let indices = if indices.len() == nnz {
+        indices
+    } else if indices.len() == nnz * shape.len() {
+        // [NNZ, rank]格式的下标转换为线性下标
+        indices
+            .chunks(shape.len().max(1))
+            .map(|index| {
+                index
+                    .iter()
+                    .zip(shape.iter())
+                    .fold(0_i64, |acc, (&i, &d)| acc * d as i64 + i)
+            })
+            .collect()
+    } else {
+        return Err(invalid_tensor(
+            &name,
+            format!("sparse tensor has {} indices for {} values", indices.len(), nnz),
+        ));
+    };
+
+    let tensor = Tensor::from_coo(&name, &shape, dtype, values, indices)
+        .map_err(|e| invalid_tensor(&name, e.to_string()))?;
+    if keep_sparse {
+        Ok(tensor)
+    } else {
+        tensor
+            .to_dense()
+            .map_err(|e| invalid_tensor(&name, e.to_string()))
+    }
+}
+
+/// 检查ONNX的数据类型，不支持的类型返回错误而不是panic
+fn trans_dtype(tensor: &str, code: i32) -> Result<DType, LoadError> {
+    u32::try_from(code)
+        .ok()
+        .and_then(DType::try_from_code)
+        .ok_or_else(|| LoadError::UnsupportedDType {
+            node: String::new(),
+            tensor: String::from(tensor),
+            dtype: code,
+            reason: String::from("unknown data type"),
+        })
+}
+
+fn invalid_tensor(tensor: &str, reason: String) -> LoadError {
+    LoadError::InvalidTensor {
+        node: String::new(),
+        tensor: String::from(tensor),
+        reason,
+    }
+}
+
+pub fn trans_valueinfo(v: &ValueInfoProto) -> Result<Tensor, LoadError> {
+    match &v.r#type {
+        Some(t) => trans_typeproto(&v.name, t),
+        None => Ok(Tensor::new_with_shape(&v.name, &[], Format::default(), DType::Undefined, Type::Variable)),
+    }
+}
+
+pub fn trans_typeproto(name: &str, t: &TypeProto) -> Result<Tensor, LoadError> {
     let mut dims = vec![];
+    // 动态维度在shape中为0，记录其符号名
+    let mut params = vec![];
     let mut dtype: DType = DType::Undefined;
-    if let Some(t) = &v.r#type {
-        if let Some(value) = &t.value {
-            let Value::TensorType(tt) = value;
-            dtype = DType::from_code(tt.elem_type as u32);
-            if let Some(d) = tt.shape.clone() {
-                for i in d.dim.iter() {
-                    if let Some(y) = &i.value {
-                        if let pb::tensor_shape_proto::dimension::Value::DimValue(dv) = y {
-                            let udv: u32 = *dv as u32;
-                            dims.push(udv);
-                        }
+    let mut scalar = false;
+    if let Some(value) = &t.value {
+        let Value::TensorType(tt) = value;
+        dtype = trans_dtype(name, tt.elem_type)?;
+        if let Some(d) = tt.shape.clone() {
+            for i in d.dim.iter() {
+                match &i.value {
+                    Some(pb::tensor_shape_proto::dimension::Value::DimValue(dv)) => {
+                        dims.push(*dv as u32);
+                        params.push(None);
+                    }
+                    Some(pb::tensor_shape_proto::dimension::Value::DimParam(p)) => {
+                        dims.push(0);
+                        params.push(Some(p.clone()));
+                    }
+                    None => {
+                        dims.push(0);
+                        params.push(Some(String::new()));
                     }
                 }
             }
+            // 没有shape表示维数未知，空shape为标量
+            scalar = d.dim.is_empty();
         }
     }
 
-    Tensor::new_with_shape(&v.name, &dims, Format::default(), dtype, Type::Variable)
+    let tensor = Tensor::new_with_shape(name, &dims, Format::default(), dtype, Type::Variable)
+        .with_dim_params(params);
+    Ok(if scalar { tensor.with_scalar() } else { tensor })
 }
 
 pub fn build_op(
+    ctx: &ParsingContext,
     name: String,
     pbnode: &pb::NodeProto,
     initializers: &mut HashMap<String, Tensor>,
     value_infos: &mut HashMap<String, Tensor>,
-) -> Result<Operator> {
+) -> Result<Operator, LoadError> {
     let mut op = Operator::new(&name, &pbnode.op_type);
     //input
     for i in 0..pbnode.input.len() {
         let iname = pbnode.input.get(i).unwrap();
         let tag = i.to_string();
+        // 名字为空表示未提供的可选输入
+        if iname.is_empty() {
+            continue;
+        }
 
         // init constant tensor
         if let Some(tensor) = initializers.remove(iname) {
-            op = op.add_input(&tag, tensor).unwrap();
+            op = op.add_input(&tag, tensor)?;
             trace!("op {} add input tensor {}", name, iname);
         } else {
             // input variable edge
@@ -130,11 +348,14 @@ pub fn build_op(
                 // 这里通过clone复制一个Tensor，不会增加很大的内存开销
                 // 如果move的方式，将导致只有input或者output能拿到正确的信息
                 if let Some(iv) = value_infos.get(iname) {
-                    op = op.add_input(&tag, iv.clone()).unwrap();
+                    op = op.add_input(&tag, iv.clone())?;
                 }
             } else {
                 // 对于既不是initializer中，又不存在value_infos中的输入，可能是一个非法的ONNX模型
-                panic!("Invalid ONNX model, Undefine input Tensor {} for op {}, create a default Tensor with none shape.", iname, name);
+                return Err(LoadError::UndefinedTensor {
+                    node: name,
+                    tensor: iname.clone(),
+                });
             }
         }
     }
@@ -142,42 +363,208 @@ pub fn build_op(
     for i in 0..pbnode.output.len() {
         let oname = pbnode.output.get(i).unwrap();
         let tag = i.to_string();
+        if oname.is_empty() {
+            continue;
+        }
 
         if value_infos.contains_key(oname) {
             // 因为value_infos存的Tensor只有描述信息，没有数据，
             // 这里通过clone复制一个Tensor，不会增加很大的内存开销
             // 如果move的方式，将导致只有input或者output能拿到正确的信息
             if let Some(ov) = value_infos.get(oname) {
-                op = op.add_output(&tag, ov.clone()).unwrap();
+                op = op.add_output(&tag, ov.clone())?;
             }
         } else {
             // 对于存在value_infos中的输出，可能是一个非法的ONNX模型
-            panic!("Invalid ONNX model, Undefine output Tensor {} for op {}, create a default Tensor with none shape.", oname, name);
+            return Err(LoadError::UndefinedTensor {
+                node: name,
+                tensor: oname.clone(),
+            });
         }
     }
 
     //attributes
     for a in &pbnode.attribute {
-        let attr = trans_attr(a)?;
-        op = op.add_attribute(&a.name, attr).unwrap();
+        let attr = trans_attr(ctx, a, initializers, value_infos).map_err(|e| e.in_node(&name))?;
+        op = op.add_attribute(&a.name, attr)?;
+    }
+    // 算子定义中的属性默认值
+    let opset = ctx.opset_version(&pbnode.domain);
+    if let Some(schema) = Registry::global().get(&pbnode.domain, &pbnode.op_type, opset) {
+        for a in schema.attributes.iter() {
+            if let Some(v) = a.default_value() {
+                op = op.add_default_attribute(&a.name, v)?;
+            }
+        }
     }
 
     Ok(op)
 }
 
-fn trans_attr(a: &AttributeProto) -> Result<Attribute> {
-    let tp = AttType::from_code(a.r#type as u32);
+fn trans_attr(
+    ctx: &ParsingContext,
+    a: &AttributeProto,
+    initializers: &HashMap<String, Tensor>,
+    value_infos: &HashMap<String, Tensor>,
+) -> Result<Attribute, LoadError> {
+    use attribute_proto::AttributeType;
+
+    let unsupported = |reason: &str| LoadError::UnsupportedAttribute {
+        node: String::new(),
+        attr: a.name.clone(),
+        reason: String::from(reason),
+    };
+    let tp = AttributeType::from_i32(a.r#type)
+        .ok_or_else(|| unsupported(&format!("invalid type {}", a.r#type)))?;
     let attr = match tp {
-        AttType::Floats => Attribute::from(a.floats.as_slice()),
-        AttType::Ints => Attribute::from(a.ints.as_slice()),
-        // TODO 待验证
-        AttType::Strings => Attribute::from_vec_u8_as_strings(a.strings.to_vec()),
-        AttType::String => Attribute::from_vec_u8_as_string(a.s.to_vec()),
-        AttType::Float => Attribute::from(a.f),
-        AttType::Int => Attribute::from(a.i),
-
-        _ => unimplemented!("FIXME, struct tensor loading"),
+        AttributeType::Float => Attribute::from(a.f),
+        AttributeType::Int => Attribute::from(a.i),
+        AttributeType::String => Attribute::from_vec_u8_as_string(a.s.to_vec()),
+        AttributeType::Tensor => {
+            let t = a
+                .t
+                .as_ref()
+                .ok_or_else(|| unsupported("no tensor value"))?;
+            Attribute::from(trans_tensor(t, ctx.external_files)?)
+        }
+        AttributeType::Graph => {
+            let g = a
+                .g
+                .as_ref()
+                .ok_or_else(|| unsupported("no graph value"))?;
+            Attribute::from(ctx.parse_subgraph(g, initializers, value_infos)?)
+        }
+        AttributeType::Floats => Attribute::from(a.floats.as_slice()),
+        AttributeType::Ints => Attribute::from(a.ints.as_slice()),
+        AttributeType::Strings => Attribute::from_vec_u8_as_strings(a.strings.to_vec()),
+        AttributeType::Tensors => Attribute::from(
+            a.tensors
+                .iter()
+                .map(|t| trans_tensor(t, ctx.external_files))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        AttributeType::Graphs => Attribute::from(
+            a.graphs
+                .iter()
+                .map(|g| ctx.parse_subgraph(g, initializers, value_infos))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        AttributeType::TypeProto => {
+            let tp = a
+                .tp
+                .as_ref()
+                .ok_or_else(|| unsupported("no type proto value"))?;
+            Attribute::TypeProto(trans_typeproto(&a.name, tp)?)
+        }
+        AttributeType::TypeProtos => Attribute::TypeProtos(
+            a.type_protos
+                .iter()
+                .map(|tp| trans_typeproto(&a.name, tp))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        AttributeType::SparseTensor => {
+            let t = a
+                .sparse_tensor
+                .as_ref()
+                .ok_or_else(|| unsupported("no sparse tensor value"))?;
+            Attribute::SparseTensor(trans_sparse_tensor(t, ctx.external_files, ctx.keep_sparse)?)
+        }
+        AttributeType::SparseTensors => Attribute::SparseTensors(
+            a.sparse_tensors
+                .iter()
+                .map(|t| trans_sparse_tensor(t, ctx.external_files, ctx.keep_sparse))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        AttributeType::Undefined => return Err(unsupported("type is undefined")),
     };
 
     Ok(attr)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    // 由tests/fixtures/tensors/gen.py生成
+    fn fixture(name: &str) -> Tensor {
+        let path = format!(
+            "{}/tests/fixtures/tensors/{}.pb",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        let bytes = std::fs::read(path).unwrap();
+        let proto = TensorProto::decode(bytes.as_slice()).unwrap();
+        let tensor = trans_tensor(&proto, None).unwrap();
+        assert_eq!(name, tensor.name());
+        tensor
+    }
+
+    #[test]
+    fn trans_tensor_works() {
+        let t = fixture("float");
+        assert_eq!(&[2, 2], t.shape().data());
+        assert_eq!(vec![1.5_f32, -2.0, 3.0, 4.0], t.to_vec::<f32>().unwrap());
+        let t = fixture("scalar");
+        assert_eq!(&[1], t.shape().data());
+        assert_eq!(vec![3.0_f32], t.to_vec::<f32>().unwrap());
+
+        assert_eq!(vec![0_u8, 128, 255], fixture("uint8").to_vec::<u8>().unwrap());
+        assert_eq!(vec![-128_i8, 0, 127], fixture("int8").to_vec::<i8>().unwrap());
+        assert_eq!(vec![0_u16, 65535], fixture("uint16").to_vec::<u16>().unwrap());
+        assert_eq!(vec![-32768_i16, 32767], fixture("int16").to_vec::<i16>().unwrap());
+        assert_eq!(vec![-1_i32, i32::MAX], fixture("int32").to_vec::<i32>().unwrap());
+        assert_eq!(vec![-1_i64, 1 << 62], fixture("int64").to_vec::<i64>().unwrap());
+        assert_eq!(vec![true, false, true], fixture("bool").to_vec::<bool>().unwrap());
+        assert_eq!(
+            vec![f16::from_f32(1.0), f16::from_f32(-2.0)],
+            fixture("float16").to_vec::<f16>().unwrap()
+        );
+        assert_eq!(
+            vec![bf16::from_f32(1.0), bf16::from_f32(-2.0)],
+            fixture("bfloat16").to_vec::<bf16>().unwrap()
+        );
+        assert_eq!(vec![1.25_f64, -2.5], fixture("double").to_vec::<f64>().unwrap());
+        assert_eq!(vec![0_u32, u32::MAX], fixture("uint32").to_vec::<u32>().unwrap());
+        assert_eq!(vec![0_u64, u64::MAX], fixture("uint64").to_vec::<u64>().unwrap());
+        assert_eq!(vec![-2_i16, 3], fixture("raw_int16").to_vec::<i16>().unwrap());
+
+        let t = fixture("string");
+        assert_eq!(DType::String, t.dtype());
+        assert_eq!(Some(&[b"hello".to_vec(), vec![0xff]] as &[Vec<u8>]), t.strings());
+
+        let t = fixture("complex64");
+        assert_eq!(DType::Complex64, t.dtype());
+        assert_eq!(
+            Some(element::to_bytes(&[1.0_f32, 2.0, 3.0, 4.0]).as_slice()),
+            t.as_bytes()
+        );
+        let t = fixture("complex128");
+        assert_eq!(
+            Some(element::to_bytes(&[1.5_f64, -0.5]).as_slice()),
+            t.as_bytes()
+        );
+    }
+
+    #[test]
+    fn trans_tensor_rejects_bad_length() {
+        let proto = TensorProto {
+            name: String::from("bad"),
+            dims: vec![3],
+            data_type: tensor_proto::DataType::Int8 as i32,
+            int32_data: vec![1, 2],
+            ..Default::default()
+        };
+        assert!(trans_tensor(&proto, None).is_err());
+
+        let proto = TensorProto {
+            name: String::from("external"),
+            dims: vec![2],
+            data_type: tensor_proto::DataType::String as i32,
+            data_location: Some(tensor_proto::DataLocation::External.into()),
+            ..Default::default()
+        };
+        let err = trans_tensor(&proto, None).unwrap_err();
+        assert!(matches!(err, LoadError::InvalidTensor { .. }), "{}", err);
+    }
+}
@@ -0,0 +1,542 @@
+//! 按后端能力切分计算图
+//!
+//! 后端支持的算子组成设备子图，其余算子在host上计算，host只支持[`HOST_OPS`]中的算子。
+//! 子图按依赖顺序执行，前面子图的输出作为后面子图的输入。
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use crate::pass::fold;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// 后端支持的算子和数据类型
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capability {
+    /// 支持所有算子
+    all: bool,
+    /// key为(域, 算子类型)，数据类型为空时不限制
+    ops: HashMap<(String, String), Vec<DType>>,
+}
+
+impl Capability {
+    /// 不支持任何算子，用`with_op`添加
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 支持所有算子，后端不报告能力时使用
+    pub fn all() -> Self {
+        Self {
+            all: true,
+            ..Default::default()
+        }
+    }
+
+    /// 支持`domain`域的`op_type`，`dtypes`为空时不限制数据类型
+    pub fn with_op(mut self, domain: &str, op_type: &str, dtypes: &[DType]) -> Self {
+        let key = (normalize_domain(domain).to_string(), String::from(op_type));
+        let supported = self.ops.entry(key).or_default();
+        for dtype in dtypes {
+            if !supported.contains(dtype) {
+                supported.push(*dtype);
+            }
+        }
+        self
+    }
+
+    /// 算子及其输入输出的数据类型都被支持
+    pub fn supports(&self, op: &Operator) -> bool {
+        if self.all {
+            return true;
+        }
+        let key = (
+            normalize_domain(op.domain()).to_string(),
+            op.r#type().clone(),
+        );
+        match self.ops.get(&key) {
+            Some(dtypes) if dtypes.is_empty() => true,
+            Some(dtypes) => op
+                .inputs()
+                .values()
+                .chain(op.outputs().values())
+                .all(|t| dtypes.contains(&t.dtype())),
+            None => false,
+        }
+    }
+}
+
+/// 子图的执行位置
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Placement {
+    Device,
+    Host,
+}
+
+/// 切分后的子图，输入输出为与其他子图或原图交换的张量
+#[derive(Clone, Debug)]
+pub struct Partition {
+    pub placement: Placement,
+    pub graph: Graph,
+}
+
+/// host上可以计算的ai.onnx算子，与常量折叠共用求值，只有形状计算和简单的逐元素算子
+pub const HOST_OPS: &[&str] = fold::FOLDABLE;
+
+/// host上计算支持的数据类型
+pub const HOST_DTYPES: &[DType] = &[
+    DType::Float16,
+    DType::Bfloat16,
+    DType::Float32,
+    DType::Float64,
+    DType::Int8,
+    DType::Int16,
+    DType::Int32,
+    DType::Int64,
+    DType::Uint8,
+    DType::Uint16,
+    DType::Uint32,
+    DType::Uint64,
+    DType::Bool,
+];
+
+/// host上可以计算的算子，算子在[`HOST_OPS`]中且输入输出的数据类型在[`HOST_DTYPES`]中
+pub fn host_supports(op: &Operator) -> bool {
+    normalize_domain(op.domain()) == ONNX_DOMAIN
+        && HOST_OPS.contains(&op.r#type().as_str())
+        && op
+            .inputs()
+            .values()
+            .chain(op.outputs().values())
+            .filter(|t| !t.name().is_empty())
+            .all(|t| HOST_DTYPES.contains(&t.dtype()))
+}
+
+/// 按能力切分计算图，返回按执行顺序排列的子图
+///
+/// 后端和host都不支持的算子返回错误
+pub fn partition(graph: &Graph, capability: &Capability) -> Result<Vec<Partition>> {
+    let order = crate::pass::sorted_operators(graph)?;
+    let producers = crate::pass::producers(graph);
+
+    // 算子的阶段为生产者的最大阶段，位置不同的生产者加1，同一阶段同一位置的算子组成一个子图
+    let mut stages: HashMap<&str, (usize, Placement)> = HashMap::new();
+    for name in order.iter() {
+        let op = graph.get_operator(name).unwrap();
+        let placement = if capability.supports(op) {
+            Placement::Device
+        } else if host_supports(op) {
+            Placement::Host
+        } else {
+            bail!(
+                "op {}({}) is supported by neither the backend nor the host",
+                name,
+                op.r#type()
+            );
+        };
+        let stage = op
+            .inputs()
+            .values()
+            .filter_map(|t| producers.get(t.name()))
+            .map(|p| match stages[p.as_str()] {
+                (s, p) if p == placement => s,
+                (s, _) => s + 1,
+            })
+            .max()
+            .unwrap_or(0);
+        stages.insert(name.as_str(), (stage, placement));
+    }
+    let mut groups: BTreeMap<(usize, Placement), Vec<&str>> = BTreeMap::new();
+    for name in order.iter() {
+        groups.entry(stages[name.as_str()]).or_default().push(name);
+    }
+
+    // 被其他子图或图输出使用的张量
+    let mut used: HashMap<&str, HashSet<(usize, Placement)>> = HashMap::new();
+    for op in graph.operators() {
+        for t in op.inputs().values() {
+            used.entry(t.name().as_str())
+                .or_default()
+                .insert(stages[op.name().as_str()]);
+        }
+    }
+    let graph_outputs: HashSet<&str> = graph.outputs().iter().map(|t| t.name().as_str()).collect();
+
+    let mut partitions = vec![];
+    for (i, (key, names)) in groups.iter().enumerate() {
+        let mut sub = Graph::new(&format!("{}_{}", graph.name(), i));
+        for (domain, version) in graph.opset_imports() {
+            sub = sub.add_opset_import(domain, *version)?;
+        }
+        let mut inputs = HashSet::new();
+        let mut outputs = HashSet::new();
+        for name in names {
+            let op = graph.get_operator(name).unwrap();
+            for t in op.inputs().values() {
+                let external = producers
+                    .get(t.name())
+                    .map_or(t.r#type() != Type::Constant, |p| stages[p.as_str()] != *key);
+                if external && !t.name().is_empty() && inputs.insert(t.name().clone()) {
+                    sub = sub.add_input(t.clone())?;
+                }
+            }
+            for t in op.outputs().values() {
+                let exported = graph_outputs.contains(t.name().as_str())
+                    || used
+                        .get(t.name().as_str())
+                        .is_some_and(|u| u.iter().any(|k| k != key));
+                if exported && outputs.insert(t.name().clone()) {
+                    sub = sub.add_output(t.clone())?;
+                }
+            }
+            sub = sub.add_operator(op.clone())?;
+        }
+        partitions.push(Partition {
+            placement: key.1,
+            graph: sub,
+        });
+    }
+    let devices = partitions
+        .iter()
+        .filter(|p| p.placement == Placement::Device)
+        .count();
+    info!(
+        "graph {} split into {} partitions, {} on device, {} on host",
+        graph.name(),
+        partitions.len(),
+        devices,
+        partitions.len() - devices
+    );
+    Ok(partitions)
+}
+
+/// 设备子图的执行回调
+pub type ExecuteCallback = Box<dyn FnOnce(Result<Vec<Tensor>>)>;
+
+/// 执行设备子图的后端
+pub trait Backend {
+    /// 执行第`index`个设备子图，输入按子图输入的顺序，输出按子图输出的顺序且在host上
+    fn execute(&self, index: usize, inputs: &[&Tensor], cb: ExecuteCallback) -> Result<()>;
+}
+
+/// 切分后的执行计划
+#[derive(Clone, Debug)]
+pub struct Plan {
+    partitions: Vec<Partition>,
+    /// 原图的输入输出
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl Plan {
+    pub fn new(graph: &Graph, partitions: Vec<Partition>) -> Self {
+        Self {
+            partitions,
+            inputs: graph.inputs().iter().map(|t| t.name().clone()).collect(),
+            outputs: graph.outputs().iter().map(|t| t.name().clone()).collect(),
+        }
+    }
+
+    pub fn partitions(&self) -> &[Partition] {
+        &self.partitions
+    }
+
+    /// 设备子图，第i个由后端的第i个子图执行
+    pub fn device_graphs(&self) -> impl Iterator<Item = &Graph> {
+        self.partitions
+            .iter()
+            .filter(|p| p.placement == Placement::Device)
+            .map(|p| &p.graph)
+    }
+
+    /// 按顺序执行各子图，`inputs`按原图输入的顺序且在host上
+    pub fn run<C>(
+        self: &Rc<Self>,
+        backend: Rc<dyn Backend>,
+        inputs: &[&Tensor],
+        cb: C,
+    ) -> Result<()>
+    where
+        C: FnOnce(Result<Vec<Tensor>>) + 'static,
+    {
+        if inputs.len() != self.inputs.len() {
+            bail!(
+                "graph needs {} inputs, but {}",
+                self.inputs.len(),
+                inputs.len()
+            );
+        }
+        let values = self
+            .inputs
+            .iter()
+            .cloned()
+            .zip(inputs.iter().map(|t| (*t).clone()))
+            .collect();
+        let cb: Callback = Rc::new(RefCell::new(Some(Box::new(cb))));
+        self.clone().run_from(backend, 0, 0, values, cb);
+        Ok(())
+    }
+
+    /// 从第`index`个子图继续执行，`device`为已执行的设备子图数
+    fn run_from(
+        self: Rc<Self>,
+        backend: Rc<dyn Backend>,
+        mut index: usize,
+        device: usize,
+        mut values: HashMap<String, Tensor>,
+        cb: Callback,
+    ) {
+        while let Some(p) = self.partitions.get(index) {
+            index += 1;
+            let inputs: Result<Vec<Tensor>> = p
+                .graph
+                .inputs()
+                .iter()
+                .map(|t| {
+                    values
+                        .get(t.name())
+                        .cloned()
+                        .ok_or_else(|| anyhow!("tensor {} has no value", t.name()))
+                })
+                .collect();
+            let inputs = match inputs {
+                Ok(inputs) => inputs,
+                Err(e) => return finish(&cb, Err(e)),
+            };
+            match p.placement {
+                Placement::Host => match run_host(&p.graph, &inputs) {
+                    Ok(outputs) => values.extend(outputs),
+                    Err(e) => return finish(&cb, Err(e)),
+                },
+                Placement::Device => {
+                    let (plan, backend_next, cb_next) = (self.clone(), backend.clone(), cb.clone());
+                    let names: Vec<String> =
+                        p.graph.outputs().iter().map(|t| t.name().clone()).collect();
+                    let refs: Vec<&Tensor> = inputs.iter().collect();
+                    let next: ExecuteCallback = Box::new(move |r| match r {
+                        Ok(outputs) if outputs.len() == names.len() => {
+                            values.extend(names.into_iter().zip(outputs));
+                            plan.run_from(backend_next, index, device + 1, values, cb_next);
+                        }
+                        Ok(outputs) => finish(
+                            &cb_next,
+                            Err(anyhow!(
+                                "partition returned {} outputs, but needs {}",
+                                outputs.len(),
+                                names.len()
+                            )),
+                        ),
+                        Err(e) => finish(&cb_next, Err(e)),
+                    });
+                    if let Err(e) = backend.execute(device, &refs, next) {
+                        finish(&cb, Err(e));
+                    }
+                    return;
+                }
+            }
+        }
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|name| {
+                values
+                    .remove(name)
+                    .ok_or_else(|| anyhow!("output {} has no value", name))
+            })
+            .collect();
+        finish(&cb, outputs);
+    }
+}
+
+type Callback = Rc<RefCell<Option<Box<dyn FnOnce(Result<Vec<Tensor>>)>>>>;
+
+/// 回调只调用一次
+fn finish(cb: &Callback, r: Result<Vec<Tensor>>) {
+    if let Some(cb) = cb.borrow_mut().take() {
+        cb(r);
+    }
+}
+
+/// 在host上计算子图，返回所有算子的输出
+pub fn run_host(graph: &Graph, inputs: &[Tensor]) -> Result<HashMap<String, Tensor>> {
+    let mut values: HashMap<String, Tensor> = graph
+        .inputs()
+        .iter()
+        .zip(inputs)
+        .map(|(t, v)| (t.name().clone(), v.clone()))
+        .collect();
+    for name in crate::pass::sorted_operators(graph)? {
+        let mut op = graph.get_operator(&name).unwrap().clone();
+        for t in op.inputs_mut().values_mut() {
+            if let Some(v) = values.get(t.name()) {
+                *t = v.clone().with_type(Type::Constant).with_format(t.format());
+            }
+        }
+        // 运行时不限制输出的大小
+        let outputs = fold::evaluate(&op, None)
+            .map_err(|e| anyhow!("host op {}({}) failed, {}", name, op.r#type(), e))?;
+        for t in outputs {
+            values.insert(t.name().clone(), t.with_type(Type::Variable));
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{constant, op, var};
+    use model::tensor::Format;
+
+    /// 只支持部分算子的后端，用host计算设备子图
+    struct RestrictedBackend {
+        graphs: Vec<Graph>,
+        executed: RefCell<Vec<usize>>,
+    }
+
+    impl Backend for RestrictedBackend {
+        fn execute(&self, index: usize, inputs: &[&Tensor], cb: ExecuteCallback) -> Result<()> {
+            self.executed.borrow_mut().push(index);
+            let graph = &self.graphs[index];
+            let inputs: Vec<Tensor> = inputs.iter().map(|t| (*t).clone()).collect();
+            let mut values = run_host(graph, &inputs)?;
+            cb(Ok(graph
+                .outputs()
+                .iter()
+                .map(|t| values.remove(t.name()).unwrap())
+                .collect()));
+            Ok(())
+        }
+    }
+
+    fn graph() -> Graph {
+        let f = |name: &str| var(name, &[3]);
+        let two = constant("two", &[1], vec![2.0_f32]);
+        Graph::new("g")
+            .add_input(f("x"))
+            .unwrap()
+            .add_output(f("y"))
+            .unwrap()
+            .add_output(f("m"))
+            .unwrap()
+            .add_operator(op("mul", "Mul", vec![f("x"), two], f("m")))
+            .unwrap()
+            .add_operator(op("sqrt", "Sqrt", vec![f("m")], f("s")))
+            .unwrap()
+            .add_operator(op("add", "Add", vec![f("s"), f("x")], f("y")))
+            .unwrap()
+    }
+
+    #[test]
+    fn partition_works() {
+        let capability = Capability::new()
+            .with_op("", "Mul", &[DType::Float32])
+            .with_op("ai.onnx", "Add", &[]);
+        let graph = graph();
+        let partitions = partition(&graph, &capability).unwrap();
+        let placements: Vec<Placement> = partitions.iter().map(|p| p.placement).collect();
+        assert_eq!(
+            vec![Placement::Device, Placement::Host, Placement::Device],
+            placements
+        );
+        let names = |ts: &[Tensor]| ts.iter().map(|t| t.name().clone()).collect::<Vec<_>>();
+        assert_eq!(vec!["x"], names(partitions[0].graph.inputs()));
+        assert_eq!(vec!["m"], names(partitions[0].graph.outputs()));
+        assert_eq!(vec!["s"], names(partitions[1].graph.outputs()));
+        let mut inputs = names(partitions[2].graph.inputs());
+        inputs.sort();
+        assert_eq!(vec!["s", "x"], inputs);
+
+        // 数据类型不支持时在host上计算，都不支持时报错
+        let capability = Capability::new().with_op("", "Mul", &[DType::Int64]);
+        let partitions = partition(&graph, &capability).unwrap();
+        assert_eq!(1, partitions.len());
+        assert_eq!(Placement::Host, partitions[0].placement);
+        let unsupported = graph
+            .add_operator(op("erf", "Erf", vec![var("y", &[3])], var("e", &[3])))
+            .unwrap();
+        assert!(partition(&unsupported, &capability).is_err());
+        // host不支持的数据类型
+        let s = |name: &str| {
+            Tensor::new_with_shape(name, &[3], Format::NCHW, DType::String, Type::Variable)
+        };
+        let strings = Graph::new("g")
+            .add_input(s("a"))
+            .unwrap()
+            .add_output(s("b"))
+            .unwrap()
+            .add_operator(op("id", "Identity", vec![s("a")], s("b")))
+            .unwrap();
+        assert!(partition(&strings, &capability).is_err());
+    }
+
+    #[test]
+    fn run_host_works() {
+        // 运行时输出大小不受常量折叠的限制
+        let shape = constant("shape", &[2], vec![512_i64, 512]);
+        let expand = op(
+            "expand",
+            "Expand",
+            vec![var("x", &[3]).with_scalar(), shape],
+            Tensor::new("y", Format::NCHW, DType::Float32, Type::Variable),
+        );
+        let graph = Graph::new("g")
+            .add_input(var("x", &[3]).with_scalar())
+            .unwrap()
+            .add_operator(expand)
+            .unwrap();
+        let x = Tensor::from_vec("x", &[1], vec![1.5_f32])
+            .unwrap()
+            .with_scalar();
+        let values = run_host(&graph, &[x]).unwrap();
+        let y = values["y"].to_vec::<f32>().unwrap();
+        assert_eq!(512 * 512, y.len());
+        assert!(y.iter().all(|v| *v == 1.5));
+    }
+
+    #[test]
+    fn plan_run_works() {
+        let graph = graph();
+        let capability = Capability::new()
+            .with_op("", "Mul", &[])
+            .with_op("", "Add", &[]);
+        let plan = Rc::new(Plan::new(&graph, partition(&graph, &capability).unwrap()));
+        let backend = Rc::new(RestrictedBackend {
+            graphs: plan.device_graphs().cloned().collect(),
+            executed: RefCell::new(vec![]),
+        });
+
+        let x = Tensor::from_vec("input", &[3], vec![2.0_f32, 8.0, 0.5]).unwrap();
+        let result = Rc::new(RefCell::new(None));
+        let r = result.clone();
+        plan.run(backend.clone(), &[&x], move |outputs| {
+            *r.borrow_mut() = Some(outputs);
+        })
+        .unwrap();
+        let outputs = result.borrow_mut().take().unwrap().unwrap();
+        assert_eq!(vec![0, 1], *backend.executed.borrow());
+        assert_eq!(
+            vec!["y", "m"],
+            outputs
+                .iter()
+                .map(|t| t.name().as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![4.0_f32, 12.0, 1.5],
+            outputs[0].to_vec::<f32>().unwrap()
+        );
+        assert_eq!(
+            vec![4.0_f32, 16.0, 1.0],
+            outputs[1].to_vec::<f32>().unwrap()
+        );
+
+        assert!(plan.run(backend, &[], |_| {}).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+//! 模型加载后对计算图的变换
+
+pub mod cleanup;
+pub mod fold;
+pub mod fuse;
+pub mod infer;
+pub mod layout;
+pub mod manager;
+pub mod precision;
+pub mod quantize;
+pub mod version;
+
+pub use manager::{Pass, PassManager, PassReport};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::Tensor;
+
+use crate::schema::{Registry, ONNX_DOMAIN};
+
+/// 各张量的生产算子，key为张量名
+pub(crate) fn producers(graph: &Graph) -> HashMap<String, String> {
+    let mut producers = HashMap::new();
+    for op in graph.operators() {
+        for t in op.outputs().values() {
+            producers.insert(t.name().clone(), op.name().clone());
+        }
+    }
+    producers
+}
+
+/// 变换时名字不能改变的张量，包括图输出和子图引用的外层张量
+pub(crate) fn kept_tensors(graph: &Graph) -> HashSet<String> {
+    let mut keep: HashSet<String> = graph.outputs().iter().map(|t| t.name().clone()).collect();
+    for op in graph.operators() {
+        cleanup::subgraph_inputs(op, &mut keep);
+    }
+    keep
+}
+
+/// 图导入的ai.onnx算子集中`op_type`的版本，没有导入时按已知的最高版本
+pub(crate) fn since_version(graph: &Graph, op_type: &str) -> i64 {
+    let registry = Registry::global();
+    let opset = graph
+        .opset_version(ONNX_DOMAIN)
+        .or_else(|| registry.max_version(ONNX_DOMAIN))
+        .unwrap_or(0);
+    registry
+        .get(ONNX_DOMAIN, op_type, opset)
+        .map_or(0, |s| s.version)
+}
+
+/// 变换区域的边界，如布局转换的Transpose、float16的Cast和量化的QuantizeLinear
+///
+/// 变换后的算子输出保留的名字时改名为`{name}_{target_suffix}`，最后统一转换回原来的名字
+pub(crate) struct Boundary<'a> {
+    keep: &'a HashSet<String>,
+    target_suffix: &'a str,
+    source_suffix: &'a str,
+    /// 变换后的算子输出的张量，key为原来的名字
+    produced: HashMap<String, Tensor>,
+    originals: HashMap<String, Tensor>,
+    /// 已经插入转换的张量，key为输出的名字
+    cache: HashMap<String, Tensor>,
+    ops: Vec<Operator>,
+}
+
+impl<'a> Boundary<'a> {
+    pub(crate) fn new(
+        keep: &'a HashSet<String>,
+        target_suffix: &'a str,
+        source_suffix: &'a str,
+    ) -> Self {
+        Self {
+            keep,
+            target_suffix,
+            source_suffix,
+            produced: HashMap::new(),
+            originals: HashMap::new(),
+            cache: HashMap::new(),
+            ops: vec![],
+        }
+    }
+
+    /// 记录变换后的算子的输出`t`，`f`按名字创建变换后的张量
+    pub(crate) fn produce(
+        &mut self,
+        t: &Tensor,
+        f: impl FnOnce(&str) -> Result<Tensor>,
+    ) -> Result<()> {
+        let name = if self.keep.contains(t.name()) {
+            format!("{}_{}", t.name(), self.target_suffix)
+        } else {
+            t.name().clone()
+        };
+        self.produced.insert(t.name().clone(), f(&name)?);
+        self.originals.insert(t.name().clone(), t.clone());
+        Ok(())
+    }
+
+    /// 变换后的算子输出的张量
+    pub(crate) fn produced(&self, name: &str) -> Option<&Tensor> {
+        self.produced.get(name)
+    }
+
+    /// 变换后的算子的输入，`f`按名字创建转换后的张量和转换算子
+    pub(crate) fn target_input(
+        &mut self,
+        t: &Tensor,
+        f: impl FnOnce(&str) -> Result<(Tensor, Operator)>,
+    ) -> Result<Tensor> {
+        if let Some(p) = self.produced.get(t.name()) {
+            return Ok(p.clone());
+        }
+        let name = format!("{}_{}", t.name(), self.target_suffix);
+        self.insert(name, f)
+    }
+
+    /// 变换前的算子的输入，`f`从变换后的张量按名字创建转换后的张量和转换算子
+    pub(crate) fn source_input(
+        &mut self,
+        t: &Tensor,
+        f: impl FnOnce(&Tensor, &str) -> Result<(Tensor, Operator)>,
+    ) -> Result<Tensor> {
+        // 保留的名字最后统一转换
+        if self.keep.contains(t.name()) {
+            return Ok(t.clone());
+        }
+        let Some(p) = self.produced.get(t.name()).cloned() else {
+            return Ok(t.clone());
+        };
+        let name = format!("{}_{}", t.name(), self.source_suffix);
+        self.insert(name, |name| f(&p, name))
+    }
+
+    fn insert(
+        &mut self,
+        name: String,
+        f: impl FnOnce(&str) -> Result<(Tensor, Operator)>,
+    ) -> Result<Tensor> {
+        if let Some(c) = self.cache.get(&name) {
+            return Ok(c.clone());
+        }
+        let (output, op) = f(&name)?;
+        self.cache.insert(name, output.clone());
+        self.ops.push(op);
+        Ok(output)
+    }
+
+    /// 保留的名字按名字顺序转换回原来的张量，返回插入的全部转换算子
+    ///
+    /// `f`创建从变换后的张量到原张量的转换算子
+    pub(crate) fn finish(
+        mut self,
+        mut f: impl FnMut(&Tensor, &Tensor) -> Result<Operator>,
+    ) -> Result<Vec<Operator>> {
+        let mut kept: Vec<&String> = self
+            .keep
+            .iter()
+            .filter(|n| self.produced.contains_key(*n))
+            .collect();
+        kept.sort();
+        for name in kept {
+            self.ops
+                .push(f(&self.produced[name], &self.originals[name])?);
+        }
+        Ok(self.ops)
+    }
+}
+
+/// 按数据依赖排序的算子名，生产者在消费者之前，无依赖关系的算子按名字排序
+pub fn sorted_operators(graph: &Graph) -> Result<Vec<String>> {
+    let producers = producers(graph);
+    let mut indegree = BTreeMap::new();
+    let mut consumers: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for op in graph.operators() {
+        let deps: BTreeSet<&str> = op
+            .inputs()
+            .values()
+            .filter_map(|t| producers.get(t.name()))
+            .map(String::as_str)
+            .filter(|p| *p != op.name())
+            .collect();
+        indegree.insert(op.name().as_str(), deps.len());
+        for p in deps {
+            consumers.entry(p).or_default().insert(op.name());
+        }
+    }
+
+    let mut ready: BTreeSet<&str> = indegree
+        .iter()
+        .filter(|(_, d)| **d == 0)
+        .map(|(n, _)| *n)
+        .collect();
+    let mut sorted = Vec::with_capacity(indegree.len());
+    while let Some(name) = ready.pop_first() {
+        sorted.push(String::from(name));
+        for c in consumers.get(name).into_iter().flatten() {
+            let d = indegree.get_mut(c).unwrap();
+            *d -= 1;
+            if *d == 0 {
+                ready.insert(c);
+            }
+        }
+    }
+    if sorted.len() != indegree.len() {
+        let cycle: Vec<&str> = indegree
+            .iter()
+            .filter(|(_, d)| **d > 0)
+            .map(|(n, _)| *n)
+            .collect();
+        bail!("graph {} has cycle in {:?}", graph.name(), cycle);
+    }
+    Ok(sorted)
+}
+
+/// 各pass测试共用的张量和算子构造
+#[cfg(test)]
+pub(crate) mod test_util {
+    use model::element::Element;
+    use model::graph::Graph;
+    use model::operator::Operator;
+    use model::tensor::{DType, Format, Tensor, Type};
+
+    /// NCHW布局的float32变量
+    pub(crate) fn var(name: &str, shape: &[u32]) -> Tensor {
+        Tensor::new_with_shape(name, shape, Format::NCHW, DType::Float32, Type::Variable)
+    }
+
+    pub(crate) fn constant<T: Element>(name: &str, shape: &[u32], vs: Vec<T>) -> Tensor {
+        Tensor::from_vec(name, shape, vs)
+            .unwrap()
+            .with_type(Type::Constant)
+    }
+
+    /// 输入按顺序编号的单输出算子
+    pub(crate) fn op(name: &str, r#type: &str, inputs: Vec<Tensor>, output: Tensor) -> Operator {
+        let mut op = Operator::new(name, r#type);
+        for (i, t) in inputs.into_iter().enumerate() {
+            op = op.add_input(&i.to_string(), t).unwrap();
+        }
+        op.add_output("0", output).unwrap()
+    }
+
+    /// 输出`tensor`的算子
+    pub(crate) fn producer<'a>(graph: &'a Graph, tensor: &str) -> &'a Operator {
+        graph
+            .operators()
+            .into_iter()
+            .find(|op| op.outputs().values().any(|t| t.name() == tensor))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::operator::Operator;
+    use model::tensor::{DType, Format, Tensor, Type};
+
+    fn tensor(name: &str) -> Tensor {
+        Tensor::new_with_shape(name, &[1], Format::NCHW, DType::Float32, Type::Variable)
+    }
+
+    fn op(name: &str, inputs: &[&str], output: &str) -> Operator {
+        let mut op = Operator::new(name, "Add");
+        for (i, input) in inputs.iter().enumerate() {
+            op = op.add_input(&i.to_string(), tensor(input)).unwrap();
+        }
+        op.add_output("0", tensor(output)).unwrap()
+    }
+
+    #[test]
+    fn sorted_operators_works() {
+        let graph = Graph::new("g")
+            .add_operator(op("c", &["a_out", "b_out"], "c_out"))
+            .unwrap()
+            .add_operator(op("b", &["a_out"], "b_out"))
+            .unwrap()
+            .add_operator(op("a", &["x"], "a_out"))
+            .unwrap();
+        assert_eq!(vec!["a", "b", "c"], sorted_operators(&graph).unwrap());
+
+        let graph = graph.add_operator(op("d", &["e_out"], "x")).unwrap();
+        let graph = graph.add_operator(op("e", &["c_out"], "e_out")).unwrap();
+        assert!(sorted_operators(&graph).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+//! 删除无用的算子
+//!
+//! 跳过Identity、推理时的Dropout、不改变张量的Cast/Reshape等空操作，再删除到达不了图输出的算子。
+//! 常量数据随算子一起删除。
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use log::*;
+use model::attribute::Attribute;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use super::infer::ValueType;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// 清理的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cleanup {
+    /// 跳过的空操作算子
+    pub bypassed: Vec<String>,
+    /// 到达不了图输出的算子
+    pub removed: Vec<String>,
+    /// 不再使用的常量数据的字节数
+    pub freed_bytes: usize,
+}
+
+/// 跳过空操作并删除到达不了图输出的算子，图输出的名字保持不变
+///
+/// 图没有输出时无法判断可达性，只跳过空操作
+pub fn cleanup(mut graph: Graph) -> Result<(Graph, Cleanup)> {
+    let before = constants(&graph);
+    let mut report = Cleanup::default();
+
+    // 子图引用的外层张量和图输出都不能改名
+    let keep = super::kept_tensors(&graph);
+    // 跳过后可能产生新的空操作，如成对的Cast
+    while bypass(&mut graph, &keep, &mut report.bypassed)? {}
+
+    let order = super::sorted_operators(&graph)?;
+    if graph.outputs().is_empty() {
+        warn!(
+            "graph {} has no output, skip dead node elimination",
+            graph.name()
+        );
+    } else {
+        report.removed = unreachable(&graph, &order);
+        for name in report.removed.iter() {
+            graph.remove_operator(name);
+        }
+    }
+
+    let after = constants(&graph);
+    report.freed_bytes = before
+        .iter()
+        .filter(|(name, _)| !after.contains_key(*name))
+        .map(|(_, len)| len)
+        .sum();
+    if !report.bypassed.is_empty() || !report.removed.is_empty() {
+        info!(
+            "graph {} bypassed {} ops, removed {} ops, freed {} bytes",
+            graph.name(),
+            report.bypassed.len(),
+            report.removed.len(),
+            report.freed_bytes
+        );
+    }
+    Ok((graph, report))
+}
+
+/// 按拓扑序跳过空操作，其输出的消费者改为使用其输入，没有跳过任何算子时返回false
+fn bypass(graph: &mut Graph, keep: &HashSet<String>, bypassed: &mut Vec<String>) -> Result<bool> {
+    let order = super::sorted_operators(graph)?;
+    let consumers = consumers(graph);
+    let noops: HashSet<String> = order
+        .iter()
+        .filter(|name| is_noop(graph, graph.get_operator(name).unwrap(), &consumers))
+        .cloned()
+        .collect();
+
+    let mut replace: HashMap<String, Tensor> = HashMap::new();
+    let mut changed = false;
+    for name in order.iter() {
+        let op = graph.get_operator_mut(name).unwrap();
+        for t in op.inputs_mut().values_mut() {
+            if let Some(r) = replace.get(t.name()) {
+                *t = r.clone();
+            }
+        }
+        if !noops.contains(name) {
+            continue;
+        }
+        let input = op.get_input("0").unwrap().clone();
+        let output = op.get_output("0").unwrap().clone();
+        if !keep.contains(output.name()) {
+            replace.insert(output.name().clone(), input);
+        } else if !rename_producer(graph, name, &input, &output, keep) {
+            continue;
+        }
+        graph.remove_operator(name);
+        bypassed.push(name.clone());
+        changed = true;
+    }
+    Ok(changed)
+}
+
+/// 各张量的消费算子
+pub(super) fn consumers(graph: &Graph) -> HashMap<String, Vec<String>> {
+    let mut consumers: HashMap<String, Vec<String>> = HashMap::new();
+    for op in graph.operators() {
+        for t in op.inputs().values() {
+            consumers
+                .entry(t.name().clone())
+                .or_default()
+                .push(op.name().clone());
+        }
+    }
+    consumers
+}
+
+/// 图中常量的名字和数据大小
+fn constants(graph: &Graph) -> HashMap<String, usize> {
+    graph
+        .operators()
+        .iter()
+        .flat_map(|op| op.inputs().values())
+        .filter(|t| t.r#type() == Type::Constant)
+        .map(|t| (t.name().clone(), t.data_len()))
+        .collect()
+}
+
+/// 子图中所有算子的输入，包括引用的外层张量
+pub(super) fn subgraph_inputs(op: &Operator, names: &mut HashSet<String>) {
+    let mut visit = |g: &Graph| {
+        for op in g.operators() {
+            names.extend(op.inputs().values().map(|t| t.name().clone()));
+            subgraph_inputs(op, names);
+        }
+    };
+    for attr in op.attributes().values() {
+        match attr {
+            Attribute::Graph(g) => visit(g),
+            Attribute::Graphs(gs) => gs.iter().for_each(&mut visit),
+            _ => {}
+        }
+    }
+}
+
+/// 输出等于第一个输入的算子
+fn is_noop(graph: &Graph, op: &Operator, consumers: &HashMap<String, Vec<String>>) -> bool {
+    if normalize_domain(op.domain()) != ONNX_DOMAIN {
+        return false;
+    }
+    let (Some(input), Some(output)) = (op.get_input("0"), op.get_output("0")) else {
+        return false;
+    };
+    // 输出只被指定类型的算子使用
+    let only_used_by = |f: &dyn Fn(&Operator) -> bool| {
+        consumers.get(output.name()).is_some_and(|c| {
+            c.iter()
+                .all(|name| graph.get_operator(name).is_some_and(f))
+        })
+    };
+    match op.r#type().as_str() {
+        "Identity" => true,
+        // 推理时Dropout不改变输入，mask没有使用时可以跳过
+        "Dropout" => {
+            let training = op
+                .get_input("2")
+                .and_then(|t| t.to_vec::<bool>().ok())
+                .is_some_and(|v| v.contains(&true));
+            let mask_used = op.get_output("1").is_some_and(|m| {
+                consumers.contains_key(m.name())
+                    || graph.outputs().iter().any(|o| o.name() == m.name())
+            });
+            !training && !mask_used
+        }
+        "Cast" => {
+            let to = op
+                .attr_int_or("to", 0)
+                .ok()
+                .and_then(|to| DType::try_from_code(to as u32));
+            match to {
+                Some(to) if to == input.dtype() => true,
+                // 无损的转换后只被Cast使用，后面的Cast可以直接从输入转换
+                Some(to) => {
+                    widens(input.dtype(), to) && only_used_by(&|c: &Operator| c.r#type() == "Cast")
+                }
+                None => false,
+            }
+        }
+        "Reshape" | "Flatten" | "Squeeze" | "Unsqueeze" => {
+            let same = {
+                let (i, o) = (
+                    ValueType::from_tensor(input),
+                    ValueType::from_tensor(output),
+                );
+                i.dims.is_some() && !input.is_dynamic() && i.dims == o.dims
+            };
+            // 只被不复制输入维度的Reshape使用时，中间的形状无关紧要
+            same || only_used_by(&|c: &Operator| {
+                c.r#type() == "Reshape"
+                    && (c.attr_int_or("allowzero", 0).unwrap_or(0) != 0
+                        || c.get_input("1")
+                            .filter(|s| s.r#type() == Type::Constant)
+                            .and_then(|s| s.to_vec::<i64>().ok())
+                            .is_some_and(|s| !s.contains(&0)))
+            })
+        }
+        _ => false,
+    }
+}
+
+/// `from`的所有值都能用`to`精确表示
+fn widens(from: DType, to: DType) -> bool {
+    use DType::*;
+    let targets: &[DType] = match from {
+        Bool => &[
+            Int8, Uint8, Int16, Uint16, Int32, Uint32, Int64, Uint64, Float16, Float32, Float64,
+        ],
+        Int8 => &[Int16, Int32, Int64, Float16, Float32, Float64],
+        Uint8 => &[
+            Int16, Uint16, Int32, Uint32, Int64, Uint64, Float16, Float32, Float64,
+        ],
+        Int16 => &[Int32, Int64, Float32, Float64],
+        Uint16 => &[Int32, Uint32, Int64, Uint64, Float32, Float64],
+        Int32 => &[Int64, Float64],
+        Uint32 => &[Int64, Uint64, Float64],
+        Float16 | Bfloat16 => &[Float32, Float64],
+        Float32 => &[Float64],
+        _ => &[],
+    };
+    targets.contains(&to)
+}
+
+/// 空操作的输出是图输出时，改为由输入的生产者直接输出该名字
+///
+/// 输入只被该空操作使用时才可以改名
+fn rename_producer(
+    graph: &mut Graph,
+    noop: &str,
+    input: &Tensor,
+    output: &Tensor,
+    keep: &HashSet<String>,
+) -> bool {
+    if keep.contains(input.name()) || graph.inputs().iter().any(|t| t.name() == input.name()) {
+        return false;
+    }
+    let used_elsewhere = graph
+        .operators()
+        .iter()
+        .filter(|op| op.name() != noop)
+        .any(|op| op.inputs().values().any(|t| t.name() == input.name()));
+    let producer = super::producers(graph).remove(input.name());
+    let (Some(producer), false) = (producer, used_elsewhere) else {
+        return false;
+    };
+    let op = graph.get_operator_mut(&producer).unwrap();
+    for t in op.outputs_mut().values_mut() {
+        if t.name() == input.name() {
+            *t = output.clone();
+        }
+    }
+    true
+}
+
+/// 按拓扑序逆序找出到达不了图输出的算子
+fn unreachable(graph: &Graph, order: &[String]) -> Vec<String> {
+    let mut live: HashSet<String> = graph.outputs().iter().map(|t| t.name().clone()).collect();
+    let mut dead = vec![];
+    for name in order.iter().rev() {
+        let Some(op) = graph.get_operator(name) else {
+            continue;
+        };
+        if op.outputs().values().any(|t| live.contains(t.name())) {
+            live.extend(op.inputs().values().map(|t| t.name().clone()));
+            subgraph_inputs(op, &mut live);
+        } else {
+            dead.push(name.clone());
+        }
+    }
+    dead.reverse();
+    dead
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{constant, op, var};
+    use model::tensor::Format;
+
+    #[test]
+    fn cleanup_works() {
+        let v = |name: &str| var(name, &[2, 3]);
+        let weight = Tensor::zeros::<f32>("w", &[2, 3]).with_type(Type::Constant);
+        let scale = constant("scale", &[1], vec![2.0_f32]);
+        let ops = vec![
+            op("dropout", "Dropout", vec![v("x")], v("d"))
+                .add_output("1", v("mask"))
+                .unwrap(),
+            op("identity", "Identity", vec![v("d")], v("i")),
+            op("add", "Add", vec![v("i"), weight], v("a")),
+            op("relu", "Relu", vec![v("a")], v("r")),
+            // 图输出由空操作产生
+            op("out", "Identity", vec![v("r")], v("y")),
+            // 到达不了输出的分支
+            op("dead", "Mul", vec![v("a"), scale], v("m")),
+            op("dead_tail", "Sigmoid", vec![v("m")], v("n")),
+        ];
+        let mut graph = Graph::new("g")
+            .add_input(v("x"))
+            .unwrap()
+            .add_output(v("y"))
+            .unwrap();
+        for op in ops {
+            graph = graph.add_operator(op).unwrap();
+        }
+
+        let (graph, report) = cleanup(graph).unwrap();
+        assert_eq!(vec!["dropout", "identity", "out"], report.bypassed);
+        assert_eq!(vec!["dead", "dead_tail"], report.removed);
+        assert_eq!(4, report.freed_bytes);
+        assert_eq!(2, graph.operators().len());
+        let add = graph.get_operator("add").unwrap();
+        assert_eq!("x", add.get_input("0").unwrap().name());
+        let relu = graph.get_operator("relu").unwrap();
+        assert_eq!("y", relu.get_output("0").unwrap().name());
+        assert_eq!("y", graph.outputs()[0].name());
+    }
+
+    #[test]
+    fn redundant_pairs_bypassed() {
+        let int = |name: &str, dtype| {
+            Tensor::new_with_shape(name, &[2], Format::NCHW, dtype, Type::Variable)
+        };
+        let cast = |name: &str, input, to: DType, output: &str| {
+            Operator::new(name, "Cast")
+                .add_input("0", input)
+                .unwrap()
+                .add_output("0", int(output, to))
+                .unwrap()
+                .add_attribute("to", Attribute::Int(to.get_code() as i64))
+                .unwrap()
+        };
+        let graph = Graph::new("g")
+            .add_output(int("z", DType::Int32))
+            .unwrap()
+            .add_operator(cast("wide", int("x", DType::Int32), DType::Int64, "w"))
+            .unwrap()
+            .add_operator(cast("narrow", int("w", DType::Int64), DType::Int32, "n"))
+            .unwrap()
+            .add_operator(cast("lossy", int("n", DType::Int32), DType::Float16, "f"))
+            .unwrap()
+            .add_operator(cast("back", int("f", DType::Float16), DType::Int32, "z"))
+            .unwrap();
+
+        let (graph, report) = cleanup(graph).unwrap();
+        // 去掉wide后narrow成为同类型的转换
+        assert_eq!(vec!["wide", "narrow"], report.bypassed);
+        let lossy = graph.get_operator("lossy").unwrap();
+        assert_eq!("x", lossy.get_input("0").unwrap().name());
+        assert!(graph.get_operator("back").is_some());
+    }
+}
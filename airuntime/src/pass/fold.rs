@@ -0,0 +1,891 @@
+//! 常量折叠
+//!
+//! 输入都是常量的算子在加载时求值，结果作为新的常量张量替换该算子。
+//! 输入形状固定的Shape和Size也按常量处理，用于折叠导出模型中的形状计算。
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use model::graph::Graph;
+use model::half::{bf16, f16};
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use super::infer;
+use crate::info::Dim;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// 折叠后数据变多时，输出的最大元素数
+const MAX_EXPAND_LEN: usize = 1 << 16;
+
+/// 可以折叠的算子
+pub(crate) const FOLDABLE: &[&str] = &[
+    "Abs",
+    "Add",
+    "And",
+    "Cast",
+    "Ceil",
+    "Concat",
+    "Constant",
+    "ConstantOfShape",
+    "Div",
+    "Equal",
+    "Expand",
+    "Flatten",
+    "Floor",
+    "Gather",
+    "Greater",
+    "Identity",
+    "Less",
+    "Max",
+    "Min",
+    "Mul",
+    "Neg",
+    "Not",
+    "Or",
+    "Range",
+    "Reciprocal",
+    "Reshape",
+    "Shape",
+    "Size",
+    "Slice",
+    "Sqrt",
+    "Squeeze",
+    "Sub",
+    "Transpose",
+    "Unsqueeze",
+    "Where",
+];
+
+/// 被折叠的算子
+#[derive(Clone, Debug, PartialEq)]
+pub struct Folded {
+    pub op: String,
+    pub op_type: String,
+    /// 替换算子输出的常量张量
+    pub constants: Vec<String>,
+}
+
+/// 折叠输入都是常量的算子，返回折叠后的图和被折叠的算子
+///
+/// 输出为图输出或被子图引用的算子不折叠
+pub fn fold_constants(mut graph: Graph) -> Result<(Graph, Vec<Folded>)> {
+    let order = super::sorted_operators(&graph)?;
+    let keep = super::kept_tensors(&graph);
+    let mut constants: HashMap<String, Tensor> = HashMap::new();
+    let mut folded = vec![];
+    for name in order.iter() {
+        let op = graph.get_operator(name).unwrap();
+        if normalize_domain(op.domain()) != ONNX_DOMAIN
+            || !FOLDABLE.contains(&op.r#type().as_str())
+            || op.outputs().values().any(|t| keep.contains(t.name()))
+        {
+            continue;
+        }
+        let mut op = op.clone();
+        for t in op.inputs_mut().values_mut() {
+            if let Some(c) = constants.get(t.name()) {
+                *t = c.clone();
+            }
+        }
+        let shape_only = matches!(op.r#type().as_str(), "Shape" | "Size");
+        if !op.inputs().values().all(|t| is_known(t, shape_only)) {
+            continue;
+        }
+
+        match evaluate(&op, Some(MAX_EXPAND_LEN)) {
+            Ok(outputs) => {
+                let mut names = vec![];
+                for t in outputs {
+                    names.push(t.name().clone());
+                    constants.insert(t.name().clone(), t);
+                }
+                trace!("fold op {}({}) to {:?}", name, op.r#type(), names);
+                folded.push(Folded {
+                    op: name.clone(),
+                    op_type: op.r#type().clone(),
+                    constants: names,
+                });
+            }
+            Err(e) => debug!("can not fold op {}({}), {}", name, op.r#type(), e),
+        }
+    }
+
+    // 删除折叠的算子，消费者的输入替换为常量
+    for f in folded.iter() {
+        graph.remove_operator(&f.op);
+    }
+    for op in graph.operators_mut() {
+        for t in op.inputs_mut().values_mut() {
+            if let Some(c) = constants.get(t.name()) {
+                *t = c.clone().with_format(t.format());
+            }
+        }
+    }
+    if !folded.is_empty() {
+        let mut types = BTreeMap::new();
+        for f in folded.iter() {
+            *types.entry(f.op_type.as_str()).or_insert(0) += 1;
+        }
+        info!(
+            "graph {} folded {} constant ops {:?}",
+            graph.name(),
+            folded.len(),
+            types
+        );
+    }
+
+    Ok((graph, folded))
+}
+
+/// 常量有Host上的数据，`shape_only`时只需要形状固定
+fn is_known(t: &Tensor, shape_only: bool) -> bool {
+    if shape_only {
+        return !t.is_dynamic() && t.shape().dim() > 0;
+    }
+    t.r#type() == Type::Constant
+        && !t.is_sparse()
+        && t.quantization().is_none()
+        && t.as_bytes().is_some()
+}
+
+/// 计算算子的输出，形状和类型由形状推导确定
+///
+/// 输出的元素数超过`max_len`和输入的元素数时报错，None时不限制
+pub(crate) fn evaluate(op: &Operator, max_len: Option<usize>) -> Result<Vec<Tensor>> {
+    let inferred = infer::infer_operator(op)?.ok_or_else(|| anyhow!("no shape inference"))?;
+    let count = op
+        .inputs()
+        .keys()
+        .filter_map(|k| k.parse::<usize>().ok())
+        .max()
+        .map_or(0, |i| i + 1);
+    let shape_only = matches!(op.r#type().as_str(), "Shape" | "Size");
+    let inputs = (0..count)
+        .map(|i| match op.get_input(&i.to_string()) {
+            Some(t) if !shape_only => Array::from_tensor(t).map(Some),
+            _ => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let input_len: usize = inputs.iter().flatten().map(|a| a.data.len()).sum();
+
+    let mut outputs = vec![];
+    for (tag, t) in op.outputs() {
+        let index: usize = tag.parse()?;
+        let out = inferred
+            .get(index)
+            .ok_or_else(|| anyhow!("output {} is not inferred", tag))?;
+        let shape: Vec<usize> = out
+            .ty
+            .dims
+            .as_ref()
+            .and_then(|d| d.iter().map(static_dim).collect())
+            .ok_or_else(|| anyhow!("output shape {:?} is not static", out.ty.dims))?;
+        let len = shape.iter().product::<usize>();
+        if max_len.is_some_and(|max| len > input_len.max(max)) {
+            bail!("output {:?} is too large", shape);
+        }
+        // 形状计算的结果已经由推导得到
+        let known: Option<Vec<i64>> = out.value.as_ref().filter(|v| v.len() == len).and_then(|v| {
+            v.iter()
+                .map(|d| match d {
+                    Dim::Value(v) => Some(*v),
+                    _ => None,
+                })
+                .collect()
+        });
+        let data = match known {
+            Some(v) => Data::Int(v),
+            None => compute(op, &inputs, &shape, out.ty.dtype)?,
+        };
+        if data.len() != len {
+            bail!("output need {} elements, but {}", len, data.len());
+        }
+        outputs.push(to_tensor(t.name(), out.ty.dtype, &shape, data)?);
+    }
+    Ok(outputs)
+}
+
+fn static_dim(d: &Dim) -> Option<usize> {
+    match d {
+        Dim::Value(v) if *v >= 0 => Some(*v as usize),
+        _ => None,
+    }
+}
+
+/// 求值用的数据，浮点和整数分开保存
+#[derive(Clone, Debug, PartialEq)]
+enum Data {
+    Float(Vec<f64>),
+    Int(Vec<i64>),
+}
+
+impl Data {
+    fn len(&self) -> usize {
+        match self {
+            Self::Float(v) => v.len(),
+            Self::Int(v) => v.len(),
+        }
+    }
+
+    fn floats(&self) -> Vec<f64> {
+        match self {
+            Self::Float(v) => v.clone(),
+            Self::Int(v) => v.iter().map(|v| *v as f64).collect(),
+        }
+    }
+
+    fn ints(&self) -> Vec<i64> {
+        match self {
+            Self::Float(v) => v.iter().map(|v| *v as i64).collect(),
+            Self::Int(v) => v.clone(),
+        }
+    }
+
+    /// 按下标取元素
+    fn select(&self, indices: impl Iterator<Item = usize>) -> Self {
+        match self {
+            Self::Float(v) => Self::Float(indices.map(|i| v[i]).collect()),
+            Self::Int(v) => Self::Int(indices.map(|i| v[i]).collect()),
+        }
+    }
+
+    /// 转换为数据类型对应的存储
+    fn cast(&self, dtype: DType) -> Self {
+        match (is_float(dtype), dtype) {
+            (true, _) => Self::Float(self.floats()),
+            (false, DType::Bool) => {
+                Self::Int(self.floats().iter().map(|v| (*v != 0.0) as i64).collect())
+            }
+            (false, _) => Self::Int(self.ints()),
+        }
+    }
+}
+
+fn is_float(dtype: DType) -> bool {
+    matches!(
+        dtype,
+        DType::Float16 | DType::Bfloat16 | DType::Float32 | DType::Float64
+    )
+}
+
+/// 常量张量的数据
+#[derive(Clone, Debug)]
+struct Array {
+    shape: Vec<usize>,
+    data: Data,
+}
+
+impl Array {
+    fn from_tensor(t: &Tensor) -> Result<Self> {
+        let shape = match t.is_scalar() {
+            true => vec![],
+            false => t.shape().data().iter().map(|d| *d as usize).collect(),
+        };
+        let ints = |v: Vec<i64>| Data::Int(v);
+        let data = match t.dtype() {
+            DType::Float32 => Data::Float(t.to_vec::<f32>()?.into_iter().map(f64::from).collect()),
+            DType::Float64 => Data::Float(t.to_vec::<f64>()?),
+            DType::Float16 => Data::Float(t.to_vec::<f16>()?.into_iter().map(f64::from).collect()),
+            DType::Bfloat16 => {
+                Data::Float(t.to_vec::<bf16>()?.into_iter().map(f64::from).collect())
+            }
+            DType::Int8 => ints(t.to_vec::<i8>()?.into_iter().map(i64::from).collect()),
+            DType::Int16 => ints(t.to_vec::<i16>()?.into_iter().map(i64::from).collect()),
+            DType::Int32 => ints(t.to_vec::<i32>()?.into_iter().map(i64::from).collect()),
+            DType::Int64 => ints(t.to_vec::<i64>()?),
+            DType::Uint8 => ints(t.to_vec::<u8>()?.into_iter().map(i64::from).collect()),
+            DType::Uint16 => ints(t.to_vec::<u16>()?.into_iter().map(i64::from).collect()),
+            DType::Uint32 => ints(t.to_vec::<u32>()?.into_iter().map(i64::from).collect()),
+            DType::Uint64 => ints(t.to_vec::<u64>()?.into_iter().map(|v| v as i64).collect()),
+            DType::Bool => ints(t.to_vec::<bool>()?.into_iter().map(i64::from).collect()),
+            d => bail!("can not fold {} tensor {}", d, t.name()),
+        };
+        Ok(Self { shape, data })
+    }
+}
+
+fn to_tensor(name: &str, dtype: DType, shape: &[usize], data: Data) -> Result<Tensor> {
+    let scalar = shape.is_empty();
+    let shape: Vec<u32> = match shape {
+        [] => vec![1],
+        s => s.iter().map(|d| *d as u32).collect(),
+    };
+    if shape.len() > 8 {
+        bail!("rank of {:?} is more than 8", shape);
+    }
+    let fs = || data.floats().into_iter();
+    let is = || data.ints().into_iter();
+    let tensor = match dtype {
+        DType::Float32 => Tensor::from_vec(name, &shape, fs().map(|v| v as f32).collect()),
+        DType::Float64 => Tensor::from_vec(name, &shape, fs().collect()),
+        DType::Float16 => Tensor::from_vec(name, &shape, fs().map(f16::from_f64).collect()),
+        DType::Bfloat16 => Tensor::from_vec(name, &shape, fs().map(bf16::from_f64).collect()),
+        DType::Int8 => Tensor::from_vec(name, &shape, is().map(|v| v as i8).collect()),
+        DType::Int16 => Tensor::from_vec(name, &shape, is().map(|v| v as i16).collect()),
+        DType::Int32 => Tensor::from_vec(name, &shape, is().map(|v| v as i32).collect()),
+        DType::Int64 => Tensor::from_vec(name, &shape, is().collect()),
+        DType::Uint8 => Tensor::from_vec(name, &shape, is().map(|v| v as u8).collect()),
+        DType::Uint16 => Tensor::from_vec(name, &shape, is().map(|v| v as u16).collect()),
+        DType::Uint32 => Tensor::from_vec(name, &shape, is().map(|v| v as u32).collect()),
+        DType::Uint64 => Tensor::from_vec(name, &shape, is().map(|v| v as u64).collect()),
+        DType::Bool => Tensor::from_vec(name, &shape, is().map(|v| v != 0).collect()),
+        d => bail!("can not fold to {} tensor", d),
+    }?;
+    let tensor = tensor.with_type(Type::Constant);
+    // 标量按一维保存，并标记为0维
+    Ok(if scalar { tensor.with_scalar() } else { tensor })
+}
+
+fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+/// 平铺下标转换为各维的下标
+fn unravel(mut index: usize, shape: &[usize]) -> Vec<usize> {
+    let mut out = vec![0; shape.len()];
+    for i in (0..shape.len()).rev() {
+        if shape[i] > 0 {
+            out[i] = index % shape[i];
+            index /= shape[i];
+        }
+    }
+    out
+}
+
+/// 输出下标对应的广播输入的平铺下标
+fn broadcast_index(out: &[usize], shape: &[usize], strides: &[usize]) -> usize {
+    let offset = out.len() - shape.len();
+    (0..shape.len())
+        .map(|i| {
+            if shape[i] == 1 {
+                0
+            } else {
+                out[i + offset] * strides[i]
+            }
+        })
+        .sum()
+}
+
+/// 广播输入到输出形状
+fn broadcast_to(a: &Array, shape: &[usize]) -> Result<Data> {
+    if a.shape.len() > shape.len() {
+        bail!("can not broadcast {:?} to {:?}", a.shape, shape);
+    }
+    let s = strides(&a.shape);
+    let len = shape.iter().product();
+    Ok(a.data
+        .select((0..len).map(|i| broadcast_index(&unravel(i, shape), &a.shape, &s))))
+}
+
+fn normalize_axis(axis: i64, rank: usize) -> Result<usize> {
+    let r = rank as i64;
+    if axis < -r || axis >= r {
+        bail!("axis {} is out of range of rank {}", axis, rank);
+    }
+    Ok(((axis + r) % r) as usize)
+}
+
+/// 计算算子的第`index`个输出
+fn compute(op: &Operator, inputs: &[Option<Array>], shape: &[usize], dtype: DType) -> Result<Data> {
+    let input = |i: usize| {
+        inputs
+            .get(i)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| anyhow!("input {} is missing", i))
+    };
+    let len = shape.iter().product::<usize>();
+    let data = match op.r#type().as_str() {
+        "Identity" | "Reshape" | "Flatten" | "Squeeze" | "Unsqueeze" => input(0)?.data.clone(),
+        "Constant" => {
+            if let Some(t) = op.try_attr::<Tensor>("value")? {
+                Array::from_tensor(&t)?.data
+            } else if let Some(v) = op.try_attr::<i64>("value_int")? {
+                Data::Int(vec![v])
+            } else if let Some(v) = op.try_attr::<Vec<i64>>("value_ints")? {
+                Data::Int(v)
+            } else if let Some(v) = op.try_attr::<f32>("value_float")? {
+                Data::Float(vec![f64::from(v)])
+            } else if let Some(v) = op.try_attr::<Vec<f32>>("value_floats")? {
+                Data::Float(v.into_iter().map(f64::from).collect())
+            } else {
+                bail!("constant {} has no foldable value", op.name())
+            }
+        }
+        "Cast" => input(0)?.data.cast(dtype),
+        "ConstantOfShape" => {
+            let value = match op.try_attr::<Tensor>("value")? {
+                Some(t) => Array::from_tensor(&t)?.data,
+                None => Data::Float(vec![0.0]),
+            };
+            value.select(std::iter::repeat_n(0, len))
+        }
+        "Expand" => broadcast_to(input(0)?, shape)?,
+        "Transpose" => {
+            let x = input(0)?;
+            let default: Vec<i64> = (0..x.shape.len() as i64).rev().collect();
+            let perm = op.attr_ints_or("perm", &default)?;
+            let s = strides(&x.shape);
+            x.data.select((0..len).map(|i| {
+                let out = unravel(i, shape);
+                perm.iter()
+                    .enumerate()
+                    .map(|(k, p)| out[k] * s[*p as usize])
+                    .sum()
+            }))
+        }
+        "Gather" => {
+            let (x, indices) = (input(0)?, input(1)?);
+            let axis = normalize_axis(op.attr_int_or("axis", 0)?, x.shape.len())?;
+            let size = x.shape[axis] as i64;
+            let idx = indices
+                .data
+                .ints()
+                .into_iter()
+                .map(|i| {
+                    let i = if i < 0 { i + size } else { i };
+                    match (0..size).contains(&i) {
+                        true => Ok(i as usize),
+                        false => Err(anyhow!("index {} is out of range {}", i, size)),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let s = strides(&x.shape);
+            let ir = indices.shape.len();
+            x.data.select((0..len).map(|i| {
+                let out = unravel(i, shape);
+                let flat = unravel_inverse(&out[axis..axis + ir], &indices.shape);
+                (0..axis).map(|k| out[k] * s[k]).sum::<usize>()
+                    + idx[flat] * s[axis]
+                    + (axis + 1..x.shape.len())
+                        .map(|k| out[k + ir - 1] * s[k])
+                        .sum::<usize>()
+            }))
+        }
+        "Slice" => {
+            let x = input(0)?;
+            let rank = x.shape.len();
+            let ints = |attr: &str, i: usize| -> Result<Option<Vec<i64>>> {
+                Ok(match op.try_attr::<Vec<i64>>(attr)? {
+                    Some(v) => Some(v),
+                    None => inputs
+                        .get(i)
+                        .and_then(Option::as_ref)
+                        .map(|a| a.data.ints()),
+                })
+            };
+            let starts = ints("starts", 1)?.ok_or_else(|| anyhow!("starts is missing"))?;
+            let axes = ints("axes", 3)?.unwrap_or_else(|| (0..starts.len() as i64).collect());
+            let steps = ints("steps", 4)?.unwrap_or_else(|| vec![1; starts.len()]);
+            let mut begin = vec![0_i64; rank];
+            let mut step = vec![1_i64; rank];
+            for (k, axis) in axes.iter().enumerate() {
+                let axis = normalize_axis(*axis, rank)?;
+                let size = x.shape[axis] as i64;
+                let s = if starts[k] < 0 {
+                    starts[k] + size
+                } else {
+                    starts[k]
+                };
+                begin[axis] = match steps[k] > 0 {
+                    true => s.clamp(0, size),
+                    // 长度为0的维度没有可取的元素
+                    false => s.clamp(0, (size - 1).max(0)),
+                };
+                step[axis] = steps[k];
+            }
+            let s = strides(&x.shape);
+            x.data.select((0..len).map(|i| {
+                let out = unravel(i, shape);
+                (0..rank)
+                    .map(|k| (begin[k] + out[k] as i64 * step[k]) as usize * s[k])
+                    .sum()
+            }))
+        }
+        "Concat" => {
+            let axis = normalize_axis(op.attr_int_or("axis", 0)?, shape.len())?;
+            let outer: usize = shape[..axis].iter().product();
+            let arrays: Vec<&Array> = inputs.iter().flatten().collect();
+            if let Some(a) = arrays.iter().find(|a| a.shape.len() != shape.len()) {
+                bail!("input rank {} not match output {:?}", a.shape.len(), shape);
+            }
+            match is_float(dtype) {
+                true => {
+                    let vs: Vec<Vec<f64>> = arrays.iter().map(|a| a.data.floats()).collect();
+                    Data::Float(concat_chunks(&vs, outer))
+                }
+                false => {
+                    let vs: Vec<Vec<i64>> = arrays.iter().map(|a| a.data.ints()).collect();
+                    Data::Int(concat_chunks(&vs, outer))
+                }
+            }
+        }
+        "Range" => {
+            let (start, delta) = (&input(0)?.data, &input(2)?.data);
+            match is_float(dtype) {
+                true => {
+                    let (s, d) = (start.floats()[0], delta.floats()[0]);
+                    Data::Float((0..len).map(|i| s + i as f64 * d).collect())
+                }
+                false => {
+                    let (s, d) = (start.ints()[0], delta.ints()[0]);
+                    Data::Int((0..len).map(|i| s + i as i64 * d).collect())
+                }
+            }
+        }
+        "Where" => {
+            let cond = broadcast_to(input(0)?, shape)?.ints();
+            let x = broadcast_to(input(1)?, shape)?;
+            let y = broadcast_to(input(2)?, shape)?;
+            match (x, y) {
+                (Data::Int(x), Data::Int(y)) => Data::Int(
+                    (0..len)
+                        .map(|i| if cond[i] != 0 { x[i] } else { y[i] })
+                        .collect(),
+                ),
+                (x, y) => {
+                    let (x, y) = (x.floats(), y.floats());
+                    Data::Float(
+                        (0..len)
+                            .map(|i| if cond[i] != 0 { x[i] } else { y[i] })
+                            .collect(),
+                    )
+                }
+            }
+        }
+        "Neg" | "Abs" | "Sqrt" | "Reciprocal" | "Floor" | "Ceil" | "Not" => {
+            unary(op.r#type(), &input(0)?.data)?
+        }
+        _ => elementwise(op.r#type(), inputs, shape)?,
+    };
+    Ok(data.cast(dtype))
+}
+
+/// 沿`outer`之后的维度拼接
+fn concat_chunks<T: Copy>(vs: &[Vec<T>], outer: usize) -> Vec<T> {
+    let mut out = vec![];
+    if outer == 0 {
+        return out;
+    }
+    for o in 0..outer {
+        for v in vs {
+            let chunk = v.len() / outer;
+            out.extend_from_slice(&v[o * chunk..(o + 1) * chunk]);
+        }
+    }
+    out
+}
+
+fn unary(op_type: &str, x: &Data) -> Result<Data> {
+    let data = match (op_type, x) {
+        ("Neg", Data::Int(v)) => Data::Int(v.iter().map(|v| -v).collect()),
+        ("Abs", Data::Int(v)) => Data::Int(v.iter().map(|v| v.abs()).collect()),
+        ("Not", x) => Data::Int(x.ints().iter().map(|v| (*v == 0) as i64).collect()),
+        (_, x) => {
+            let f: fn(f64) -> f64 = match op_type {
+                "Neg" => |v| -v,
+                "Abs" => f64::abs,
+                "Sqrt" => f64::sqrt,
+                "Reciprocal" => f64::recip,
+                "Floor" => f64::floor,
+                "Ceil" => f64::ceil,
+                _ => bail!("unsupported op {}", op_type),
+            };
+            Data::Float(x.floats().into_iter().map(f).collect())
+        }
+    };
+    Ok(data)
+}
+
+/// 广播的二元和多元运算
+fn elementwise(op_type: &str, inputs: &[Option<Array>], shape: &[usize]) -> Result<Data> {
+    let arrays: Vec<&Array> = inputs.iter().flatten().collect();
+    if arrays.is_empty() {
+        bail!("inputs are missing");
+    }
+    let data = arrays
+        .iter()
+        .map(|a| broadcast_to(a, shape))
+        .collect::<Result<Vec<_>>>()?;
+    let ints = data.iter().all(|d| matches!(d, Data::Int(_)));
+    let compare = |f: fn(f64, f64) -> bool| -> Data {
+        let (a, b) = (data[0].floats(), data[1].floats());
+        Data::Int(a.iter().zip(b).map(|(a, b)| f(*a, b) as i64).collect())
+    };
+    let out = match op_type {
+        "Equal" => compare(|a, b| a == b),
+        "Less" => compare(|a, b| a < b),
+        "Greater" => compare(|a, b| a > b),
+        "And" => compare(|a, b| a != 0.0 && b != 0.0),
+        "Or" => compare(|a, b| a != 0.0 || b != 0.0),
+        _ if ints => {
+            let f: fn(i64, i64) -> Option<i64> = match op_type {
+                "Add" => i64::checked_add,
+                "Sub" => i64::checked_sub,
+                "Mul" => i64::checked_mul,
+                "Div" => i64::checked_div,
+                "Max" => |a, b| Some(a.max(b)),
+                "Min" => |a, b| Some(a.min(b)),
+                _ => bail!("unsupported op {}", op_type),
+            };
+            let mut acc = data[0].ints();
+            for d in data[1..].iter() {
+                for (a, b) in acc.iter_mut().zip(d.ints()) {
+                    *a = f(*a, b).ok_or_else(|| anyhow!("{} overflow or divide by 0", op_type))?;
+                }
+            }
+            Data::Int(acc)
+        }
+        _ => {
+            let f: fn(f64, f64) -> f64 = match op_type {
+                "Add" => |a, b| a + b,
+                "Sub" => |a, b| a - b,
+                "Mul" => |a, b| a * b,
+                "Div" => |a, b| a / b,
+                "Max" => f64::max,
+                "Min" => f64::min,
+                _ => bail!("unsupported op {}", op_type),
+            };
+            let mut acc = data[0].floats();
+            for d in data[1..].iter() {
+                for (a, b) in acc.iter_mut().zip(d.floats()) {
+                    *a = f(*a, b);
+                }
+            }
+            Data::Float(acc)
+        }
+    };
+    Ok(out)
+}
+
+/// 各维的下标转换为平铺下标
+fn unravel_inverse(index: &[usize], shape: &[usize]) -> usize {
+    index.iter().zip(strides(shape)).map(|(i, s)| i * s).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{constant, op, var};
+    use model::attribute::Attribute;
+    use model::tensor::Format;
+
+    fn unknown(name: &str) -> Tensor {
+        Tensor::new(name, Format::NCHW, DType::Undefined, Type::Variable)
+    }
+
+    fn build(ops: Vec<Operator>, output: &str) -> Graph {
+        let mut graph = Graph::new("g").add_output(unknown(output)).unwrap();
+        for op in ops {
+            graph = graph.add_operator(op).unwrap();
+        }
+        infer::infer_shapes(graph).unwrap()
+    }
+
+    #[test]
+    fn fold_shape_works() {
+        let x = var("x", &[2, 3, 4]);
+        let graph = build(
+            vec![
+                op("shape", "Shape", vec![x.clone()], unknown("s")),
+                op(
+                    "gather",
+                    "Gather",
+                    vec![unknown("s"), constant("i", &[1], vec![0_i64]).with_scalar()],
+                    unknown("b"),
+                ),
+                op(
+                    "unsqueeze",
+                    "Unsqueeze",
+                    vec![unknown("b"), constant("axes", &[1], vec![0_i64])],
+                    unknown("u"),
+                ),
+                op(
+                    "concat",
+                    "Concat",
+                    vec![unknown("u"), constant("m", &[1], vec![-1_i64])],
+                    unknown("shape"),
+                )
+                .add_attribute("axis", Attribute::Int(0))
+                .unwrap(),
+                op(
+                    "reshape",
+                    "Reshape",
+                    vec![x, unknown("shape")],
+                    unknown("y"),
+                ),
+            ],
+            "y",
+        );
+
+        let (graph, folded) = fold_constants(graph).unwrap();
+        assert_eq!(4, folded.len());
+        assert_eq!(1, graph.operators().len());
+        let shape = graph
+            .get_operator("reshape")
+            .unwrap()
+            .get_input("1")
+            .unwrap();
+        assert_eq!(Type::Constant, shape.r#type());
+        assert_eq!(vec![2_i64, -1], shape.to_vec::<i64>().unwrap());
+
+        // 标量下标取出的元素为0维常量
+        let index = constant("i", &[1], vec![1_i64]).with_scalar();
+        let gather = op("g", "Gather", vec![shape.clone(), index], unknown("e"));
+        let e = evaluate(&gather, None).unwrap().remove(0);
+        assert!(e.is_scalar());
+        assert_eq!(vec![-1_i64], e.to_vec::<i64>().unwrap());
+    }
+
+    #[test]
+    fn subgraph_inputs_kept() {
+        let add = op(
+            "add",
+            "Add",
+            vec![
+                constant("a", &[1], vec![1.0_f32]),
+                constant("b", &[1], vec![2.0_f32]),
+            ],
+            unknown("s"),
+        );
+        // 子图引用外层的s
+        let branch = |name: &str| {
+            Graph::new(name)
+                .add_output(unknown(&format!("{}_y", name)))
+                .unwrap()
+                .add_operator(op(
+                    &format!("{}_id", name),
+                    "Identity",
+                    vec![unknown("s")],
+                    unknown(&format!("{}_y", name)),
+                ))
+                .unwrap()
+        };
+        let r#if = op("if", "If", vec![var("cond", &[1])], unknown("y"))
+            .add_attribute("then_branch", Attribute::Graph(branch("then")))
+            .unwrap()
+            .add_attribute("else_branch", Attribute::Graph(branch("else")))
+            .unwrap();
+        let graph = Graph::new("g")
+            .add_output(unknown("y"))
+            .unwrap()
+            .add_operator(add)
+            .unwrap()
+            .add_operator(r#if)
+            .unwrap();
+
+        let (graph, folded) = fold_constants(graph).unwrap();
+        assert!(folded.is_empty());
+        assert!(graph.get_operator("add").is_some());
+    }
+
+    #[test]
+    fn fold_constant_attributes_works() {
+        let constant_op = |attr: &str, value: Attribute| {
+            Operator::new("c", "Constant")
+                .add_attribute(attr, value)
+                .unwrap()
+                .add_output("0", unknown("c"))
+                .unwrap()
+        };
+        let eval = |op: Operator| evaluate(&op, None).unwrap().remove(0);
+
+        // 单个值的属性得到0维常量
+        let c = eval(constant_op("value_int", Attribute::Int(3)));
+        assert!(c.is_scalar());
+        assert_eq!(DType::Int64, c.dtype());
+        assert_eq!(vec![3_i64], c.to_vec::<i64>().unwrap());
+        let c = eval(constant_op("value_float", Attribute::Float(0.5)));
+        assert!(c.is_scalar());
+        assert_eq!(vec![0.5_f32], c.to_vec::<f32>().unwrap());
+
+        let c = eval(constant_op("value_ints", Attribute::Ints(vec![1, -2, 3])));
+        assert!(!c.is_scalar());
+        assert_eq!(&[3], c.shape().data());
+        assert_eq!(vec![1_i64, -2, 3], c.to_vec::<i64>().unwrap());
+        // 推导没有给出值时直接由属性计算
+        let ints = constant_op("value_ints", Attribute::Ints(vec![1, -2, 3]));
+        let data = compute(&ints, &[], &[3], DType::Int64).unwrap();
+        assert_eq!(vec![1_i64, -2, 3], data.ints());
+        let c = eval(constant_op(
+            "value_floats",
+            Attribute::Floats(vec![1.5, 2.5]),
+        ));
+        assert_eq!(&[2], c.shape().data());
+        assert_eq!(vec![1.5_f32, 2.5], c.to_vec::<f32>().unwrap());
+    }
+
+    #[test]
+    fn fold_values_works() {
+        let w = constant("w", &[2, 3], vec![1.0_f32, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let graph = build(
+            vec![
+                op("transpose", "Transpose", vec![w], unknown("t")),
+                op(
+                    "add",
+                    "Add",
+                    vec![unknown("t"), constant("one", &[1], vec![1.0_f32])],
+                    unknown("a"),
+                ),
+                op(
+                    "slice",
+                    "Slice",
+                    vec![
+                        unknown("a"),
+                        constant("starts", &[1], vec![-1_i64]),
+                        constant("ends", &[1], vec![i64::MIN]),
+                        constant("axes", &[1], vec![0_i64]),
+                        constant("steps", &[1], vec![-1_i64]),
+                    ],
+                    unknown("r"),
+                ),
+                op(
+                    "matmul",
+                    "MatMul",
+                    vec![var("x", &[1, 3]), unknown("r")],
+                    unknown("y"),
+                ),
+                // 图输出保持由算子产生
+                op("identity", "Identity", vec![unknown("y")], unknown("z")),
+            ],
+            "z",
+        );
+
+        let (graph, folded) = fold_constants(graph).unwrap();
+        let types: Vec<&str> = folded.iter().map(|f| f.op_type.as_str()).collect();
+        assert_eq!(vec!["Transpose", "Add", "Slice"], types);
+        assert!(graph.get_operator("identity").is_some());
+        let r = graph
+            .get_operator("matmul")
+            .unwrap()
+            .get_input("1")
+            .unwrap();
+        assert_eq!(&[3, 2], r.shape().data());
+        assert_eq!(
+            vec![4.0_f32, 7.0, 3.0, 6.0, 2.0, 5.0],
+            r.to_vec::<f32>().unwrap()
+        );
+
+        // 反向取长度为0的维度
+        let slice = op(
+            "slice",
+            "Slice",
+            vec![
+                constant("e", &[2, 0], Vec::<f32>::new()),
+                constant("starts", &[1], vec![-1_i64]),
+                constant("ends", &[1], vec![i64::MIN]),
+                constant("axes", &[1], vec![1_i64]),
+                constant("steps", &[1], vec![-1_i64]),
+            ],
+            unknown("r"),
+        );
+        let r = evaluate(&slice, None).unwrap().remove(0);
+        assert_eq!(&[2, 0], r.shape().data());
+        assert!(r.to_vec::<f32>().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,663 @@
+//! 算子融合
+//!
+//! 把Conv/Gemm后面的BatchNormalization和逐通道的常量Mul/Add折叠到权重和偏置中。
+//! 后端选择融合激活函数时，后面的Relu/Clip/Sigmoid和Conv/Gemm合并为com.microsoft域的
+//! FusedConv/FusedGemm，激活函数记在`activation`属性中，FusedConv中Clip的范围记在
+//! `activation_params`属性中。FusedGemm的参数属性是`activation_alpha`等，不支持Clip，
+//! Gemm后面的Clip不融合。
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use log::*;
+use model::attribute::Attribute;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use super::cleanup::consumers;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// `Config.ops`中是否融合激活函数的key，值为true或false
+pub const FUSE_ACTIVATION_KEY: &str = "fuse_activation";
+/// 融合算子的域
+pub const FUSED_DOMAIN: &str = "com.microsoft";
+
+/// 融合选项
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FuseOptions {
+    /// 把激活函数融合进Conv/Gemm，需要后端支持FusedConv/FusedGemm
+    pub activation: bool,
+}
+
+impl FuseOptions {
+    /// 从`Config.ops`读取融合选项
+    pub fn from_ops(ops: &HashMap<String, String>) -> Result<Self> {
+        let activation = match ops.get(FUSE_ACTIVATION_KEY).map(|v| v.trim()) {
+            None | Some("false") | Some("0") => false,
+            Some("true") | Some("1") => true,
+            Some(v) => bail!("{} {} is not a bool", FUSE_ACTIVATION_KEY, v),
+        };
+        Ok(Self { activation })
+    }
+}
+
+/// 一个Conv/Gemm的融合结果
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fused {
+    /// 融合后的算子
+    pub op: String,
+    /// 被融合并删除的算子，按数据流顺序
+    pub absorbed: Vec<String>,
+}
+
+/// 融合Conv/Gemm和后面的逐通道线性变换及激活函数，图输出的名字保持不变
+///
+/// 被融合的算子只能有一个消费者，且输出不是图输出
+pub fn fuse(mut graph: Graph, opts: &FuseOptions) -> Result<(Graph, Vec<Fused>)> {
+    let keep = super::kept_tensors(&graph);
+
+    let mut report = vec![];
+    for name in super::sorted_operators(&graph)? {
+        let mut absorbed = vec![];
+        // 连续的BN/Mul/Add依次折叠，最后融合激活函数
+        let mut activation = opts.activation;
+        while let Some(op) = graph.get_operator(&name) {
+            let Some(next) = next_operator(&graph, op, &keep) else {
+                break;
+            };
+            let fused = match fold_affine(&graph, op, next, &keep)? {
+                Some(fused) => fused,
+                None if activation => {
+                    activation = false;
+                    match fuse_activation(op, next)? {
+                        Some(fused) => fused,
+                        None => break,
+                    }
+                }
+                None => break,
+            };
+            let next = next.name().clone();
+            graph.remove_operator(&next);
+            *graph.get_operator_mut(&name).unwrap() = fused;
+            absorbed.push(next);
+        }
+        if !absorbed.is_empty() {
+            report.push(Fused { op: name, absorbed });
+        }
+    }
+
+    if !report.is_empty() {
+        let fused_ops = graph
+            .operators()
+            .iter()
+            .any(|op| op.domain() == FUSED_DOMAIN);
+        if fused_ops && graph.opset_version(FUSED_DOMAIN).is_none() {
+            graph = graph.add_opset_import(FUSED_DOMAIN, 1)?;
+        }
+        info!(
+            "graph {} fused {} ops into {} ops",
+            graph.name(),
+            report.iter().map(|f| f.absorbed.len()).sum::<usize>(),
+            report.len()
+        );
+    }
+    Ok((graph, report))
+}
+
+/// 输出只被一个ONNX算子使用时返回该算子
+fn next_operator<'a>(
+    graph: &'a Graph,
+    op: &Operator,
+    keep: &HashSet<String>,
+) -> Option<&'a Operator> {
+    let output = op.get_output("0")?;
+    if op.outputs().len() != 1 || keep.contains(output.name()) {
+        return None;
+    }
+    let consumers = consumers(graph);
+    let users = consumers.get(output.name())?;
+    let mut users: Vec<&String> = users.iter().collect();
+    users.dedup();
+    match users[..] {
+        [next] => graph
+            .get_operator(next)
+            .filter(|next| normalize_domain(next.domain()) == ONNX_DOMAIN),
+        _ => None,
+    }
+}
+
+/// 没有量化的float32常量数据
+fn constant(t: &Tensor) -> Option<Vec<f32>> {
+    let dense = t.r#type() == Type::Constant
+        && t.dtype() == DType::Float32
+        && !t.is_sparse()
+        && t.quantization().is_none();
+    dense.then(|| t.to_vec::<f32>().ok()).flatten()
+}
+
+/// 把`next`表示的`y * scale + shift`折叠进Conv/Gemm的权重和偏置
+fn fold_affine(
+    graph: &Graph,
+    op: &Operator,
+    next: &Operator,
+    keep: &HashSet<String>,
+) -> Result<Option<Operator>> {
+    if normalize_domain(op.domain()) != ONNX_DOMAIN {
+        return Ok(None);
+    }
+    let Some(weight) = op.get_input("1") else {
+        return Ok(None);
+    };
+    let Some(w) = constant(weight) else {
+        return Ok(None);
+    };
+    let dims = weight.shape().data();
+    // 输出通道数、输出的维度和输出通道在权重中是否为第一维
+    let (channels, rank, leading) = match op.r#type().as_str() {
+        "Conv" if dims.len() >= 3 => (dims[0] as usize, dims.len(), true),
+        "Gemm" if dims.len() == 2 => {
+            if op.attr_int_or("transB", 0)? != 0 {
+                (dims[0] as usize, 2, true)
+            } else {
+                (dims[1] as usize, 2, false)
+            }
+        }
+        _ => return Ok(None),
+    };
+    let output = op.get_output("0").unwrap();
+    let Some((scale, shift)) = affine(next, output.name(), channels, rank)? else {
+        return Ok(None);
+    };
+
+    let w: Vec<f32> = if leading {
+        let per = w.len() / channels;
+        w.iter()
+            .enumerate()
+            .map(|(i, v)| v * scale[i / per])
+            .collect()
+    } else {
+        w.iter()
+            .enumerate()
+            .map(|(i, v)| v * scale[i % channels])
+            .collect()
+    };
+
+    let (bias, bias_shape) = match op.get_input("2") {
+        Some(b) => {
+            let Some(values) = constant(b) else {
+                return Ok(None);
+            };
+            // Gemm的C可以广播到[M, N]，按[rows, cols]展开到每个输出通道
+            let bdims = b.shape().data();
+            let (rows, cols) = match bdims {
+                [c] => (1, *c as usize),
+                [r, c] => (*r as usize, *c as usize),
+                _ => return Ok(None),
+            };
+            if (cols != 1 && cols != channels) || (op.r#type() == "Conv" && rows != 1) {
+                return Ok(None);
+            }
+            let beta = match op.r#type().as_str() {
+                "Gemm" => op.attr_float_or("beta", 1.0)?,
+                _ => 1.0,
+            };
+            let bias: Vec<f32> = (0..rows * channels)
+                .map(|i| {
+                    let (r, c) = (i / channels, i % channels);
+                    let v = values[r * cols + if cols == 1 { 0 } else { c }];
+                    beta * v * scale[c] + shift[c]
+                })
+                .collect();
+            let shape = if rows == 1 {
+                vec![channels as u32]
+            } else {
+                vec![rows as u32, channels as u32]
+            };
+            (bias, shape)
+        }
+        None => (shift, vec![channels as u32]),
+    };
+
+    let mut fused = op.clone();
+    let weight = Tensor::from_vec(&constant_name(graph, op, weight, keep), dims, w)?
+        .with_type(Type::Constant)
+        .with_format(weight.format());
+    let bias_name = match op.get_input("2") {
+        Some(b) => constant_name(graph, op, b, keep),
+        None => format!("{}_bias", op.name()),
+    };
+    let bias = Tensor::from_vec(&bias_name, &bias_shape, bias)?.with_type(Type::Constant);
+    fused.inputs_mut().insert(String::from("1"), weight);
+    fused.inputs_mut().insert(String::from("2"), bias);
+    fused
+        .outputs_mut()
+        .insert(String::from("0"), next.get_output("0").unwrap().clone());
+    if op.r#type() == "Gemm" {
+        fused = fused.add_attribute("beta", Attribute::Float(1.0))?;
+    }
+    Ok(Some(fused))
+}
+
+/// 常量被其他算子共用时改名，避免影响其他算子
+fn constant_name(graph: &Graph, op: &Operator, t: &Tensor, keep: &HashSet<String>) -> String {
+    let shared = keep.contains(t.name())
+        || graph
+            .operators()
+            .iter()
+            .filter(|o| o.name() != op.name())
+            .any(|o| o.inputs().values().any(|i| i.name() == t.name()));
+    if shared {
+        format!("{}_{}", t.name(), op.name())
+    } else {
+        t.name().clone()
+    }
+}
+
+/// 逐通道的线性变换`y * scale + shift`，通道在第1维
+fn affine(
+    next: &Operator,
+    input: &str,
+    channels: usize,
+    rank: usize,
+) -> Result<Option<(Vec<f32>, Vec<f32>)>> {
+    if next.outputs().len() != 1 {
+        return Ok(None);
+    }
+    let (Some(a), b) = (next.get_input("0"), next.get_input("1")) else {
+        return Ok(None);
+    };
+    match next.r#type().as_str() {
+        "BatchNormalization" => {
+            if a.name() != input
+                || next.attr_int_or("spatial", 1)? == 0
+                || next.attr_int_or("training_mode", 0)? != 0
+            {
+                return Ok(None);
+            }
+            let params: Option<Vec<Vec<f32>>> = ["1", "2", "3", "4"]
+                .iter()
+                .map(|tag| {
+                    next.get_input(tag)
+                        .and_then(constant)
+                        .filter(|v| v.len() == channels)
+                })
+                .collect();
+            let Some(params) = params else {
+                return Ok(None);
+            };
+            let (gamma, beta, mean, var) = (&params[0], &params[1], &params[2], &params[3]);
+            let epsilon = next.attr_float_or("epsilon", 1e-5)?;
+            let scale: Vec<f32> = (0..channels)
+                .map(|c| gamma[c] / (var[c] + epsilon).sqrt())
+                .collect();
+            let shift = (0..channels)
+                .map(|c| beta[c] - mean[c] * scale[c])
+                .collect();
+            Ok(Some((scale, shift)))
+        }
+        "Mul" | "Add" => {
+            let Some(b) = b else {
+                return Ok(None);
+            };
+            let other = match (a.name() == input, b.name() == input) {
+                (true, false) => b,
+                (false, true) => a,
+                _ => return Ok(None),
+            };
+            let Some(values) = channel_vector(other, rank, channels) else {
+                return Ok(None);
+            };
+            if next.r#type() == "Mul" {
+                Ok(Some((values, vec![0.0; channels])))
+            } else {
+                Ok(Some((vec![1.0; channels], values)))
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 只在通道维上变化的常量展开为每个通道的值，按右对齐广播到`rank`维
+fn channel_vector(t: &Tensor, rank: usize, channels: usize) -> Option<Vec<f32>> {
+    let values = constant(t)?;
+    let dims = t.shape().data();
+    if dims.len() > rank {
+        return None;
+    }
+    let mut aligned = vec![1; rank - dims.len()];
+    aligned.extend_from_slice(dims);
+    if aligned.iter().enumerate().any(|(i, d)| i != 1 && *d != 1) {
+        return None;
+    }
+    match aligned[1] as usize {
+        1 => Some(vec![values[0]; channels]),
+        c if c == channels => Some(values),
+        _ => None,
+    }
+}
+
+/// 把激活函数合并为FusedConv/FusedGemm
+fn fuse_activation(op: &Operator, next: &Operator) -> Result<Option<Operator>> {
+    let r#type = match op.r#type().as_str() {
+        "Conv" => "FusedConv",
+        "Gemm" => "FusedGemm",
+        _ => return Ok(None),
+    };
+    if normalize_domain(op.domain()) != ONNX_DOMAIN || next.outputs().len() != 1 {
+        return Ok(None);
+    }
+    let params = match next.r#type().as_str() {
+        "Relu" | "Sigmoid" => vec![],
+        // 范围来自常量输入或旧版本的属性，没有设置的一侧不限制
+        "Clip" if r#type == "FusedConv" => {
+            let bound = |tag: &str, attr: &str, default: f32| -> Result<Option<f32>> {
+                match next.get_input(tag) {
+                    Some(t) => Ok(constant(t).and_then(|v| v.first().copied())),
+                    None => Ok(Some(next.attr_float_or(attr, default)?)),
+                }
+            };
+            match (bound("1", "min", f32::MIN)?, bound("2", "max", f32::MAX)?) {
+                (Some(min), Some(max)) => vec![min, max],
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    let mut fused = Operator::new(op.name(), r#type).set_opset(FUSED_DOMAIN, 1)?;
+    for (tag, t) in op.inputs() {
+        fused = fused.add_input(tag, t.clone())?;
+    }
+    for (tag, attr) in op.attributes() {
+        fused = fused.add_attribute(tag, attr.clone())?;
+    }
+    for (tag, attr) in op.defaults() {
+        fused = fused.add_default_attribute(tag, attr.clone())?;
+    }
+    fused = fused
+        .add_output("0", next.get_output("0").unwrap().clone())?
+        .add_attribute("activation", Attribute::from(next.r#type().as_str()))?;
+    if !params.is_empty() {
+        fused = fused.add_attribute("activation_params", Attribute::from(params))?;
+    }
+    Ok(Some(fused))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{constant, op, var};
+
+    fn sample(n: usize, seed: usize) -> Vec<f32> {
+        (0..n)
+            .map(|i| ((i * 7 + seed) % 11) as f32 * 0.2 - 1.0)
+            .collect()
+    }
+
+    fn input(op: &Operator, tag: &str) -> Vec<f32> {
+        op.get_input(tag).unwrap().to_vec::<f32>().unwrap()
+    }
+
+    /// 步长为1、没有填充的NCHW卷积，batch为1
+    fn conv(x: &[f32], c: usize, h: usize, w: &[f32], m: usize, k: usize, b: &[f32]) -> Vec<f32> {
+        let o = h - k + 1;
+        let mut y = vec![0.0; m * o * o];
+        for (i, y) in y.iter_mut().enumerate() {
+            let (oc, oy, ox) = (i / (o * o), i / o % o, i % o);
+            *y = b[oc];
+            for ic in 0..c {
+                for ky in 0..k {
+                    for kx in 0..k {
+                        let xv = x[(ic * h + oy + ky) * h + ox + kx];
+                        *y += xv * w[((oc * c + ic) * k + ky) * k + kx];
+                    }
+                }
+            }
+        }
+        y
+    }
+
+    fn assert_close(expected: &[f32], actual: &[f32]) {
+        assert_eq!(expected.len(), actual.len());
+        for (e, a) in expected.iter().zip(actual) {
+            assert!((e - a).abs() < 1e-4, "{:?} != {:?}", expected, actual);
+        }
+    }
+
+    fn conv_graph() -> Graph {
+        let (gamma, beta) = (vec![0.5_f32, 1.5, -1.0], vec![0.1_f32, -0.2, 0.3]);
+        let (mean, var_) = (vec![0.2_f32, -0.1, 0.4], vec![1.0_f32, 0.25, 2.0]);
+        let ops = vec![
+            op(
+                "conv",
+                "Conv",
+                vec![
+                    var("x", &[1, 2, 4, 4]),
+                    constant("w", &[3, 2, 2, 2], sample(24, 3)),
+                    constant("b", &[3], vec![0.3_f32, -0.5, 0.7]),
+                ],
+                var("c", &[1, 3, 3, 3]),
+            ),
+            op(
+                "bn",
+                "BatchNormalization",
+                vec![
+                    var("c", &[1, 3, 3, 3]),
+                    constant("gamma", &[3], gamma),
+                    constant("beta", &[3], beta),
+                    constant("mean", &[3], mean),
+                    constant("var", &[3], var_),
+                ],
+                var("n", &[1, 3, 3, 3]),
+            ),
+            op(
+                "mul",
+                "Mul",
+                vec![
+                    constant("s", &[3, 1, 1], vec![2.0_f32, -0.5, 1.0]),
+                    var("n", &[1, 3, 3, 3]),
+                ],
+                var("m", &[1, 3, 3, 3]),
+            ),
+            op(
+                "add",
+                "Add",
+                vec![var("m", &[1, 3, 3, 3]), constant("t", &[1], vec![0.25_f32])],
+                var("a", &[1, 3, 3, 3]),
+            ),
+            op(
+                "relu",
+                "Relu",
+                vec![var("a", &[1, 3, 3, 3])],
+                var("y", &[1, 3, 3, 3]),
+            ),
+        ];
+        let mut graph = Graph::new("g")
+            .add_input(var("x", &[1, 2, 4, 4]))
+            .unwrap()
+            .add_output(var("y", &[1, 3, 3, 3]))
+            .unwrap();
+        for op in ops {
+            graph = graph.add_operator(op).unwrap();
+        }
+        graph
+    }
+
+    #[test]
+    fn fold_affine_works() {
+        let graph = conv_graph();
+        let x = sample(32, 1);
+        let reference = {
+            let conv_op = graph.get_operator("conv").unwrap();
+            let mut y = conv(&x, 2, 4, &input(conv_op, "1"), 3, 2, &input(conv_op, "2"));
+            let bn = graph.get_operator("bn").unwrap();
+            let (gamma, beta) = (input(bn, "1"), input(bn, "2"));
+            let (mean, var_) = (input(bn, "3"), input(bn, "4"));
+            let s = [2.0, -0.5, 1.0];
+            for (i, v) in y.iter_mut().enumerate() {
+                let c = i / 9;
+                let n = (*v - mean[c]) / (var_[c] + 1e-5).sqrt() * gamma[c] + beta[c];
+                *v = n * s[c] + 0.25;
+            }
+            y
+        };
+
+        let (fused, report) = fuse(graph.clone(), &FuseOptions::default()).unwrap();
+        assert_eq!(
+            vec![Fused {
+                op: String::from("conv"),
+                absorbed: vec![String::from("bn"), String::from("mul"), String::from("add")],
+            }],
+            report
+        );
+        assert_eq!(2, fused.operators().len());
+        let conv_op = fused.get_operator("conv").unwrap();
+        assert_eq!("a", conv_op.get_output("0").unwrap().name());
+        let y = conv(&x, 2, 4, &input(conv_op, "1"), 3, 2, &input(conv_op, "2"));
+        assert_close(&reference, &y);
+
+        // 后端支持时融合激活函数
+        let opts = FuseOptions::from_ops(&HashMap::from([(
+            String::from(FUSE_ACTIVATION_KEY),
+            String::from("true"),
+        )]))
+        .unwrap();
+        let (fused, report) = fuse(graph, &opts).unwrap();
+        assert_eq!(4, report[0].absorbed.len());
+        assert_eq!(1, fused.operators().len());
+        let conv_op = fused.get_operator("conv").unwrap();
+        assert_eq!("FusedConv", conv_op.r#type());
+        assert_eq!(FUSED_DOMAIN, conv_op.domain());
+        assert_eq!("Relu", conv_op.attr_string("activation").unwrap());
+        assert_eq!("y", conv_op.get_output("0").unwrap().name());
+        assert_eq!(Some(1), fused.opset_version(FUSED_DOMAIN));
+    }
+
+    #[test]
+    fn fold_gemm_works() {
+        let gemm = op(
+            "gemm",
+            "Gemm",
+            vec![
+                var("a", &[2, 3]),
+                constant("w", &[4, 3], sample(12, 5)),
+                constant("c", &[1, 4], vec![0.5_f32, -1.0, 0.0, 2.0]),
+            ],
+            var("g", &[2, 4]),
+        )
+        .add_attribute("transB", Attribute::Int(1))
+        .unwrap()
+        .add_attribute("alpha", Attribute::Float(0.5))
+        .unwrap()
+        .add_attribute("beta", Attribute::Float(2.0))
+        .unwrap();
+        let bn = op(
+            "bn",
+            "BatchNormalization",
+            vec![
+                var("g", &[2, 4]),
+                constant("gamma", &[4], vec![1.0_f32, 2.0, 0.5, -1.0]),
+                constant("beta", &[4], vec![0.0_f32, 0.1, 0.2, 0.3]),
+                constant("mean", &[4], vec![0.1_f32, 0.2, 0.3, 0.4]),
+                constant("var", &[4], vec![1.0_f32, 4.0, 0.5, 2.0]),
+            ],
+            var("n", &[2, 4]),
+        )
+        .add_attribute("epsilon", Attribute::Float(1e-3))
+        .unwrap();
+        let clip = op(
+            "clip",
+            "Clip",
+            vec![
+                var("n", &[2, 4]),
+                constant("min", &[1], vec![0.0_f32]),
+                constant("max", &[1], vec![6.0_f32]),
+            ],
+            var("y", &[2, 4]),
+        );
+        let graph = Graph::new("g")
+            .add_output(var("y", &[2, 4]))
+            .unwrap()
+            .add_operator(gemm)
+            .unwrap()
+            .add_operator(bn)
+            .unwrap()
+            .add_operator(clip)
+            .unwrap();
+
+        // Y = alpha * A * W^T + beta * C
+        let gemm = |a: &[f32], op: &Operator| -> Vec<f32> {
+            let (w, c) = (input(op, "1"), input(op, "2"));
+            let alpha = op.attr_float_or("alpha", 1.0).unwrap();
+            let beta = op.attr_float_or("beta", 1.0).unwrap();
+            (0..8)
+                .map(|i| {
+                    let (m, n) = (i / 4, i % 4);
+                    let dot: f32 = (0..3).map(|k| a[m * 3 + k] * w[n * 3 + k]).sum();
+                    alpha * dot + beta * c[n % c.len()]
+                })
+                .collect()
+        };
+        let a = sample(6, 2);
+        let reference: Vec<f32> = {
+            let bn = graph.get_operator("bn").unwrap();
+            let (gamma, beta) = (input(bn, "1"), input(bn, "2"));
+            let (mean, var_) = (input(bn, "3"), input(bn, "4"));
+            gemm(&a, graph.get_operator("gemm").unwrap())
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let c = i % 4;
+                    (v - mean[c]) / (var_[c] + 1e-3).sqrt() * gamma[c] + beta[c]
+                })
+                .collect()
+        };
+
+        let opts = FuseOptions { activation: true };
+        let (fused, report) = fuse(graph, &opts).unwrap();
+        // FusedGemm不支持Clip
+        assert_eq!(vec!["bn"], report[0].absorbed);
+        assert!(fused.get_operator("clip").is_some());
+        let op = fused.get_operator("gemm").unwrap();
+        assert_eq!("Gemm", op.r#type());
+        assert_close(&reference, &gemm(&a, op));
+    }
+
+    #[test]
+    fn unfusable_kept() {
+        let graph = conv_graph();
+        // 沿宽度变化的Add不是逐通道的
+        let add = op(
+            "add",
+            "Add",
+            vec![
+                var("m", &[1, 3, 3, 3]),
+                constant("t", &[3], vec![1.0_f32, 2.0, 3.0]),
+            ],
+            var("a", &[1, 3, 3, 3]),
+        );
+        // BN的输出同时被其他算子使用
+        let tap = op(
+            "tap",
+            "Sigmoid",
+            vec![var("n", &[1, 3, 3, 3])],
+            var("z", &[1, 3, 3, 3]),
+        );
+        let graph = graph
+            .add_operator(add)
+            .unwrap()
+            .add_operator(tap)
+            .unwrap()
+            .add_output(var("z", &[1, 3, 3, 3]))
+            .unwrap();
+        let (fused, report) = fuse(graph, &FuseOptions::default()).unwrap();
+        assert_eq!(vec!["bn"], report[0].absorbed);
+        assert_eq!(5, fused.operators().len());
+        assert!(FuseOptions::from_ops(&HashMap::from([(
+            String::from(FUSE_ACTIVATION_KEY),
+            String::from("yes"),
+        )]))
+        .is_err());
+    }
+}
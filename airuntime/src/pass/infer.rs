@@ -0,0 +1,1893 @@
+//! 张量类型和形状推导
+//!
+//! 按拓扑序由算子输入推导输出的数据类型和形状，补全模型中没有value_info的中间张量。
+//! 元素较少的整数张量同时推导其值，用于`Shape->Gather->Concat->Reshape`一类的形状计算。
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use crate::info::Dim;
+use crate::LoadError;
+
+/// 推导值的整数张量的最大元素数
+const MAX_VALUE_LEN: usize = 64;
+/// Tensor最多支持的维数
+const MAX_RANK: usize = 8;
+
+/// 张量的数据类型和形状
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueType {
+    pub dtype: DType,
+    /// 形状，None表示维数未知
+    pub dims: Option<Vec<Dim>>,
+}
+
+impl ValueType {
+    pub fn new(dtype: DType, dims: Vec<Dim>) -> Self {
+        Self {
+            dtype,
+            dims: Some(dims),
+        }
+    }
+
+    /// 维数未知
+    pub fn unranked(dtype: DType) -> Self {
+        Self { dtype, dims: None }
+    }
+
+    /// 张量描述的类型和形状，形状为空的张量按维数未知处理
+    pub fn from_tensor(t: &Tensor) -> Self {
+        if t.is_scalar() {
+            return Self::new(t.dtype(), vec![]);
+        }
+        let shape = t.shape().data();
+        if shape.is_empty() {
+            return Self::unranked(t.dtype());
+        }
+        let dims = shape
+            .iter()
+            .enumerate()
+            .map(|(i, d)| match t.dim_param(i) {
+                Some("") => Dim::Unknown,
+                Some(p) => Dim::Param(String::from(p)),
+                None => Dim::Value(*d as i64),
+            })
+            .collect();
+        Self::new(t.dtype(), dims)
+    }
+
+    /// 用推导结果替换张量描述，保留名字、格式、张量类型和量化参数，标量按一维保存并标记为0维
+    pub fn to_tensor(&self, t: &Tensor) -> Tensor {
+        let dims = self.dims.as_deref().unwrap_or(&[]);
+        let shape: Vec<u32> = dims
+            .iter()
+            .map(|d| match d {
+                Dim::Value(v) => *v as u32,
+                _ => 0,
+            })
+            .collect();
+        let params = dims
+            .iter()
+            .map(|d| match d {
+                Dim::Value(_) => None,
+                Dim::Param(p) => Some(p.clone()),
+                Dim::Unknown => Some(String::new()),
+            })
+            .collect();
+        let mut tensor =
+            Tensor::new_with_shape(t.name(), &shape, t.format(), self.dtype, t.r#type())
+                .with_dim_params(params);
+        if self.dims.as_ref().is_some_and(Vec::is_empty) {
+            tensor = tensor.with_scalar();
+        }
+        match t.quantization() {
+            Some(q) => tensor.with_quantization(q.clone()),
+            None => tensor,
+        }
+    }
+
+    fn rank(&self) -> Option<usize> {
+        self.dims.as_ref().map(Vec::len)
+    }
+}
+
+/// 推导得到的张量类型和值
+#[derive(Clone, Debug)]
+pub(crate) struct Value {
+    pub(crate) ty: ValueType,
+    /// 一维整数张量的值
+    pub(crate) value: Option<Vec<Dim>>,
+}
+
+impl Value {
+    fn new(dtype: DType, dims: Vec<Dim>) -> Self {
+        Self {
+            ty: ValueType::new(dtype, dims),
+            value: None,
+        }
+    }
+
+    fn unranked(dtype: DType) -> Self {
+        Self {
+            ty: ValueType::unranked(dtype),
+            value: None,
+        }
+    }
+
+    fn with_value(mut self, value: Option<Vec<Dim>>) -> Self {
+        if matches!(self.ty.dtype, DType::Int64 | DType::Int32) {
+            self.value = value;
+        }
+        self
+    }
+
+    fn from_tensor(t: &Tensor) -> Self {
+        Self {
+            ty: ValueType::from_tensor(t),
+            value: const_value(t),
+        }
+    }
+}
+
+/// 推导图中所有算子输出的类型和形状，补全未知的中间张量
+///
+/// 模型中已声明的类型和形状优先，推导结果只补充其中未知的部分；没有推导规则的算子，其输出保持原样。
+/// 推导规则失败说明算子的输入不一致，返回错误。
+pub fn infer_shapes(mut graph: Graph) -> Result<Graph, LoadError> {
+    let order = super::sorted_operators(&graph)?;
+    let mut known: HashMap<String, Value> = HashMap::new();
+    let mut unsupported = BTreeSet::new();
+    for name in order.iter() {
+        let op = graph.get_operator(name).unwrap();
+        let node = Node::new(op, &known);
+        let inferred = match infer_op(&node) {
+            Ok(Some(inferred)) => inferred,
+            Ok(None) => {
+                unsupported.insert(op.r#type().clone());
+                vec![]
+            }
+            Err(e) => {
+                return Err(LoadError::ShapeInference {
+                    node: name.clone(),
+                    reason: format!("{}, {}", op.r#type(), e),
+                })
+            }
+        };
+
+        for (tag, t) in op.outputs() {
+            let declared = ValueType::from_tensor(t);
+            let out = match tag.parse::<usize>().ok().and_then(|i| inferred.get(i)) {
+                Some(v) => Value {
+                    ty: merge(t.name(), &declared, &v.ty),
+                    value: v.value.clone(),
+                },
+                None => Value {
+                    ty: declared,
+                    value: None,
+                },
+            };
+            known.insert(t.name().clone(), out);
+        }
+    }
+    if !unsupported.is_empty() {
+        warn!(
+            "graph {} has no shape inference for {:?}, their outputs may be unknown",
+            graph.name(),
+            unsupported
+        );
+    }
+
+    // 替换算子和图输出中同名的张量描述
+    let mut filled = BTreeSet::new();
+    let mut update = |t: &mut Tensor| {
+        if t.r#type() == Type::Constant {
+            return;
+        }
+        let Some(v) = known.get(t.name()) else {
+            return;
+        };
+        if v.ty.rank().is_some_and(|r| r > MAX_RANK) {
+            return;
+        }
+        let tensor = v.ty.to_tensor(t);
+        if ValueType::from_tensor(&tensor) != ValueType::from_tensor(t) {
+            filled.insert(t.name().clone());
+            *t = tensor;
+        }
+    };
+    for op in graph.operators_mut() {
+        op.inputs_mut().values_mut().for_each(&mut update);
+        op.outputs_mut().values_mut().for_each(&mut update);
+    }
+    graph.outputs_mut().iter_mut().for_each(&mut update);
+    debug!("graph {} infer shapes of {:?}", graph.name(), filled);
+
+    Ok(graph)
+}
+
+/// 由算子中的张量描述推导其输出，没有推导规则时返回None
+pub(crate) fn infer_operator(op: &Operator) -> Result<Option<Vec<Value>>> {
+    infer_op(&Node::new(op, &HashMap::new()))
+}
+
+/// 合并声明的和推导的类型，两者冲突时以声明为准
+fn merge(name: &str, declared: &ValueType, inferred: &ValueType) -> ValueType {
+    let dtype = match (declared.dtype, inferred.dtype) {
+        (DType::Undefined, d) => d,
+        (d, DType::Undefined) => d,
+        (d, i) => {
+            if d != i {
+                warn!("tensor {} declared {}, but inferred {}", name, d, i);
+            }
+            d
+        }
+    };
+    let dims = match (&declared.dims, &inferred.dims) {
+        (None, dims) | (dims, None) => dims.clone(),
+        (Some(d), Some(i)) if d.len() != i.len() => {
+            warn!(
+                "tensor {} declared shape {:?}, but inferred {:?}",
+                name, d, i
+            );
+            Some(d.clone())
+        }
+        (Some(d), Some(i)) => Some(
+            d.iter()
+                .zip(i.iter())
+                .map(|(d, i)| match (d, i) {
+                    (Dim::Value(a), Dim::Value(b)) => {
+                        if a != b {
+                            warn!(
+                                "tensor {} declared shape {:?}, but inferred {:?}",
+                                name, d, i
+                            );
+                        }
+                        Dim::Value(*a)
+                    }
+                    (Dim::Value(_), _) => d.clone(),
+                    (_, Dim::Value(_)) => i.clone(),
+                    (Dim::Unknown, _) => i.clone(),
+                    _ => d.clone(),
+                })
+                .collect(),
+        ),
+    };
+    ValueType { dtype, dims }
+}
+
+/// 常量整数张量的值
+fn const_value(t: &Tensor) -> Option<Vec<Dim>> {
+    if t.r#type() != Type::Constant || t.is_sparse() || t.shape().len() > MAX_VALUE_LEN {
+        return None;
+    }
+    let vs = match t.dtype() {
+        DType::Int64 => t.to_vec::<i64>().ok()?,
+        DType::Int32 => t.to_vec::<i32>().ok()?.into_iter().map(i64::from).collect(),
+        _ => return None,
+    };
+    Some(vs.into_iter().map(Dim::Value).collect())
+}
+
+/// 推导中的算子及其输入
+struct Node<'a> {
+    op: &'a Operator,
+    inputs: Vec<Option<Value>>,
+    tensors: Vec<Option<&'a Tensor>>,
+}
+
+impl<'a> Node<'a> {
+    fn new(op: &'a Operator, known: &HashMap<String, Value>) -> Self {
+        let count = op
+            .inputs()
+            .keys()
+            .filter_map(|k| k.parse::<usize>().ok())
+            .max()
+            .map_or(0, |i| i + 1);
+        let tensors: Vec<_> = (0..count).map(|i| op.get_input(&i.to_string())).collect();
+        let inputs = tensors
+            .iter()
+            .map(|t| {
+                t.map(|t| {
+                    let mut v = known
+                        .get(t.name())
+                        .cloned()
+                        .unwrap_or_else(|| Value::from_tensor(t));
+                    if v.ty.dtype == DType::Undefined {
+                        v.ty.dtype = t.dtype();
+                    }
+                    v
+                })
+            })
+            .collect();
+        Self {
+            op,
+            inputs,
+            tensors,
+        }
+    }
+
+    fn input(&self, i: usize) -> Result<&Value> {
+        self.inputs
+            .get(i)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| anyhow!("input {} is missing", i))
+    }
+
+    fn has_input(&self, i: usize) -> bool {
+        self.inputs.get(i).is_some_and(Option::is_some)
+    }
+
+    fn dtype(&self, i: usize) -> DType {
+        self.input(i).map_or(DType::Undefined, |v| v.ty.dtype)
+    }
+
+    fn dims(&self, i: usize) -> Option<&[Dim]> {
+        self.input(i).ok().and_then(|v| v.ty.dims.as_deref())
+    }
+
+    fn value(&self, i: usize) -> Option<&[Dim]> {
+        self.input(i).ok().and_then(|v| v.value.as_deref())
+    }
+
+    /// 全部已知的整数输入值
+    fn ints(&self, i: usize) -> Option<Vec<i64>> {
+        self.value(i)?.iter().map(dim_value).collect()
+    }
+
+    /// 常量浮点输入值
+    fn floats(&self, i: usize) -> Option<Vec<f32>> {
+        let t = self.tensors.get(i).copied().flatten()?;
+        match (t.r#type(), t.dtype()) {
+            (Type::Constant, DType::Float32) => t.to_vec::<f32>().ok(),
+            _ => None,
+        }
+    }
+
+    /// 整数列表属性，不存在时读取输入值，都没有时返回None
+    fn ints_from(&self, attr: &str, input: usize) -> Result<Option<Vec<i64>>> {
+        match self.op.try_attr::<Vec<i64>>(attr)? {
+            Some(v) => Ok(Some(v)),
+            None => Ok(self.ints(input)),
+        }
+    }
+
+    fn int(&self, attr: &str, default: i64) -> Result<i64> {
+        Ok(self.op.attr_int_or(attr, default)?)
+    }
+
+    fn output_count(&self) -> usize {
+        self.op
+            .outputs()
+            .keys()
+            .filter_map(|k| k.parse::<usize>().ok())
+            .max()
+            .map_or(0, |i| i + 1)
+    }
+
+    /// 与输入`i`类型和形状相同
+    fn same_as(&self, i: usize) -> Result<Value> {
+        Ok(Value {
+            ty: self.input(i)?.ty.clone(),
+            value: None,
+        })
+    }
+}
+
+/// 推导算子各输出的类型和形状，没有推导规则时返回None
+fn infer_op(node: &Node) -> Result<Option<Vec<Value>>> {
+    let outputs = match node.op.r#type().as_str() {
+        "Abs"
+        | "Acos"
+        | "Acosh"
+        | "Asin"
+        | "Asinh"
+        | "Atan"
+        | "Atanh"
+        | "BatchNormalization"
+        | "Ceil"
+        | "Celu"
+        | "Clip"
+        | "Cos"
+        | "Cosh"
+        | "CumSum"
+        | "Elu"
+        | "Erf"
+        | "Exp"
+        | "Floor"
+        | "Gelu"
+        | "HardSigmoid"
+        | "HardSwish"
+        | "Hardmax"
+        | "InstanceNormalization"
+        | "LRN"
+        | "LayerNormalization"
+        | "LeakyRelu"
+        | "Log"
+        | "LogSoftmax"
+        | "LpNormalization"
+        | "MeanVarianceNormalization"
+        | "Mish"
+        | "Neg"
+        | "PRelu"
+        | "Reciprocal"
+        | "Relu"
+        | "Round"
+        | "Selu"
+        | "Shrink"
+        | "Sigmoid"
+        | "Sign"
+        | "Sin"
+        | "Sinh"
+        | "Softmax"
+        | "Softplus"
+        | "Softsign"
+        | "Sqrt"
+        | "Tan"
+        | "Tanh"
+        | "ThresholdedRelu"
+        | "Trilu" => vec![node.same_as(0)?],
+        "Identity" => vec![node.input(0)?.clone()],
+        "Dropout" => {
+            let x = node.same_as(0)?;
+            let mask = Value {
+                ty: ValueType {
+                    dtype: DType::Bool,
+                    dims: x.ty.dims.clone(),
+                },
+                value: None,
+            };
+            vec![x, mask]
+        }
+        "Not" | "IsNaN" | "IsInf" => {
+            let mut x = node.same_as(0)?;
+            x.ty.dtype = DType::Bool;
+            vec![x]
+        }
+        "Cast" => {
+            let to = node.int("to", 0)?;
+            let dtype = DType::try_from_code(to as u32)
+                .ok_or_else(|| anyhow!("unsupported cast to {}", to))?;
+            let x = node.input(0)?;
+            vec![Value {
+                ty: ValueType {
+                    dtype,
+                    dims: x.ty.dims.clone(),
+                },
+                value: None,
+            }
+            .with_value(x.value.clone())]
+        }
+        "CastLike" => {
+            let x = node.input(0)?;
+            vec![Value {
+                ty: ValueType {
+                    dtype: node.dtype(1),
+                    dims: x.ty.dims.clone(),
+                },
+                value: None,
+            }
+            .with_value(x.value.clone())]
+        }
+        "Add" | "Sub" | "Mul" | "Div" | "Pow" | "Mod" | "BitShift" | "BitwiseAnd" | "BitwiseOr"
+        | "BitwiseXor" | "Max" | "Min" | "Sum" | "Mean" => {
+            let out = broadcast_inputs(node, node.dtype(0), 0)?;
+            vec![out.with_value(arithmetic(node))]
+        }
+        "And" | "Or" | "Xor" | "Equal" | "Less" | "Greater" | "LessOrEqual" | "GreaterOrEqual" => {
+            vec![broadcast_inputs(node, DType::Bool, 0)?]
+        }
+        "Where" => vec![broadcast_inputs(node, node.dtype(1), 0)?],
+        "Conv" | "ConvInteger" | "QLinearConv" => vec![conv(node)?],
+        "ConvTranspose" => vec![conv_transpose(node)?],
+        "MaxPool" | "AveragePool" | "LpPool" => {
+            let out = pool(node)?;
+            let indices = Value {
+                ty: ValueType {
+                    dtype: DType::Int64,
+                    dims: out.ty.dims.clone(),
+                },
+                value: None,
+            };
+            vec![out, indices]
+        }
+        "GlobalAveragePool" | "GlobalMaxPool" | "GlobalLpPool" => {
+            let x = node.input(0)?;
+            match x.ty.dims.as_deref() {
+                Some(dims) if dims.len() >= 2 => {
+                    let mut out = dims[..2].to_vec();
+                    out.resize(dims.len(), Dim::Value(1));
+                    vec![Value::new(x.ty.dtype, out)]
+                }
+                _ => vec![Value::unranked(x.ty.dtype)],
+            }
+        }
+        "Gemm" => vec![gemm(node)?],
+        "MatMul" | "MatMulInteger" | "QLinearMatMul" => {
+            let (a, b, dtype) = match node.op.r#type().as_str() {
+                "MatMul" => (0, 1, node.dtype(0)),
+                "MatMulInteger" => (0, 1, DType::Int32),
+                _ => (0, 3, node.dtype(7)),
+            };
+            match (node.dims(a), node.dims(b)) {
+                (Some(a), Some(b)) => vec![Value::new(dtype, matmul(a, b)?)],
+                _ => vec![Value::unranked(dtype)],
+            }
+        }
+        "Flatten" => vec![flatten(node)?],
+        "Reshape" => vec![reshape(node)?],
+        "Transpose" => vec![transpose(node)?],
+        "Squeeze" => vec![squeeze(node)?],
+        "Unsqueeze" => vec![unsqueeze(node)?],
+        "Concat" => vec![concat(node)?],
+        "Split" => split(node)?,
+        "Slice" => vec![slice(node)?],
+        "Gather" => vec![gather(node)?],
+        "GatherElements" => {
+            let indices = node.input(1)?;
+            vec![Value {
+                ty: ValueType {
+                    dtype: node.dtype(0),
+                    dims: indices.ty.dims.clone(),
+                },
+                value: None,
+            }]
+        }
+        "Shape" => vec![shape(node)?],
+        "Size" => {
+            let size = node.dims(0).map(product).unwrap_or(Dim::Unknown);
+            vec![Value::new(DType::Int64, vec![]).with_value(Some(vec![size]))]
+        }
+        "Constant" => vec![constant(node)?],
+        "ConstantOfShape" => {
+            let dtype = match node.op.try_attr::<Tensor>("value")? {
+                Some(t) => t.dtype(),
+                None => DType::Float32,
+            };
+            vec![from_shape_input(node, 0, dtype)]
+        }
+        "Expand" => vec![expand(node)?],
+        "Tile" => vec![tile(node)?],
+        "Pad" => vec![pad(node)?],
+        "ReduceL1" | "ReduceL2" | "ReduceLogSum" | "ReduceLogSumExp" | "ReduceMax"
+        | "ReduceMean" | "ReduceMin" | "ReduceProd" | "ReduceSum" | "ReduceSumSquare" => {
+            vec![reduce(node)?]
+        }
+        "ArgMax" | "ArgMin" => {
+            let axis = node.int("axis", 0)?;
+            let mut out = reduce_axes(node, Some(vec![axis]))?;
+            out.ty.dtype = DType::Int64;
+            vec![out]
+        }
+        "TopK" => {
+            let k = match node.has_input(1) {
+                true => node.ints(1).and_then(|k| k.first().copied()),
+                false => Some(node.int("k", 0)?),
+            };
+            let x = node.input(0)?;
+            let dims = match x.ty.dims.as_deref() {
+                Some(dims) => {
+                    let axis = normalize_axis(node.int("axis", -1)?, dims.len())?;
+                    let mut dims = dims.to_vec();
+                    dims[axis] = k.map_or(Dim::Unknown, Dim::Value);
+                    Some(dims)
+                }
+                None => None,
+            };
+            vec![
+                Value {
+                    ty: ValueType {
+                        dtype: x.ty.dtype,
+                        dims: dims.clone(),
+                    },
+                    value: None,
+                },
+                Value {
+                    ty: ValueType {
+                        dtype: DType::Int64,
+                        dims,
+                    },
+                    value: None,
+                },
+            ]
+        }
+        "NonZero" => {
+            let rank = node
+                .dims(0)
+                .map_or(Dim::Unknown, |d| Dim::Value(d.len() as i64));
+            vec![Value::new(DType::Int64, vec![rank, Dim::Unknown])]
+        }
+        "Range" => {
+            let len = match (node.ints(0), node.ints(1), node.ints(2)) {
+                (Some(s), Some(l), Some(d)) if s.len() == 1 && l.len() == 1 && d == [0] => {
+                    bail!("delta of range is 0")
+                }
+                (Some(s), Some(l), Some(d)) if s.len() == 1 && l.len() == 1 && d.len() == 1 => {
+                    Dim::Value(ceil_div(l[0] - s[0], d[0]).max(0))
+                }
+                _ => Dim::Unknown,
+            };
+            vec![Value::new(node.dtype(0), vec![len])]
+        }
+        "Resize" | "Upsample" => vec![resize(node)?],
+        "DepthToSpace" | "SpaceToDepth" => vec![depth_space(node)?],
+        "QuantizeLinear" => {
+            let dtype = match node.dtype(2) {
+                DType::Undefined => DType::Uint8,
+                d => d,
+            };
+            let mut out = node.same_as(0)?;
+            out.ty.dtype = dtype;
+            vec![out]
+        }
+        "DequantizeLinear" => {
+            let mut out = node.same_as(0)?;
+            out.ty.dtype = match node.dtype(1) {
+                DType::Undefined => DType::Float32,
+                d => d,
+            };
+            vec![out]
+        }
+        "DynamicQuantizeLinear" => {
+            let mut y = node.same_as(0)?;
+            y.ty.dtype = DType::Uint8;
+            vec![
+                y,
+                Value::new(DType::Float32, vec![]),
+                Value::new(DType::Uint8, vec![]),
+            ]
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(outputs))
+}
+
+fn dim_value(d: &Dim) -> Option<i64> {
+    match d {
+        Dim::Value(v) => Some(*v),
+        _ => None,
+    }
+}
+
+fn ceil_div(a: i64, b: i64) -> i64 {
+    let d = a / b;
+    if a % b != 0 && ((a < 0) == (b < 0)) {
+        d + 1
+    } else {
+        d
+    }
+}
+
+fn mul(a: &Dim, b: &Dim) -> Dim {
+    match (a, b) {
+        (Dim::Value(a), Dim::Value(b)) => Dim::Value(a * b),
+        (Dim::Value(1), d) | (d, Dim::Value(1)) => d.clone(),
+        _ => Dim::Unknown,
+    }
+}
+
+fn product(dims: &[Dim]) -> Dim {
+    dims.iter().fold(Dim::Value(1), |acc, d| mul(&acc, d))
+}
+
+fn normalize_axis(axis: i64, rank: usize) -> Result<usize> {
+    let r = rank as i64;
+    if axis < -r || axis >= r {
+        bail!("axis {} is out of range of rank {}", axis, rank);
+    }
+    Ok(((axis + r) % r) as usize)
+}
+
+/// 多向广播
+fn broadcast(shapes: &[&[Dim]]) -> Result<Vec<Dim>> {
+    let rank = shapes.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut out = Vec::with_capacity(rank);
+    for i in 0..rank {
+        let mut dim = Dim::Value(1);
+        for s in shapes {
+            let offset = rank - s.len();
+            if i < offset {
+                continue;
+            }
+            let d = &s[i - offset];
+            dim = match (&dim, d) {
+                (_, Dim::Value(1)) => dim,
+                (Dim::Value(1), _) => d.clone(),
+                (Dim::Value(a), Dim::Value(b)) if a != b => {
+                    bail!("can not broadcast {:?}", shapes)
+                }
+                (Dim::Value(_), _) => dim,
+                (_, Dim::Value(_)) => d.clone(),
+                (a, b) if a == b => dim,
+                _ => Dim::Unknown,
+            };
+        }
+        out.push(dim);
+    }
+    Ok(out)
+}
+
+/// 从输入`start`开始的所有输入广播
+fn broadcast_inputs(node: &Node, dtype: DType, start: usize) -> Result<Value> {
+    let mut shapes = vec![];
+    for i in start..node.inputs.len() {
+        if !node.has_input(i) {
+            continue;
+        }
+        match node.dims(i) {
+            Some(dims) => shapes.push(dims),
+            None => return Ok(Value::unranked(dtype)),
+        }
+    }
+    Ok(Value::new(dtype, broadcast(&shapes)?))
+}
+
+/// 整数形状计算中的逐元素运算
+fn arithmetic(node: &Node) -> Option<Vec<Dim>> {
+    let (a, b) = (node.value(0)?, node.value(1)?);
+    let len = a.len().max(b.len());
+    if node.inputs.len() != 2
+        || (a.len() != len && a.len() != 1)
+        || (b.len() != len && b.len() != 1)
+    {
+        return None;
+    }
+    let at = |v: &[Dim], i: usize| v[if v.len() == 1 { 0 } else { i }].clone();
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        let (x, y) = (at(a, i), at(b, i));
+        let d = match (node.op.r#type().as_str(), dim_value(&x), dim_value(&y)) {
+            ("Mul", _, _) => mul(&x, &y),
+            ("Add", Some(x), Some(y)) => Dim::Value(x + y),
+            ("Sub", Some(x), Some(y)) => Dim::Value(x - y),
+            ("Div", Some(x), Some(y)) if y != 0 => Dim::Value(x.div_euclid(y)),
+            ("Div", _, Some(1)) | ("Add", _, Some(0)) | ("Sub", _, Some(0)) => x,
+            _ => Dim::Unknown,
+        };
+        out.push(d);
+    }
+    Some(out)
+}
+
+/// 卷积和池化输出的空间维度
+fn spatial(node: &Node, x: &[Dim], kernel: &[i64], ceil_mode: bool) -> Result<Vec<Dim>> {
+    let n = kernel.len();
+    let strides = node.op.attr_ints_or("strides", &vec![1; n])?;
+    let dilations = node.op.attr_ints_or("dilations", &vec![1; n])?;
+    let pads = node.op.attr_ints_or("pads", &vec![0; 2 * n])?;
+    let auto_pad = node.op.attr_string_or("auto_pad", "NOTSET")?;
+    if strides.len() != n || dilations.len() != n || pads.len() != 2 * n || x.len() != n + 2 {
+        bail!("kernel {:?} not match input {:?}", kernel, x);
+    }
+
+    let mut out = vec![];
+    for i in 0..n {
+        let Dim::Value(size) = x[i + 2] else {
+            out.push(Dim::Unknown);
+            continue;
+        };
+        let (s, k) = (strides[i], (kernel[i] - 1) * dilations[i] + 1);
+        let d = match auto_pad.as_str() {
+            "SAME_UPPER" | "SAME_LOWER" => ceil_div(size, s),
+            "VALID" => (size - k) / s + 1,
+            _ => {
+                let padded = size + pads[i] + pads[i + n] - k;
+                match ceil_mode {
+                    // 最后一个窗口需要从输入或左侧填充开始
+                    true if (ceil_div(padded, s)) * s >= size + pads[i] => ceil_div(padded, s),
+                    true => ceil_div(padded, s) + 1,
+                    false => padded / s + 1,
+                }
+            }
+        };
+        out.push(Dim::Value(d));
+    }
+    Ok(out)
+}
+
+fn conv(node: &Node) -> Result<Value> {
+    let (w, dtype) = match node.op.r#type().as_str() {
+        "Conv" => (1, node.dtype(0)),
+        "ConvInteger" => (1, DType::Int32),
+        _ => (3, node.dtype(7)),
+    };
+    let (Some(x), Some(w)) = (node.dims(0), node.dims(w)) else {
+        return Ok(Value::unranked(dtype));
+    };
+    if w.len() < 3 || w.len() != x.len() {
+        bail!("weight {:?} not match input {:?}", w, x);
+    }
+    let kernel = match node.op.try_attr::<Vec<i64>>("kernel_shape")? {
+        Some(k) => k,
+        None => match w[2..].iter().map(dim_value).collect::<Option<Vec<_>>>() {
+            Some(k) => k,
+            None => {
+                let mut dims = vec![x[0].clone(), w[0].clone()];
+                dims.resize(x.len(), Dim::Unknown);
+                return Ok(Value::new(dtype, dims));
+            }
+        },
+    };
+    let mut dims = vec![x[0].clone(), w[0].clone()];
+    dims.extend(spatial(node, x, &kernel, false)?);
+    Ok(Value::new(dtype, dims))
+}
+
+fn conv_transpose(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let (Some(x), Some(w)) = (node.dims(0), node.dims(1)) else {
+        return Ok(Value::unranked(dtype));
+    };
+    if w.len() < 3 || w.len() != x.len() {
+        bail!("weight {:?} not match input {:?}", w, x);
+    }
+    let n = x.len() - 2;
+    let group = node.int("group", 1)?;
+    let mut dims = vec![x[0].clone(), mul(&w[1], &Dim::Value(group))];
+    if let Some(shape) = node.op.try_attr::<Vec<i64>>("output_shape")? {
+        let shape = &shape[shape.len().saturating_sub(n)..];
+        dims.extend(shape.iter().map(|d| Dim::Value(*d)));
+        return Ok(Value::new(dtype, dims));
+    }
+
+    let kernel = match node.op.try_attr::<Vec<i64>>("kernel_shape")? {
+        Some(k) => Some(k),
+        None => w[2..].iter().map(dim_value).collect(),
+    };
+    let strides = node.op.attr_ints_or("strides", &vec![1; n])?;
+    let dilations = node.op.attr_ints_or("dilations", &vec![1; n])?;
+    let pads = node.op.attr_ints_or("pads", &vec![0; 2 * n])?;
+    let output_padding = node.op.attr_ints_or("output_padding", &vec![0; n])?;
+    let auto_pad = node.op.attr_string_or("auto_pad", "NOTSET")?;
+    for i in 0..n {
+        let d = match (&x[i + 2], &kernel) {
+            (Dim::Value(size), _) if auto_pad.starts_with("SAME") => Dim::Value(size * strides[i]),
+            (Dim::Value(size), Some(k)) => Dim::Value(
+                strides[i] * (size - 1) + output_padding[i] + (k[i] - 1) * dilations[i] + 1
+                    - pads[i]
+                    - pads[i + n],
+            ),
+            _ => Dim::Unknown,
+        };
+        dims.push(d);
+    }
+    Ok(Value::new(dtype, dims))
+}
+
+fn pool(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let kernel = node.op.attr_ints("kernel_shape")?;
+    let ceil_mode = node.int("ceil_mode", 0)? != 0;
+    let mut dims = x[..2.min(x.len())].to_vec();
+    dims.extend(spatial(node, x, &kernel, ceil_mode)?);
+    Ok(Value::new(dtype, dims))
+}
+
+fn gemm(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let (Some(a), Some(b)) = (node.dims(0), node.dims(1)) else {
+        return Ok(Value::unranked(dtype));
+    };
+    if a.len() != 2 || b.len() != 2 {
+        bail!("gemm need 2-D inputs, but {:?} and {:?}", a, b);
+    }
+    let m = &a[(node.int("transA", 0)? != 0) as usize];
+    let n = &b[(node.int("transB", 0)? == 0) as usize];
+    Ok(Value::new(dtype, vec![m.clone(), n.clone()]))
+}
+
+fn matmul(a: &[Dim], b: &[Dim]) -> Result<Vec<Dim>> {
+    if a.is_empty() || b.is_empty() {
+        bail!("matmul need inputs with rank >= 1");
+    }
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    let (a_vector, b_vector) = (a.len() == 1, b.len() == 1);
+    if a_vector {
+        a.insert(0, Dim::Value(1));
+    }
+    if b_vector {
+        b.push(Dim::Value(1));
+    }
+    let (ra, rb) = (a.len(), b.len());
+    let mut dims = broadcast(&[&a[..ra - 2], &b[..rb - 2]])?;
+    if !a_vector {
+        dims.push(a[ra - 2].clone());
+    }
+    if !b_vector {
+        dims.push(b[rb - 1].clone());
+    }
+    Ok(dims)
+}
+
+fn flatten(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let r = x.len() as i64;
+    let axis = node.int("axis", 1)?;
+    if axis < -r || axis > r {
+        bail!("axis {} is out of range of rank {}", axis, r);
+    }
+    let axis = if axis < 0 { axis + r } else { axis } as usize;
+    Ok(Value::new(
+        dtype,
+        vec![product(&x[..axis]), product(&x[axis..])],
+    ))
+}
+
+fn reshape(node: &Node) -> Result<Value> {
+    let x = node.input(0)?;
+    let dtype = x.ty.dtype;
+    let Some(shape) = node.value(1) else {
+        return Ok(from_shape_input(node, 1, dtype));
+    };
+    let allowzero = node.int("allowzero", 0)? != 0;
+    let input = x.ty.dims.as_deref();
+    let mut dims = vec![];
+    let mut infer = None;
+    for (i, d) in shape.iter().enumerate() {
+        let d = match d {
+            Dim::Value(0) if !allowzero => input
+                .and_then(|x| x.get(i))
+                .cloned()
+                .unwrap_or(Dim::Unknown),
+            Dim::Value(-1) => {
+                infer = Some(i);
+                Dim::Unknown
+            }
+            d => d.clone(),
+        };
+        dims.push(d);
+    }
+    if let (Some(i), Some(input)) = (infer, input) {
+        dims[i] = remaining(input, &dims, i);
+    }
+    Ok(Value::new(dtype, dims).with_value(x.value.clone()))
+}
+
+/// `-1`维度的大小，两侧相同的符号维度相互抵消
+fn remaining(input: &[Dim], dims: &[Dim], skip: usize) -> Dim {
+    let mut symbols: Vec<&Dim> = input.iter().filter(|d| dim_value(d).is_none()).collect();
+    let mut total: i64 = input.iter().filter_map(dim_value).product();
+    let mut known: i64 = 1;
+    for (i, d) in dims.iter().enumerate() {
+        if i == skip {
+            continue;
+        }
+        match d {
+            Dim::Value(v) => known *= v,
+            Dim::Param(_) => match symbols.iter().position(|s| *s == d) {
+                Some(p) => {
+                    symbols.remove(p);
+                }
+                None => return Dim::Unknown,
+            },
+            Dim::Unknown => return Dim::Unknown,
+        }
+    }
+    if known == 0 || total % known != 0 {
+        return Dim::Unknown;
+    }
+    total /= known;
+    match symbols.as_slice() {
+        [] => Dim::Value(total),
+        [s] if total == 1 => (*s).clone(),
+        _ => Dim::Unknown,
+    }
+}
+
+fn transpose(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let default: Vec<i64> = (0..x.len() as i64).rev().collect();
+    let perm = node.op.attr_ints_or("perm", &default)?;
+    if perm.len() != x.len() {
+        bail!("perm {:?} not match rank {}", perm, x.len());
+    }
+    let dims = perm
+        .iter()
+        .map(|p| normalize_axis(*p, x.len()).map(|p| x[p].clone()))
+        .collect::<Result<_>>()?;
+    Ok(Value::new(dtype, dims))
+}
+
+fn squeeze(node: &Node) -> Result<Value> {
+    let x = node.input(0)?;
+    let dtype = x.ty.dtype;
+    let Some(dims) = x.ty.dims.as_deref() else {
+        return Ok(Value::unranked(dtype));
+    };
+    let axes = match node.ints_from("axes", 1)? {
+        Some(axes) => axes
+            .iter()
+            .map(|a| normalize_axis(*a, dims.len()))
+            .collect::<Result<BTreeSet<_>>>()?,
+        None if node.has_input(1) => return Ok(Value::unranked(dtype)),
+        None => {
+            if dims.iter().any(|d| dim_value(d).is_none()) {
+                return Ok(Value::unranked(dtype));
+            }
+            (0..dims.len())
+                .filter(|i| dims[*i] == Dim::Value(1))
+                .collect()
+        }
+    };
+    let out = dims
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !axes.contains(i))
+        .map(|(_, d)| d.clone())
+        .collect();
+    Ok(Value::new(dtype, out).with_value(x.value.clone()))
+}
+
+fn unsqueeze(node: &Node) -> Result<Value> {
+    let x = node.input(0)?;
+    let dtype = x.ty.dtype;
+    let (Some(dims), Some(axes)) = (x.ty.dims.as_deref(), node.ints_from("axes", 1)?) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let rank = dims.len() + axes.len();
+    let axes = axes
+        .iter()
+        .map(|a| normalize_axis(*a, rank))
+        .collect::<Result<BTreeSet<_>>>()?;
+    let mut rest = dims.iter();
+    let out = (0..rank)
+        .map(|i| match axes.contains(&i) {
+            true => Dim::Value(1),
+            false => rest.next().cloned().unwrap_or(Dim::Unknown),
+        })
+        .collect();
+    Ok(Value::new(dtype, out).with_value(x.value.clone()))
+}
+
+fn concat(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let mut shapes = vec![];
+    for i in 0..node.inputs.len() {
+        match node.dims(i) {
+            Some(dims) => shapes.push(dims),
+            None => return Ok(Value::unranked(dtype)),
+        }
+    }
+    let rank = shapes.first().map_or(0, |s| s.len());
+    if let Some(s) = shapes.iter().find(|s| s.len() != rank) {
+        bail!("inputs rank not match, {:?} and {:?}", shapes[0], s);
+    }
+    let axis = normalize_axis(node.int("axis", 0)?, rank)?;
+    let mut dims: Vec<Dim> = (0..rank)
+        .map(|i| {
+            shapes
+                .iter()
+                .map(|s| &s[i])
+                .find(|d| **d != Dim::Unknown)
+                .cloned()
+                .unwrap_or(Dim::Unknown)
+        })
+        .collect();
+    let sizes: Option<Vec<i64>> = shapes.iter().map(|s| dim_value(&s[axis])).collect();
+    dims[axis] = sizes.map_or(Dim::Unknown, |s| Dim::Value(s.iter().sum()));
+
+    let value = (0..node.inputs.len())
+        .map(|i| node.value(i))
+        .collect::<Option<Vec<_>>>()
+        .map(|vs| vs.concat());
+    Ok(Value::new(dtype, dims).with_value(value))
+}
+
+fn split(node: &Node) -> Result<Vec<Value>> {
+    let dtype = node.dtype(0);
+    let count = node.output_count();
+    let Some(x) = node.dims(0) else {
+        return Ok(vec![Value::unranked(dtype); count]);
+    };
+    let axis = normalize_axis(node.int("axis", 0)?, x.len())?;
+    let sizes = match node.ints_from("split", 1)? {
+        Some(split) => split.into_iter().map(Dim::Value).collect(),
+        None => {
+            let n = node.int("num_outputs", count as i64)?.max(1);
+            match &x[axis] {
+                Dim::Value(size) => {
+                    let chunk = ceil_div(*size, n);
+                    (0..n)
+                        .map(|i| Dim::Value(chunk.min(size - chunk * i).max(0)))
+                        .collect()
+                }
+                _ => vec![Dim::Unknown; n as usize],
+            }
+        }
+    };
+    Ok(sizes
+        .into_iter()
+        .map(|size| {
+            let mut dims = x.to_vec();
+            dims[axis] = size;
+            Value::new(dtype, dims)
+        })
+        .collect())
+}
+
+fn slice(node: &Node) -> Result<Value> {
+    let x = node.input(0)?;
+    let dtype = x.ty.dtype;
+    let Some(dims) = x.ty.dims.as_deref() else {
+        return Ok(Value::unranked(dtype));
+    };
+    let attrs = node.op.try_attr::<Vec<i64>>("starts")?.is_some();
+    let (starts, ends, axes, steps) = match attrs {
+        true => (
+            Some(node.op.attr_ints("starts")?),
+            Some(node.op.attr_ints("ends")?),
+            node.op.try_attr::<Vec<i64>>("axes")?,
+            None,
+        ),
+        false => (node.ints(1), node.ints(2), node.ints(3), node.ints(4)),
+    };
+    let (Some(starts), Some(ends)) = (starts, ends) else {
+        return Ok(Value::new(dtype, vec![Dim::Unknown; dims.len()]));
+    };
+    // 有axes输入但值未知
+    if axes.is_none() && !attrs && node.has_input(3) {
+        return Ok(Value::new(dtype, vec![Dim::Unknown; dims.len()]));
+    }
+    let axes = axes.unwrap_or_else(|| (0..starts.len() as i64).collect());
+    let steps = match steps {
+        Some(steps) => steps,
+        None if !attrs && node.has_input(4) => {
+            return Ok(Value::new(dtype, vec![Dim::Unknown; dims.len()]))
+        }
+        None => vec![1; starts.len()],
+    };
+    if ends.len() != starts.len() || axes.len() != starts.len() || steps.len() != starts.len() {
+        bail!("starts, ends, axes and steps need same length");
+    }
+
+    let mut out = dims.to_vec();
+    let mut value = x.value.clone();
+    for i in 0..starts.len() {
+        let axis = normalize_axis(axes[i], dims.len())?;
+        let (start, end, step) = (starts[i], ends[i], steps[i]);
+        if step == 0 {
+            bail!("step of slice is 0");
+        }
+        let Dim::Value(size) = dims[axis] else {
+            if !(start == 0 && end >= i32::MAX as i64 && step == 1) {
+                out[axis] = Dim::Unknown;
+            }
+            value = None;
+            continue;
+        };
+        let (start, end) = slice_range(start, end, step, size);
+        let len = match step > 0 {
+            true => ceil_div(end - start, step),
+            false => ceil_div(start - end, -step),
+        }
+        .max(0);
+        out[axis] = Dim::Value(len);
+        value = match (axis, value) {
+            (0, Some(v)) if dims.len() == 1 && v.len() as i64 == size => Some(
+                (0..len)
+                    .map(|j| v[(start + j * step) as usize].clone())
+                    .collect(),
+            ),
+            _ => None,
+        };
+    }
+    Ok(Value::new(dtype, out).with_value(value))
+}
+
+/// 切片的起止位置，负数从末尾计算
+fn slice_range(start: i64, end: i64, step: i64, size: i64) -> (i64, i64) {
+    let adjust = |v: i64| if v < 0 { v.saturating_add(size) } else { v };
+    let (start, end) = (adjust(start), adjust(end));
+    match step > 0 {
+        true => (start.clamp(0, size), end.clamp(0, size)),
+        // 长度为0的维度没有可取的元素
+        false if size == 0 => (0, 0),
+        false => (start.clamp(0, size - 1), end.clamp(-1, size - 1)),
+    }
+}
+
+fn gather(node: &Node) -> Result<Value> {
+    let data = node.input(0)?;
+    let dtype = data.ty.dtype;
+    let (Some(dims), Some(indices)) = (data.ty.dims.as_deref(), node.dims(1)) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let axis = normalize_axis(node.int("axis", 0)?, dims.len())?;
+    let mut out = dims[..axis].to_vec();
+    out.extend_from_slice(indices);
+    out.extend_from_slice(&dims[axis + 1..]);
+
+    let value = match (&data.value, node.ints(1)) {
+        (Some(v), Some(idx)) if dims.len() == 1 => idx
+            .iter()
+            .map(|i| {
+                let i = if *i < 0 { i + v.len() as i64 } else { *i };
+                v.get(i as usize).cloned()
+            })
+            .collect(),
+        _ => None,
+    };
+    Ok(Value::new(dtype, out).with_value(value))
+}
+
+fn shape(node: &Node) -> Result<Value> {
+    let Some(dims) = node.dims(0) else {
+        return Ok(Value::new(DType::Int64, vec![Dim::Unknown]));
+    };
+    let r = dims.len() as i64;
+    let clamp = |v: i64| if v < 0 { v + r } else { v }.clamp(0, r) as usize;
+    let start = clamp(node.int("start", 0)?);
+    let end = clamp(node.int("end", r)?).max(start);
+    let value = dims[start..end].to_vec();
+    Ok(Value::new(DType::Int64, vec![Dim::Value(value.len() as i64)]).with_value(Some(value)))
+}
+
+fn constant(node: &Node) -> Result<Value> {
+    let op = node.op;
+    if let Some(t) = op.try_attr::<Tensor>("value")? {
+        return Ok(Value::from_tensor(&t));
+    }
+    if let Some(t) = op.try_attr::<Tensor>("sparse_value")? {
+        return Ok(Value::from_tensor(&t));
+    }
+    if let Some(v) = op.try_attr::<i64>("value_int")? {
+        return Ok(Value::new(DType::Int64, vec![]).with_value(Some(vec![Dim::Value(v)])));
+    }
+    if let Some(v) = op.try_attr::<Vec<i64>>("value_ints")? {
+        let dims = vec![Dim::Value(v.len() as i64)];
+        let value = v.into_iter().map(Dim::Value).collect();
+        return Ok(Value::new(DType::Int64, dims).with_value(Some(value)));
+    }
+    if op.try_attr::<f32>("value_float")?.is_some() {
+        return Ok(Value::new(DType::Float32, vec![]));
+    }
+    if let Some(v) = op.try_attr::<Vec<f32>>("value_floats")? {
+        return Ok(Value::new(DType::Float32, vec![Dim::Value(v.len() as i64)]));
+    }
+    if op.get_attribute("value_string").is_some() {
+        return Ok(Value::new(DType::String, vec![]));
+    }
+    if let Some(v) = op.try_attr::<Vec<Vec<u8>>>("value_strings")? {
+        return Ok(Value::new(DType::String, vec![Dim::Value(v.len() as i64)]));
+    }
+    bail!("constant has no value")
+}
+
+/// 形状由一维整数输入`i`的值确定
+fn from_shape_input(node: &Node, i: usize, dtype: DType) -> Value {
+    if let Some(shape) = node.value(i) {
+        return Value::new(dtype, shape.to_vec());
+    }
+    match node.dims(i) {
+        Some([Dim::Value(rank)]) => Value::new(dtype, vec![Dim::Unknown; *rank as usize]),
+        _ => Value::unranked(dtype),
+    }
+}
+
+fn expand(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let (Some(x), Some(shape)) = (node.dims(0), node.value(1)) else {
+        return Ok(Value::unranked(dtype));
+    };
+    Ok(Value::new(dtype, broadcast(&[x, shape])?))
+}
+
+fn tile(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let Some(repeats) = node.value(1) else {
+        return Ok(Value::new(dtype, vec![Dim::Unknown; x.len()]));
+    };
+    if repeats.len() != x.len() {
+        bail!("repeats {:?} not match rank {}", repeats, x.len());
+    }
+    let dims = x.iter().zip(repeats).map(|(d, r)| mul(d, r)).collect();
+    Ok(Value::new(dtype, dims))
+}
+
+fn pad(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let r = x.len();
+    let Some(pads) = node.ints_from("pads", 1)? else {
+        return Ok(Value::new(dtype, vec![Dim::Unknown; r]));
+    };
+    let axes = match node.has_input(3) {
+        true => match node.ints(3) {
+            Some(axes) => axes
+                .iter()
+                .map(|a| normalize_axis(*a, r))
+                .collect::<Result<Vec<_>>>()?,
+            None => return Ok(Value::new(dtype, vec![Dim::Unknown; r])),
+        },
+        false => (0..r).collect(),
+    };
+    let n = axes.len();
+    if pads.len() != 2 * n {
+        bail!("pads {:?} not match axes {:?}", pads, axes);
+    }
+    let mut dims = x.to_vec();
+    for (i, axis) in axes.into_iter().enumerate() {
+        if let Dim::Value(d) = dims[axis] {
+            dims[axis] = Dim::Value(d + pads[i] + pads[i + n]);
+        } else if pads[i] + pads[i + n] != 0 {
+            dims[axis] = Dim::Unknown;
+        }
+    }
+    Ok(Value::new(dtype, dims))
+}
+
+fn reduce(node: &Node) -> Result<Value> {
+    let axes = match node.ints_from("axes", 1)? {
+        Some(axes) => Some(axes),
+        // 有axes输入但值未知
+        None if node.has_input(1) => {
+            let x = node.input(0)?;
+            return Ok(
+                match (x.ty.dims.as_deref(), node.int("keepdims", 1)? != 0) {
+                    (Some(dims), true) => Value::new(x.ty.dtype, vec![Dim::Unknown; dims.len()]),
+                    _ => Value::unranked(x.ty.dtype),
+                },
+            );
+        }
+        None => None,
+    };
+    let axes = axes.filter(|a| !a.is_empty());
+    if axes.is_none() && node.int("noop_with_empty_axes", 0)? != 0 {
+        return node.same_as(0);
+    }
+    reduce_axes(node, axes)
+}
+
+/// 按axes归约，None表示所有维度
+fn reduce_axes(node: &Node, axes: Option<Vec<i64>>) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    let axes = match axes {
+        Some(axes) => axes
+            .iter()
+            .map(|a| normalize_axis(*a, x.len()))
+            .collect::<Result<BTreeSet<_>>>()?,
+        None => (0..x.len()).collect(),
+    };
+    let keepdims = node.int("keepdims", 1)? != 0;
+    let dims = x
+        .iter()
+        .enumerate()
+        .filter_map(|(i, d)| match (axes.contains(&i), keepdims) {
+            (false, _) => Some(d.clone()),
+            (true, true) => Some(Dim::Value(1)),
+            (true, false) => None,
+        })
+        .collect();
+    Ok(Value::new(dtype, dims))
+}
+
+fn resize(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    if node.op.get_attribute("axes").is_some() {
+        return Ok(Value::new(dtype, vec![Dim::Unknown; x.len()]));
+    }
+    // Resize-10和Upsample的scales是第二个输入，之后的版本为第三个输入
+    let scales_input = match node.op.r#type().as_str() {
+        "Resize" if node.inputs.len() > 2 => 2,
+        _ => 1,
+    };
+    if let Some(sizes) = node.value(3).filter(|s| !s.is_empty()) {
+        return Ok(Value::new(dtype, sizes.to_vec()));
+    }
+    let scales = match node.op.try_attr::<Vec<f32>>("scales")? {
+        Some(scales) => Some(scales),
+        None => node.floats(scales_input).filter(|s| !s.is_empty()),
+    };
+    let Some(scales) = scales else {
+        return Ok(Value::new(dtype, vec![Dim::Unknown; x.len()]));
+    };
+    if scales.len() != x.len() {
+        bail!("scales {:?} not match rank {}", scales, x.len());
+    }
+    let dims = x
+        .iter()
+        .zip(scales.iter())
+        .map(|(d, s)| match d {
+            _ if *s == 1.0 => d.clone(),
+            Dim::Value(v) => Dim::Value((*v as f32 * s).floor() as i64),
+            _ => Dim::Unknown,
+        })
+        .collect();
+    Ok(Value::new(dtype, dims))
+}
+
+fn depth_space(node: &Node) -> Result<Value> {
+    let dtype = node.dtype(0);
+    let Some(x) = node.dims(0) else {
+        return Ok(Value::unranked(dtype));
+    };
+    if x.len() != 4 {
+        bail!("need 4-D input, but {:?}", x);
+    }
+    let b = node.op.attr_int("blocksize")?;
+    let scale = |d: &Dim, up: bool| match (d, up) {
+        (Dim::Value(v), true) => Dim::Value(v * b),
+        (Dim::Value(v), false) => Dim::Value(v / b),
+        _ => Dim::Unknown,
+    };
+    let to_depth = node.op.r#type() == "SpaceToDepth";
+    let c = match (&x[1], to_depth) {
+        (Dim::Value(c), true) => Dim::Value(c * b * b),
+        (Dim::Value(c), false) => Dim::Value(c / (b * b)),
+        _ => Dim::Unknown,
+    };
+    let dims = vec![
+        x[0].clone(),
+        c,
+        scale(&x[2], !to_depth),
+        scale(&x[3], !to_depth),
+    ];
+    Ok(Value::new(dtype, dims))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::attribute::Attribute;
+    use model::tensor::Format;
+
+    fn var(name: &str, shape: &[u32], params: &[Option<&str>]) -> Tensor {
+        Tensor::new_with_shape(name, shape, Format::NCHW, DType::Float32, Type::Variable)
+            .with_dim_params(params.iter().map(|p| p.map(String::from)).collect())
+    }
+
+    fn unknown(name: &str) -> Tensor {
+        Tensor::new(name, Format::NCHW, DType::Undefined, Type::Variable)
+    }
+
+    fn op(name: &str, r#type: &str, inputs: Vec<Tensor>, outputs: &[&str]) -> Operator {
+        let mut op = Operator::new(name, r#type);
+        for (i, t) in inputs.into_iter().enumerate() {
+            op = op.add_input(&i.to_string(), t).unwrap();
+        }
+        for (i, o) in outputs.iter().enumerate() {
+            op = op.add_output(&i.to_string(), unknown(o)).unwrap();
+        }
+        op
+    }
+
+    /// 推导单个算子，输入都来自算子上的张量描述
+    fn run(op: &Operator) -> Vec<Value> {
+        infer_op(&Node::new(op, &HashMap::new())).unwrap().unwrap()
+    }
+
+    fn known(dims: &[i64]) -> Option<Vec<Dim>> {
+        Some(dims.iter().map(|d| Dim::Value(*d)).collect())
+    }
+
+    fn ints(name: &str, vs: Vec<i64>) -> Tensor {
+        Tensor::from_vec(name, &[vs.len() as u32], vs)
+            .unwrap()
+            .with_type(Type::Constant)
+    }
+
+    fn output(graph: &Graph, op: &str) -> ValueType {
+        ValueType::from_tensor(graph.get_operator(op).unwrap().get_output("0").unwrap())
+    }
+
+    #[test]
+    fn infer_shapes_works() {
+        let batch = Some("batch");
+        let x = var("x", &[0, 3, 8, 8], &[batch, None, None, None]);
+        let w = Tensor::zeros::<f32>("w", &[4, 3, 3, 3]).with_type(Type::Constant);
+        let conv = op("conv", "Conv", vec![x, w], &["c"])
+            .add_attribute("pads", Attribute::Ints(vec![1, 1, 1, 1]))
+            .unwrap()
+            .add_attribute("strides", Attribute::Ints(vec![2, 2]))
+            .unwrap();
+        let relu = op("relu", "Relu", vec![unknown("c")], &["r"]);
+        // 形状计算：reshape为[batch, -1]
+        let shape = op("shape", "Shape", vec![unknown("r")], &["s"]);
+        let index = Tensor::from_vec("i", &[1], vec![0_i64])
+            .unwrap()
+            .with_type(Type::Constant);
+        let gather = op("gather", "Gather", vec![unknown("s"), index], &["b"]);
+        let minus = Tensor::from_vec("m", &[1], vec![-1_i64])
+            .unwrap()
+            .with_type(Type::Constant);
+        let concat = op("concat", "Concat", vec![unknown("b"), minus], &["shape"])
+            .add_attribute("axis", Attribute::Int(0))
+            .unwrap();
+        let reshape = op(
+            "reshape",
+            "Reshape",
+            vec![unknown("r"), unknown("shape")],
+            &["y"],
+        );
+        let less = op("less", "Less", vec![unknown("y"), unknown("y")], &["z"]);
+        let custom = op("custom", "Custom", vec![unknown("z")], &["u"]);
+        let mut graph = Graph::new("g");
+        for op in [conv, relu, shape, gather, concat, reshape, less, custom] {
+            graph = graph.add_operator(op).unwrap();
+        }
+
+        let graph = infer_shapes(graph).unwrap();
+        let symbolic = |dims: &[Dim]| ValueType::new(DType::Float32, dims.to_vec());
+        let batch = Dim::Param(String::from("batch"));
+        let c = symbolic(&[batch.clone(), Dim::Value(4), Dim::Value(4), Dim::Value(4)]);
+        assert_eq!(c, output(&graph, "conv"));
+        assert_eq!(c, output(&graph, "relu"));
+        // 消费者中的同名张量也被更新
+        let input = graph.get_operator("relu").unwrap().get_input("0").unwrap();
+        assert_eq!(c, ValueType::from_tensor(input));
+        assert_eq!(
+            ValueType::new(DType::Int64, vec![Dim::Value(2)]),
+            output(&graph, "concat")
+        );
+        assert_eq!(
+            symbolic(&[batch.clone(), Dim::Value(64)]),
+            output(&graph, "reshape")
+        );
+        assert_eq!(
+            ValueType::new(DType::Bool, vec![batch, Dim::Value(64)]),
+            output(&graph, "less")
+        );
+        // 没有推导规则的算子保持原样
+        assert_eq!(
+            ValueType::unranked(DType::Undefined),
+            output(&graph, "custom")
+        );
+    }
+
+    #[test]
+    fn infer_rules_works() {
+        let infer = |op: Operator| {
+            let node = Node::new(&op, &HashMap::new());
+            infer_op(&node).unwrap().unwrap()[0].ty.clone()
+        };
+        let dims = |dims: &[i64]| Some(dims.iter().map(|d| Dim::Value(*d)).collect::<Vec<_>>());
+
+        let pool = op("p", "MaxPool", vec![var("x", &[1, 3, 7, 7], &[])], &["y"])
+            .add_attribute("kernel_shape", Attribute::Ints(vec![2, 2]))
+            .unwrap()
+            .add_attribute("strides", Attribute::Ints(vec![2, 2]))
+            .unwrap()
+            .add_attribute("ceil_mode", Attribute::Int(1))
+            .unwrap();
+        assert_eq!(dims(&[1, 3, 4, 4]), infer(pool).dims);
+
+        let matmul = op(
+            "m",
+            "MatMul",
+            vec![var("a", &[2, 1, 4, 5], &[]), var("b", &[3, 5, 6], &[])],
+            &["y"],
+        );
+        assert_eq!(dims(&[2, 3, 4, 6]), infer(matmul).dims);
+
+        let starts = Tensor::from_vec("s", &[1], vec![-1_i64])
+            .unwrap()
+            .with_type(Type::Constant);
+        let ends = Tensor::from_vec("e", &[1], vec![i64::MAX])
+            .unwrap()
+            .with_type(Type::Constant);
+        let slice = op(
+            "s",
+            "Slice",
+            vec![var("x", &[2, 10], &[]), starts, ends],
+            &["y"],
+        );
+        assert_eq!(dims(&[1, 10]), infer(slice).dims);
+
+        let x = var("x", &[0, 6], &[Some(""), None]);
+        let transpose = op("t", "Transpose", vec![x.clone()], &["y"]);
+        assert_eq!(
+            Some(vec![Dim::Value(6), Dim::Unknown]),
+            infer(transpose).dims
+        );
+        let reduce = op("r", "ReduceMean", vec![x], &["y"])
+            .add_attribute("axes", Attribute::Ints(vec![-1]))
+            .unwrap()
+            .add_attribute("keepdims", Attribute::Int(0))
+            .unwrap();
+        assert_eq!(Some(vec![Dim::Unknown]), infer(reduce).dims);
+
+        let a = var("a", &[2, 3], &[]);
+        let b = var("b", &[4, 3], &[]);
+        let add = op("a", "Add", vec![a, b], &["y"]);
+        let node = Node::new(&add, &HashMap::new());
+        assert!(infer_op(&node).is_err());
+    }
+
+    #[test]
+    fn broadcast_works() {
+        let add = |a: Tensor, b: Tensor| run(&op("a", "Add", vec![a, b], &["y"])).remove(0).ty.dims;
+        assert_eq!(
+            known(&[2, 3, 4]),
+            add(var("a", &[2, 1, 4], &[]), var("b", &[3, 1], &[]))
+        );
+        // 与1广播保留符号维度，与静态维度广播取静态值
+        let batch = Dim::Param(String::from("batch"));
+        assert_eq!(
+            Some(vec![batch.clone(), Dim::Value(3)]),
+            add(
+                var("a", &[0, 1], &[Some("batch"), None]),
+                var("b", &[3], &[])
+            )
+        );
+        assert_eq!(
+            known(&[4, 3]),
+            add(var("a", &[0, 3], &[Some(""), None]), var("b", &[4, 1], &[]))
+        );
+        // 不同的符号维度无法确定
+        assert_eq!(
+            Some(vec![batch, Dim::Unknown]),
+            add(
+                var("a", &[0, 0], &[Some("batch"), Some("n")]),
+                var("b", &[0], &[Some("m")])
+            )
+        );
+
+        let node_err = |a: Tensor, b: Tensor| {
+            let add = op("a", "Add", vec![a, b], &["y"]);
+            infer_op(&Node::new(&add, &HashMap::new())).is_err()
+        };
+        assert!(node_err(var("a", &[2, 3], &[]), var("b", &[4, 3], &[])));
+        assert!(node_err(var("a", &[2], &[]), var("b", &[3, 3], &[])));
+    }
+
+    #[test]
+    fn reshape_works() {
+        let reshape = |x: Tensor, shape: Vec<i64>, allowzero: i64| {
+            let op = op("r", "Reshape", vec![x, ints("s", shape)], &["y"])
+                .add_attribute("allowzero", Attribute::Int(allowzero))
+                .unwrap();
+            run(&op).remove(0).ty.dims
+        };
+        let x = var("x", &[2, 3, 4], &[]);
+        // 0复制输入的维度，-1由剩余的元素数计算
+        assert_eq!(known(&[2, 12]), reshape(x.clone(), vec![0, -1], 0));
+        assert_eq!(known(&[6, 4]), reshape(x.clone(), vec![-1, 4], 0));
+        assert_eq!(
+            known(&[2, 3, 2, 2]),
+            reshape(x.clone(), vec![0, 0, 2, -1], 0)
+        );
+        // allowzero时0就是维度大小
+        assert_eq!(known(&[0, 4]), reshape(x, vec![0, 4], 1));
+        // 形状输入未知时维数由形状输入的形状确定
+        let op = op(
+            "r",
+            "Reshape",
+            vec![var("x", &[6], &[]), var("s", &[2], &[])],
+            &["y"],
+        );
+        assert_eq!(Some(vec![Dim::Unknown; 2]), run(&op).remove(0).ty.dims);
+    }
+
+    #[test]
+    fn gather_works() {
+        let data = var("x", &[5, 6, 7], &[]);
+        let gather = |indices: Tensor, axis: i64| {
+            let op = op("g", "Gather", vec![data.clone(), indices], &["y"])
+                .add_attribute("axis", Attribute::Int(axis))
+                .unwrap();
+            run(&op).remove(0)
+        };
+        assert_eq!(
+            known(&[5, 2, 3, 7]),
+            gather(var("i", &[2, 3], &[]), 1).ty.dims
+        );
+        // 标量下标去掉对应的维度
+        let scalar = ints("i", vec![-1]).with_scalar();
+        assert_eq!(known(&[5, 6]), gather(scalar.clone(), -1).ty.dims);
+
+        // 一维整数张量按下标取值
+        let shape = op("s", "Shape", vec![data.clone()], &["s"]);
+        let shape = run(&shape).remove(0);
+        let mut known_values = HashMap::new();
+        known_values.insert(String::from("s"), shape);
+        let op = op("g", "Gather", vec![unknown("s"), scalar], &["y"]);
+        let y = infer_op(&Node::new(&op, &known_values))
+            .unwrap()
+            .unwrap()
+            .remove(0);
+        assert_eq!(Some(vec![]), y.ty.dims);
+        assert_eq!(known(&[7]), y.value);
+    }
+
+    #[test]
+    fn concat_split_works() {
+        let concat = op(
+            "c",
+            "Concat",
+            vec![
+                var("a", &[2, 3, 4], &[]),
+                var("b", &[2, 0, 4], &[None, Some("n"), None]),
+            ],
+            &["y"],
+        )
+        .add_attribute("axis", Attribute::Int(1))
+        .unwrap();
+        assert_eq!(
+            Some(vec![Dim::Value(2), Dim::Unknown, Dim::Value(4)]),
+            run(&concat).remove(0).ty.dims
+        );
+        let concat = op(
+            "c",
+            "Concat",
+            vec![ints("a", vec![1, 2]), ints("b", vec![3])],
+            &["y"],
+        )
+        .add_attribute("axis", Attribute::Int(-1))
+        .unwrap();
+        let y = run(&concat).remove(0);
+        assert_eq!(known(&[3]), y.ty.dims);
+        assert_eq!(known(&[1, 2, 3]), y.value);
+
+        let split = |x: Tensor, sizes: Option<Vec<i64>>, count: usize| {
+            let mut inputs = vec![x];
+            inputs.extend(sizes.map(|s| ints("s", s)));
+            let outputs: Vec<String> = (0..count).map(|i| format!("y{}", i)).collect();
+            let outputs: Vec<&str> = outputs.iter().map(String::as_str).collect();
+            let op = op("s", "Split", inputs, &outputs)
+                .add_attribute("axis", Attribute::Int(-1))
+                .unwrap();
+            run(&op).into_iter().map(|v| v.ty.dims).collect::<Vec<_>>()
+        };
+        let x = var("x", &[2, 6], &[]);
+        assert_eq!(
+            vec![known(&[2, 2]), known(&[2, 4])],
+            split(x.clone(), Some(vec![2, 4]), 2)
+        );
+        // 不能整除时最后一块较小
+        assert_eq!(vec![known(&[2, 3]), known(&[2, 3])], split(x, None, 2));
+        assert_eq!(
+            vec![known(&[2, 3]), known(&[2, 3]), known(&[2, 1])],
+            split(var("x", &[2, 7], &[]), None, 3)
+        );
+        let dynamic = vec![Dim::Value(2), Dim::Unknown];
+        assert_eq!(
+            vec![Some(dynamic.clone()), Some(dynamic)],
+            split(var("x", &[2, 0], &[None, Some("n")]), None, 2)
+        );
+    }
+
+    #[test]
+    fn slice_works() {
+        let slice = |x: Tensor, starts: i64, ends: i64, step: i64| {
+            let inputs = vec![
+                x,
+                ints("starts", vec![starts]),
+                ints("ends", vec![ends]),
+                ints("axes", vec![-1]),
+                ints("steps", vec![step]),
+            ];
+            run(&op("s", "Slice", inputs, &["y"])).remove(0)
+        };
+        let x = var("x", &[3, 10], &[]);
+        // 负的步长从后向前取，结束位置超出范围时截断
+        assert_eq!(known(&[3, 10]), slice(x.clone(), -1, i64::MIN, -1).ty.dims);
+        assert_eq!(known(&[3, 3]), slice(x.clone(), 8, 1, -3).ty.dims);
+        assert_eq!(known(&[3, 0]), slice(x.clone(), 1, 8, -1).ty.dims);
+        assert_eq!(known(&[3, 5]), slice(x, 20, -20, -2).ty.dims);
+
+        let y = slice(ints("v", vec![0, 1, 2, 3, 4]), -1, -6, -2);
+        assert_eq!(known(&[3]), y.ty.dims);
+        assert_eq!(known(&[4, 2, 0]), y.value);
+
+        // 步长为0报错
+        let inputs = vec![
+            var("x", &[10], &[]),
+            ints("starts", vec![0]),
+            ints("ends", vec![5]),
+            ints("axes", vec![0]),
+            ints("steps", vec![0]),
+        ];
+        let op = op("s", "Slice", inputs, &["y"]);
+        assert!(infer_op(&Node::new(&op, &HashMap::new())).is_err());
+    }
+
+    #[test]
+    fn dynamic_dims_works() {
+        let batch = Dim::Param(String::from("batch"));
+        let x = var("x", &[0, 3, 4], &[Some("batch"), None, None]);
+        // Shape的值保留符号维度
+        let shape = run(&op("s", "Shape", vec![x.clone()], &["y"])).remove(0);
+        assert_eq!(known(&[3]), shape.ty.dims);
+        assert_eq!(
+            Some(vec![batch.clone(), Dim::Value(3), Dim::Value(4)]),
+            shape.value
+        );
+        // -1可以由符号维度推导
+        let reshape = |shape: Vec<i64>| {
+            let op = op("r", "Reshape", vec![x.clone(), ints("s", shape)], &["y"]);
+            run(&op).remove(0).ty.dims
+        };
+        assert_eq!(
+            Some(vec![batch.clone(), Dim::Value(12)]),
+            reshape(vec![0, -1])
+        );
+        assert_eq!(
+            Some(vec![batch.clone(), Dim::Value(12)]),
+            reshape(vec![-1, 12])
+        );
+        // 符号维度与静态维度相乘时无法表示
+        assert_eq!(
+            Some(vec![Dim::Unknown, Dim::Value(2)]),
+            reshape(vec![-1, 2])
+        );
+
+        // 符号维度与未知维度广播的结果未知
+        let y = var("y", &[0, 4, 5], &[Some(""), None, None]);
+        let matmul = op("m", "MatMul", vec![x, y], &["z"]);
+        assert_eq!(
+            Some(vec![Dim::Unknown, Dim::Value(3), Dim::Value(5)]),
+            run(&matmul).remove(0).ty.dims
+        );
+        let unranked = op("r", "Relu", vec![unknown("u")], &["y"]);
+        assert_eq!(None, run(&unranked).remove(0).ty.dims);
+    }
+
+    #[test]
+    fn scalar_works() {
+        let scalar = Tensor::from_vec("s", &[1], vec![3_i64])
+            .unwrap()
+            .with_type(Type::Constant)
+            .with_scalar();
+        assert_eq!(
+            ValueType::new(DType::Int64, vec![]),
+            ValueType::from_tensor(&scalar)
+        );
+        let one = Tensor::from_vec("o", &[1], vec![-1_i64])
+            .unwrap()
+            .with_type(Type::Constant);
+
+        // 标量不能直接与一维张量拼接
+        let concat = op("c", "Concat", vec![scalar.clone(), one.clone()], &["y"])
+            .add_attribute("axis", Attribute::Int(0))
+            .unwrap();
+        let node = Node::new(&concat, &HashMap::new());
+        assert!(infer_op(&node).is_err());
+
+        // Unsqueeze为一维后拼接
+        let unsqueeze = op("u", "Unsqueeze", vec![scalar], &["u"])
+            .add_attribute("axes", Attribute::Ints(vec![0]))
+            .unwrap();
+        let concat = op("c", "Concat", vec![unknown("u"), one], &["y"])
+            .add_attribute("axis", Attribute::Int(0))
+            .unwrap();
+        let mut graph = Graph::new("g");
+        for op in [unsqueeze, concat] {
+            graph = graph.add_operator(op).unwrap();
+        }
+        let graph = infer_shapes(graph).unwrap();
+        assert_eq!(
+            ValueType::new(DType::Int64, vec![Dim::Value(2)]),
+            output(&graph, "c")
+        );
+
+        // 0维的推导结果保存为标记的一维张量
+        let t = ValueType::new(DType::Int64, vec![]).to_tensor(&unknown("r"));
+        assert!(t.is_scalar());
+        assert_eq!(&[1], t.shape().data());
+    }
+}
@@ -0,0 +1,1030 @@
+//! 数据布局转换
+//!
+//! 把4维和5维张量在通道在前(NCHW/NCDHW)和通道在后(NHWC/NDHWC)之间转换。常量权重直接重排，
+//! Concat、Softmax等算子与维度有关的属性和Pad的pads按新的维度顺序修改。
+//! 图的输入输出保持原来的布局，Transpose只插在图的边界和无法转换的算子前后。
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use model::attribute::Attribute;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::{DType, Format, Tensor, Type};
+
+use super::fuse::FUSED_DOMAIN;
+use super::Boundary;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// 逐元素计算的算子，所有输入按广播对齐
+const ELEMENTWISE: &[&str] = &[
+    "Abs",
+    "Add",
+    "And",
+    "Ceil",
+    "Cos",
+    "Div",
+    "Elu",
+    "Equal",
+    "Erf",
+    "Exp",
+    "Floor",
+    "Gelu",
+    "Greater",
+    "GreaterOrEqual",
+    "HardSigmoid",
+    "HardSwish",
+    "Identity",
+    "LeakyRelu",
+    "Less",
+    "LessOrEqual",
+    "Log",
+    "Max",
+    "Mean",
+    "Min",
+    "Mish",
+    "Mul",
+    "Neg",
+    "Not",
+    "Or",
+    "Pow",
+    "PRelu",
+    "Reciprocal",
+    "Relu",
+    "Round",
+    "Selu",
+    "Sigmoid",
+    "Sign",
+    "Sin",
+    "Softplus",
+    "Softsign",
+    "Sqrt",
+    "Sub",
+    "Sum",
+    "Tanh",
+    "Where",
+    "Xor",
+];
+/// 只有第一个输入是数据的逐元素算子，其余输入为标量或按axis的参数
+const ELEMENTWISE_FIRST: &[&str] = &[
+    "Cast",
+    "Clip",
+    "DequantizeLinear",
+    "Dropout",
+    "QuantizeLinear",
+];
+/// 按通道计算的算子，后端按张量的布局确定通道维
+const CHANNEL: &[&str] = &[
+    "AveragePool",
+    "BatchNormalization",
+    "DepthToSpace",
+    "GlobalAveragePool",
+    "GlobalLpPool",
+    "GlobalMaxPool",
+    "InstanceNormalization",
+    "LpPool",
+    "LRN",
+    "MaxPool",
+    "SpaceToDepth",
+];
+/// 带axes和keepdims的归约算子
+const REDUCE: &[&str] = &[
+    "ReduceL1",
+    "ReduceL2",
+    "ReduceLogSum",
+    "ReduceLogSumExp",
+    "ReduceMax",
+    "ReduceMean",
+    "ReduceMin",
+    "ReduceProd",
+    "ReduceSum",
+    "ReduceSumSquare",
+];
+
+/// 布局转换的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layout {
+    /// 转换为目标布局的算子
+    pub converted: Vec<String>,
+    /// 插入的Transpose算子
+    pub transposes: Vec<String>,
+}
+
+/// 把计算图转换为`format`布局，`format`为NCHW、NHWC、NCDHW或NDHWC
+///
+/// 4维和5维张量都按对应的布局转换，图的输入输出的名字和布局保持不变
+pub fn convert_layout(mut graph: Graph, format: Format) -> Result<(Graph, Layout)> {
+    let target = Target::new(format)?;
+    let keep = super::kept_tensors(&graph);
+    let order = super::sorted_operators(&graph)?;
+
+    let mut converted = HashMap::new();
+    for name in order.iter() {
+        let op = graph.get_operator(name).unwrap();
+        if let Some(new) = target.convert(op)? {
+            converted.insert(name.clone(), new);
+        }
+    }
+    let mut report = Layout::default();
+    if converted.is_empty() {
+        return Ok((graph, report));
+    }
+
+    // 转换后的算子输出的张量，图输出改名后在边界转换回原来的名字
+    let mut edges = Edges {
+        target: &target,
+        boundary: Boundary::new(&keep, target.tag, target.source_tag),
+        since_version: super::since_version(&graph, "Transpose"),
+    };
+    for name in converted.keys() {
+        for t in graph.get_operator(name).unwrap().outputs().values() {
+            edges.boundary.produce(t, |name| target.permute(t, name))?;
+        }
+    }
+
+    for name in order.iter() {
+        let orig = graph.get_operator(name).unwrap().clone();
+        let is_converted = converted.contains_key(name);
+        let mut op = converted.remove(name).unwrap_or_else(|| orig.clone());
+        for (tag, t) in orig.inputs() {
+            if !target.needs(t) {
+                continue;
+            }
+            let produced = edges.boundary.produced(t.name()).cloned();
+            let input = if is_converted {
+                edges.target_input(t)?
+            } else if let Some(p) = produced.filter(|_| target.keeps_order(&orig, t)) {
+                // 元素顺序不变时直接使用转换后的张量
+                p
+            } else {
+                edges.source_input(t)?
+            };
+            op.inputs_mut().insert(tag.clone(), input);
+        }
+        if is_converted {
+            for (tag, t) in orig.outputs() {
+                let p = edges.boundary.produced(t.name()).unwrap().clone();
+                op.outputs_mut().insert(tag.clone(), p);
+            }
+            report.converted.push(name.clone());
+        }
+        *graph.get_operator_mut(name).unwrap() = op;
+    }
+    // 图输出和子图引用的张量转换回原来的布局
+    let since_version = edges.since_version;
+    let transposes = edges
+        .boundary
+        .finish(|input, output| transpose(&target, since_version, input, output, false))?;
+
+    report.transposes = transposes.iter().map(|op| op.name().clone()).collect();
+    for op in transposes {
+        graph = graph.add_operator(op)?;
+    }
+    info!(
+        "graph {} converted {} ops to {:?}, inserted {} transposes",
+        graph.name(),
+        report.converted.len(),
+        format,
+        report.transposes.len()
+    );
+    Ok((graph, report))
+}
+
+/// 转换的目标布局
+struct Target {
+    /// 通道在后
+    last: bool,
+    /// 4维的目标布局的小写名字，用于新张量的命名
+    tag: &'static str,
+    /// 4维的原布局的小写名字
+    source_tag: &'static str,
+}
+
+impl Target {
+    fn new(format: Format) -> Result<Self> {
+        let last = match format {
+            Format::NCHW | Format::NCDHW => false,
+            Format::NHWC | Format::NDHWC => true,
+            f => bail!("layout {:?} is not supported", f),
+        };
+        let (tag, source_tag) = if last {
+            ("nhwc", "nchw")
+        } else {
+            ("nchw", "nhwc")
+        };
+        Ok(Self {
+            last,
+            tag,
+            source_tag,
+        })
+    }
+
+    /// 需要转换的4维或5维变量
+    fn needs(&self, t: &Tensor) -> bool {
+        t.r#type() != Type::Constant
+            && matches!(t.shape().dim(), 4 | 5)
+            && channels_last(t.format()) != self.last
+    }
+
+    /// 新的第i维对应原来的第perm[i]维
+    fn perm(&self, rank: usize) -> Vec<usize> {
+        if self.last {
+            [0].into_iter().chain(2..rank).chain([1]).collect()
+        } else {
+            [0, rank - 1].into_iter().chain(1..rank - 1).collect()
+        }
+    }
+
+    fn format(&self, rank: usize) -> Format {
+        match (self.last, rank) {
+            (true, 5) => Format::NDHWC,
+            (true, _) => Format::NHWC,
+            (false, 5) => Format::NCDHW,
+            (false, _) => Format::NCHW,
+        }
+    }
+
+    /// 原来的维度在新布局中的位置，负数的维度从后往前数
+    fn axis(&self, rank: usize, axis: i64) -> Option<i64> {
+        let axis = if axis < 0 { axis + rank as i64 } else { axis };
+        self.perm(rank)
+            .iter()
+            .position(|p| *p as i64 == axis)
+            .map(|a| a as i64)
+    }
+
+    /// 按目标布局重排张量，常量的数据同时重排
+    fn permute(&self, t: &Tensor, name: &str) -> Result<Tensor> {
+        let rank = t.shape().dim();
+        relayout(t, name, &self.perm(rank), self.format(rank))
+            .ok_or_else(|| anyhow!("tensor {} can not be permuted", t.name()))
+    }
+
+    /// 常量按广播规则补齐到`rank`维后重排
+    fn broadcast(&self, t: &Tensor, rank: usize) -> Option<Tensor> {
+        let dims = t.shape().data();
+        if dims.is_empty() || dims.len() > rank {
+            return None;
+        }
+        let mut aligned = vec![1; rank - dims.len()];
+        aligned.extend_from_slice(dims);
+        let mut t = t.clone();
+        let data = t.as_bytes()?.to_vec();
+        let q = t.quantization().cloned();
+        t = Tensor::new_with_shape(t.name(), &aligned, t.format(), t.dtype(), Type::Constant);
+        t.set_vec_u8(data, t.dtype());
+        if let Some(mut q) = q {
+            q.axis += (rank - dims.len()) as i32;
+            t = t.with_quantization(q);
+        }
+        let name = format!("{}_{}", t.name(), self.tag);
+        relayout(&t, &name, &self.perm(rank), self.format(rank))
+    }
+
+    /// 元素顺序在转换前后相同，只按总数使用输入的算子可以直接使用转换后的张量
+    fn keeps_order(&self, op: &Operator, t: &Tensor) -> bool {
+        let dims = t.shape().data();
+        if t.is_dynamic() || dims.contains(&0) {
+            return false;
+        }
+        let perm = self.perm(dims.len());
+        let before: Vec<usize> = (0..dims.len()).filter(|i| dims[*i] != 1).collect();
+        let after: Vec<usize> = perm.into_iter().filter(|i| dims[*i] != 1).collect();
+        if before != after || op.get_input("0").map(|i| i.name()) != Some(t.name()) {
+            return false;
+        }
+        match op.r#type().as_str() {
+            "Flatten" => matches!(op.attr_int_or("axis", 1), Ok(0 | 1)),
+            "Reshape" => op
+                .get_input("1")
+                .and_then(const_ints)
+                .is_some_and(|s| !s.contains(&0)),
+            _ => false,
+        }
+    }
+
+    /// 转换算子的属性和常量，不能转换或没有需要转换的张量时返回None
+    fn convert(&self, op: &Operator) -> Result<Option<Operator>> {
+        let domain = normalize_domain(op.domain());
+        let op_type = op.r#type().as_str();
+        let fused_conv = domain == FUSED_DOMAIN && op_type == "FusedConv";
+        if domain != ONNX_DOMAIN && !fused_conv {
+            return Ok(None);
+        }
+        let Some(x) = op.get_input("0").filter(|x| self.needs(x)) else {
+            return Ok(None);
+        };
+        let rank = x.shape().dim();
+        let perm = self.perm(rank);
+        // 输出都是同样维度的张量
+        let outputs_ok = op
+            .outputs()
+            .values()
+            .all(|t| self.needs(t) && t.shape().dim() == rank);
+        if !outputs_ok {
+            return Ok(None);
+        }
+        // 只有第一个输入是数据，其余输入保持不变
+        let data_first = op
+            .inputs()
+            .iter()
+            .filter(|(tag, _)| tag.as_str() != "0")
+            .all(|(_, t)| !self.needs(t));
+
+        let mut new = op.clone();
+        match op_type {
+            t if ELEMENTWISE.contains(&t) || t == "Concat" => {
+                for (tag, input) in op.inputs() {
+                    if self.needs(input) && input.shape().dim() == rank {
+                        continue;
+                    }
+                    if input.r#type() != Type::Constant
+                        || (t == "Concat" && input.shape().dim() != rank)
+                    {
+                        return Ok(None);
+                    }
+                    let Some(c) = self.broadcast(input, rank) else {
+                        return Ok(None);
+                    };
+                    replace_input(&mut new, tag, c);
+                }
+                if t == "Concat" {
+                    let Some(axis) = self.axis(rank, op.attr_int("axis")?) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("axis", Attribute::Int(axis))?;
+                }
+            }
+            t if ELEMENTWISE_FIRST.contains(&t) => {
+                if !data_first {
+                    return Ok(None);
+                }
+                // 逐通道量化的axis
+                let per_axis = op.get_input("1").is_some_and(|s| s.shape().len() > 1);
+                if t.ends_with("QuantizeLinear") && per_axis {
+                    let Some(axis) = self.axis(rank, new.attr_int_or("axis", 1)?) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("axis", Attribute::Int(axis))?;
+                }
+            }
+            "Conv" | "FusedConv" => {
+                let Some(w) = op
+                    .get_input("1")
+                    .filter(|w| w.r#type() == Type::Constant && w.shape().dim() == rank)
+                else {
+                    return Ok(None);
+                };
+                let wdims = w.shape().data();
+                let name = format!("{}_{}", w.name(), self.tag);
+                // TFLite的深度卷积权重为[1, H, W, C * M]，转换为[C * M, 1, H, W]
+                let depthwise = !self.last
+                    && op.attr_int_or("group", 1)? > 1
+                    && wdims[0] == 1
+                    && wdims[rank - 1] > 1;
+                let weight = if depthwise {
+                    let perm: Vec<usize> = [rank - 1].into_iter().chain(0..rank - 1).collect();
+                    relayout(w, &name, &perm, self.format(rank))
+                } else {
+                    relayout(w, &name, &perm, self.format(rank))
+                };
+                let Some(weight) = weight else {
+                    return Ok(None);
+                };
+                replace_input(&mut new, "1", weight);
+            }
+            t if CHANNEL.contains(&t) => {
+                // MaxPool的indices按原布局计算
+                if t == "MaxPool" && op.get_output("1").is_some() {
+                    return Ok(None);
+                }
+                if !data_first {
+                    return Ok(None);
+                }
+            }
+            // 版本未知时不能判断参数是属性还是输入，以及是否按二维计算
+            "Softmax" | "LogSoftmax" | "Hardmax" | "Slice" | "Pad" if op.since_version() == 0 => {
+                return Ok(None);
+            }
+            "Softmax" | "LogSoftmax" | "Hardmax" => {
+                if op.since_version() >= 13 {
+                    let Some(axis) = self.axis(rank, op.attr_int_or("axis", -1)?) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("axis", Attribute::Int(axis))?;
+                } else {
+                    // 旧版本按axis展开为二维，通道和空间维都在后一部分时不变
+                    let axis = op.attr_int_or("axis", 1)?;
+                    let axis = if axis < 0 { axis + rank as i64 } else { axis };
+                    if !matches!(axis, 0 | 1) {
+                        return Ok(None);
+                    }
+                }
+            }
+            "Split" => {
+                let Some(axis) = self.axis(rank, op.attr_int_or("axis", 0)?) else {
+                    return Ok(None);
+                };
+                new = new.add_attribute("axis", Attribute::Int(axis))?;
+            }
+            "ArgMax" | "ArgMin" => {
+                let Some(axis) = self.axis(rank, op.attr_int_or("axis", 0)?) else {
+                    return Ok(None);
+                };
+                if op.attr_int_or("keepdims", 1)? == 0 {
+                    return Ok(None);
+                }
+                new = new.add_attribute("axis", Attribute::Int(axis))?;
+            }
+            t if REDUCE.contains(&t) => {
+                if op.attr_int_or("keepdims", 1)? == 0 {
+                    return Ok(None);
+                }
+                if let Some(axes) = op.try_attr::<Vec<i64>>("axes")? {
+                    let Some(axes) = self.axes(rank, &axes) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("axes", Attribute::Ints(axes))?;
+                } else if let Some(t) = op.get_input("1") {
+                    let Some(axes) = const_ints(t).and_then(|a| self.axes(rank, &a)) else {
+                        return Ok(None);
+                    };
+                    let name = format!("{}_{}", t.name(), self.tag);
+                    replace_input(&mut new, "1", int_tensor(&name, axes, t.dtype())?);
+                }
+            }
+            "Slice" => {
+                if op.since_version() < 10 {
+                    let starts = op.attr_ints("starts")?;
+                    let axes =
+                        op.attr_ints_or("axes", &(0..starts.len() as i64).collect::<Vec<_>>())?;
+                    let Some(axes) = self.axes(rank, &axes) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("axes", Attribute::Ints(axes))?;
+                } else {
+                    let Some(starts) = op.get_input("1") else {
+                        return Ok(None);
+                    };
+                    let (name, axes) = match op.get_input("3") {
+                        Some(t) => (format!("{}_{}", t.name(), self.tag), const_ints(t)),
+                        None => (
+                            format!("{}_axes", op.name()),
+                            Some((0..starts.shape().len() as i64).collect()),
+                        ),
+                    };
+                    let Some(axes) = axes.and_then(|a| self.axes(rank, &a)) else {
+                        return Ok(None);
+                    };
+                    replace_input(&mut new, "3", int_tensor(&name, axes, starts.dtype())?);
+                }
+            }
+            "Pad" => {
+                if op.since_version() < 11 {
+                    let pads = op.attr_ints("pads")?;
+                    let Some(pads) = reorder(&pads, &perm) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("pads", Attribute::Ints(pads))?;
+                } else if let Some(t) = op.get_input("3") {
+                    // 指定了axes时pads按axes的顺序
+                    let Some(axes) = const_ints(t).and_then(|a| self.axes(rank, &a)) else {
+                        return Ok(None);
+                    };
+                    let name = format!("{}_{}", t.name(), self.tag);
+                    replace_input(&mut new, "3", int_tensor(&name, axes, t.dtype())?);
+                } else {
+                    let Some(t) = op.get_input("1") else {
+                        return Ok(None);
+                    };
+                    let Some(pads) = const_ints(t).and_then(|p| reorder(&p, &perm)) else {
+                        return Ok(None);
+                    };
+                    let name = format!("{}_{}", t.name(), self.tag);
+                    replace_input(&mut new, "1", int_tensor(&name, pads, t.dtype())?);
+                }
+            }
+            "Transpose" => {
+                let default: Vec<i64> = (0..rank as i64).rev().collect();
+                let old = op.attr_ints_or("perm", &default)?;
+                if old.len() != rank {
+                    return Ok(None);
+                }
+                let inv = inverse(&perm);
+                let new_perm = perm.iter().map(|p| inv[old[*p] as usize] as i64).collect();
+                new = new.add_attribute("perm", Attribute::Ints(new_perm))?;
+            }
+            "Resize" | "Upsample" => {
+                if let Some(axes) = op.try_attr::<Vec<i64>>("axes")? {
+                    let Some(axes) = self.axes(rank, &axes) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("axes", Attribute::Ints(axes))?;
+                } else if let Some(scales) = op.try_attr::<Vec<f32>>("scales")? {
+                    let Some(scales) = reorder(&scales, &perm) else {
+                        return Ok(None);
+                    };
+                    new = new.add_attribute("scales", Attribute::Floats(scales))?;
+                } else {
+                    // 各输入中按维度给出的roi、scales和sizes
+                    let tags: &[&str] = if op_type == "Upsample" || op.since_version() < 11 {
+                        &["1"]
+                    } else {
+                        &["1", "2", "3"]
+                    };
+                    for tag in tags {
+                        let Some(t) = op.get_input(tag).filter(|t| t.shape().len() > 0) else {
+                            continue;
+                        };
+                        let name = format!("{}_{}", t.name(), self.tag);
+                        let t = match t.dtype() {
+                            DType::Int64 => const_ints(t)
+                                .and_then(|v| reorder(&v, &perm))
+                                .map(|v| int_tensor(&name, v, DType::Int64))
+                                .transpose()?,
+                            DType::Float32 if t.r#type() == Type::Constant => t
+                                .to_vec::<f32>()
+                                .ok()
+                                .and_then(|v| reorder(&v, &perm))
+                                .map(|v| Tensor::from_vec(&name, &[v.len() as u32], v))
+                                .transpose()?
+                                .map(|t| t.with_type(Type::Constant)),
+                            _ => None,
+                        };
+                        let Some(t) = t else {
+                            return Ok(None);
+                        };
+                        replace_input(&mut new, tag, t);
+                    }
+                }
+            }
+            "Tile" => {
+                let Some(t) = op.get_input("1") else {
+                    return Ok(None);
+                };
+                let Some(repeats) = const_ints(t).and_then(|r| reorder(&r, &perm)) else {
+                    return Ok(None);
+                };
+                let name = format!("{}_{}", t.name(), self.tag);
+                replace_input(&mut new, "1", int_tensor(&name, repeats, t.dtype())?);
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some(new))
+    }
+
+    /// 按新布局映射的axes
+    fn axes(&self, rank: usize, axes: &[i64]) -> Option<Vec<i64>> {
+        axes.iter().map(|a| self.axis(rank, *a)).collect()
+    }
+}
+
+/// 插入的Transpose和转换后的张量
+struct Edges<'a> {
+    target: &'a Target,
+    boundary: Boundary<'a>,
+    since_version: i64,
+}
+
+impl Edges<'_> {
+    /// 目标布局的张量
+    fn target_input(&mut self, t: &Tensor) -> Result<Tensor> {
+        let (target, since_version) = (self.target, self.since_version);
+        self.boundary.target_input(t, |name| {
+            let output = target.permute(t, name)?;
+            let op = transpose(target, since_version, t, &output, true)?;
+            Ok((output, op))
+        })
+    }
+
+    /// 原布局的张量
+    fn source_input(&mut self, t: &Tensor) -> Result<Tensor> {
+        let (target, since_version) = (self.target, self.since_version);
+        self.boundary.source_input(t, |p, name| {
+            let output = relayout(
+                t,
+                name,
+                &(0..t.shape().dim()).collect::<Vec<_>>(),
+                t.format(),
+            )
+            .ok_or_else(|| anyhow!("tensor {} can not be renamed", t.name()))?;
+            let op = transpose(target, since_version, p, &output, false)?;
+            Ok((output, op))
+        })
+    }
+}
+
+/// `input`到`output`的Transpose，`forward`为转换到目标布局
+fn transpose(
+    target: &Target,
+    since_version: i64,
+    input: &Tensor,
+    output: &Tensor,
+    forward: bool,
+) -> Result<Operator> {
+    let perm = target.perm(input.shape().dim());
+    let perm = if forward { perm } else { inverse(&perm) };
+    Operator::new(&format!("{}_Transpose", output.name()), "Transpose")
+        .add_input("0", input.clone())?
+        .add_output("0", output.clone())?
+        .add_attribute(
+            "perm",
+            perm.iter().map(|p| *p as i64).collect::<Vec<_>>().into(),
+        )?
+        .set_opset(ONNX_DOMAIN, since_version)
+}
+
+fn channels_last(format: Format) -> bool {
+    matches!(format, Format::NHWC | Format::NDHWC)
+}
+
+fn inverse(perm: &[usize]) -> Vec<usize> {
+    let mut inv = vec![0; perm.len()];
+    for (i, p) in perm.iter().enumerate() {
+        inv[*p] = i;
+    }
+    inv
+}
+
+fn replace_input(op: &mut Operator, tag: &str, t: Tensor) {
+    op.inputs_mut().insert(String::from(tag), t);
+}
+
+/// 按维度排列的参数重排，长度为维度的两倍时前后两半分别重排
+fn reorder<T: Clone>(values: &[T], perm: &[usize]) -> Option<Vec<T>> {
+    let rank = perm.len();
+    if values.len() != rank && values.len() != 2 * rank {
+        return None;
+    }
+    Some(
+        values
+            .chunks(rank)
+            .flat_map(|half| perm.iter().map(|p| half[*p].clone()))
+            .collect(),
+    )
+}
+
+fn const_ints(t: &Tensor) -> Option<Vec<i64>> {
+    if t.r#type() != Type::Constant {
+        return None;
+    }
+    match t.dtype() {
+        DType::Int64 => t.to_vec::<i64>().ok(),
+        DType::Int32 => t
+            .to_vec::<i32>()
+            .ok()
+            .map(|v| v.into_iter().map(i64::from).collect()),
+        _ => None,
+    }
+}
+
+/// 一维的整数常量，类型与原来的常量一致
+fn int_tensor(name: &str, values: Vec<i64>, dtype: DType) -> Result<Tensor> {
+    let shape = [values.len() as u32];
+    let t = match dtype {
+        DType::Int32 => Tensor::from_vec(name, &shape, values.iter().map(|v| *v as i32).collect())?,
+        _ => Tensor::from_vec(name, &shape, values)?,
+    };
+    Ok(t.with_type(Type::Constant))
+}
+
+/// 按`perm`重排维度并改名，新的第i维为原来的第perm[i]维，常量的数据同时重排
+fn relayout(t: &Tensor, name: &str, perm: &[usize], format: Format) -> Option<Tensor> {
+    let dims = t.shape().data();
+    let shape: Vec<u32> = perm.iter().map(|p| dims[*p]).collect();
+    let mut new = Tensor::new_with_shape(name, &shape, format, t.dtype(), t.r#type());
+    if t.is_dynamic() {
+        let params = perm
+            .iter()
+            .map(|p| t.dim_param(*p).map(String::from))
+            .collect();
+        new = new.with_dim_params(params);
+    }
+    if let Some(q) = t.quantization() {
+        let mut q = q.clone();
+        let axis = inverse(perm).get(q.axis as usize).copied().unwrap_or(0);
+        if q.scale.len() > 1 {
+            q.axis = axis as i32;
+        }
+        new = new.with_quantization(q);
+    }
+    if t.r#type() == Type::Constant {
+        if t.is_sparse() {
+            return None;
+        }
+        let bytes = t.as_bytes()?;
+        let count = t.shape().len();
+        if count == 0 || bytes.len() % count != 0 {
+            return None;
+        }
+        let data = permute_bytes(bytes, dims, perm, bytes.len() / count);
+        new.set_vec_u8(data, t.dtype());
+    }
+    Some(new)
+}
+
+fn permute_bytes(bytes: &[u8], dims: &[u32], perm: &[usize], elem: usize) -> Vec<u8> {
+    let rank = dims.len();
+    let mut strides = vec![1; rank];
+    for i in (0..rank.saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * dims[i + 1] as usize;
+    }
+    let new_dims: Vec<usize> = perm.iter().map(|p| dims[*p] as usize).collect();
+    let mut index = vec![0; rank];
+    let mut data = Vec::with_capacity(bytes.len());
+    for _ in 0..bytes.len() / elem {
+        let offset: usize = (0..rank).map(|i| index[i] * strides[perm[i]]).sum();
+        data.extend_from_slice(&bytes[offset * elem..(offset + 1) * elem]);
+        for i in (0..rank).rev() {
+            index[i] += 1;
+            if index[i] < new_dims[i] {
+                break;
+            }
+            index[i] = 0;
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{op, producer, var};
+
+    #[test]
+    fn convert_layout_works() {
+        let nchw = |name: &str, shape: &[u32]| var(name, shape);
+        let weight = Tensor::from_vec("w", &[1, 2, 2, 2], (0..8).map(|v| v as f32).collect())
+            .unwrap()
+            .with_type(Type::Constant);
+        let softmax = op(
+            "softmax",
+            "Softmax",
+            vec![nchw("cat", &[1, 2, 3, 3])],
+            nchw("y", &[1, 2, 3, 3]),
+        )
+        .add_attribute("axis", Attribute::Int(1))
+        .unwrap()
+        .set_opset(ONNX_DOMAIN, 13)
+        .unwrap();
+        let ops = vec![
+            op(
+                "conv",
+                "Conv",
+                vec![nchw("x", &[1, 2, 4, 4]), weight],
+                nchw("c", &[1, 1, 3, 3]),
+            ),
+            op(
+                "relu",
+                "Relu",
+                vec![nchw("c", &[1, 1, 3, 3])],
+                nchw("r", &[1, 1, 3, 3]),
+            ),
+            op(
+                "concat",
+                "Concat",
+                vec![nchw("r", &[1, 1, 3, 3]), nchw("c", &[1, 1, 3, 3])],
+                nchw("cat", &[1, 2, 3, 3]),
+            )
+            .add_attribute("axis", Attribute::Int(1))
+            .unwrap(),
+            softmax,
+            op(
+                "pool",
+                "GlobalAveragePool",
+                vec![nchw("cat", &[1, 2, 3, 3])],
+                nchw("p", &[1, 2, 1, 1]),
+            ),
+            op(
+                "flatten",
+                "Flatten",
+                vec![nchw("p", &[1, 2, 1, 1])],
+                nchw("f", &[1, 2]),
+            ),
+            // 不能转换的算子
+            op(
+                "shape",
+                "Shape",
+                vec![nchw("r", &[1, 1, 3, 3])],
+                nchw("s", &[4]),
+            ),
+            op(
+                "size",
+                "Size",
+                vec![nchw("p", &[1, 2, 1, 1])],
+                nchw("n", &[1]),
+            ),
+        ];
+        let mut graph = Graph::new("g").add_input(nchw("x", &[1, 2, 4, 4])).unwrap();
+        for out in [
+            nchw("y", &[1, 2, 3, 3]),
+            nchw("f", &[1, 2]),
+            nchw("r", &[1, 1, 3, 3]),
+        ] {
+            graph = graph.add_output(out).unwrap();
+        }
+        for op in ops {
+            graph = graph.add_operator(op).unwrap();
+        }
+
+        let (graph, report) = convert_layout(graph, Format::NHWC).unwrap();
+        assert_eq!(vec!["concat", "conv", "pool", "relu", "softmax"], {
+            let mut c = report.converted.clone();
+            c.sort();
+            c
+        });
+        assert_eq!(
+            vec![
+                "p_nchw_Transpose",
+                "r_Transpose",
+                "x_nhwc_Transpose",
+                "y_Transpose"
+            ],
+            {
+                let mut t = report.transposes.clone();
+                t.sort();
+                t
+            }
+        );
+
+        // 图输入在边界转换
+        let conv = graph.get_operator("conv").unwrap();
+        assert_eq!("x_nhwc", conv.get_input("0").unwrap().name());
+        assert_eq!(&[1, 4, 4, 2], conv.get_input("0").unwrap().shape().data());
+        let transpose = producer(&graph, "x_nhwc");
+        assert_eq!(vec![0, 2, 3, 1], transpose.attr_ints("perm").unwrap());
+        // OIHW的权重转换为OHWI
+        let w = conv.get_input("1").unwrap();
+        assert_eq!(&[1, 2, 2, 2], w.shape().data());
+        assert_eq!(Format::NHWC, w.format());
+        assert_eq!(
+            vec![0.0, 4.0, 1.0, 5.0, 2.0, 6.0, 3.0, 7.0],
+            w.to_vec::<f32>().unwrap()
+        );
+
+        let concat = graph.get_operator("concat").unwrap();
+        assert_eq!(3, concat.attr_int("axis").unwrap());
+        assert_eq!("r_nhwc", concat.get_input("0").unwrap().name());
+        assert_eq!(
+            3,
+            graph
+                .get_operator("softmax")
+                .unwrap()
+                .attr_int("axis")
+                .unwrap()
+        );
+
+        // 图输出保持名字和布局
+        for name in ["y", "r"] {
+            let back = producer(&graph, name);
+            assert_eq!("Transpose", back.r#type());
+            assert_eq!(vec![0, 3, 1, 2], back.attr_ints("perm").unwrap());
+            assert_eq!(Format::NCHW, back.get_output("0").unwrap().format());
+        }
+        assert_eq!(
+            "r",
+            graph
+                .get_operator("shape")
+                .unwrap()
+                .get_input("0")
+                .unwrap()
+                .name()
+        );
+        // 元素顺序不变的Flatten直接使用转换后的张量
+        let flatten = graph.get_operator("flatten").unwrap();
+        assert_eq!(
+            &[1, 1, 1, 2],
+            flatten.get_input("0").unwrap().shape().data()
+        );
+        assert_eq!(
+            "p_nchw",
+            graph
+                .get_operator("size")
+                .unwrap()
+                .get_input("0")
+                .unwrap()
+                .name()
+        );
+    }
+
+    #[test]
+    fn convert_to_nchw_works() {
+        let nhwc = |name: &str, shape: &[u32]| var(name, shape).with_format(Format::NHWC);
+        let weight = Tensor::zeros::<f32>("w", &[1, 3, 3, 4])
+            .with_type(Type::Constant)
+            .with_format(Format::NHWC);
+        let pads = Tensor::from_vec("pads", &[8], vec![0_i64, 1, 2, 0, 0, 1, 2, 0])
+            .unwrap()
+            .with_type(Type::Constant);
+        let axes = Tensor::from_vec("axes", &[2], vec![1_i64, 2])
+            .unwrap()
+            .with_type(Type::Constant);
+        let graph = Graph::new("g")
+            .add_input(nhwc("x", &[1, 5, 5, 4]))
+            .unwrap()
+            .add_output(nhwc("y", &[1, 1, 1, 4]))
+            .unwrap()
+            .add_operator(
+                op(
+                    "conv",
+                    "Conv",
+                    vec![nhwc("x", &[1, 5, 5, 4]), weight],
+                    nhwc("c", &[1, 3, 3, 4]),
+                )
+                .add_attribute("group", Attribute::Int(4))
+                .unwrap(),
+            )
+            .unwrap()
+            .add_operator(
+                op(
+                    "pad",
+                    "Pad",
+                    vec![nhwc("c", &[1, 3, 3, 4]), pads],
+                    nhwc("p", &[1, 5, 7, 4]),
+                )
+                .set_opset(ONNX_DOMAIN, 13)
+                .unwrap(),
+            )
+            .unwrap()
+            .add_operator(
+                op(
+                    "mean",
+                    "ReduceMean",
+                    vec![nhwc("p", &[1, 5, 7, 4]), axes],
+                    nhwc("y", &[1, 1, 1, 4]),
+                )
+                .set_opset(ONNX_DOMAIN, 18)
+                .unwrap(),
+            )
+            .unwrap();
+
+        let (graph, report) = convert_layout(graph, Format::NCHW).unwrap();
+        assert_eq!(3, report.converted.len());
+        let conv = graph.get_operator("conv").unwrap();
+        // 深度卷积的权重转换为[C * M, 1, H, W]
+        assert_eq!(&[4, 1, 3, 3], conv.get_input("1").unwrap().shape().data());
+        assert_eq!(&[1, 4, 5, 5], conv.get_input("0").unwrap().shape().data());
+        let pad = graph.get_operator("pad").unwrap();
+        assert_eq!(
+            vec![0, 0, 1, 2, 0, 0, 1, 2],
+            pad.get_input("1").unwrap().to_vec::<i64>().unwrap()
+        );
+        let mean = graph.get_operator("mean").unwrap();
+        assert_eq!(
+            vec![2, 3],
+            mean.get_input("1").unwrap().to_vec::<i64>().unwrap()
+        );
+        assert_eq!("y_nchw", mean.get_output("0").unwrap().name());
+        assert_eq!(
+            vec![0, 2, 3, 1],
+            producer(&graph, "y").attr_ints("perm").unwrap()
+        );
+        assert!(convert_layout(Graph::new("g"), Format::CHWN).is_err());
+    }
+
+    #[test]
+    fn unknown_version_kept() {
+        let nhwc = |name: &str, shape: &[u32]| var(name, shape).with_format(Format::NHWC);
+        let pads = Tensor::from_vec("pads", &[8], vec![0_i64, 1, 2, 0, 0, 1, 2, 0])
+            .unwrap()
+            .with_type(Type::Constant);
+        let starts = Tensor::from_vec("starts", &[1], vec![1_i64])
+            .unwrap()
+            .with_type(Type::Constant);
+        let ends = Tensor::from_vec("ends", &[1], vec![3_i64])
+            .unwrap()
+            .with_type(Type::Constant);
+        // 没有算子定义的版本为0，参数在输入中
+        let graph = Graph::new("g")
+            .add_input(nhwc("x", &[1, 3, 3, 4]))
+            .unwrap()
+            .add_output(nhwc("y", &[1, 2, 7, 4]))
+            .unwrap()
+            .add_operator(op(
+                "pad",
+                "Pad",
+                vec![nhwc("x", &[1, 3, 3, 4]), pads],
+                nhwc("p", &[1, 5, 7, 4]),
+            ))
+            .unwrap()
+            .add_operator(op(
+                "slice",
+                "Slice",
+                vec![nhwc("p", &[1, 5, 7, 4]), starts, ends],
+                nhwc("y", &[1, 2, 7, 4]),
+            ))
+            .unwrap();
+
+        let (graph, report) = convert_layout(graph, Format::NCHW).unwrap();
+        assert!(report.converted.is_empty());
+        let pad = graph.get_operator("pad").unwrap();
+        assert_eq!(&[1, 3, 3, 4], pad.get_input("0").unwrap().shape().data());
+        assert_eq!(
+            vec![0, 1, 2, 0, 0, 1, 2, 0],
+            pad.get_input("1").unwrap().to_vec::<i64>().unwrap()
+        );
+        let slice = graph.get_operator("slice").unwrap();
+        assert!(slice.get_input("3").is_none());
+    }
+}
@@ -0,0 +1,486 @@
+//! 计算图变换的流水线
+//!
+//! 每个变换实现[`Pass`]，按名字注册到[`PassManager`]中。流水线按配置的顺序执行，
+//! 依赖的pass自动提前执行，每个pass记录耗时和改动，可以在pass之间检查图的有效性。
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use model::graph::Graph;
+use model::tensor::{Format, Type};
+
+use super::fuse::FuseOptions;
+use super::precision::PrecisionOptions;
+use super::version::{target_opset, TARGET_OPSET_KEY};
+use crate::schema::{self, Registry};
+use crate::{Config, PrecisionType};
+
+/// `Config.ops`中流水线的key，值为逗号分隔的pass名字，为空时不执行任何pass
+pub const PASSES_KEY: &str = "passes";
+/// `Config.ops`中是否在pass之间检查图的key，值为true或false
+pub const VALIDATE_KEY: &str = "validate_passes";
+
+/// 默认的流水线，为空，pass需要通过`passes`或[`PassManager::with_pipeline`]显式启用
+pub const DEFAULT_PIPELINE: &[&str] = &[];
+
+/// 计算图的变换
+pub trait Pass {
+    /// 唯一的名字，用于在流水线中引用
+    fn name(&self) -> &str;
+
+    /// 需要在本pass之前执行的pass
+    fn dependencies(&self) -> Vec<&str> {
+        vec![]
+    }
+
+    /// 变换计算图，返回变换后的图和改动的算子
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)>;
+}
+
+/// 一个pass的执行结果
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassReport {
+    pub name: String,
+    pub elapsed: Duration,
+    /// 改动的算子
+    pub changes: Vec<String>,
+    /// 执行前后的算子数
+    pub ops_before: usize,
+    pub ops_after: usize,
+}
+
+/// 按名字注册pass并按流水线执行
+#[derive(Clone)]
+pub struct PassManager {
+    passes: HashMap<String, Rc<dyn Pass>>,
+    pipeline: Vec<String>,
+    validate: bool,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    /// 注册内置的pass，流水线为[`DEFAULT_PIPELINE`]
+    pub fn new() -> Self {
+        let builtins: Vec<Rc<dyn Pass>> = vec![
+            Rc::new(InferPass),
+            Rc::new(FoldPass),
+            Rc::new(CleanupPass),
+            Rc::new(FusePass(FuseOptions::default())),
+            Rc::new(Float16Pass(PrecisionOptions::default())),
+            Rc::new(LayoutPass(None)),
+            Rc::new(VersionPass(None)),
+        ];
+        Self {
+            passes: builtins
+                .into_iter()
+                .map(|p| (String::from(p.name()), p))
+                .collect(),
+            pipeline: DEFAULT_PIPELINE.iter().map(|s| String::from(*s)).collect(),
+            validate: false,
+        }
+    }
+
+    /// 按配置设置内置pass的选项和流水线
+    ///
+    /// 没有配置`passes`时，配置了`target_opset`则先转换版本，按`precision`和`layout`追加float16和layout，
+    /// 它们依赖的pass自动加入
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let target = target_opset(&config.ops)?;
+        let mut manager = Self::new()
+            .register(FusePass(FuseOptions::from_ops(&config.ops)?))
+            .register(Float16Pass(PrecisionOptions::from_ops(&config.ops)))
+            .register(LayoutPass(config.layout))
+            .register(VersionPass(target));
+        match config.ops.get(PASSES_KEY) {
+            Some(passes) => {
+                let names: Vec<&str> = passes
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                manager = manager.with_pipeline(&names);
+            }
+            None => {
+                if target.is_some() {
+                    manager.pipeline.insert(0, String::from("version"));
+                }
+                if config.precision == PrecisionType::Float16 {
+                    manager.pipeline.push(String::from("float16"));
+                }
+                if config.layout.is_some() {
+                    manager.pipeline.push(String::from("layout"));
+                }
+            }
+        }
+        manager.validate = match config.ops.get(VALIDATE_KEY).map(|v| v.trim()) {
+            None | Some("false") | Some("0") => false,
+            Some("true") | Some("1") => true,
+            Some(v) => bail!("{} {} is not a bool", VALIDATE_KEY, v),
+        };
+        Ok(manager)
+    }
+
+    /// 注册pass，同名的pass被替换，不改变流水线
+    pub fn register(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.insert(String::from(pass.name()), Rc::new(pass));
+        self
+    }
+
+    /// 注册pass并追加到流水线末尾
+    pub fn add_pass(mut self, pass: impl Pass + 'static) -> Result<Self> {
+        let name = String::from(pass.name());
+        if self.pipeline.contains(&name) {
+            bail!("pass {} is already in the pipeline", name);
+        }
+        self = self.register(pass);
+        self.pipeline.push(name);
+        Ok(self)
+    }
+
+    pub fn with_pipeline(mut self, names: &[&str]) -> Self {
+        self.pipeline = names.iter().map(|s| String::from(*s)).collect();
+        self
+    }
+
+    /// 每个pass之后检查图的有效性
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    /// 执行顺序，依赖的pass排在前面
+    pub fn pipeline(&self) -> Result<Vec<String>> {
+        let mut order = vec![];
+        let mut visiting = vec![];
+        for name in self.pipeline.iter() {
+            self.visit(name, &mut visiting, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit(&self, name: &str, visiting: &mut Vec<String>, order: &mut Vec<String>) -> Result<()> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        if visiting.iter().any(|n| n == name) {
+            bail!(
+                "pass dependencies form a cycle: {} -> {}",
+                visiting.join(" -> "),
+                name
+            );
+        }
+        let pass = self
+            .passes
+            .get(name)
+            .ok_or_else(|| anyhow!("pass {} is not registered", name))?;
+        visiting.push(String::from(name));
+        for dep in pass.dependencies() {
+            self.visit(dep, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(String::from(name));
+        Ok(())
+    }
+
+    /// 按流水线变换计算图
+    pub fn run(&self, mut graph: Graph) -> Result<(Graph, Vec<PassReport>)> {
+        let mut reports = vec![];
+        for name in self.pipeline()? {
+            let pass = &self.passes[&name];
+            let ops_before = graph.operators().len();
+            let start = Instant::now();
+            let (next, changes) = pass
+                .run(graph)
+                .map_err(|e| anyhow!("pass {} failed, {}", name, e))?;
+            graph = next;
+            let report = PassReport {
+                name,
+                elapsed: start.elapsed(),
+                changes,
+                ops_before,
+                ops_after: graph.operators().len(),
+            };
+            debug!(
+                "pass {} took {:?}, changed {} ops, {} -> {} ops",
+                report.name,
+                report.elapsed,
+                report.changes.len(),
+                report.ops_before,
+                report.ops_after
+            );
+            if self.validate {
+                validate(&graph)
+                    .map_err(|e| anyhow!("graph is invalid after pass {}, {}", report.name, e))?;
+            }
+            reports.push(report);
+        }
+        Ok((graph, reports))
+    }
+}
+
+/// 检查计算图：没有环，张量只有一个生产者，变量都有来源，已知算子满足定义
+pub fn validate(graph: &Graph) -> Result<()> {
+    super::sorted_operators(graph)?;
+    let mut produced = HashSet::new();
+    for op in graph.operators() {
+        for t in op.outputs().values() {
+            if !produced.insert(t.name().as_str()) {
+                bail!("tensor {} is produced by more than one op", t.name());
+            }
+        }
+    }
+    let inputs: HashSet<&str> = graph.inputs().iter().map(|t| t.name().as_str()).collect();
+    let defined = |name: &str| produced.contains(name) || inputs.contains(name);
+    for op in graph.operators() {
+        for t in op.inputs().values() {
+            if !t.name().is_empty() && t.r#type() != Type::Constant && !defined(t.name()) {
+                bail!("input {} of op {} has no producer", t.name(), op.name());
+            }
+        }
+        let domain = schema::normalize_domain(op.domain());
+        let opset = graph.opset_version(domain).unwrap_or(op.since_version());
+        match Registry::global().check(domain, opset, op) {
+            Ok(_) | Err(schema::Error::UnknownOp { .. }) => {}
+            // 量化张量直接作为浮点算子的输入输出
+            Err(schema::Error::DType { .. })
+                if op.inputs().values().any(|t| t.quantization().is_some()) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    for t in graph.outputs() {
+        if !defined(t.name()) {
+            bail!("graph output {} has no producer", t.name());
+        }
+    }
+    Ok(())
+}
+
+/// 推导中间张量的类型和形状
+struct InferPass;
+
+impl Pass for InferPass {
+    fn name(&self) -> &str {
+        "infer"
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        Ok((super::infer::infer_shapes(graph)?, vec![]))
+    }
+}
+
+struct FoldPass;
+
+impl Pass for FoldPass {
+    fn name(&self) -> &str {
+        "fold"
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        let (graph, folded) = super::fold::fold_constants(graph)?;
+        Ok((graph, folded.into_iter().map(|f| f.op).collect()))
+    }
+}
+
+struct CleanupPass;
+
+impl Pass for CleanupPass {
+    fn name(&self) -> &str {
+        "cleanup"
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        let (graph, cleanup) = super::cleanup::cleanup(graph)?;
+        Ok((graph, [cleanup.bypassed, cleanup.removed].concat()))
+    }
+}
+
+/// BN等折叠依赖常量折叠得到的参数
+struct FusePass(FuseOptions);
+
+impl Pass for FusePass {
+    fn name(&self) -> &str {
+        "fuse"
+    }
+
+    fn dependencies(&self) -> Vec<&str> {
+        vec!["fold"]
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        let (graph, fused) = super::fuse::fuse(graph, &self.0)?;
+        Ok((graph, fused.into_iter().map(|f| f.op).collect()))
+    }
+}
+
+/// 融合只处理float32的权重，需要在转换为float16之前
+struct Float16Pass(PrecisionOptions);
+
+impl Pass for Float16Pass {
+    fn name(&self) -> &str {
+        "float16"
+    }
+
+    fn dependencies(&self) -> Vec<&str> {
+        vec!["fuse"]
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        let (graph, precision) = super::precision::convert_float16(graph, &self.0)?;
+        Ok((graph, precision.converted))
+    }
+}
+
+struct LayoutPass(Option<Format>);
+
+impl Pass for LayoutPass {
+    fn name(&self) -> &str {
+        "layout"
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        let Some(format) = self.0 else {
+            bail!("no target layout, Config.layout is not set");
+        };
+        let (graph, layout) = super::layout::convert_layout(graph, format)?;
+        Ok((graph, layout.converted))
+    }
+}
+
+/// 旧版本的算子在其他pass之前转换，后续的pass只处理目标版本的定义
+struct VersionPass(Option<i64>);
+
+impl Pass for VersionPass {
+    fn name(&self) -> &str {
+        "version"
+    }
+
+    fn run(&self, graph: Graph) -> Result<(Graph, Vec<String>)> {
+        let Some(target) = self.0 else {
+            bail!("no target opset, {} is not set", TARGET_OPSET_KEY);
+        };
+        let (graph, converted) = super::version::convert_version(graph, target)?;
+        Ok((graph, [converted.migrated, converted.inserted].concat()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{op, var};
+
+    fn sample() -> Graph {
+        let v = |name: &str| var(name, &[2]);
+        Graph::new("g")
+            .add_input(v("x"))
+            .unwrap()
+            .add_output(v("y"))
+            .unwrap()
+            .add_operator(op("identity", "Identity", vec![v("x")], v("i")))
+            .unwrap()
+            .add_operator(op("relu", "Relu", vec![v("i")], v("y")))
+            .unwrap()
+    }
+
+    /// 把Relu改为输入未定义的Sigmoid
+    struct Broken;
+
+    impl Pass for Broken {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn dependencies(&self) -> Vec<&str> {
+            vec!["cleanup"]
+        }
+
+        fn run(&self, mut graph: Graph) -> Result<(Graph, Vec<String>)> {
+            let sigmoid = op(
+                "relu",
+                "Sigmoid",
+                vec![var("missing", &[2])],
+                var("y", &[2]),
+            );
+            *graph.get_operator_mut("relu").unwrap() = sigmoid;
+            Ok((graph, vec![String::from("relu")]))
+        }
+    }
+
+    #[test]
+    fn pass_manager_works() {
+        assert!(PassManager::new().pipeline().unwrap().is_empty());
+        let (graph, reports) = PassManager::new().run(sample()).unwrap();
+        assert_eq!(2, graph.operators().len());
+        assert!(reports.is_empty());
+
+        let manager = PassManager::new()
+            .with_pipeline(&["broken", "fuse"])
+            .register(Broken);
+        assert_eq!(
+            vec!["cleanup", "broken", "fold", "fuse"],
+            manager.pipeline().unwrap()
+        );
+        let (graph, reports) = manager.run(sample()).unwrap();
+        assert_eq!("Sigmoid", graph.get_operator("relu").unwrap().r#type());
+        assert_eq!(vec![String::from("identity")], reports[0].changes);
+        assert_eq!((2, 1), (reports[0].ops_before, reports[0].ops_after));
+        assert_eq!(4, reports.len());
+
+        let err = manager.with_validation(true).run(sample()).unwrap_err();
+        assert!(err.to_string().contains("after pass broken"), "{}", err);
+        assert!(PassManager::new()
+            .with_pipeline(&["unknown"])
+            .run(sample())
+            .is_err());
+    }
+
+    #[test]
+    fn from_config_works() {
+        let manager = PassManager::from_config(&Config::default()).unwrap();
+        assert!(manager.pipeline().unwrap().is_empty());
+
+        let mut config = Config {
+            layout: Some(Format::NHWC),
+            precision: PrecisionType::Float16,
+            ..Default::default()
+        };
+        let manager = PassManager::from_config(&config).unwrap();
+        assert_eq!(
+            vec!["fold", "fuse", "float16", "layout"],
+            manager.pipeline().unwrap()
+        );
+        config
+            .ops
+            .insert(String::from(TARGET_OPSET_KEY), String::from("13"));
+        let manager = PassManager::from_config(&config).unwrap();
+        assert_eq!("version", manager.pipeline().unwrap()[0]);
+
+        config
+            .ops
+            .insert(String::from(PASSES_KEY), String::from("cleanup, infer"));
+        config
+            .ops
+            .insert(String::from(VALIDATE_KEY), String::from("true"));
+        let manager = PassManager::from_config(&config)
+            .unwrap()
+            .add_pass(Broken)
+            .unwrap();
+        assert_eq!(
+            vec!["cleanup", "infer", "broken"],
+            manager.pipeline().unwrap()
+        );
+        assert!(manager.run(sample()).is_err());
+
+        config
+            .ops
+            .insert(String::from(VALIDATE_KEY), String::from("yes"));
+        assert!(PassManager::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,465 @@
+//! float32转换为float16的混合精度
+//!
+//! 常量和中间张量转换为float16，数值敏感的算子保持float32。图的输入输出保持原来的数据类型，
+//! 在float16区域的边界插入Cast。
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use log::*;
+use model::attribute::Attribute;
+use model::graph::Graph;
+use model::half::f16;
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use super::Boundary;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// `Config.ops`中追加保持float32的算子的key，值为逗号分隔的算子类型
+pub const BLOCK_LIST_KEY: &str = "float16_block_list";
+
+/// 默认保持float32的算子，float16的范围或精度不够
+pub const DEFAULT_BLOCK_LIST: &[&str] = &[
+    "Exp",
+    "GroupNormalization",
+    "InstanceNormalization",
+    "LayerNormalization",
+    "Log",
+    "LogSoftmax",
+    "Pow",
+    "Reciprocal",
+    "ReduceL1",
+    "ReduceL2",
+    "ReduceLogSum",
+    "ReduceLogSumExp",
+    "ReduceMean",
+    "ReduceProd",
+    "ReduceSum",
+    "ReduceSumSquare",
+    "Softmax",
+    "Sqrt",
+];
+
+/// 由属性决定输出类型的算子属性，这些算子保持不变
+const DTYPE_ATTRIBUTES: &[&str] = &["dtype", "to", "value"];
+
+/// QDQ模型中的量化算子，opset 19之前scale只能是float32
+const QDQ_OPS: &[&str] = &["QuantizeLinear", "DequantizeLinear"];
+
+/// 混合精度选项
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrecisionOptions {
+    /// 保持float32的算子类型
+    pub block_list: HashSet<String>,
+}
+
+impl Default for PrecisionOptions {
+    fn default() -> Self {
+        Self {
+            block_list: DEFAULT_BLOCK_LIST
+                .iter()
+                .map(|s| String::from(*s))
+                .collect(),
+        }
+    }
+}
+
+impl PrecisionOptions {
+    /// 从`Config.ops`读取选项，配置的算子追加到默认列表中
+    pub fn from_ops(ops: &HashMap<String, String>) -> Self {
+        let mut opts = Self::default();
+        if let Some(list) = ops.get(BLOCK_LIST_KEY) {
+            opts.block_list.extend(
+                list.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(String::from),
+            );
+        }
+        opts
+    }
+}
+
+/// 混合精度转换的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Precision {
+    /// 转换为float16的算子
+    pub converted: Vec<String>,
+    /// 有float32张量但保持float32的算子
+    pub blocked: Vec<String>,
+    /// 插入的Cast
+    pub casts: Vec<String>,
+}
+
+/// 把float32的常量和中间张量转换为float16，图输入输出的名字和数据类型不变
+pub fn convert_float16(mut graph: Graph, opts: &PrecisionOptions) -> Result<(Graph, Precision)> {
+    let keep = super::kept_tensors(&graph);
+    let order = super::sorted_operators(&graph)?;
+    let mut report = Precision::default();
+
+    let mut converted = HashSet::new();
+    for name in order.iter() {
+        let op = graph.get_operator(name).unwrap();
+        let float = op
+            .inputs()
+            .values()
+            .chain(op.outputs().values())
+            .any(|t| t.dtype() == DType::Float32);
+        if !float {
+            continue;
+        }
+        if convertible(op, opts) {
+            converted.insert(name.clone());
+        } else {
+            report.blocked.push(name.clone());
+        }
+    }
+    if converted.is_empty() {
+        return Ok((graph, report));
+    }
+
+    // 转换后的算子输出的float16张量，保留的名字改名后在边界转换回float32
+    let mut casts = Casts {
+        boundary: Boundary::new(&keep, "float16", "float32"),
+        version: super::since_version(&graph, "Cast"),
+    };
+    // 被保持float32的算子使用的常量，转换后的常量需要改名
+    let mut shared = HashSet::new();
+    for op in graph.operators() {
+        if converted.contains(op.name()) {
+            for t in op
+                .outputs()
+                .values()
+                .filter(|t| t.dtype() == DType::Float32)
+            {
+                casts
+                    .boundary
+                    .produce(t, |name| Ok(retyped(t, name, DType::Float16)))?;
+            }
+        } else {
+            shared.extend(
+                op.inputs()
+                    .values()
+                    .filter(|t| t.r#type() == Type::Constant)
+                    .map(|t| t.name().clone()),
+            );
+        }
+    }
+    shared.extend(keep.iter().cloned());
+
+    for name in order.iter() {
+        let mut op = graph.get_operator(name).unwrap().clone();
+        let is_converted = converted.contains(name);
+        let tags: Vec<String> = op.inputs().keys().cloned().collect();
+        for tag in tags {
+            let t = op.get_input(&tag).unwrap();
+            if t.dtype() != DType::Float32 {
+                continue;
+            }
+            let input = if !is_converted {
+                casts.float32_input(t)?
+            } else if keeps_float32(&op, &tag) {
+                continue;
+            } else if t.r#type() == Type::Constant {
+                let name = if shared.contains(t.name()) {
+                    format!("{}_float16", t.name())
+                } else {
+                    t.name().clone()
+                };
+                to_float16(t, &name)?
+            } else {
+                casts.float16_input(t)?
+            };
+            op.inputs_mut().insert(tag, input);
+        }
+        if is_converted {
+            for t in op.outputs_mut().values_mut() {
+                if let Some(p) = casts.boundary.produced(t.name()) {
+                    *t = p.clone();
+                }
+            }
+            report.converted.push(name.clone());
+        }
+        *graph.get_operator_mut(name).unwrap() = op;
+    }
+    let version = casts.version;
+    let casts = casts
+        .boundary
+        .finish(|input, output| cast(version, input, output))?;
+
+    report.casts = casts.iter().map(|op| op.name().clone()).collect();
+    for op in casts {
+        graph = graph.add_operator(op)?;
+    }
+    info!(
+        "graph {} converted {} ops to float16, {} ops kept float32, inserted {} casts",
+        graph.name(),
+        report.converted.len(),
+        report.blocked.len(),
+        report.casts.len()
+    );
+    Ok((graph, report))
+}
+
+/// 算子可以在float16下计算
+fn convertible(op: &Operator, opts: &PrecisionOptions) -> bool {
+    if normalize_domain(op.domain()) != ONNX_DOMAIN || opts.block_list.contains(op.r#type()) {
+        return false;
+    }
+    if QDQ_OPS.contains(&op.r#type().as_str()) && op.since_version() < 19 {
+        return false;
+    }
+    let fixed = op.attributes().iter().any(|(k, v)| {
+        DTYPE_ATTRIBUTES.contains(&k.as_str())
+            || matches!(v, Attribute::Graph(_) | Attribute::Graphs(_))
+    });
+    // 稀疏和量化的常量不转换
+    let special = op
+        .inputs()
+        .values()
+        .any(|t| t.is_sparse() || t.quantization().is_some());
+    !fixed && !special
+}
+
+/// 规定为float类型的输入，如Resize的scales
+fn keeps_float32(op: &Operator, tag: &str) -> bool {
+    matches!(
+        (op.r#type().as_str(), tag),
+        ("Resize", "2") | ("Upsample", "1")
+    )
+}
+
+/// float32常量转换为float16，超出范围的值截断为float16的最大值
+fn to_float16(t: &Tensor, name: &str) -> Result<Tensor> {
+    let data: Vec<f16> = t
+        .to_vec::<f32>()?
+        .into_iter()
+        .map(|v| f16::from_f32(v.clamp(f16::MIN.to_f32(), f16::MAX.to_f32())))
+        .collect();
+    Ok(Tensor::from_vec(name, t.shape().data(), data)?
+        .with_type(Type::Constant)
+        .with_format(t.format()))
+}
+
+/// 数据类型为`dtype`的变量，形状和布局与`t`相同
+fn retyped(t: &Tensor, name: &str, dtype: DType) -> Tensor {
+    let mut new = Tensor::new_with_shape(name, t.shape().data(), t.format(), dtype, t.r#type());
+    if t.is_dynamic() {
+        new = new.with_dim_params(
+            (0..t.shape().dim())
+                .map(|i| t.dim_param(i).map(String::from))
+                .collect(),
+        );
+    }
+    new
+}
+
+/// 插入的Cast和转换后的张量
+struct Casts<'a> {
+    boundary: Boundary<'a>,
+    version: i64,
+}
+
+impl Casts<'_> {
+    /// float16算子的输入
+    fn float16_input(&mut self, t: &Tensor) -> Result<Tensor> {
+        let version = self.version;
+        self.boundary.target_input(t, |name| {
+            let output = retyped(t, name, DType::Float16);
+            let op = cast(version, t, &output)?;
+            Ok((output, op))
+        })
+    }
+
+    /// float32算子的输入
+    fn float32_input(&mut self, t: &Tensor) -> Result<Tensor> {
+        let version = self.version;
+        self.boundary.source_input(t, |p, name| {
+            let output = retyped(t, name, DType::Float32);
+            let op = cast(version, p, &output)?;
+            Ok((output, op))
+        })
+    }
+}
+
+/// `input`到`output`的数据类型的Cast
+fn cast(version: i64, input: &Tensor, output: &Tensor) -> Result<Operator> {
+    Operator::new(&format!("{}_Cast", output.name()), "Cast")
+        .add_input("0", input.clone())?
+        .add_output("0", output.clone())?
+        .add_attribute("to", Attribute::Int(output.dtype().get_code() as i64))?
+        .set_opset(ONNX_DOMAIN, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{constant, op, producer, var};
+    use model::tensor::Format;
+
+    #[test]
+    fn convert_float16_works() {
+        let w = Tensor::from_vec("w", &[2, 2], vec![1.5_f32, -2.0, 1e6, 0.25])
+            .unwrap()
+            .with_type(Type::Constant);
+        let graph = Graph::new("g")
+            .add_input(var("x", &[1, 2]))
+            .unwrap()
+            .add_output(var("y", &[1, 2]))
+            .unwrap()
+            .add_output(var("r", &[1, 2]))
+            .unwrap()
+            .add_operator(op(
+                "mm",
+                "MatMul",
+                vec![var("x", &[1, 2]), w.clone()],
+                var("m", &[1, 2]),
+            ))
+            .unwrap()
+            .add_operator(op(
+                "relu",
+                "Relu",
+                vec![var("m", &[1, 2])],
+                var("r", &[1, 2]),
+            ))
+            .unwrap()
+            .add_operator(op(
+                "softmax",
+                "Softmax",
+                vec![var("r", &[1, 2])],
+                var("s", &[1, 2]),
+            ))
+            .unwrap()
+            // 常量同时被float32的算子使用
+            .add_operator(op(
+                "add",
+                "Add",
+                vec![var("s", &[1, 2]), w],
+                var("y", &[1, 2]),
+            ))
+            .unwrap();
+
+        let opts = PrecisionOptions::from_ops(&HashMap::from([(
+            String::from(BLOCK_LIST_KEY),
+            String::from("Add, "),
+        )]));
+        let (graph, report) = convert_float16(graph, &opts).unwrap();
+        assert_eq!(vec!["mm", "relu"], report.converted);
+        assert_eq!(vec!["softmax", "add"], report.blocked);
+
+        // 图输入在边界转换为float16
+        let mm = graph.get_operator("mm").unwrap();
+        let x = mm.get_input("0").unwrap();
+        assert_eq!(
+            ("x_float16", DType::Float16),
+            (x.name().as_str(), x.dtype())
+        );
+        assert_eq!("Cast", producer(&graph, "x_float16").r#type());
+        let w = mm.get_input("1").unwrap();
+        assert_eq!("w_float16", w.name());
+        assert_eq!(
+            vec![1.5, -2.0, 65504.0, 0.25],
+            w.to_vec::<f16>()
+                .unwrap()
+                .iter()
+                .map(|v| v.to_f32())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(DType::Float16, mm.get_output("0").unwrap().dtype());
+        assert_eq!(
+            DType::Float32,
+            graph
+                .get_operator("add")
+                .unwrap()
+                .get_input("1")
+                .unwrap()
+                .dtype()
+        );
+
+        // 图输出r改名后转换回float32，softmax使用转换后的r
+        let relu = graph.get_operator("relu").unwrap();
+        assert_eq!("r_float16", relu.get_output("0").unwrap().name());
+        let cast = producer(&graph, "r");
+        assert_eq!("Cast", cast.r#type());
+        assert_eq!(
+            Some(DType::Float32.get_code() as i64),
+            cast.attr_int_or("to", 0).ok()
+        );
+        let softmax = graph.get_operator("softmax").unwrap();
+        assert_eq!("r", softmax.get_input("0").unwrap().name());
+        assert_eq!(
+            DType::Float32,
+            producer(&graph, "y").get_output("0").unwrap().dtype()
+        );
+        assert_eq!(2, report.casts.len());
+    }
+
+    #[test]
+    fn internal_boundary_cast() {
+        let graph = Graph::new("g")
+            .add_input(var("x", &[4]))
+            .unwrap()
+            .add_output(var("y", &[4]))
+            .unwrap()
+            .add_operator(op("exp", "Exp", vec![var("x", &[4])], var("e", &[4])))
+            .unwrap()
+            .add_operator(op("relu", "Relu", vec![var("e", &[4])], var("r", &[4])))
+            .unwrap()
+            .add_operator(op("sqrt", "Sqrt", vec![var("r", &[4])], var("y", &[4])))
+            .unwrap();
+        let (graph, report) = convert_float16(graph, &PrecisionOptions::default()).unwrap();
+        assert_eq!(vec!["relu"], report.converted);
+        let relu = graph.get_operator("relu").unwrap();
+        assert_eq!("e_float16", relu.get_input("0").unwrap().name());
+        assert_eq!(DType::Float16, relu.get_output("0").unwrap().dtype());
+        let sqrt = graph.get_operator("sqrt").unwrap();
+        assert_eq!("r_float32", sqrt.get_input("0").unwrap().name());
+        assert_eq!(DType::Float32, sqrt.get_input("0").unwrap().dtype());
+        assert_eq!("y", graph.outputs()[0].name());
+    }
+
+    #[test]
+    fn qdq_scale_kept_float32() {
+        let int8 = |name: &str| {
+            Tensor::new_with_shape(name, &[1, 2], Format::NCHW, DType::Int8, Type::Variable)
+        };
+        let scale = constant("scale", &[1], vec![0.5_f32]);
+        let zero_point = constant("zero_point", &[1], vec![0_i8]);
+        let dequantize = |version| {
+            op(
+                "dq",
+                "DequantizeLinear",
+                vec![int8("q"), scale.clone(), zero_point.clone()],
+                var("d", &[1, 2]),
+            )
+            .set_opset(ONNX_DOMAIN, version)
+            .unwrap()
+        };
+        let graph = |version| {
+            Graph::new("g")
+                .add_input(int8("q"))
+                .unwrap()
+                .add_output(var("y", &[1, 2]))
+                .unwrap()
+                .add_operator(dequantize(version))
+                .unwrap()
+                .add_operator(op(
+                    "relu",
+                    "Relu",
+                    vec![var("d", &[1, 2])],
+                    var("y", &[1, 2]),
+                ))
+                .unwrap()
+        };
+
+        let (graph13, report) = convert_float16(graph(13), &PrecisionOptions::default()).unwrap();
+        assert_eq!(vec!["dq"], report.blocked);
+        let dq = graph13.get_operator("dq").unwrap();
+        assert_eq!(DType::Float32, dq.get_input("1").unwrap().dtype());
+        assert_eq!(DType::Float32, dq.get_output("0").unwrap().dtype());
+        // opset 19起scale可以是float16
+        let (_, report) = convert_float16(graph(19), &PrecisionOptions::default()).unwrap();
+        assert!(report.converted.contains(&String::from("dq")));
+    }
+}
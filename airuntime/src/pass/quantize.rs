@@ -0,0 +1,944 @@
+//! int8训练后量化
+//!
+//! 按校准数据统计激活值的范围，把浮点图中支持量化的算子改为int8计算。量化后的张量带有`Quantization`参数，
+//! 与TFLite量化模型的表示一致：权重和偏置为量化后的常量，图的输入输出保持浮点，
+//! 在量化区域的边界插入QuantizeLinear/DequantizeLinear。
+//!
+//! 激活值由调用方在后端上运行[`instrument`]后的图得到，逐个交给[`Calibrator`]统计。
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use log::*;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::{DType, Quantization, Tensor, Type};
+
+use super::Boundary;
+use crate::schema::{normalize_domain, ONNX_DOMAIN};
+
+/// 直方图的桶数
+const BINS: usize = 2048;
+/// int8正数部分的量化级数，用于熵校准
+const LEVELS: usize = 128;
+
+/// 输入输出都可以量化的算子
+const QUANTIZABLE: &[&str] = &[
+    "Add",
+    "AveragePool",
+    "Clip",
+    "Concat",
+    "Conv",
+    "Flatten",
+    "Gemm",
+    "GlobalAveragePool",
+    "HardSwish",
+    "LeakyRelu",
+    "MatMul",
+    "MaxPool",
+    "Mul",
+    "Pad",
+    "ReduceMean",
+    "Relu",
+    "Reshape",
+    "Resize",
+    "Sigmoid",
+    "Softmax",
+    "Squeeze",
+    "Sub",
+    "Tanh",
+    "Transpose",
+    "Unsqueeze",
+];
+/// 只搬移数据的算子，输出沿用输入的量化参数
+const PASS_THROUGH: &[&str] = &[
+    "Flatten",
+    "MaxPool",
+    "Pad",
+    "Reshape",
+    "Resize",
+    "Squeeze",
+    "Transpose",
+    "Unsqueeze",
+];
+
+/// 激活值范围的统计方法
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Observer {
+    /// 最小值和最大值
+    #[default]
+    MinMax,
+    /// 绝对值的百分位数，如99.99
+    Percentile(f32),
+    /// 量化前后分布的KL散度最小的截断值
+    Entropy,
+}
+
+/// 量化方式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// 零点为0，范围按绝对值的最大值
+    Symmetric,
+    /// 范围按最小值和最大值，零点可以不为0
+    Asymmetric,
+}
+
+/// 量化选项
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantizeOptions {
+    /// 激活值的量化方式
+    pub activation: Mode,
+    /// 权重的量化方式
+    pub weight: Mode,
+    /// 权重按输出通道量化
+    pub per_channel: bool,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self {
+            activation: Mode::Asymmetric,
+            weight: Mode::Symmetric,
+            per_channel: true,
+        }
+    }
+}
+
+/// 量化的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quantized {
+    /// 量化后的算子
+    pub ops: Vec<String>,
+    /// 有浮点激活值但没有量化的算子
+    pub skipped: Vec<String>,
+    /// 插入的QuantizeLinear和DequantizeLinear算子
+    pub boundaries: Vec<String>,
+}
+
+/// 一个张量的统计
+#[derive(Clone, Debug)]
+struct Stats {
+    min: f32,
+    max: f32,
+    /// 绝对值的直方图，覆盖`[0, width * BINS)`
+    hist: Vec<u64>,
+    width: f32,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            min: f32::MAX,
+            max: f32::MIN,
+            hist: vec![],
+            width: 0.0,
+        }
+    }
+
+    fn add(&mut self, values: &[f32], histogram: bool) {
+        for v in values.iter().filter(|v| v.is_finite()) {
+            self.min = self.min.min(*v);
+            self.max = self.max.max(*v);
+        }
+        if !histogram || self.min > self.max {
+            return;
+        }
+        let max_abs = self.min.abs().max(self.max.abs());
+        if self.hist.is_empty() {
+            self.hist = vec![0; BINS];
+        }
+        // 范围变大时按桶的中心合并到新的桶中
+        if max_abs >= self.width * BINS as f32 {
+            let width = (max_abs / BINS as f32).max(f32::MIN_POSITIVE) * (1.0 + f32::EPSILON);
+            let mut hist = vec![0; BINS];
+            for (i, c) in self.hist.iter().enumerate().filter(|(_, c)| **c > 0) {
+                let center = (i as f32 + 0.5) * self.width;
+                hist[((center / width) as usize).min(BINS - 1)] += c;
+            }
+            self.hist = hist;
+            self.width = width;
+        }
+        for v in values.iter().filter(|v| v.is_finite()) {
+            self.hist[((v.abs() / self.width) as usize).min(BINS - 1)] += 1;
+        }
+    }
+
+    /// 绝对值的截断值
+    fn threshold(&self, observer: Observer) -> f32 {
+        let total: u64 = self.hist.iter().sum();
+        if total == 0 {
+            return self.min.abs().max(self.max.abs());
+        }
+        let bins = match observer {
+            Observer::MinMax => BINS,
+            Observer::Percentile(p) => {
+                let target = (total as f64 * (p as f64 / 100.0).clamp(0.0, 1.0)).ceil() as u64;
+                let mut sum = 0;
+                self.hist
+                    .iter()
+                    .position(|c| {
+                        sum += c;
+                        sum >= target
+                    })
+                    .map_or(BINS, |i| i + 1)
+            }
+            Observer::Entropy => entropy_bins(&self.hist),
+        };
+        bins as f32 * self.width
+    }
+}
+
+/// KL散度最小的截断桶数
+fn entropy_bins(hist: &[u64]) -> usize {
+    let mut best = (f64::MAX, hist.len());
+    for i in LEVELS..=hist.len() {
+        // 截断的部分计入最后一个桶
+        let mut p: Vec<f64> = hist[..i].iter().map(|c| *c as f64).collect();
+        p[i - 1] += hist[i..].iter().sum::<u64>() as f64;
+        // 合并为LEVELS个桶后按非零桶平均展开
+        let mut q = vec![0.0; i];
+        for j in 0..LEVELS {
+            let start = j * i / LEVELS;
+            let end = ((j + 1) * i / LEVELS).max(start + 1);
+            let chunk = &hist[start..end];
+            let nonzero = chunk.iter().filter(|c| **c > 0).count();
+            if nonzero == 0 {
+                continue;
+            }
+            let avg = chunk.iter().sum::<u64>() as f64 / nonzero as f64;
+            for k in start..end {
+                if hist[k] > 0 {
+                    q[k] = avg;
+                }
+            }
+        }
+        let (p_sum, q_sum) = (p.iter().sum::<f64>(), q.iter().sum::<f64>());
+        if p_sum == 0.0 || q_sum == 0.0 {
+            continue;
+        }
+        let kl: f64 = p
+            .iter()
+            .zip(q.iter())
+            .filter(|(p, _)| **p > 0.0)
+            .map(|(p, q)| {
+                let (p, q) = (p / p_sum, (q / q_sum).max(1e-10));
+                p * (p / q).ln()
+            })
+            .sum();
+        if kl < best.0 {
+            best = (kl, i);
+        }
+    }
+    best.1
+}
+
+/// 按校准数据统计激活值的范围
+#[derive(Clone, Debug)]
+pub struct Calibrator {
+    observer: Observer,
+    stats: HashMap<String, Stats>,
+}
+
+impl Calibrator {
+    pub fn new(observer: Observer) -> Self {
+        Self {
+            observer,
+            stats: HashMap::new(),
+        }
+    }
+
+    /// 统计一个float32张量，按张量名累计
+    pub fn observe(&mut self, tensor: &Tensor) -> Result<()> {
+        if tensor.dtype() != DType::Float32 {
+            bail!(
+                "tensor {} is {}, only float32 can be calibrated",
+                tensor.name(),
+                tensor.dtype()
+            );
+        }
+        let values = tensor.to_vec::<f32>()?;
+        self.observe_values(tensor.name(), &values);
+        Ok(())
+    }
+
+    pub fn observe_values(&mut self, name: &str, values: &[f32]) {
+        let histogram = self.observer != Observer::MinMax;
+        self.stats
+            .entry(String::from(name))
+            .or_insert_with(Stats::new)
+            .add(values, histogram);
+    }
+
+    /// 各张量的范围`(min, max)`，截断值只限制超出的一侧
+    pub fn ranges(&self) -> HashMap<String, (f32, f32)> {
+        self.stats
+            .iter()
+            .filter(|(_, s)| s.min <= s.max)
+            .map(|(name, s)| {
+                let range = match self.observer {
+                    Observer::MinMax => (s.min, s.max),
+                    observer => {
+                        let t = s.threshold(observer);
+                        (s.min.max(-t), s.max.min(t))
+                    }
+                };
+                (name.clone(), range)
+            })
+            .collect()
+    }
+}
+
+/// 把所有浮点中间张量加入图输出，运行后的输出用于校准
+pub fn instrument(graph: &Graph) -> Result<Graph> {
+    let mut graph = graph.clone();
+    let mut names: HashSet<String> = graph.outputs().iter().map(|t| t.name().clone()).collect();
+    let mut extra: Vec<Tensor> = graph
+        .operators()
+        .iter()
+        .flat_map(|op| op.outputs().values())
+        .filter(|t| t.dtype() == DType::Float32 && t.r#type() != Type::Constant)
+        .filter(|t| names.insert(t.name().clone()))
+        .cloned()
+        .collect();
+    extra.sort_by(|a, b| a.name().cmp(b.name()));
+    for t in extra {
+        graph = graph.add_output(t)?;
+    }
+    Ok(graph)
+}
+
+impl Mode {
+    /// 范围`[min, max]`对应的int8量化参数，范围总是包含0
+    fn params(self, min: f32, max: f32) -> (f32, i64) {
+        let (min, max) = (min.min(0.0), max.max(0.0));
+        match self {
+            Mode::Symmetric => {
+                let scale = min.abs().max(max) / 127.0;
+                (if scale > 0.0 { scale } else { 1.0 }, 0)
+            }
+            Mode::Asymmetric => {
+                let scale = (max - min) / 255.0;
+                let scale = if scale > 0.0 { scale } else { 1.0 };
+                let zero_point = (-128.0 - min / scale).round().clamp(-128.0, 127.0);
+                (scale, zero_point as i64)
+            }
+        }
+    }
+}
+
+/// 用范围`ranges`把图量化为int8，范围的key为张量名
+///
+/// 没有范围的激活值所在的算子保持浮点
+pub fn quantize(
+    mut graph: Graph,
+    ranges: &HashMap<String, (f32, f32)>,
+    opts: &QuantizeOptions,
+) -> Result<(Graph, Quantized)> {
+    let keep = super::kept_tensors(&graph);
+    let order = super::sorted_operators(&graph)?;
+    let mut report = Quantized::default();
+
+    // 按拓扑序确定量化的算子，直通算子的输出参数依赖输入
+    let mut params: HashMap<String, Quantization> = HashMap::new();
+    let mut quantized = HashMap::new();
+    for name in order.iter() {
+        let op = graph.get_operator(name).unwrap();
+        let activation = |t: &Tensor| -> Option<Quantization> {
+            params.get(t.name()).cloned().or_else(|| {
+                let (min, max) = ranges.get(t.name())?;
+                let (scale, zero_point) = opts.activation.params(*min, *max);
+                Some(Quantization::per_tensor(scale, zero_point))
+            })
+        };
+        match quantize_op(op, &activation, opts)? {
+            Some((new, outputs)) => {
+                params.extend(outputs);
+                quantized.insert(name.clone(), new);
+            }
+            None => {
+                let float = op
+                    .inputs()
+                    .values()
+                    .chain(op.outputs().values())
+                    .any(is_activation);
+                if float {
+                    report.skipped.push(name.clone());
+                }
+            }
+        }
+    }
+    if quantized.is_empty() {
+        return Ok((graph, report));
+    }
+
+    // 量化算子输出的张量，图输出改名后在边界反量化为原来的名字
+    let mut edges = Edges {
+        boundary: Boundary::new(&keep, "quantized", "dequantized"),
+        quantize_version: super::since_version(&graph, "QuantizeLinear"),
+        dequantize_version: super::since_version(&graph, "DequantizeLinear"),
+    };
+    for name in quantized.keys() {
+        for t in graph.get_operator(name).unwrap().outputs().values() {
+            if let Some(q) = params.get(t.name()) {
+                edges
+                    .boundary
+                    .produce(t, |name| Ok(quantized_var(t, name, q.clone())))?;
+            }
+        }
+    }
+    for name in order.iter() {
+        let orig = graph.get_operator(name).unwrap().clone();
+        let is_quantized = quantized.contains_key(name);
+        let mut op = quantized.remove(name).unwrap_or_else(|| orig.clone());
+        for (tag, t) in orig.inputs() {
+            if !is_activation(t) {
+                continue;
+            }
+            let input = if !is_quantized {
+                edges.float_input(t)?
+            } else if op
+                .get_input(tag)
+                .is_some_and(|i| i.dtype() == DType::Float32)
+            {
+                // 不是数据的浮点输入，如Resize的scales
+                continue;
+            } else {
+                let q = op.get_input(tag).unwrap().quantization().cloned().unwrap();
+                edges.quantized_input(t, q)?
+            };
+            op.inputs_mut().insert(tag.clone(), input);
+        }
+        if is_quantized {
+            for (tag, t) in orig.outputs() {
+                if let Some(p) = edges.boundary.produced(t.name()) {
+                    op.outputs_mut().insert(tag.clone(), p.clone());
+                }
+            }
+            report.ops.push(name.clone());
+        }
+        *graph.get_operator_mut(name).unwrap() = op;
+    }
+    let version = edges.dequantize_version;
+    let boundaries = edges
+        .boundary
+        .finish(|input, output| boundary("DequantizeLinear", version, input, output))?;
+
+    report.boundaries = boundaries.iter().map(|op| op.name().clone()).collect();
+    for op in boundaries {
+        graph = graph.add_operator(op)?;
+    }
+    info!(
+        "graph {} quantized {} ops, {} ops kept float, inserted {} quantize/dequantize ops",
+        graph.name(),
+        report.ops.len(),
+        report.skipped.len(),
+        report.boundaries.len()
+    );
+    Ok((graph, report))
+}
+
+/// 浮点的激活值
+fn is_activation(t: &Tensor) -> bool {
+    t.dtype() == DType::Float32 && t.r#type() != Type::Constant
+}
+
+type QuantizedOp = (Operator, Vec<(String, Quantization)>);
+
+/// 量化算子的常量和输入输出，不能量化时返回None
+///
+/// 量化后的算子中激活值输入带有量化参数，由调用方替换为量化后的张量
+fn quantize_op(
+    op: &Operator,
+    activation: &dyn Fn(&Tensor) -> Option<Quantization>,
+    opts: &QuantizeOptions,
+) -> Result<Option<QuantizedOp>> {
+    let op_type = op.r#type().as_str();
+    if normalize_domain(op.domain()) != ONNX_DOMAIN || !QUANTIZABLE.contains(&op_type) {
+        return Ok(None);
+    }
+    let Some(x) = op.get_input("0").filter(|x| is_activation(x)) else {
+        return Ok(None);
+    };
+    if op_type == "Gemm"
+        && (op.attr_float_or("alpha", 1.0)? != 1.0 || op.attr_float_or("beta", 1.0)? != 1.0)
+    {
+        return Ok(None);
+    }
+    let Some(xq) = activation(x) else {
+        return Ok(None);
+    };
+
+    let mut new = op.clone();
+    let mut weight_scale = None;
+    let mut tags: Vec<&String> = op.inputs().keys().collect();
+    tags.sort();
+    for tag in tags {
+        let t = op.get_input(tag).unwrap();
+        if t.dtype() != DType::Float32 {
+            continue;
+        }
+        let is_weight = tag == "1" && matches!(op_type, "Conv" | "Gemm" | "MatMul");
+        let q = match (tag.as_str(), op_type) {
+            // 只有第一个输入是数据
+            (_, "Resize") if tag != "0" => continue,
+            ("2", "Conv" | "Gemm") => {
+                // 偏置为int32，scale为输入和权重的scale之积
+                let Some(w) = weight_scale.take() else {
+                    return Ok(None);
+                };
+                let Some(b) = quantize_bias(t, &xq, &w)? else {
+                    return Ok(None);
+                };
+                new.inputs_mut().insert(tag.clone(), b);
+                continue;
+            }
+            // 范围参数与数据相同
+            ("1" | "2", "Clip") | ("2", "Pad") => xq.clone(),
+            _ if t.r#type() == Type::Constant => {
+                let axis = if is_weight && opts.per_channel {
+                    weight_axis(op, t)?
+                } else {
+                    None
+                };
+                let mode = if is_weight {
+                    opts.weight
+                } else {
+                    opts.activation
+                };
+                let Some(q) = quantize_weight(t, axis, mode)? else {
+                    return Ok(None);
+                };
+                if is_weight {
+                    weight_scale = q.quantization().cloned();
+                }
+                new.inputs_mut().insert(tag.clone(), q);
+                continue;
+            }
+            _ => match activation(t) {
+                Some(q) => q,
+                None => return Ok(None),
+            },
+        };
+        let t = if t.r#type() == Type::Constant {
+            let Some(c) = quantize_with(t, &q)? else {
+                return Ok(None);
+            };
+            c
+        } else {
+            quantized_var(t, t.name(), q)
+        };
+        new.inputs_mut().insert(tag.clone(), t);
+    }
+
+    let mut outputs = vec![];
+    for (tag, t) in op.outputs() {
+        if !is_activation(t) {
+            continue;
+        }
+        let q = if PASS_THROUGH.contains(&op_type) {
+            Some(xq.clone())
+        } else {
+            match op_type {
+                // 输出范围固定
+                "Sigmoid" | "Softmax" => Some(opts.activation.params(0.0, 1.0)),
+                "Tanh" => Some(opts.activation.params(-1.0, 1.0)),
+                _ => None,
+            }
+            .map(|(scale, zero_point)| Quantization::per_tensor(scale, zero_point))
+            .or_else(|| activation(t))
+        };
+        let Some(q) = q else {
+            return Ok(None);
+        };
+        new.outputs_mut()
+            .insert(tag.clone(), quantized_var(t, t.name(), q.clone()));
+        outputs.push((t.name().clone(), q));
+    }
+    Ok(Some((new, outputs)))
+}
+
+/// 权重的输出通道维
+fn weight_axis(op: &Operator, w: &Tensor) -> Result<Option<usize>> {
+    let rank = w.shape().dim();
+    let axis = match op.r#type().as_str() {
+        "Conv" if rank >= 3 => {
+            // TFLite的深度卷积权重为[1, H, W, C * M]
+            let dims = w.shape().data();
+            if op.attr_int_or("group", 1)? > 1 && dims[0] == 1 && dims[rank - 1] > 1 {
+                Some(rank - 1)
+            } else {
+                Some(0)
+            }
+        }
+        "Gemm" if rank == 2 => Some(if op.attr_int_or("transB", 0)? != 0 {
+            0
+        } else {
+            1
+        }),
+        "MatMul" if rank == 2 => Some(1),
+        _ => None,
+    };
+    Ok(axis)
+}
+
+/// 按`axis`维逐通道或整体量化常量
+fn quantize_weight(t: &Tensor, axis: Option<usize>, mode: Mode) -> Result<Option<Tensor>> {
+    let Some(values) = float_values(t) else {
+        return Ok(None);
+    };
+    let dims = t.shape().data();
+    let (channels, inner) = match axis {
+        Some(a) => (
+            dims[a] as usize,
+            dims[a + 1..].iter().product::<u32>() as usize,
+        ),
+        None => (1, values.len().max(1)),
+    };
+    let channel = |i: usize| (i / inner) % channels;
+    let mut ranges = vec![(0.0_f32, 0.0_f32); channels];
+    for (i, v) in values.iter().enumerate() {
+        let r = &mut ranges[channel(i)];
+        *r = (r.0.min(*v), r.1.max(*v));
+    }
+    let params: Vec<(f32, i64)> = ranges
+        .iter()
+        .map(|(min, max)| mode.params(*min, *max))
+        .collect();
+    // 对称量化的权重不使用-128
+    let low = if mode == Mode::Symmetric { -127 } else { -128 };
+    let data: Vec<i8> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let (scale, zero_point) = params[channel(i)];
+            ((v / scale).round() as i64 + zero_point).clamp(low, 127) as i8
+        })
+        .collect();
+    let q = Quantization {
+        scale: params.iter().map(|p| p.0).collect(),
+        zero_point: params.iter().map(|p| p.1).collect(),
+        axis: axis.unwrap_or(0) as i32,
+    };
+    Ok(Some(constant(t, data, q)?))
+}
+
+/// 按给定的参数量化常量
+fn quantize_with(t: &Tensor, q: &Quantization) -> Result<Option<Tensor>> {
+    let Some(values) = float_values(t) else {
+        return Ok(None);
+    };
+    let (scale, zero_point) = (q.scale[0], q.zero_point[0]);
+    let data = values
+        .iter()
+        .map(|v| ((v / scale).round() as i64 + zero_point).clamp(-128, 127) as i8)
+        .collect();
+    Ok(Some(constant(t, data, q.clone())?))
+}
+
+/// 偏置量化为int32，scale为输入和权重的scale之积，零点为0
+fn quantize_bias(t: &Tensor, x: &Quantization, w: &Quantization) -> Result<Option<Tensor>> {
+    let Some(values) = float_values(t) else {
+        return Ok(None);
+    };
+    if t.shape().dim() != 1 || (w.is_per_channel() && w.scale.len() != values.len()) {
+        return Ok(None);
+    }
+    let scale: Vec<f32> = w.scale.iter().map(|s| s * x.scale[0]).collect();
+    let data: Vec<i32> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let s = scale[if w.is_per_channel() { i } else { 0 }];
+            (v / s).round().clamp(i32::MIN as f32, i32::MAX as f32) as i32
+        })
+        .collect();
+    let q = Quantization {
+        zero_point: vec![0; scale.len()],
+        scale,
+        axis: 0,
+    };
+    let b = Tensor::from_vec(t.name(), t.shape().data(), data)?
+        .with_type(Type::Constant)
+        .with_format(t.format())
+        .with_quantization(q);
+    Ok(Some(b))
+}
+
+fn float_values(t: &Tensor) -> Option<Vec<f32>> {
+    if t.is_sparse() || t.quantization().is_some() {
+        return None;
+    }
+    t.to_vec::<f32>().ok()
+}
+
+fn constant(t: &Tensor, data: Vec<i8>, q: Quantization) -> Result<Tensor> {
+    Ok(Tensor::from_vec(t.name(), t.shape().data(), data)?
+        .with_type(Type::Constant)
+        .with_format(t.format())
+        .with_quantization(q))
+}
+
+/// int8的变量，形状和布局与`t`相同
+fn quantized_var(t: &Tensor, name: &str, q: Quantization) -> Tensor {
+    let mut new =
+        Tensor::new_with_shape(name, t.shape().data(), t.format(), DType::Int8, t.r#type())
+            .with_quantization(q);
+    if t.is_dynamic() {
+        new = new.with_dim_params(
+            (0..t.shape().dim())
+                .map(|i| t.dim_param(i).map(String::from))
+                .collect(),
+        );
+    }
+    new
+}
+
+/// 插入的QuantizeLinear/DequantizeLinear和转换后的张量
+struct Edges<'a> {
+    boundary: Boundary<'a>,
+    quantize_version: i64,
+    dequantize_version: i64,
+}
+
+impl Edges<'_> {
+    /// 量化算子的输入
+    fn quantized_input(&mut self, t: &Tensor, q: Quantization) -> Result<Tensor> {
+        let version = self.quantize_version;
+        self.boundary.target_input(t, |name| {
+            let output = quantized_var(t, name, q);
+            let op = boundary("QuantizeLinear", version, t, &output)?;
+            Ok((output, op))
+        })
+    }
+
+    /// 浮点算子的输入
+    fn float_input(&mut self, t: &Tensor) -> Result<Tensor> {
+        let version = self.dequantize_version;
+        self.boundary.source_input(t, |p, name| {
+            let output = Tensor::new_with_shape(
+                name,
+                t.shape().data(),
+                t.format(),
+                DType::Float32,
+                t.r#type(),
+            );
+            let op = boundary("DequantizeLinear", version, p, &output)?;
+            Ok((output, op))
+        })
+    }
+}
+
+/// 量化或反量化算子，参数取自量化的一侧
+fn boundary(op_type: &str, version: i64, input: &Tensor, output: &Tensor) -> Result<Operator> {
+    let quantized = if op_type == "QuantizeLinear" {
+        output
+    } else {
+        input
+    };
+    let q = quantized.quantization().cloned().unwrap_or_default();
+    let name = format!("{}_{}", output.name(), op_type);
+    let shape = [q.scale.len() as u32];
+    let scale = Tensor::from_vec(&format!("{}_scale", name), &shape, q.scale.clone())?
+        .with_type(Type::Constant);
+    let zero_point = q.zero_point.iter().map(|z| *z as i8).collect();
+    let zero_point = Tensor::from_vec(&format!("{}_zero_point", name), &shape, zero_point)?
+        .with_type(Type::Constant);
+    Operator::new(&name, op_type)
+        .add_input("0", input.clone())?
+        .add_input("1", scale)?
+        .add_input("2", zero_point)?
+        .add_output("0", output.clone())?
+        .set_opset(ONNX_DOMAIN, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{op, producer, var};
+
+    #[test]
+    fn calibrator_works() {
+        let values: Vec<f32> = (0..1000).map(|i| i as f32 / 100.0 - 2.0).collect();
+        let mut minmax = Calibrator::new(Observer::MinMax);
+        let x = Tensor::from_vec("x", &[1000], values.clone()).unwrap();
+        minmax.observe(&x).unwrap();
+        minmax.observe_values("x", &[-3.0]);
+        assert_eq!(Some(&(-3.0, 7.99)), minmax.ranges().get("x"));
+        assert!(minmax.observe(&Tensor::zeros::<i64>("i", &[2])).is_err());
+
+        let mut percentile = Calibrator::new(Observer::Percentile(90.0));
+        percentile.observe_values("x", &values);
+        let (min, max) = percentile.ranges()["x"];
+        assert_eq!(-2.0, min);
+        assert!((max - 7.0).abs() < 0.05, "{}", max);
+
+        // 大部分值很小，少量离群值
+        let mut values: Vec<f32> = (0..10000).map(|i| (i % 100) as f32 / 100.0).collect();
+        values.extend([50.0, -50.0]);
+        let mut entropy = Calibrator::new(Observer::Entropy);
+        entropy.observe_values("x", &values[..5000]);
+        entropy.observe_values("x", &values[5000..]);
+        let (min, max) = entropy.ranges()["x"];
+        assert!((1.0..10.0).contains(&max), "{}", max);
+        assert_eq!(-max, min);
+    }
+
+    #[test]
+    fn mode_params_works() {
+        assert_eq!((4.0 / 255.0, -64), Mode::Asymmetric.params(-1.0, 3.0));
+        assert_eq!((3.0 / 127.0, 0), Mode::Symmetric.params(-1.0, 3.0));
+        // 范围总是包含0
+        assert_eq!((2.0 / 255.0, -128), Mode::Asymmetric.params(1.0, 2.0));
+        assert_eq!((1.0, 0), Mode::Symmetric.params(0.0, 0.0));
+    }
+
+    #[test]
+    fn quantize_works() {
+        let weight = Tensor::from_vec(
+            "w",
+            &[3, 2, 1, 1],
+            vec![0.5_f32, -0.25, 1.0, 2.0, -0.1, 0.05],
+        )
+        .unwrap()
+        .with_type(Type::Constant);
+        let bias = Tensor::from_vec("b", &[3], vec![0.1_f32, -0.2, 0.3])
+            .unwrap()
+            .with_type(Type::Constant);
+        let graph = Graph::new("g")
+            .add_input(var("x", &[1, 2, 3, 3]))
+            .unwrap()
+            .add_output(var("y", &[1, 3, 3, 3]))
+            .unwrap()
+            .add_operator(op(
+                "conv",
+                "Conv",
+                vec![var("x", &[1, 2, 3, 3]), weight, bias],
+                var("c", &[1, 3, 3, 3]),
+            ))
+            .unwrap()
+            .add_operator(op(
+                "relu",
+                "Relu",
+                vec![var("c", &[1, 3, 3, 3])],
+                var("r", &[1, 3, 3, 3]),
+            ))
+            .unwrap()
+            // 不支持量化的算子
+            .add_operator(op(
+                "erf",
+                "Erf",
+                vec![var("r", &[1, 3, 3, 3])],
+                var("e", &[1, 3, 3, 3]),
+            ))
+            .unwrap()
+            .add_operator(op(
+                "add",
+                "Add",
+                vec![var("e", &[1, 3, 3, 3]), var("r", &[1, 3, 3, 3])],
+                var("y", &[1, 3, 3, 3]),
+            ))
+            .unwrap();
+
+        let instrumented = instrument(&graph).unwrap();
+        let mut outputs: Vec<&str> = instrumented
+            .outputs()
+            .iter()
+            .map(|t| t.name().as_str())
+            .collect();
+        outputs.sort();
+        assert_eq!(vec!["c", "e", "r", "y"], outputs);
+
+        let ranges = HashMap::from([
+            (String::from("x"), (-1.0, 3.0)),
+            (String::from("c"), (-4.0, 4.0)),
+            (String::from("r"), (0.0, 4.0)),
+            (String::from("e"), (0.0, 1.0)),
+            (String::from("y"), (0.0, 5.0)),
+        ]);
+        let (graph, report) = quantize(graph, &ranges, &QuantizeOptions::default()).unwrap();
+        let mut ops = report.ops.clone();
+        ops.sort();
+        assert_eq!(vec!["add", "conv", "relu"], ops);
+        assert_eq!(vec!["erf"], report.skipped);
+
+        let conv = graph.get_operator("conv").unwrap();
+        let x = conv.get_input("0").unwrap();
+        assert_eq!("x_quantized", x.name());
+        assert_eq!(DType::Int8, x.dtype());
+        let xq = x.quantization().unwrap().clone();
+        assert_eq!(vec![-64], xq.zero_point);
+        assert_eq!("QuantizeLinear", producer(&graph, "x_quantized").r#type());
+
+        // 权重逐输出通道对称量化
+        let w = conv.get_input("1").unwrap();
+        let wq = w.quantization().unwrap();
+        assert_eq!(DType::Int8, w.dtype());
+        assert_eq!(vec![0.5 / 127.0, 2.0 / 127.0, 0.1 / 127.0], wq.scale);
+        assert_eq!(vec![0, 0, 0], wq.zero_point);
+        let expected = [0.5, -0.25, 1.0, 2.0, -0.1, 0.05];
+        for (i, q) in w.to_vec::<i8>().unwrap().iter().enumerate() {
+            let v = *q as f32 * wq.scale[i / 2];
+            assert!((v - expected[i]).abs() <= wq.scale[i / 2] / 2.0);
+        }
+        let b = conv.get_input("2").unwrap();
+        assert_eq!(DType::Int32, b.dtype());
+        let bq = b.quantization().unwrap();
+        assert_eq!(xq.scale[0] * wq.scale[1], bq.scale[1]);
+        assert_eq!(
+            (-0.2 / bq.scale[1]).round() as i32,
+            b.to_vec::<i32>().unwrap()[1]
+        );
+
+        // 浮点算子前反量化，后面的量化算子重新量化
+        let erf = graph.get_operator("erf").unwrap();
+        assert_eq!("r_dequantized", erf.get_input("0").unwrap().name());
+        let add = graph.get_operator("add").unwrap();
+        assert_eq!("e_quantized", add.get_input("0").unwrap().name());
+        assert_eq!("r", add.get_input("1").unwrap().name());
+        assert_eq!(DType::Int8, add.get_input("1").unwrap().dtype());
+        // 图输出保持浮点
+        assert_eq!("y_quantized", add.get_output("0").unwrap().name());
+        let out = producer(&graph, "y");
+        assert_eq!("DequantizeLinear", out.r#type());
+        assert_eq!(DType::Float32, out.get_output("0").unwrap().dtype());
+        assert_eq!(4, report.boundaries.len());
+    }
+
+    #[test]
+    fn missing_range_kept_float() {
+        let graph = Graph::new("g")
+            .add_input(var("x", &[1, 4]))
+            .unwrap()
+            .add_output(var("y", &[1, 4]))
+            .unwrap()
+            .add_operator(op(
+                "relu",
+                "Relu",
+                vec![var("x", &[1, 4])],
+                var("r", &[1, 4]),
+            ))
+            .unwrap()
+            .add_operator(op(
+                "sigmoid",
+                "Sigmoid",
+                vec![var("r", &[1, 4])],
+                var("y", &[1, 4]),
+            ))
+            .unwrap();
+        let ranges = HashMap::from([(String::from("r"), (0.0, 6.0))]);
+        let opts = QuantizeOptions {
+            activation: Mode::Symmetric,
+            ..Default::default()
+        };
+        let (graph, report) = quantize(graph, &ranges, &opts).unwrap();
+        // x没有范围，relu保持浮点，sigmoid的输出范围固定
+        assert_eq!(vec!["relu"], report.skipped);
+        assert_eq!(vec!["sigmoid"], report.ops);
+        let sigmoid = graph.get_operator("sigmoid").unwrap();
+        assert_eq!(
+            Some(&Quantization::per_tensor(1.0 / 127.0, 0)),
+            sigmoid.get_output("0").unwrap().quantization()
+        );
+        assert_eq!("r_quantized", sigmoid.get_input("0").unwrap().name());
+    }
+}
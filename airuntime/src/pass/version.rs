@@ -0,0 +1,874 @@
+//! 算子集版本转换
+//!
+//! 把计算图从导入的ai.onnx算子集升级到目标版本。新版本中改为输入的属性转换为常量输入，
+//! 被替换的算子换成新的算子，其余算子按目标版本的定义更新版本和默认属性。
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use model::attribute::Attribute;
+use model::graph::Graph;
+use model::half::f16;
+use model::operator::Operator;
+use model::tensor::{DType, Tensor, Type};
+
+use super::cleanup::subgraph_inputs;
+use crate::schema::{normalize_domain, OpSchema, Registry, ONNX_DOMAIN};
+
+/// `Config.ops`中目标算子集版本的key，设置后编译前把模型转换到该版本
+pub const TARGET_OPSET_KEY: &str = "target_opset";
+
+/// 从`Config.ops`读取目标算子集版本，没有配置时为None
+pub fn target_opset(ops: &HashMap<String, String>) -> Result<Option<i64>> {
+    match ops.get(TARGET_OPSET_KEY) {
+        Some(v) => Ok(Some(v.trim().parse::<i64>().map_err(|e| {
+            anyhow!("{} {} is not an opset version, {}", TARGET_OPSET_KEY, v, e)
+        })?)),
+        None => Ok(None),
+    }
+}
+
+/// 改为输入的属性：算子类型，属性名，输入位置
+const ATTRIBUTE_INPUTS: &[(&str, &str, usize)] = &[
+    ("Clip", "min", 1),
+    ("Clip", "max", 2),
+    ("Pad", "paddings", 1),
+    ("Pad", "pads", 1),
+    ("Pad", "value", 2),
+    ("ReduceL1", "axes", 1),
+    ("ReduceL2", "axes", 1),
+    ("ReduceLogSum", "axes", 1),
+    ("ReduceLogSumExp", "axes", 1),
+    ("ReduceMax", "axes", 1),
+    ("ReduceMean", "axes", 1),
+    ("ReduceMin", "axes", 1),
+    ("ReduceProd", "axes", 1),
+    ("ReduceSum", "axes", 1),
+    ("ReduceSumSquare", "axes", 1),
+    ("Reshape", "shape", 1),
+    ("Slice", "starts", 1),
+    ("Slice", "ends", 2),
+    ("Slice", "axes", 3),
+    ("Split", "split", 1),
+    ("Squeeze", "axes", 1),
+    ("TopK", "k", 1),
+    ("Unsqueeze", "axes", 1),
+    ("Upsample", "scales", 1),
+];
+
+/// 新版本去掉的属性中推理时可以忽略的：算子类型，属性名，可以忽略的值
+///
+/// 类型为空表示所有算子，值为None表示任意值。没有axis时旧的单向广播与多向广播结果相同
+const DROPPED: &[(&str, &str, Option<i64>)] = &[
+    ("", "consumed_inputs", None),
+    ("Add", "broadcast", None),
+    ("And", "broadcast", None),
+    ("BatchNormalization", "is_test", Some(1)),
+    ("BatchNormalization", "spatial", Some(1)),
+    ("Div", "broadcast", None),
+    ("Dropout", "is_test", Some(1)),
+    ("Dropout", "ratio", None),
+    ("Equal", "broadcast", None),
+    ("Gemm", "broadcast", None),
+    ("Greater", "broadcast", None),
+    ("Less", "broadcast", None),
+    ("Mul", "broadcast", None),
+    ("Or", "broadcast", None),
+    ("Pow", "broadcast", None),
+    ("Sub", "broadcast", None),
+    ("Xor", "broadcast", None),
+];
+
+/// 被替换的算子：旧类型，新类型，替换的版本
+const REPLACED: &[(&str, &str, i64)] = &[
+    ("Scatter", "ScatterElements", 11),
+    ("Upsample", "Resize", 10),
+];
+
+/// 13版本前按axis展开为二维计算的算子
+const COERCED_2D: &[&str] = &["Hardmax", "LogSoftmax", "Softmax"];
+
+/// 版本转换的结果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Converted {
+    /// 属性改为输入、被替换或去掉了输出的算子
+    pub migrated: Vec<String>,
+    /// 插入的算子
+    pub inserted: Vec<String>,
+    /// 目标版本中没有定义的算子，保持不变
+    pub unknown: Vec<String>,
+}
+
+/// 把计算图的ai.onnx算子集升级到`target`版本，子图一起转换
+pub fn convert_version(graph: Graph, target: i64) -> Result<(Graph, Converted)> {
+    let source = graph
+        .opset_version(ONNX_DOMAIN)
+        .ok_or_else(|| anyhow!("graph {} has no {} opset import", graph.name(), ONNX_DOMAIN))?;
+    if target < source {
+        bail!(
+            "cannot convert graph {} from opset {} down to {}",
+            graph.name(),
+            source,
+            target
+        );
+    }
+    match Registry::global().max_version(ONNX_DOMAIN) {
+        Some(max) if target <= max => {}
+        max => bail!(
+            "target opset {} is newer than the known version {:?}",
+            target,
+            max
+        ),
+    }
+    let mut report = Converted::default();
+    if target == source {
+        return Ok((graph, report));
+    }
+
+    let graph = convert_graph(graph, source, target, &mut report)?;
+    info!(
+        "graph {} converted from opset {} to {}, migrated {} ops, inserted {} ops, {} unknown ops",
+        graph.name(),
+        source,
+        target,
+        report.migrated.len(),
+        report.inserted.len(),
+        report.unknown.len()
+    );
+    Ok((graph, report))
+}
+
+fn convert_graph(
+    mut graph: Graph,
+    source: i64,
+    target: i64,
+    report: &mut Converted,
+) -> Result<Graph> {
+    // 子图按自己导入的版本转换
+    let source = graph.opset_version(ONNX_DOMAIN).unwrap_or(source);
+    let mut used: HashSet<String> = graph.outputs().iter().map(|t| t.name().clone()).collect();
+    for op in graph.operators() {
+        used.extend(op.inputs().values().map(|t| t.name().clone()));
+        subgraph_inputs(op, &mut used);
+    }
+    let mut names = used.clone();
+    names.extend(graph.inputs().iter().map(|t| t.name().clone()));
+    for op in graph.operators() {
+        names.extend(op.outputs().values().map(|t| t.name().clone()));
+    }
+    let converter = Converter {
+        source,
+        target,
+        used: &used,
+        names: RefCell::new(names),
+    };
+
+    let mut inserted = vec![];
+    for name in super::sorted_operators(&graph)? {
+        let op = graph.get_operator(&name).unwrap();
+        if normalize_domain(op.domain()) != ONNX_DOMAIN {
+            continue;
+        }
+        let Some(rewrite) = converter.convert_op(op, report)? else {
+            warn!(
+                "op {} {} has no definition in opset {}, keep it unchanged",
+                name,
+                op.r#type(),
+                target
+            );
+            report.unknown.push(name);
+            continue;
+        };
+        if rewrite.migrated {
+            report.migrated.push(name.clone());
+        }
+        let mut ops = rewrite.ops.into_iter();
+        *graph.get_operator_mut(&name).unwrap() = ops.next().unwrap();
+        inserted.extend(ops);
+    }
+    for op in inserted {
+        report.inserted.push(op.name().clone());
+        graph = graph.add_operator(op)?;
+    }
+    if graph.opset_version(ONNX_DOMAIN).is_some() {
+        graph = graph.add_opset_import(ONNX_DOMAIN, target)?;
+    }
+    Ok(graph)
+}
+
+/// 转换后的算子，第一个替换原来的算子
+struct Rewrite {
+    ops: Vec<Operator>,
+    migrated: bool,
+}
+
+struct Converter<'a> {
+    source: i64,
+    target: i64,
+    /// 被使用的张量，去掉的输出不能被使用
+    used: &'a HashSet<String>,
+    /// 图中已有的张量名字，插入的张量不能重名
+    names: RefCell<HashSet<String>>,
+}
+
+impl Converter<'_> {
+    fn convert_op(&self, op: &Operator, report: &mut Converted) -> Result<Option<Rewrite>> {
+        let registry = Registry::global();
+        let Some(schema) = registry.get(ONNX_DOMAIN, op.r#type(), self.target) else {
+            return Ok(None);
+        };
+        let r#type = REPLACED
+            .iter()
+            .find(|(old, _, version)| old == op.r#type() && self.target >= *version)
+            .map_or(op.r#type().as_str(), |(_, new, _)| *new);
+        let Some(new_schema) = registry.get(ONNX_DOMAIN, r#type, self.target) else {
+            return Ok(None);
+        };
+        let mut migrated = r#type != op.r#type();
+
+        let mut new = Operator::new(op.name(), r#type);
+        for (tag, t) in op.inputs() {
+            new = new.add_input(tag, t.clone())?;
+        }
+        for (tag, t) in op.outputs() {
+            new = new.add_output(tag, t.clone())?;
+        }
+        if r#type == "BatchNormalization" {
+            migrated |= self.inference_outputs(op, new_schema, &mut new)?;
+        }
+
+        let mut attributes: Vec<(&String, &Attribute)> = op.attributes().iter().collect();
+        attributes.sort_by_key(|(k, _)| *k);
+        // Upsample-1的缩放比例
+        let mut scales = [None, None];
+        for (k, v) in attributes {
+            if schema.get_attribute(k).is_some() {
+                let v = match v {
+                    Attribute::Graph(g) => Attribute::Graph(convert_graph(
+                        g.clone(),
+                        self.source,
+                        self.target,
+                        report,
+                    )?),
+                    Attribute::Graphs(gs) => Attribute::Graphs(
+                        gs.iter()
+                            .map(|g| convert_graph(g.clone(), self.source, self.target, report))
+                            .collect::<Result<_>>()?,
+                    ),
+                    v => v.clone(),
+                };
+                new = new.add_attribute(k, v)?;
+                continue;
+            }
+            migrated = true;
+            if let Some((_, _, index)) = ATTRIBUTE_INPUTS
+                .iter()
+                .find(|(t, a, _)| t == op.r#type() && a == k)
+            {
+                let tag = index.to_string();
+                if new.get_input(&tag).is_some() {
+                    bail!(
+                        "op {} already has input {} for attribute {}",
+                        op.name(),
+                        tag,
+                        k
+                    );
+                }
+                let input = attribute_input(op, &format!("{}_{}", op.name(), k), v)?;
+                new = new.add_input(&tag, input)?;
+            } else if op.r#type() == "Upsample" && (k == "height_scale" || k == "width_scale") {
+                let Attribute::Float(scale) = v else {
+                    bail!("attribute {} of op {} is not float", k, op.name());
+                };
+                scales[(k == "width_scale") as usize] = Some(*scale);
+            } else {
+                self.drop_attribute(op, k, v)?;
+            }
+        }
+        if op.r#type() == "Upsample" {
+            new = self.upsample_scales(op, schema, new, scales)?;
+        }
+
+        let mut ops = vec![];
+        if r#type == "Resize" && op.r#type() == "Upsample" {
+            new = upsample_to_resize(new, new_schema)?;
+        }
+        if COERCED_2D.contains(&r#type) && self.coerced_2d(op) {
+            let (softmax, extra) = self.softmax_axis(op, new)?;
+            new = softmax;
+            migrated |= !extra.is_empty();
+            ops.extend(extra);
+        }
+        ops.insert(0, new);
+        let ops = ops
+            .into_iter()
+            .map(|op| self.finish(op))
+            .collect::<Result<_>>()?;
+        Ok(Some(Rewrite { ops, migrated }))
+    }
+
+    /// Upsample-1的height_scale和width_scale按NCHW合并为scales，双线性插值改为linear
+    fn upsample_scales(
+        &self,
+        op: &Operator,
+        schema: &OpSchema,
+        mut new: Operator,
+        scales: [Option<f32>; 2],
+    ) -> Result<Operator> {
+        match scales {
+            [Some(h), Some(w)] => {
+                let scales = vec![1.0, 1.0, h, w];
+                // 7、8版本的scales还是属性
+                if schema.get_attribute("scales").is_some() {
+                    new = new.add_attribute("scales", Attribute::Floats(scales))?;
+                } else if new.get_input("1").is_some() {
+                    bail!("op {} already has input 1 for scales", op.name());
+                } else {
+                    let name = format!("{}_scales", op.name());
+                    let t = Tensor::from_vec(&name, &[4], scales)?.with_type(Type::Constant);
+                    new = new.add_input("1", t)?;
+                }
+            }
+            [None, None] => {}
+            _ => bail!("op {} needs both height_scale and width_scale", op.name()),
+        }
+        if matches!(new.attributes().get("mode"), Some(Attribute::String(m)) if m == b"bilinear") {
+            new = new.add_attribute("mode", Attribute::String(b"linear".to_vec()))?;
+        }
+        Ok(new)
+    }
+
+    /// 去掉目标版本中没有的属性，只允许推理时可以忽略的属性和值
+    fn drop_attribute(&self, op: &Operator, name: &str, value: &Attribute) -> Result<()> {
+        let Some((_, _, expected)) = DROPPED
+            .iter()
+            .find(|(t, a, _)| (t.is_empty() || t == op.r#type()) && *a == name)
+        else {
+            bail!(
+                "attribute {} of op {} {} is removed in opset {} and can not be converted",
+                name,
+                op.r#type(),
+                op.name(),
+                self.target
+            );
+        };
+        if let Some(expected) = expected {
+            if !matches!(value, Attribute::Int(v) if v == expected) {
+                bail!(
+                    "op {} uses {}={:?} which opset {} does not support",
+                    op.name(),
+                    name,
+                    value,
+                    self.target
+                );
+            }
+        }
+        debug!(
+            "drop attribute {} of op {}, removed in opset {}",
+            name,
+            op.name(),
+            self.target
+        );
+        Ok(())
+    }
+
+    /// 算子在源版本中按二维计算而目标版本不是
+    fn coerced_2d(&self, op: &Operator) -> bool {
+        let version = |opset| {
+            Registry::global()
+                .get(ONNX_DOMAIN, op.r#type(), opset)
+                .map_or(0, |s| s.version)
+        };
+        version(self.source) < 13 && version(self.target) >= 13
+    }
+
+    /// 推理模式的BatchNormalization只有一个输出，去掉训练用的输出
+    fn inference_outputs(
+        &self,
+        op: &Operator,
+        schema: &OpSchema,
+        new: &mut Operator,
+    ) -> Result<bool> {
+        let training =
+            matches!(op.attributes().get("training_mode"), Some(Attribute::Int(v)) if *v != 0);
+        if schema.get_attribute("training_mode").is_none() || training {
+            return Ok(false);
+        }
+        let extra: Vec<String> = new
+            .outputs()
+            .keys()
+            .filter(|tag| *tag != "0")
+            .cloned()
+            .collect();
+        for tag in extra.iter() {
+            let t = new.outputs_mut().remove(tag).unwrap();
+            if self.used.contains(t.name()) {
+                bail!(
+                    "output {} of op {} is used, only training mode has it in opset {}",
+                    t.name(),
+                    op.name(),
+                    self.target
+                );
+            }
+        }
+        Ok(!extra.is_empty())
+    }
+
+    /// 以`base`为前缀且不与图中张量重名的名字
+    fn fresh_name(&self, base: String) -> String {
+        let mut names = self.names.borrow_mut();
+        let mut name = base.clone();
+        let mut i = 0;
+        while names.contains(&name) {
+            i += 1;
+            name = format!("{}_{}", base, i);
+        }
+        names.insert(name.clone());
+        name
+    }
+
+    /// 设置旧版本的axis，不是最后一维时展开为二维计算后恢复形状
+    fn softmax_axis(&self, op: &Operator, new: Operator) -> Result<(Operator, Vec<Operator>)> {
+        let axis = match op.attributes().get("axis") {
+            Some(Attribute::Int(axis)) => *axis,
+            Some(_) => bail!("attribute axis of op {} is not int", op.name()),
+            None => 1,
+        };
+        let x = op
+            .get_input("0")
+            .ok_or_else(|| anyhow!("op {} has no input", op.name()))?;
+        let y = op
+            .get_output("0")
+            .ok_or_else(|| anyhow!("op {} has no output", op.name()))?;
+        let rank = x.shape().dim() as i64;
+        if rank > 0 && !(-rank..rank).contains(&axis) {
+            bail!(
+                "axis {} of op {} is out of range for rank {}",
+                axis,
+                op.name(),
+                rank
+            );
+        }
+        if rank > 0 && (axis == rank - 1 || axis == -1) {
+            return Ok((new.add_attribute("axis", Attribute::Int(rank - 1))?, vec![]));
+        }
+
+        // 形状未知时不能判断axis是否为最后一维
+        let var = |name: String, shape: &[u32], dtype| {
+            let name = self.fresh_name(name);
+            Tensor::new_with_shape(&name, shape, y.format(), dtype, Type::Variable)
+        };
+        let flat_shape = match rank {
+            0 => vec![],
+            _ => {
+                let axis = if axis < 0 { axis + rank } else { axis } as usize;
+                let dims = x.shape().data();
+                vec![dims[..axis].iter().product(), dims[axis..].iter().product()]
+            }
+        };
+        let flattened = var(format!("{}_flatten", y.name()), &flat_shape, x.dtype());
+        let result = var(format!("{}_2d", y.name()), &flat_shape, y.dtype());
+        let shape_dims = if rank > 0 { vec![rank as u32] } else { vec![] };
+        let shape = var(format!("{}_shape", y.name()), &shape_dims, DType::Int64);
+        let name = op.name();
+
+        let mut softmax = Operator::new(name, new.r#type())
+            .add_input("0", flattened.clone())?
+            .add_output("0", result.clone())?;
+        for (k, v) in new.attributes() {
+            softmax = softmax.add_attribute(k, v.clone())?;
+        }
+        let softmax = softmax.add_attribute("axis", Attribute::Int(1))?;
+        let flatten = Operator::new(&format!("{}_Flatten", name), "Flatten")
+            .add_input("0", x.clone())?
+            .add_output("0", flattened)?
+            .add_attribute("axis", Attribute::Int(axis))?;
+        let shape_op = Operator::new(&format!("{}_Shape", name), "Shape")
+            .add_input("0", x.clone())?
+            .add_output("0", shape.clone())?;
+        let reshape = Operator::new(&format!("{}_Reshape", name), "Reshape")
+            .add_input("0", result)?
+            .add_input("1", shape)?
+            .add_output("0", y.clone())?;
+        Ok((softmax, vec![flatten, shape_op, reshape]))
+    }
+
+    /// 设置目标版本的定义和默认属性
+    fn finish(&self, mut op: Operator) -> Result<Operator> {
+        let schema = Registry::global()
+            .get(ONNX_DOMAIN, op.r#type(), self.target)
+            .ok_or_else(|| {
+                anyhow!(
+                    "op {} has no definition in opset {}",
+                    op.r#type(),
+                    self.target
+                )
+            })?;
+        for a in schema.attributes.iter() {
+            if let Some(v) = a.default_value() {
+                op = op.add_default_attribute(&a.name, v)?;
+            }
+        }
+        op.set_opset(ONNX_DOMAIN, schema.version)
+    }
+}
+
+/// Upsample的输入为X和scales，Resize在11版本后增加了roi并需要指定坐标变换
+fn upsample_to_resize(op: Operator, schema: &OpSchema) -> Result<Operator> {
+    if schema.version < 11 {
+        return Ok(op);
+    }
+    let mut resize = Operator::new(op.name(), op.r#type());
+    for (tag, t) in op.inputs() {
+        let tag = if tag == "1" { "2" } else { tag.as_str() };
+        resize = resize.add_input(tag, t.clone())?;
+    }
+    for (tag, t) in op.outputs() {
+        resize = resize.add_output(tag, t.clone())?;
+    }
+    for (k, v) in op.attributes() {
+        resize = resize.add_attribute(k, v.clone())?;
+    }
+    let roi = Tensor::from_vec(&format!("{}_roi", op.name()), &[0], Vec::<f32>::new())?
+        .with_type(Type::Constant);
+    resize
+        .add_input("1", roi)?
+        .add_attribute(
+            "coordinate_transformation_mode",
+            Attribute::String(b"asymmetric".to_vec()),
+        )?
+        .add_attribute("nearest_mode", Attribute::String(b"floor".to_vec()))
+}
+
+/// 属性值转换为常量输入，浮点标量与算子的输入类型一致
+fn attribute_input(op: &Operator, name: &str, attr: &Attribute) -> Result<Tensor> {
+    let t = match attr {
+        Attribute::Ints(vs) => Tensor::from_slice(name, &[vs.len() as u32], vs)?,
+        Attribute::Int(v) => Tensor::from_vec(name, &[1], vec![*v])?,
+        Attribute::Floats(vs) => Tensor::from_slice(name, &[vs.len() as u32], vs)?,
+        Attribute::Float(v) => {
+            let dtype = op.get_input("0").map_or(DType::Float32, |t| t.dtype());
+            match dtype {
+                DType::Float32 => Tensor::from_vec(name, &[1], vec![*v])?,
+                DType::Float16 => Tensor::from_vec(name, &[1], vec![f16::from_f32(*v)])?,
+                dtype => bail!(
+                    "cannot convert attribute {} of op {} to {:?} input",
+                    name,
+                    op.name(),
+                    dtype
+                ),
+            }
+        }
+        attr => bail!(
+            "attribute {} of op {} has unexpected type {:?}",
+            name,
+            op.name(),
+            attr.r#type()
+        ),
+    };
+    Ok(t.with_type(Type::Constant))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pass::test_util::{op, var};
+
+    fn ints(op: &Operator, tag: &str) -> Vec<i64> {
+        op.get_input(tag).unwrap().to_vec::<i64>().unwrap()
+    }
+
+    #[test]
+    fn convert_version_works() {
+        let slice = op(
+            "slice",
+            "Slice",
+            vec![var("x", &[1, 3, 4, 4])],
+            var("s", &[1, 3, 2, 4]),
+        )
+        .add_attribute("starts", Attribute::Ints(vec![1]))
+        .unwrap()
+        .add_attribute("ends", Attribute::Ints(vec![3]))
+        .unwrap()
+        .add_attribute("axes", Attribute::Ints(vec![2]))
+        .unwrap()
+        .set_opset(ONNX_DOMAIN, 1)
+        .unwrap();
+        let upsample = op(
+            "up",
+            "Upsample",
+            vec![var("s", &[1, 3, 2, 4])],
+            var("u", &[1, 3, 4, 8]),
+        )
+        .add_attribute("scales", Attribute::Floats(vec![1.0, 1.0, 2.0, 2.0]))
+        .unwrap()
+        .set_opset(ONNX_DOMAIN, 7)
+        .unwrap();
+        let mut bn = Operator::new("bn", "BatchNormalization")
+            .add_input("0", var("u", &[1, 3, 4, 8]))
+            .unwrap();
+        for (i, name) in ["scale", "bias", "mean", "var"].iter().enumerate() {
+            let t = Tensor::from_vec(name, &[3], vec![1.0_f32; 3]).unwrap();
+            bn = bn
+                .add_input(&(i + 1).to_string(), t.with_type(Type::Constant))
+                .unwrap();
+        }
+        let bn = bn
+            .add_output("0", var("b", &[1, 3, 4, 8]))
+            .unwrap()
+            .add_output("1", var("running_mean", &[3]))
+            .unwrap()
+            .add_attribute("spatial", Attribute::Int(1))
+            .unwrap()
+            .set_opset(ONNX_DOMAIN, 7)
+            .unwrap();
+        let squeeze = op(
+            "squeeze",
+            "Squeeze",
+            vec![var("b", &[1, 3, 4, 8])],
+            var("y", &[3, 4, 8]),
+        )
+        .add_attribute("axes", Attribute::Ints(vec![0]))
+        .unwrap()
+        .set_opset(ONNX_DOMAIN, 1)
+        .unwrap();
+        let graph = Graph::new("g")
+            .add_opset_import(ONNX_DOMAIN, 9)
+            .unwrap()
+            .add_input(var("x", &[1, 3, 4, 4]))
+            .unwrap()
+            .add_output(var("y", &[3, 4, 8]))
+            .unwrap()
+            .add_operator(slice)
+            .unwrap()
+            .add_operator(upsample)
+            .unwrap()
+            .add_operator(bn)
+            .unwrap()
+            .add_operator(squeeze)
+            .unwrap();
+
+        assert!(convert_version(graph.clone(), 7).is_err());
+        let (graph, report) = convert_version(graph, 15).unwrap();
+        assert_eq!(Some(15), graph.opset_version(ONNX_DOMAIN));
+        assert_eq!(vec!["slice", "up", "bn", "squeeze"], report.migrated);
+
+        let slice = graph.get_operator("slice").unwrap();
+        assert!(slice.attributes().is_empty());
+        assert_eq!(
+            (vec![1], vec![3], vec![2]),
+            (ints(slice, "1"), ints(slice, "2"), ints(slice, "3"))
+        );
+        assert_eq!(13, slice.since_version());
+
+        let resize = graph.get_operator("up").unwrap();
+        assert_eq!("Resize", resize.r#type());
+        assert_eq!(0, resize.get_input("1").unwrap().shape().len());
+        assert_eq!(
+            vec![1.0, 1.0, 2.0, 2.0],
+            resize.get_input("2").unwrap().to_vec::<f32>().unwrap()
+        );
+        assert_eq!(
+            "asymmetric",
+            resize
+                .attr_string("coordinate_transformation_mode")
+                .unwrap()
+        );
+
+        let bn = graph.get_operator("bn").unwrap();
+        assert_eq!(1, bn.outputs().len());
+        assert!(bn.attributes().is_empty());
+        assert_eq!(vec![0], ints(graph.get_operator("squeeze").unwrap(), "1"));
+        super::super::manager::validate(&graph).unwrap();
+    }
+
+    #[test]
+    fn removed_attributes_works() {
+        let graph = |op: Operator| {
+            Graph::new("g")
+                .add_opset_import(ONNX_DOMAIN, 6)
+                .unwrap()
+                .add_input(var("x", &[1, 3, 4, 4]))
+                .unwrap()
+                .add_output(var("y", &[1, 3, 8, 12]))
+                .unwrap()
+                .add_operator(op.set_opset(ONNX_DOMAIN, 1).unwrap())
+                .unwrap()
+        };
+        let height = op(
+            "up",
+            "Upsample",
+            vec![var("x", &[1, 3, 4, 4])],
+            var("y", &[1, 3, 8, 12]),
+        )
+        .add_attribute("height_scale", Attribute::Float(2.0))
+        .unwrap()
+        .add_attribute("mode", Attribute::String(b"bilinear".to_vec()))
+        .unwrap();
+        let upsample = height
+            .clone()
+            .add_attribute("width_scale", Attribute::Float(3.0))
+            .unwrap();
+        let (converted, _) = convert_version(graph(upsample.clone()), 15).unwrap();
+        let resize = converted.get_operator("up").unwrap();
+        assert_eq!(
+            vec![1.0, 1.0, 2.0, 3.0],
+            resize.get_input("2").unwrap().to_vec::<f32>().unwrap()
+        );
+        assert_eq!("linear", resize.attr_string("mode").unwrap());
+        // 8版本的scales是属性
+        let (converted, _) = convert_version(graph(upsample), 8).unwrap();
+        let upsample8 = converted.get_operator("up").unwrap();
+        assert!(upsample8.get_input("1").is_none());
+        assert_eq!(
+            vec![1.0, 1.0, 2.0, 3.0],
+            upsample8.attr_floats("scales").unwrap()
+        );
+        assert!(convert_version(graph(height), 15).is_err());
+
+        // 推理模式的is_test和consumed_inputs可以去掉
+        let bn = |is_test: i64| {
+            let mut bn = Operator::new("bn", "BatchNormalization")
+                .add_input("0", var("x", &[1, 3, 4, 4]))
+                .unwrap();
+            for (i, name) in ["scale", "bias", "mean", "var"].iter().enumerate() {
+                let t = Tensor::from_vec(name, &[3], vec![1.0_f32; 3]).unwrap();
+                bn = bn
+                    .add_input(&(i + 1).to_string(), t.with_type(Type::Constant))
+                    .unwrap();
+            }
+            bn.add_output("0", var("y", &[1, 3, 4, 4]))
+                .unwrap()
+                .add_attribute("is_test", Attribute::Int(is_test))
+                .unwrap()
+                .add_attribute("consumed_inputs", Attribute::Ints(vec![0, 0, 0, 1, 1]))
+                .unwrap()
+        };
+        let (converted, _) = convert_version(graph(bn(1)), 15).unwrap();
+        assert!(converted
+            .get_operator("bn")
+            .unwrap()
+            .attributes()
+            .is_empty());
+        assert!(convert_version(graph(bn(0)), 15).is_err());
+
+        // 其他去掉的属性不能忽略
+        let add = Operator::new("add", "Add")
+            .add_input("0", var("x", &[1, 3, 4, 4]))
+            .unwrap()
+            .add_input("1", var("b", &[3]))
+            .unwrap()
+            .add_output("0", var("y", &[1, 3, 4, 4]))
+            .unwrap()
+            .add_attribute("broadcast", Attribute::Int(1))
+            .unwrap();
+        assert!(convert_version(graph(add.clone()), 15).is_ok());
+        let add = add.add_attribute("axis", Attribute::Int(1)).unwrap();
+        assert!(convert_version(graph(add), 15).is_err());
+    }
+
+    #[test]
+    fn softmax_axis_works() {
+        let softmax = op(
+            "softmax",
+            "Softmax",
+            vec![var("x", &[2, 3, 4])],
+            var("y", &[2, 3, 4]),
+        );
+        let last = op(
+            "last",
+            "LogSoftmax",
+            vec![var("y", &[2, 3, 4])],
+            var("z", &[2, 3, 4]),
+        )
+        .add_attribute("axis", Attribute::Int(2))
+        .unwrap();
+        let graph = Graph::new("g")
+            .add_opset_import(ONNX_DOMAIN, 11)
+            .unwrap()
+            .add_input(var("x", &[2, 3, 4]))
+            .unwrap()
+            .add_output(var("z", &[2, 3, 4]))
+            .unwrap()
+            .add_operator(softmax)
+            .unwrap()
+            .add_operator(last)
+            .unwrap();
+        let (graph, report) = convert_version(graph, 13).unwrap();
+        assert_eq!(vec!["softmax"], report.migrated);
+        assert_eq!(
+            vec!["softmax_Flatten", "softmax_Shape", "softmax_Reshape"],
+            report.inserted
+        );
+        let softmax = graph.get_operator("softmax").unwrap();
+        assert_eq!(1, softmax.attr_int("axis").unwrap());
+        assert_eq!(&[2, 12], softmax.get_input("0").unwrap().shape().data());
+        assert_eq!("y_2d", softmax.get_output("0").unwrap().name());
+        assert_eq!(
+            2,
+            graph
+                .get_operator("last")
+                .unwrap()
+                .attr_int("axis")
+                .unwrap()
+        );
+        super::super::manager::validate(&graph).unwrap();
+    }
+
+    #[test]
+    fn softmax_axis_checked() {
+        let graph = |axis: i64, extra: Option<Operator>| {
+            let softmax = op(
+                "softmax",
+                "Softmax",
+                vec![var("x", &[2, 3, 4])],
+                var("y", &[2, 3, 4]),
+            )
+            .add_attribute("axis", Attribute::Int(axis))
+            .unwrap();
+            let mut graph = Graph::new("g")
+                .add_opset_import(ONNX_DOMAIN, 11)
+                .unwrap()
+                .add_input(var("x", &[2, 3, 4]))
+                .unwrap()
+                .add_output(var("y", &[2, 3, 4]))
+                .unwrap()
+                .add_operator(softmax)
+                .unwrap();
+            if let Some(extra) = extra {
+                let output = extra.get_output("0").unwrap().clone();
+                graph = graph
+                    .add_output(output)
+                    .unwrap()
+                    .add_operator(extra)
+                    .unwrap();
+            }
+            graph
+        };
+        assert!(convert_version(graph(3, None), 13).is_err());
+        assert!(convert_version(graph(-4, None), 13).is_err());
+
+        // 插入的张量不与图中已有的张量重名
+        let relu = op(
+            "relu",
+            "Relu",
+            vec![var("x", &[2, 3, 4])],
+            var("y_2d", &[2, 3, 4]),
+        );
+        let (graph, _) = convert_version(graph(-3, Some(relu)), 13).unwrap();
+        let softmax = graph.get_operator("softmax").unwrap();
+        assert_eq!("y_2d_1", softmax.get_output("0").unwrap().name());
+        assert_eq!(
+            "y_2d",
+            graph
+                .get_operator("relu")
+                .unwrap()
+                .get_output("0")
+                .unwrap()
+                .name()
+        );
+        super::super::manager::validate(&graph).unwrap();
+    }
+}
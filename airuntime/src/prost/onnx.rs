@@ -50,6 +50,9 @@ pub struct AttributeProto {
     /// sparse tensor value
     #[prost(message, optional, tag = "22")]
     pub sparse_tensor: ::core::option::Option<SparseTensorProto>,
+    /// type proto
+    #[prost(message, optional, tag = "14")]
+    pub tp: ::core::option::Option<TypeProto>,
     // Do not use field below, it's deprecated.
     // optional ValueProto v = 12;         // value - subsumes everything but graph
     /// list of floats
@@ -0,0 +1,564 @@
+//! 算子定义
+//!
+//! 从`onnx/onnx-metadata.json`加载各算子在不同版本下的定义
+
+use log::*;
+use model::attribute::{AttType, Attribute};
+use model::operator::Operator;
+use model::tensor::{DType, Tensor};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+static METADATA: &str = include_str!("../onnx/onnx-metadata.json");
+
+pub use model::operator::ONNX_DOMAIN;
+
+/// 属性定义
+#[derive(Debug, Clone, Deserialize)]
+pub struct AttributeSchema {
+    pub name: String,
+    /// 属性类型，如`int64`、`float32[]`
+    #[serde(rename = "type")]
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    pub default: Option<Value>,
+}
+
+impl AttributeSchema {
+    /// 将定义中的默认值按属性类型转换为Attribute
+    pub fn default_value(&self) -> Option<Attribute> {
+        let default = self.default.as_ref()?;
+        // DataType的默认值是TensorProto.DataType的编号，也接受`float`形式的类型名
+        let data_type = |v: &Value| match v {
+            Value::String(t) => {
+                dtype_from_type_str(&format!("tensor({})", t)).map(|d| d.get_code() as i64)
+            }
+            v => v.as_i64(),
+        };
+        let attr = match self.r#type.as_deref()? {
+            "int64" => Attribute::from(default.as_i64()?),
+            "DataType" => Attribute::from(data_type(default)?),
+            "float32" => Attribute::from(default.as_f64()? as f32),
+            "string" => Attribute::from(default.as_str()?),
+            "int64[]" => Attribute::from(
+                default
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_i64())
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+            "DataType[]" => Attribute::from(
+                default
+                    .as_array()?
+                    .iter()
+                    .map(data_type)
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+            "float32[]" => Attribute::from(
+                default
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_f64().map(|v| v as f32))
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+            "string[]" => Attribute::from(
+                default
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_str().map(String::from))
+                    .collect::<Option<Vec<_>>>()?,
+            ),
+            _ => return None,
+        };
+        Some(attr)
+    }
+
+    /// 定义中的属性类型对应的AttType，未知类型返回None
+    pub fn att_type(&self) -> Option<AttType> {
+        let tp = match self.r#type.as_deref()? {
+            "int64" | "DataType" => AttType::Int,
+            "int64[]" | "DataType[]" => AttType::Ints,
+            "float32" => AttType::Float,
+            "float32[]" => AttType::Floats,
+            "string" => AttType::String,
+            "string[]" => AttType::Strings,
+            "tensor" => AttType::Tensor,
+            "tensor[]" => AttType::Tensors,
+            "graph" => AttType::Graph,
+            "graph[]" => AttType::Graphs,
+            "sparse_tensor" => AttType::SparseTensor,
+            "sparse_tensor[]" => AttType::SparseTensors,
+            "type" => AttType::TypeProto,
+            "type[]" => AttType::TypeProtos,
+            _ => return None,
+        };
+        Some(tp)
+    }
+}
+
+/// 输入输出定义
+#[derive(Debug, Clone, Deserialize)]
+pub struct FormalParameter {
+    pub name: String,
+    /// 类型参数(如`T`)或具体类型(如`tensor(int64)`)
+    #[serde(rename = "type")]
+    pub r#type: String,
+    /// `optional`表示可选
+    pub option: Option<String>,
+    /// 为true时表示可变数量的参数
+    #[serde(default)]
+    pub list: bool,
+}
+
+impl FormalParameter {
+    pub fn is_optional(&self) -> bool {
+        self.option.as_deref() == Some("optional")
+    }
+}
+
+/// 类型约束
+#[derive(Debug, Clone, Deserialize)]
+pub struct TypeConstraint {
+    pub type_param_str: String,
+    pub allowed_type_strs: Vec<String>,
+}
+
+/// 算子定义
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpSchema {
+    pub name: String,
+    /// 算子所属的domain
+    pub module: String,
+    /// 算子定义开始生效的算子集版本
+    pub version: i64,
+    #[serde(default)]
+    pub attributes: Vec<AttributeSchema>,
+    #[serde(default)]
+    pub inputs: Vec<FormalParameter>,
+    #[serde(default)]
+    pub outputs: Vec<FormalParameter>,
+    #[serde(default)]
+    pub min_input: usize,
+    #[serde(default = "max_count")]
+    pub max_input: usize,
+    #[serde(default)]
+    pub min_output: usize,
+    #[serde(default = "max_count")]
+    pub max_output: usize,
+    #[serde(default)]
+    pub type_constraints: Vec<TypeConstraint>,
+}
+
+fn max_count() -> usize {
+    i32::MAX as usize
+}
+
+/// 算子与定义不符时的错误
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("算子{op}的类型{domain}::{op_type}在算子集版本{opset}中没有定义")]
+    UnknownOp {
+        op: String,
+        op_type: String,
+        domain: String,
+        opset: i64,
+    },
+    #[error("算子{op}({op_type})有{count}个{kind}, 需要{min}到{max}个")]
+    Count {
+        op: String,
+        op_type: String,
+        kind: &'static str,
+        count: usize,
+        min: usize,
+        max: usize,
+    },
+    #[error("算子{op}({op_type})缺少必需属性{attr}")]
+    MissingAttribute {
+        op: String,
+        op_type: String,
+        attr: String,
+    },
+    #[error("算子{op}({op_type})的属性{attr}类型为{actual:?}, 需要{expected:?}")]
+    AttributeType {
+        op: String,
+        op_type: String,
+        attr: String,
+        expected: AttType,
+        actual: AttType,
+    },
+    #[error("算子{op}({op_type})的{kind}{tensor}数据类型为{dtype:?}, 允许的类型为{allowed:?}")]
+    DType {
+        op: String,
+        op_type: String,
+        kind: &'static str,
+        tensor: String,
+        dtype: DType,
+        allowed: Vec<String>,
+    },
+}
+
+impl OpSchema {
+    pub fn get_attribute(&self, name: &str) -> Option<&AttributeSchema> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    /// 第index个输入的定义，可变数量的输入共用最后一个定义
+    pub fn input(&self, index: usize) -> Option<&FormalParameter> {
+        formal_parameter(&self.inputs, index)
+    }
+
+    /// 第index个输出的定义，可变数量的输出共用最后一个定义
+    pub fn output(&self, index: usize) -> Option<&FormalParameter> {
+        formal_parameter(&self.outputs, index)
+    }
+
+    /// 参数允许的数据类型，不是张量类型的约束不返回
+    pub fn allowed_types<'a>(&'a self, param: &'a FormalParameter) -> Vec<&'a str> {
+        match self
+            .type_constraints
+            .iter()
+            .find(|c| c.type_param_str == param.r#type)
+        {
+            Some(c) => c.allowed_type_strs.iter().map(|t| t.as_str()).collect(),
+            None => vec![param.r#type.as_str()],
+        }
+    }
+
+    /// 检查算子的输入输出数量、属性和数据类型是否满足定义
+    pub fn check(&self, op: &Operator) -> Result<(), Error> {
+        let count = |tensors: &HashMap<String, Tensor>| {
+            tensors
+                .keys()
+                .filter_map(|tag| tag.parse::<usize>().ok())
+                .max()
+                .map_or(0, |i| i + 1)
+        };
+        let n = count(op.inputs());
+        if n < self.min_input || n > self.max_input {
+            return Err(Error::Count {
+                op: op.name().clone(),
+                op_type: op.r#type().clone(),
+                kind: "输入",
+                count: n,
+                min: self.min_input,
+                max: self.max_input,
+            });
+        }
+        let n = count(op.outputs());
+        if n < self.min_output || n > self.max_output {
+            return Err(Error::Count {
+                op: op.name().clone(),
+                op_type: op.r#type().clone(),
+                kind: "输出",
+                count: n,
+                min: self.min_output,
+                max: self.max_output,
+            });
+        }
+
+        for a in self.attributes.iter() {
+            match op.get_attribute(&a.name) {
+                None if a.required => {
+                    return Err(Error::MissingAttribute {
+                        op: op.name().clone(),
+                        op_type: op.r#type().clone(),
+                        attr: a.name.clone(),
+                    })
+                }
+                Some(attr) => match a.att_type() {
+                    Some(expected) if expected != attr.r#type() => {
+                        return Err(Error::AttributeType {
+                            op: op.name().clone(),
+                            op_type: op.r#type().clone(),
+                            attr: a.name.clone(),
+                            expected,
+                            actual: attr.r#type(),
+                        })
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        for (kind, tensors, params) in [
+            ("输入", op.inputs(), &self.inputs),
+            ("输出", op.outputs(), &self.outputs),
+        ] {
+            for (tag, tensor) in tensors.iter() {
+                let param = match tag.parse().ok().and_then(|i| formal_parameter(params, i)) {
+                    Some(p) => p,
+                    None => continue,
+                };
+                // 数据类型未知时不检查
+                if tensor.dtype() == DType::Undefined {
+                    continue;
+                }
+                let allowed = self.allowed_types(param);
+                let tensor_types: Vec<DType> =
+                    allowed.iter().filter_map(|t| dtype_from_type_str(t)).collect();
+                if !tensor_types.is_empty() && !tensor_types.contains(&tensor.dtype()) {
+                    return Err(Error::DType {
+                        op: op.name().clone(),
+                        op_type: op.r#type().clone(),
+                        kind,
+                        tensor: tensor.name().clone(),
+                        dtype: tensor.dtype(),
+                        allowed: allowed.iter().map(|t| String::from(*t)).collect(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn formal_parameter(params: &[FormalParameter], index: usize) -> Option<&FormalParameter> {
+    match params.get(index) {
+        Some(p) => Some(p),
+        None => params.last().filter(|p| p.list),
+    }
+}
+
+/// 将`tensor(float)`形式的类型转换为DType，不是张量的类型返回None
+pub fn dtype_from_type_str(t: &str) -> Option<DType> {
+    let elem = t.strip_prefix("tensor(")?.strip_suffix(')')?;
+    let dtype = match elem {
+        "float" => DType::Float32,
+        "uint8" => DType::Uint8,
+        "int8" => DType::Int8,
+        "uint16" => DType::Uint16,
+        "int16" => DType::Int16,
+        "int32" => DType::Int32,
+        "int64" => DType::Int64,
+        "string" => DType::String,
+        "bool" => DType::Bool,
+        "float16" => DType::Float16,
+        "double" => DType::Float64,
+        "uint32" => DType::Uint32,
+        "uint64" => DType::Uint64,
+        "complex64" => DType::Complex64,
+        "complex128" => DType::Complex128,
+        "bfloat16" => DType::Bfloat16,
+        _ => return None,
+    };
+    Some(dtype)
+}
+
+/// 算子定义表，按(domain, 算子类型)索引，同一算子的定义按版本升序排列
+pub struct Registry {
+    schemas: HashMap<(String, String), Vec<OpSchema>>,
+    // 每个domain已知的最高算子集版本
+    max_versions: HashMap<String, i64>,
+}
+
+impl Registry {
+    /// 内置的算子定义表，首次使用时解析
+    pub fn global() -> &'static Registry {
+        static REGISTRY: OnceLock<Registry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            Registry::from_json(METADATA).expect("内置的onnx-metadata.json解析失败")
+        })
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let list: Vec<OpSchema> = serde_json::from_str(json)?;
+        let mut schemas: HashMap<(String, String), Vec<OpSchema>> = HashMap::new();
+        let mut max_versions: HashMap<String, i64> = HashMap::new();
+        for schema in list {
+            let max = max_versions.entry(schema.module.clone()).or_default();
+            *max = (*max).max(schema.version);
+            schemas
+                .entry((schema.module.clone(), schema.name.clone()))
+                .or_default()
+                .push(schema);
+        }
+        schemas
+            .values_mut()
+            .for_each(|v| v.sort_by_key(|s| s.version));
+        debug!("loaded {} operator schemas", schemas.len());
+
+        Ok(Self {
+            schemas,
+            max_versions,
+        })
+    }
+
+    /// domain已知的最高算子集版本，没有该domain的定义时返回None
+    pub fn max_version(&self, domain: &str) -> Option<i64> {
+        self.max_versions.get(normalize_domain(domain)).copied()
+    }
+
+    /// 查找算子在指定算子集版本下生效的定义，即版本不大于`opset`的最新定义
+    pub fn get(&self, domain: &str, op_type: &str, opset: i64) -> Option<&OpSchema> {
+        let versions = self
+            .schemas
+            .get(&(String::from(normalize_domain(domain)), String::from(op_type)))?;
+        versions.iter().rev().find(|s| s.version <= opset)
+    }
+
+    /// 按算子集版本查找定义并检查算子，返回生效的定义
+    pub fn check(&self, domain: &str, opset: i64, op: &Operator) -> Result<&OpSchema, Error> {
+        match self.get(domain, op.r#type(), opset) {
+            Some(schema) => schema.check(op).map(|_| schema),
+            None => Err(Error::UnknownOp {
+                op: op.name().clone(),
+                op_type: op.r#type().clone(),
+                domain: String::from(normalize_domain(domain)),
+                opset,
+            }),
+        }
+    }
+}
+
+/// 空domain等价于ai.onnx
+pub fn normalize_domain(domain: &str) -> &str {
+    if domain.is_empty() {
+        ONNX_DOMAIN
+    } else {
+        domain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::tensor::{Format, Type};
+
+    #[test]
+    fn schema_works() {
+        let registry = Registry::global();
+
+        let conv = registry.get("", "Conv", 13).unwrap();
+        assert_eq!(11, conv.version);
+        let group: i64 = conv
+            .get_attribute("group")
+            .unwrap()
+            .default_value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(1, group);
+        let auto_pad: String = conv
+            .get_attribute("auto_pad")
+            .unwrap()
+            .default_value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!("NOTSET", auto_pad);
+        let dtype: i64 = registry
+            .get("", "RandomNormal", 13)
+            .unwrap()
+            .get_attribute("dtype")
+            .unwrap()
+            .default_value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(DType::Float32.get_code() as i64, dtype);
+        let attr = |r#type: &str, default: Value| AttributeSchema {
+            name: String::from("to"),
+            r#type: Some(String::from(r#type)),
+            required: false,
+            default: Some(default),
+        };
+        let to: i64 = attr("DataType", Value::from("float16"))
+            .default_value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(DType::Float16.get_code() as i64, to);
+        let types: Vec<i64> = attr("DataType[]", serde_json::json!([1, "int64"]))
+            .default_value()
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(vec![1, DType::Int64.get_code() as i64], types);
+
+        assert_eq!(1, registry.get("ai.onnx", "Conv", 10).unwrap().version);
+        assert_eq!(Some(20), registry.max_version(""));
+        assert_eq!(Some(3), registry.max_version("ai.onnx.ml"));
+        assert_eq!(None, registry.max_version("com.example"));
+        assert!(registry.get("", "Conv", 0).is_none());
+        assert!(registry.get("", "NotExist", 13).is_none());
+    }
+
+    fn tensor(name: &str, dtype: DType) -> Tensor {
+        Tensor::new_with_shape(name, &[1, 3, 4, 4], Format::NCHW, dtype, Type::Variable)
+    }
+
+    #[test]
+    fn check_works() {
+        let registry = Registry::global();
+        let op = Operator::new("conv", "Conv")
+            .add_input("0", tensor("x", DType::Float32))
+            .unwrap()
+            .add_input("1", tensor("w", DType::Float32))
+            .unwrap()
+            .add_output("0", tensor("y", DType::Float32))
+            .unwrap()
+            .add_attribute("pads", Attribute::from(&[1_i64, 1, 1, 1] as &[i64]))
+            .unwrap();
+        assert_eq!(11, registry.check("", 13, &op).unwrap().version);
+
+        // 输入数量不足
+        let op = Operator::new("conv", "Conv")
+            .add_input("0", tensor("x", DType::Float32))
+            .unwrap()
+            .add_output("0", tensor("y", DType::Float32))
+            .unwrap();
+        assert!(matches!(
+            registry.check("", 13, &op),
+            Err(Error::Count { count: 1, min: 2, max: 3, .. })
+        ));
+
+        // 不允许的数据类型
+        let op = Operator::new("conv", "Conv")
+            .add_input("0", tensor("x", DType::Int32))
+            .unwrap()
+            .add_input("1", tensor("w", DType::Float32))
+            .unwrap()
+            .add_output("0", tensor("y", DType::Float32))
+            .unwrap();
+        let e = registry.check("", 13, &op).unwrap_err();
+        assert!(matches!(e, Error::DType { dtype: DType::Int32, .. }));
+
+        // 属性类型错误
+        let op = Operator::new("concat", "Concat")
+            .add_input("0", tensor("a", DType::Float32))
+            .unwrap()
+            .add_input("1", tensor("b", DType::Float32))
+            .unwrap()
+            .add_input("2", tensor("c", DType::Float32))
+            .unwrap()
+            .add_output("0", tensor("y", DType::Float32))
+            .unwrap()
+            .add_attribute("axis", Attribute::from(1.0))
+            .unwrap();
+        assert!(matches!(
+            registry.check("", 13, &op),
+            Err(Error::AttributeType { expected: AttType::Int, actual: AttType::Float, .. })
+        ));
+
+        // 缺少必需属性
+        let op = Operator::new("concat", "Concat")
+            .add_input("0", tensor("a", DType::Float32))
+            .unwrap()
+            .add_output("0", tensor("y", DType::Float32))
+            .unwrap();
+        assert!(matches!(
+            registry.check("", 13, &op),
+            Err(Error::MissingAttribute { .. })
+        ));
+
+        let op = Operator::new("custom", "MyOp");
+        let e = registry.check("com.example", 1, &op).unwrap_err();
+        assert!(matches!(e, Error::UnknownOp { .. }));
+        assert!(e.to_string().contains("com.example::MyOp"));
+    }
+}
@@ -0,0 +1,1405 @@
+//! TensorFlow Lite模型加载
+//!
+//! 内置算子转换为ONNX中等价的算子和属性，按[`ONNX_OPSET`]版本的算子定义检查。
+//! 张量保持TFLite的NHWC布局并记录在`Format`中，卷积权重为OHWI，深度卷积权重为1HWO，
+//! 量化张量的参数保存在`Quantization`中
+
+mod schema;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use log::*;
+use model::attribute::Attribute;
+use model::graph::Graph;
+use model::operator::Operator;
+use model::tensor::*;
+
+use self::schema::{options, tensor_type};
+use crate::info::{Dim, ModelInfo, Signature};
+use crate::loader::{self, LoadError, MappedFile, Options};
+use crate::schema::{Error as SchemaError, Registry, ONNX_DOMAIN};
+
+/// 转换后的算子使用的ONNX算子集版本
+const ONNX_OPSET: i64 = 14;
+/// 自定义算子的domain，算子类型为custom_code
+pub const CUSTOM_DOMAIN: &str = "ai.tflite.custom";
+
+/// 内置算子编号
+mod builtin {
+    pub const ADD: i32 = 0;
+    pub const AVERAGE_POOL_2D: i32 = 1;
+    pub const CONCATENATION: i32 = 2;
+    pub const CONV_2D: i32 = 3;
+    pub const DEPTHWISE_CONV_2D: i32 = 4;
+    pub const DEQUANTIZE: i32 = 6;
+    pub const FLOOR: i32 = 8;
+    pub const FULLY_CONNECTED: i32 = 9;
+    pub const LOGISTIC: i32 = 14;
+    pub const MAX_POOL_2D: i32 = 17;
+    pub const MUL: i32 = 18;
+    pub const RELU: i32 = 19;
+    pub const RELU_N1_TO_1: i32 = 20;
+    pub const RELU6: i32 = 21;
+    pub const RESHAPE: i32 = 22;
+    pub const SOFTMAX: i32 = 25;
+    pub const TANH: i32 = 28;
+    pub const CUSTOM: i32 = 32;
+    pub const PAD: i32 = 34;
+    pub const GATHER: i32 = 36;
+    pub const TRANSPOSE: i32 = 39;
+    pub const MEAN: i32 = 40;
+    pub const SUB: i32 = 41;
+    pub const DIV: i32 = 42;
+    pub const SQUEEZE: i32 = 43;
+    pub const EXP: i32 = 47;
+    pub const CAST: i32 = 53;
+    pub const PRELU: i32 = 54;
+    pub const MAXIMUM: i32 = 55;
+    pub const MINIMUM: i32 = 57;
+    pub const LESS: i32 = 58;
+    pub const NEG: i32 = 59;
+    pub const GREATER: i32 = 61;
+    pub const EQUAL: i32 = 71;
+    pub const LOG: i32 = 73;
+    pub const SQRT: i32 = 75;
+    pub const POW: i32 = 78;
+    pub const REDUCE_MAX: i32 = 82;
+    pub const LEAKY_RELU: i32 = 98;
+    pub const ABS: i32 = 101;
+    pub const QUANTIZE: i32 = 114;
+    pub const HARD_SWISH: i32 = 117;
+}
+
+/// 融合在算子中的激活函数
+mod activation {
+    pub const NONE: i8 = 0;
+    pub const RELU: i8 = 1;
+    pub const RELU_N1_TO_1: i8 = 2;
+    pub const RELU6: i8 = 3;
+    pub const TANH: i8 = 4;
+}
+
+/// 数据是否为TFLite模型，按文件标识判断
+pub fn is_tflite(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && flatbuffers::buffer_has_identifier(bytes, schema::FILE_IDENTIFIER, false)
+}
+
+/// 文件是否为TFLite模型，只读取文件头
+pub fn is_tflite_file(model_file: &str) -> bool {
+    let mut head = [0u8; 8];
+    fs::File::open(model_file)
+        .and_then(|mut f| f.read_exact(&mut head))
+        .is_ok()
+        && is_tflite(&head)
+}
+
+pub fn load(model_file: &str, opts: &Options) -> Result<(Graph, ModelInfo), LoadError> {
+    let path = Path::new(model_file);
+    if !path.exists() {
+        warn!("{:?}模型文件不存在", model_file);
+        return Err(LoadError::FileNotFound {
+            path: String::from(model_file),
+        });
+    }
+    // 常量直接引用映射的文件，不复制
+    let file = MappedFile::open(path)?;
+    let integrity = loader::model_integrity(path, opts)?;
+    loader::verify_model(&integrity, model_file, file.bytes())?;
+
+    parse(Rc::new(file))
+}
+
+/// 从内存中加载模型，常量在加载后仍然需要，因此会复制一份数据
+pub fn load_from_bytes(bytes: &[u8], opts: &Options) -> Result<(Graph, ModelInfo), LoadError> {
+    loader::verify_model(&opts.integrity, "", bytes)?;
+    parse(Rc::new(bytes.to_vec()))
+}
+
+fn parse(data: Rc<dyn SharedBytes>) -> Result<(Graph, ModelInfo), LoadError> {
+    let model = schema::root(data.bytes()).map_err(|e| anyhow!("TFLite模型校验失败, {}", e))?;
+    if model.version() != 3 {
+        warn!("TFLite模型的版本为{}, 按版本3解析", model.version());
+    }
+    let subgraphs = model
+        .subgraphs()
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("TFLite模型没有子图"))?;
+    if subgraphs.len() > 1 {
+        warn!("TFLite模型有{}个子图, 只加载主图", subgraphs.len());
+    }
+
+    let mut converter = Converter {
+        model,
+        subgraph: subgraphs.get(0),
+        data: data.clone(),
+        tensors: vec![],
+    };
+    converter.convert_tensors()?;
+    let info = converter.model_info();
+    let graph = converter.convert()?;
+
+    Ok((graph, info))
+}
+
+struct Converter<'a> {
+    model: schema::Model<'a>,
+    subgraph: schema::SubGraph<'a>,
+    /// 模型数据，常量张量共享
+    data: Rc<dyn SharedBytes>,
+    /// 按编号转换后的张量
+    tensors: Vec<Tensor>,
+}
+
+impl<'a> Converter<'a> {
+    fn convert_tensors(&mut self) -> Result<(), LoadError> {
+        for (i, t) in self.subgraph.tensors().into_iter().flatten().enumerate() {
+            let tensor = self.convert_tensor(i, t)?;
+            trace!("tflite tensor {}: {}", i, tensor);
+            self.tensors.push(tensor);
+        }
+        Ok(())
+    }
+
+    fn convert_tensor(&self, index: usize, t: schema::Tensor) -> Result<Tensor, LoadError> {
+        let name = match t.name() {
+            Some(name) if !name.is_empty() => String::from(name),
+            _ => format!("tensor_{}", index),
+        };
+        let dtype = trans_dtype(&name, t.r#type())?;
+        let mut shape = vec![];
+        for d in t.shape().into_iter().flatten() {
+            if d < 0 {
+                return Err(invalid_tensor(&name, format!("invalid dim {}", d)));
+            }
+            shape.push(d as u32);
+        }
+        if shape.len() > 8 {
+            return Err(invalid_tensor(&name, format!("rank {} > 8", shape.len())));
+        }
+        let mut tensor = Tensor::new_with_shape(&name, &shape, Format::NHWC, dtype, Type::Variable);
+        // 标量按一维保存，并标记为0维
+        if shape.is_empty() {
+            tensor = tensor.with_scalar();
+        }
+        if let Some(q) = t.quantization().and_then(trans_quantization) {
+            tensor = tensor.with_quantization(q);
+        }
+        let range = self
+            .buffer(t.buffer())
+            .map_err(|e| invalid_tensor(&name, e.to_string()))?;
+        if let Some((offset, length)) = range {
+            if dtype == DType::String {
+                return Err(LoadError::UnsupportedDType {
+                    node: String::new(),
+                    tensor: name,
+                    dtype: tensor_type::STRING as i32,
+                    reason: String::from("constant string tensor"),
+                });
+            }
+            if length != tensor.shape().len() * dtype.size_of() {
+                return Err(invalid_tensor(
+                    &name,
+                    format!(
+                        "buffer length {} not match shape {}",
+                        length,
+                        tensor.shape()
+                    ),
+                ));
+            }
+            tensor
+                .set_shared_data(self.data.clone(), offset, length)
+                .map_err(|e| invalid_tensor(&name, e.to_string()))?;
+            // 变量的数据是初始值
+            if !t.is_variable() {
+                tensor = tensor.with_type(Type::Constant);
+            }
+        }
+
+        Ok(tensor)
+    }
+
+    /// 数据在模型中的位置，没有数据时返回None
+    fn buffer(&self, index: u32) -> anyhow::Result<Option<(usize, usize)>> {
+        // 0号buffer约定为空
+        if index == 0 {
+            return Ok(None);
+        }
+        let buffer = self
+            .model
+            .buffers()
+            .filter(|b| (index as usize) < b.len())
+            .map(|b| b.get(index as usize))
+            .ok_or_else(|| anyhow!("buffer {} not exist", index))?;
+        let bytes = self.data.bytes();
+        // 大模型的数据放在flatbuffer之后，offset为1表示没有数据
+        if buffer.offset() > 1 {
+            let offset = usize::try_from(buffer.offset())?;
+            let size = usize::try_from(buffer.size())?;
+            if !matches!(offset.checked_add(size), Some(end) if end <= bytes.len()) {
+                return Err(anyhow!(
+                    "buffer {} range {}+{} out of {} bytes",
+                    index,
+                    offset,
+                    size,
+                    bytes.len()
+                ));
+            }
+            return Ok(Some((offset, size)));
+        }
+        match buffer.data() {
+            Some(v) if !v.is_empty() => {
+                let offset = v.bytes().as_ptr() as usize - bytes.as_ptr() as usize;
+                Ok(Some((offset, v.len())))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn tensor(&self, node: &str, index: i32) -> Result<Tensor, LoadError> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| self.tensors.get(i))
+            .cloned()
+            .ok_or_else(|| LoadError::UndefinedTensor {
+                node: String::from(node),
+                tensor: index.to_string(),
+            })
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        let signatures = |indices: Option<flatbuffers::Vector<i32>>| {
+            indices
+                .into_iter()
+                .flatten()
+                .filter_map(|i| {
+                    let t = self.subgraph.tensors()?.get(usize::try_from(i).ok()?);
+                    let tensor = self.tensors.get(i as usize)?;
+                    let dims = t
+                        .shape_signature()
+                        .or_else(|| t.shape())
+                        .map(|s| {
+                            s.iter()
+                                .map(|d| {
+                                    if d < 0 {
+                                        Dim::Unknown
+                                    } else {
+                                        Dim::Value(d as i64)
+                                    }
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    Some(Signature {
+                        name: tensor.name().clone(),
+                        dtype: tensor.dtype(),
+                        dims: Some(dims),
+                        doc_string: String::new(),
+                    })
+                })
+                .collect()
+        };
+
+        // 元数据中只保留文本，如min_runtime_version
+        let metadata_props = self
+            .model
+            .metadata()
+            .into_iter()
+            .flatten()
+            .filter_map(|m| {
+                let (offset, length) = self.buffer(m.buffer()).ok()??;
+                let value = &self.data.bytes()[offset..offset + length];
+                let value = std::str::from_utf8(value).ok()?;
+                Some((
+                    String::from(m.name()?),
+                    String::from(value.trim_end_matches('\0')),
+                ))
+            })
+            .collect();
+
+        ModelInfo {
+            model_version: self.model.version() as i64,
+            doc_string: String::from(self.model.description().unwrap_or_default()),
+            metadata_props,
+            inputs: signatures(self.subgraph.inputs()),
+            outputs: signatures(self.subgraph.outputs()),
+            ..Default::default()
+        }
+    }
+
+    fn convert(&self) -> Result<Graph, LoadError> {
+        let mut graph = Graph::new(self.subgraph.name().unwrap_or("main"))
+            .add_opset_import(ONNX_DOMAIN, ONNX_OPSET)?;
+        let mut has_custom = false;
+        for (i, op) in self.subgraph.operators().into_iter().flatten().enumerate() {
+            for node in self.convert_operator(i, op)? {
+                has_custom |= node.domain() == CUSTOM_DOMAIN;
+                graph = graph.add_operator(check_operator(node)?)?;
+            }
+        }
+        if has_custom {
+            graph = graph.add_opset_import(CUSTOM_DOMAIN, 1)?;
+        }
+        let io = |v: Option<flatbuffers::Vector<'a, i32>>| -> Vec<Tensor> {
+            v.into_iter()
+                .flatten()
+                .filter_map(|i| self.tensors.get(i as usize))
+                .filter(|t| t.r#type() == Type::Variable)
+                .cloned()
+                .collect()
+        };
+        for input in io(self.subgraph.inputs()) {
+            graph = graph.add_input(input)?;
+        }
+        for output in io(self.subgraph.outputs()) {
+            graph = graph.add_output(output)?;
+        }
+
+        Ok(graph)
+    }
+
+    /// 转换一个算子，融合的激活函数拆成单独的算子放在后面
+    fn convert_operator(
+        &self,
+        index: usize,
+        op: schema::Operator<'a>,
+    ) -> Result<Vec<Operator>, LoadError> {
+        let (code, custom_code) = self
+            .model
+            .operator_codes()
+            .filter(|c| (op.opcode_index() as usize) < c.len())
+            .map(|c| c.get(op.opcode_index() as usize))
+            .map(|c| (c.builtin_code(), c.custom_code()))
+            .ok_or_else(|| anyhow!("算子{}的opcode_index {}无效", index, op.opcode_index()))?;
+        // float16模型中的DEQUANTIZE只是转换为float32
+        let fp16 = code == builtin::DEQUANTIZE
+            && op
+                .inputs()
+                .filter(|v| !v.is_empty())
+                .and_then(|v| self.tensors.get(v.get(0) as usize))
+                .is_some_and(|t| t.dtype() == DType::Float16);
+        let op_type = match code {
+            builtin::CUSTOM => custom_code.unwrap_or_default(),
+            builtin::DEQUANTIZE if fp16 => "Cast",
+            _ => op_type(code).ok_or_else(|| anyhow!("不支持TFLite内置算子{}", code))?,
+        };
+        let name = format!("{}_{}", op_type, index);
+
+        let mut inputs = vec![];
+        for i in op.inputs().into_iter().flatten() {
+            // -1表示未提供的可选输入
+            inputs.push(match i {
+                -1 => None,
+                i => Some(self.tensor(&name, i)?),
+            });
+        }
+        let mut outputs = vec![];
+        for i in op.outputs().into_iter().flatten() {
+            outputs.push(self.tensor(&name, i)?);
+        }
+        let input = |i: usize| {
+            inputs
+                .get(i)
+                .cloned()
+                .flatten()
+                .ok_or_else(|| LoadError::UndefinedTensor {
+                    node: name.clone(),
+                    tensor: format!("input {}", i),
+                })
+        };
+        let output = |i: usize| {
+            outputs
+                .get(i)
+                .cloned()
+                .ok_or_else(|| LoadError::UndefinedTensor {
+                    node: name.clone(),
+                    tensor: format!("output {}", i),
+                })
+        };
+        let opts = |kind: u8| {
+            op.builtin_options(kind)
+                .ok_or_else(|| LoadError::UnsupportedAttribute {
+                    node: name.clone(),
+                    attr: String::from("builtin_options"),
+                    reason: format!("options type {} is not {}", op.builtin_options_type(), kind),
+                })
+        };
+        let unsupported = |attr: &str, reason: String| LoadError::UnsupportedAttribute {
+            node: name.clone(),
+            attr: String::from(attr),
+            reason,
+        };
+
+        let mut node = Operator::new(&name, op_type);
+        // 大部分算子的输入与ONNX中的顺序一致
+        let mut same_inputs = true;
+        let mut fused = activation::NONE;
+        match code {
+            builtin::CONV_2D | builtin::DEPTHWISE_CONV_2D => {
+                let depthwise = code == builtin::DEPTHWISE_CONV_2D;
+                let o = opts(if depthwise {
+                    options::DEPTHWISE_CONV_2D
+                } else {
+                    options::CONV_2D
+                })?;
+                // 深度卷积多了depth_multiplier
+                let shift = depthwise as u16;
+                let (x, w) = (input(0)?, input(1)?);
+                let (xs, ws) = (x.shape().data(), w.shape().data());
+                if xs.len() != 4 || ws.len() != 4 {
+                    return Err(unsupported(
+                        "input",
+                        format!("input {} and filter {} need rank 4", x.shape(), w.shape()),
+                    ));
+                }
+                let group = if depthwise {
+                    xs[3]
+                } else if ws[3] > 0 && xs[3] % ws[3] == 0 {
+                    xs[3] / ws[3]
+                } else {
+                    1
+                };
+                fused = o.i8(3 + shift, 0);
+                node = node
+                    .add_attribute("kernel_shape", vec![ws[1] as i64, ws[2] as i64].into())?
+                    .add_attribute(
+                        "strides",
+                        vec![o.i32(2, 1) as i64, o.i32(1, 1) as i64].into(),
+                    )?
+                    .add_attribute(
+                        "dilations",
+                        vec![o.i32(5 + shift, 1) as i64, o.i32(4 + shift, 1) as i64].into(),
+                    )?
+                    .add_attribute("auto_pad", auto_pad(o.i8(0, 0)).into())?
+                    .add_attribute("group", (group as i64).into())?;
+            }
+            builtin::AVERAGE_POOL_2D | builtin::MAX_POOL_2D => {
+                let o = opts(options::POOL_2D)?;
+                fused = o.i8(5, 0);
+                node = node
+                    .add_attribute(
+                        "kernel_shape",
+                        vec![o.i32(4, 0) as i64, o.i32(3, 0) as i64].into(),
+                    )?
+                    .add_attribute(
+                        "strides",
+                        vec![o.i32(2, 1) as i64, o.i32(1, 1) as i64].into(),
+                    )?
+                    .add_attribute("auto_pad", auto_pad(o.i8(0, 0)).into())?;
+            }
+            builtin::FULLY_CONNECTED => {
+                let o = opts(options::FULLY_CONNECTED)?;
+                if o.i8(1, 0) != 0 {
+                    return Err(unsupported("weights_format", format!("{}", o.i8(1, 0))));
+                }
+                let x = input(0)?;
+                if x.shape().dim() != 2 || o.bool(2, false) {
+                    return Err(unsupported(
+                        "keep_num_dims",
+                        format!("input {} need rank 2", x.shape()),
+                    ));
+                }
+                fused = o.i8(0, 0);
+                // 权重为[out, in]
+                node = node.add_attribute("transB", 1.into())?;
+            }
+            builtin::SOFTMAX => {
+                let beta = opts(options::SOFTMAX)?.f32(0, 0.0);
+                if beta != 1.0 {
+                    return Err(unsupported("beta", format!("{} is not 1", beta)));
+                }
+                node = node.add_attribute("axis", (-1).into())?;
+            }
+            builtin::CONCATENATION => {
+                let o = opts(options::CONCATENATION)?;
+                fused = o.i8(1, 0);
+                node = node.add_attribute("axis", (o.i32(0, 0) as i64).into())?;
+            }
+            builtin::ADD => fused = opts(options::ADD)?.i8(0, 0),
+            builtin::SUB => fused = opts(options::SUB)?.i8(0, 0),
+            builtin::MUL => fused = opts(options::MUL)?.i8(0, 0),
+            builtin::DIV => fused = opts(options::DIV)?.i8(0, 0),
+            builtin::RELU6 | builtin::RELU_N1_TO_1 => {
+                let x = input(0)?;
+                let (min, max) = if code == builtin::RELU6 {
+                    (0.0, 6.0)
+                } else {
+                    (-1.0, 1.0)
+                };
+                same_inputs = false;
+                node = node
+                    .add_input("0", x.clone())?
+                    .add_input("1", scalar_like(&format!("{}_min", name), &x, min)?)?
+                    .add_input("2", scalar_like(&format!("{}_max", name), &x, max)?)?;
+            }
+            builtin::CAST => {
+                node = node.add_attribute("to", (output(0)?.dtype().get_code() as i64).into())?;
+            }
+            builtin::RESHAPE => {
+                // 形状依次取常量输入、选项和输出的形状
+                let shape = match inputs
+                    .get(1)
+                    .cloned()
+                    .flatten()
+                    .and_then(|t| const_ints(&t))
+                {
+                    Some(shape) => shape,
+                    None => match op
+                        .builtin_options(options::RESHAPE)
+                        .and_then(|o| o.i32s(0))
+                        .filter(|s| !s.is_empty())
+                    {
+                        Some(s) => s.iter().map(i64::from).collect(),
+                        None => output(0)?
+                            .shape()
+                            .data()
+                            .iter()
+                            .map(|&d| d as i64)
+                            .collect(),
+                    },
+                };
+                same_inputs = false;
+                node = node
+                    .add_input("0", input(0)?)?
+                    .add_input("1", int64_constant(&format!("{}_shape", name), shape)?)?;
+            }
+            builtin::TRANSPOSE => {
+                let perm = input(1)
+                    .ok()
+                    .and_then(|t| const_ints(&t))
+                    .ok_or_else(|| unsupported("perm", String::from("not constant")))?;
+                same_inputs = false;
+                node = node
+                    .add_input("0", input(0)?)?
+                    .add_attribute("perm", perm.into())?;
+            }
+            builtin::MEAN | builtin::REDUCE_MAX => {
+                let axes = input(1)
+                    .ok()
+                    .and_then(|t| const_ints(&t))
+                    .ok_or_else(|| unsupported("axes", String::from("not constant")))?;
+                let keep_dims = opts(options::REDUCER)?.bool(0, false);
+                same_inputs = false;
+                node = node
+                    .add_input("0", input(0)?)?
+                    .add_attribute("axes", axes.into())?
+                    .add_attribute("keepdims", (keep_dims as i64).into())?;
+            }
+            builtin::SQUEEZE => {
+                let axes: Vec<i64> = opts(options::SQUEEZE)?
+                    .i32s(0)
+                    .into_iter()
+                    .flatten()
+                    .map(i64::from)
+                    .collect();
+                same_inputs = false;
+                node = node.add_input("0", input(0)?)?;
+                if !axes.is_empty() {
+                    node = node.add_input("1", int64_constant(&format!("{}_axes", name), axes)?)?;
+                }
+            }
+            builtin::PAD => {
+                let x = input(0)?;
+                // 形状为[rank, 2]，转换为[begins..., ends...]
+                let paddings = input(1)
+                    .ok()
+                    .and_then(|t| const_ints(&t))
+                    .ok_or_else(|| unsupported("paddings", String::from("not constant")))?;
+                let pads: Vec<i64> = paddings
+                    .iter()
+                    .step_by(2)
+                    .chain(paddings.iter().skip(1).step_by(2))
+                    .copied()
+                    .collect();
+                same_inputs = false;
+                node = node
+                    .add_input("0", x.clone())?
+                    .add_input("1", int64_constant(&format!("{}_pads", name), pads)?)?;
+                // 量化张量按零点填充
+                if x.quantization().is_some() {
+                    node =
+                        node.add_input("2", scalar_like(&format!("{}_value", name), &x, 0.0)?)?;
+                }
+            }
+            builtin::GATHER => {
+                let o = opts(options::GATHER)?;
+                if o.i32(1, 0) != 0 {
+                    return Err(unsupported("batch_dims", format!("{}", o.i32(1, 0))));
+                }
+                node = node.add_attribute("axis", (o.i32(0, 0) as i64).into())?;
+            }
+            builtin::LEAKY_RELU => {
+                node =
+                    node.add_attribute("alpha", opts(options::LEAKY_RELU)?.f32(0, 0.0).into())?;
+            }
+            builtin::DEQUANTIZE if fp16 => {
+                node = node.add_attribute("to", (DType::Float32.get_code() as i64).into())?;
+            }
+            builtin::QUANTIZE | builtin::DEQUANTIZE => {
+                // 量化参数在量化后的张量上
+                let quantized = if code == builtin::QUANTIZE {
+                    output(0)?
+                } else {
+                    input(0)?
+                };
+                let q = quantized.quantization().cloned().ok_or_else(|| {
+                    invalid_tensor(quantized.name(), String::from("no quantization parameters"))
+                        .in_node(&name)
+                })?;
+                let channels = q.scale.len() as u32;
+                let scale =
+                    Tensor::from_vec(&format!("{}_scale", name), &[channels], q.scale.clone())?;
+                let zero_point = zero_point(&format!("{}_zero_point", name), &quantized, &q)?;
+                same_inputs = false;
+                node = node
+                    .add_input("0", input(0)?)?
+                    .add_input("1", constant(scale))?
+                    .add_input("2", constant(zero_point))?;
+                if q.is_per_channel() {
+                    node = node.add_attribute("axis", (q.axis as i64).into())?;
+                }
+            }
+            builtin::CUSTOM => {
+                node = node.set_opset(CUSTOM_DOMAIN, 0)?;
+                if let Some(o) = op.custom_options() {
+                    node = node.add_attribute(
+                        "custom_options",
+                        Attribute::from_vec_u8_as_string(o.bytes().to_vec()),
+                    )?;
+                }
+            }
+            _ => {}
+        }
+
+        if same_inputs {
+            for (i, t) in inputs.iter().enumerate() {
+                if let Some(t) = t {
+                    node = node.add_input(&i.to_string(), t.clone())?;
+                }
+            }
+        }
+        if fused == activation::NONE {
+            for (i, t) in outputs.iter().enumerate() {
+                node = node.add_output(&i.to_string(), t.clone())?;
+            }
+            return Ok(vec![node]);
+        }
+
+        // 融合的激活函数在输出的量化参数下计算，中间结果使用相同的参数
+        let y = output(0)?;
+        let mut x = Tensor::new_with_shape(
+            &format!("{}_{}", y.name(), "preact"),
+            y.shape().data(),
+            y.format(),
+            y.dtype(),
+            Type::Variable,
+        );
+        if let Some(q) = y.quantization() {
+            x = x.with_quantization(q.clone());
+        }
+        node = node.add_output("0", x.clone())?;
+        let act_type = match fused {
+            activation::RELU => "Relu",
+            activation::RELU_N1_TO_1 | activation::RELU6 => "Clip",
+            activation::TANH => "Tanh",
+            _ => {
+                return Err(unsupported(
+                    "fused_activation_function",
+                    format!("{}", fused),
+                ))
+            }
+        };
+        let act_name = format!("{}_{}", name, act_type);
+        let mut act = Operator::new(&act_name, act_type).add_input("0", x.clone())?;
+        if act_type == "Clip" {
+            let (min, max) = if fused == activation::RELU6 {
+                (0.0, 6.0)
+            } else {
+                (-1.0, 1.0)
+            };
+            act = act
+                .add_input("1", scalar_like(&format!("{}_min", act_name), &x, min)?)?
+                .add_input("2", scalar_like(&format!("{}_max", act_name), &x, max)?)?;
+        }
+        act = act.add_output("0", y)?;
+
+        Ok(vec![node, act])
+    }
+}
+
+/// 按ONNX算子定义检查算子并设置版本，补充属性默认值
+fn check_operator(mut op: Operator) -> Result<Operator, LoadError> {
+    if op.domain() == ONNX_DOMAIN {
+        let registry = Registry::global();
+        let schema = match registry.check(ONNX_DOMAIN, ONNX_OPSET, &op) {
+            Ok(schema) => schema,
+            // 量化张量直接作为浮点算子的输入输出，按量化参数计算
+            Err(SchemaError::DType { .. }) if has_quantized(&op) => registry
+                .get(ONNX_DOMAIN, op.r#type(), ONNX_OPSET)
+                .ok_or_else(|| anyhow!("算子{}没有定义", op.r#type()))?,
+            Err(e) => return Err(e.into()),
+        };
+        for a in schema.attributes.iter() {
+            if let Some(v) = a.default_value() {
+                op = op.add_default_attribute(&a.name, v)?;
+            }
+        }
+        op = op.set_opset(ONNX_DOMAIN, schema.version)?;
+    }
+    trace!("tflite op {}({})", op.name(), op.r#type());
+
+    Ok(op)
+}
+
+/// 内置算子对应的ONNX算子
+fn op_type(code: i32) -> Option<&'static str> {
+    use builtin::*;
+
+    let op_type = match code {
+        ADD => "Add",
+        SUB => "Sub",
+        MUL => "Mul",
+        DIV => "Div",
+        RELU => "Relu",
+        RELU6 | RELU_N1_TO_1 => "Clip",
+        LOGISTIC => "Sigmoid",
+        TANH => "Tanh",
+        EXP => "Exp",
+        LOG => "Log",
+        FLOOR => "Floor",
+        NEG => "Neg",
+        ABS => "Abs",
+        SQRT => "Sqrt",
+        POW => "Pow",
+        MAXIMUM => "Max",
+        MINIMUM => "Min",
+        LESS => "Less",
+        GREATER => "Greater",
+        EQUAL => "Equal",
+        HARD_SWISH => "HardSwish",
+        CAST => "Cast",
+        PRELU => "PRelu",
+        LEAKY_RELU => "LeakyRelu",
+        CONV_2D | DEPTHWISE_CONV_2D => "Conv",
+        AVERAGE_POOL_2D => "AveragePool",
+        MAX_POOL_2D => "MaxPool",
+        FULLY_CONNECTED => "Gemm",
+        SOFTMAX => "Softmax",
+        CONCATENATION => "Concat",
+        RESHAPE => "Reshape",
+        TRANSPOSE => "Transpose",
+        MEAN => "ReduceMean",
+        REDUCE_MAX => "ReduceMax",
+        SQUEEZE => "Squeeze",
+        PAD => "Pad",
+        GATHER => "Gather",
+        QUANTIZE => "QuantizeLinear",
+        DEQUANTIZE => "DequantizeLinear",
+        _ => return None,
+    };
+    Some(op_type)
+}
+
+fn trans_dtype(tensor: &str, code: i8) -> Result<DType, LoadError> {
+    let dtype = match code {
+        tensor_type::FLOAT32 => DType::Float32,
+        tensor_type::FLOAT16 => DType::Float16,
+        tensor_type::FLOAT64 => DType::Float64,
+        tensor_type::INT8 => DType::Int8,
+        tensor_type::INT16 => DType::Int16,
+        tensor_type::INT32 => DType::Int32,
+        tensor_type::INT64 => DType::Int64,
+        tensor_type::UINT8 => DType::Uint8,
+        tensor_type::UINT16 => DType::Uint16,
+        tensor_type::UINT32 => DType::Uint32,
+        tensor_type::UINT64 => DType::Uint64,
+        tensor_type::BOOL => DType::Bool,
+        tensor_type::STRING => DType::String,
+        tensor_type::COMPLEX64 => DType::Complex64,
+        tensor_type::COMPLEX128 => DType::Complex128,
+        _ => {
+            return Err(LoadError::UnsupportedDType {
+                node: String::new(),
+                tensor: String::from(tensor),
+                dtype: code as i32,
+                reason: String::from("unknown TFLite tensor type"),
+            })
+        }
+    };
+    Ok(dtype)
+}
+
+fn trans_quantization(q: schema::QuantizationParameters) -> Option<Quantization> {
+    let scale: Vec<f32> = q.scale()?.iter().collect();
+    if scale.is_empty() {
+        return None;
+    }
+    let mut zero_point: Vec<i64> = q.zero_point().into_iter().flatten().collect();
+    zero_point.resize(scale.len(), 0);
+
+    Some(Quantization {
+        scale,
+        zero_point,
+        axis: q.quantized_dimension(),
+    })
+}
+
+fn has_quantized(op: &Operator) -> bool {
+    op.inputs()
+        .values()
+        .chain(op.outputs().values())
+        .any(|t| t.quantization().is_some())
+}
+
+fn auto_pad(padding: i8) -> &'static str {
+    // 0为SAME，1为VALID
+    match padding {
+        1 => "VALID",
+        _ => "SAME_UPPER",
+    }
+}
+
+fn invalid_tensor(tensor: &str, reason: String) -> LoadError {
+    LoadError::InvalidTensor {
+        node: String::new(),
+        tensor: String::from(tensor),
+        reason,
+    }
+}
+
+fn constant(tensor: Tensor) -> Tensor {
+    tensor.with_format(Format::NHWC).with_type(Type::Constant)
+}
+
+fn int64_constant(name: &str, vs: Vec<i64>) -> Result<Tensor, LoadError> {
+    Ok(constant(Tensor::from_vec(name, &[vs.len() as u32], vs)?))
+}
+
+/// 常量整数张量的值
+fn const_ints(t: &Tensor) -> Option<Vec<i64>> {
+    if t.r#type() != Type::Constant {
+        return None;
+    }
+    match t.dtype() {
+        DType::Int32 => t
+            .to_vec::<i32>()
+            .ok()
+            .map(|v| v.into_iter().map(i64::from).collect()),
+        DType::Int64 => t.to_vec::<i64>().ok(),
+        _ => None,
+    }
+}
+
+/// 与`t`类型和量化参数相同，值为`v`的标量
+fn scalar_like(name: &str, t: &Tensor, v: f32) -> Result<Tensor, LoadError> {
+    let quantize = |q: &Quantization| (v / q.scale[0]).round() as i64 + q.zero_point[0];
+    let tensor = match (t.dtype(), t.quantization()) {
+        (DType::Float32, _) => Tensor::from_vec(name, &[1], vec![v])?,
+        (DType::Int8, Some(q)) => {
+            Tensor::from_vec(name, &[1], vec![quantize(q).clamp(-128, 127) as i8])?
+                .with_quantization(Quantization::per_tensor(q.scale[0], q.zero_point[0]))
+        }
+        (DType::Uint8, Some(q)) => {
+            Tensor::from_vec(name, &[1], vec![quantize(q).clamp(0, 255) as u8])?
+                .with_quantization(Quantization::per_tensor(q.scale[0], q.zero_point[0]))
+        }
+        (dtype, _) => {
+            return Err(LoadError::UnsupportedDType {
+                node: String::new(),
+                tensor: t.name().clone(),
+                dtype: dtype.get_code() as i32,
+                reason: format!("can not represent {}", v),
+            })
+        }
+    };
+    Ok(constant(tensor))
+}
+
+/// 零点的类型与量化后的张量相同
+fn zero_point(name: &str, t: &Tensor, q: &Quantization) -> Result<Tensor, LoadError> {
+    let shape = [q.zero_point.len() as u32];
+    let zp = &q.zero_point;
+    let tensor = match t.dtype() {
+        DType::Int8 => Tensor::from_vec(name, &shape, zp.iter().map(|&z| z as i8).collect())?,
+        DType::Uint8 => Tensor::from_vec(name, &shape, zp.iter().map(|&z| z as u8).collect())?,
+        DType::Int16 => Tensor::from_vec(name, &shape, zp.iter().map(|&z| z as i16).collect())?,
+        DType::Int32 => Tensor::from_vec(name, &shape, zp.iter().map(|&z| z as i32).collect())?,
+        dtype => {
+            return Err(LoadError::UnsupportedDType {
+                node: String::new(),
+                tensor: t.name().clone(),
+                dtype: dtype.get_code() as i32,
+                reason: String::from("not a quantized type"),
+            })
+        }
+    };
+    Ok(tensor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flatbuffers::{FlatBufferBuilder, UnionWIPOffset, WIPOffset};
+
+    enum Field {
+        I8(i8),
+        U8(u8),
+        I32(i32),
+        U32(u32),
+        Offset(WIPOffset<UnionWIPOffset>),
+    }
+
+    fn table(b: &mut FlatBufferBuilder, fields: &[(u16, Field)]) -> WIPOffset<UnionWIPOffset> {
+        let start = b.start_table();
+        for (id, field) in fields {
+            let slot = 4 + 2 * id;
+            match field {
+                Field::I8(v) => b.push_slot_always(slot, *v),
+                Field::U8(v) => b.push_slot_always(slot, *v),
+                Field::I32(v) => b.push_slot_always(slot, *v),
+                Field::U32(v) => b.push_slot_always(slot, *v),
+                Field::Offset(v) => b.push_slot_always(slot, *v),
+            }
+        }
+        b.end_table(start).as_union_value()
+    }
+
+    fn ints(b: &mut FlatBufferBuilder, vs: &[i32]) -> Field {
+        Field::Offset(b.create_vector(vs).as_union_value())
+    }
+
+    fn tensor(
+        b: &mut FlatBufferBuilder,
+        name: &str,
+        shape: &[i32],
+        dtype: i8,
+        buffer: u32,
+        quantization: Option<(&[f32], &[i64])>,
+    ) -> WIPOffset<UnionWIPOffset> {
+        let mut fields = vec![
+            (0, ints(b, shape)),
+            (1, Field::I8(dtype)),
+            (2, Field::U32(buffer)),
+            (3, Field::Offset(b.create_string(name).as_union_value())),
+        ];
+        if let Some((scale, zero_point)) = quantization {
+            let scale = Field::Offset(b.create_vector(scale).as_union_value());
+            let zero_point = Field::Offset(b.create_vector(zero_point).as_union_value());
+            let q = table(b, &[(2, scale), (3, zero_point)]);
+            fields.push((4, Field::Offset(q)));
+        }
+        table(b, &fields)
+    }
+
+    fn operator(
+        b: &mut FlatBufferBuilder,
+        opcode: u32,
+        inputs: &[i32],
+        outputs: &[i32],
+        options: Option<(u8, WIPOffset<UnionWIPOffset>)>,
+    ) -> WIPOffset<UnionWIPOffset> {
+        let mut fields = vec![
+            (0, Field::U32(opcode)),
+            (1, ints(b, inputs)),
+            (2, ints(b, outputs)),
+        ];
+        if let Some((kind, o)) = options {
+            fields.push((3, Field::U8(kind)));
+            fields.push((4, Field::Offset(o)));
+        }
+        table(b, &fields)
+    }
+
+    /// 只有一个子图的模型，0号buffer为空，codes为(内置算子编号, 自定义算子名)
+    fn single_graph(
+        mut b: FlatBufferBuilder,
+        buffers: &[&[u8]],
+        tensors: &[WIPOffset<UnionWIPOffset>],
+        operators: &[WIPOffset<UnionWIPOffset>],
+        codes: &[(i32, Option<&str>)],
+        inputs: &[i32],
+        outputs: &[i32],
+    ) -> Vec<u8> {
+        let buffers: Vec<_> = [&[][..]]
+            .iter()
+            .chain(buffers)
+            .map(|data| {
+                let data = Field::Offset(b.create_vector(data).as_union_value());
+                table(&mut b, &[(0, data)])
+            })
+            .collect();
+        let buffers = Field::Offset(b.create_vector(&buffers).as_union_value());
+        let tensors = Field::Offset(b.create_vector(tensors).as_union_value());
+        let operators = Field::Offset(b.create_vector(operators).as_union_value());
+        let (inputs, outputs) = (ints(&mut b, inputs), ints(&mut b, outputs));
+        let subgraph = table(
+            &mut b,
+            &[(0, tensors), (1, inputs), (2, outputs), (3, operators)],
+        );
+        let subgraphs = Field::Offset(b.create_vector(&[subgraph]).as_union_value());
+        let codes: Vec<_> = codes
+            .iter()
+            .map(|&(code, custom)| {
+                let mut fields = vec![(0, Field::I8(code as i8)), (3, Field::I32(code))];
+                if let Some(custom) = custom {
+                    fields.push((1, Field::Offset(b.create_string(custom).as_union_value())));
+                }
+                table(&mut b, &fields)
+            })
+            .collect();
+        let codes = Field::Offset(b.create_vector(&codes).as_union_value());
+        let model = table(
+            &mut b,
+            &[(0, Field::U32(3)), (1, codes), (2, subgraphs), (4, buffers)],
+        );
+        b.finish(model, Some(schema::FILE_IDENTIFIER));
+        b.finished_data().to_vec()
+    }
+
+    fn le_bytes(vs: &[i32]) -> Vec<u8> {
+        vs.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// int8的1x1卷积融合Relu，输出反量化为float32
+    fn conv_model() -> Vec<u8> {
+        let mut b = FlatBufferBuilder::new();
+        let buffers: Vec<_> = [
+            &[][..],
+            &[1, 2, 3, 4, 5, 6],
+            &[1, 0, 0, 0, 2, 0, 0, 0],
+            b"1.14.0\0\0",
+        ]
+        .iter()
+        .map(|data| {
+            let data = Field::Offset(b.create_vector(data).as_union_value());
+            table(&mut b, &[(0, data)])
+        })
+        .collect();
+        let buffers = Field::Offset(b.create_vector(&buffers).as_union_value());
+
+        let tensors = vec![
+            tensor(
+                &mut b,
+                "input",
+                &[1, 4, 4, 3],
+                tensor_type::INT8,
+                0,
+                Some((&[0.5], &[-1])),
+            ),
+            tensor(
+                &mut b,
+                "filter",
+                &[2, 1, 1, 3],
+                tensor_type::INT8,
+                1,
+                Some((&[0.1, 0.2], &[0, 0])),
+            ),
+            tensor(&mut b, "bias", &[2], tensor_type::INT32, 2, None),
+            tensor(
+                &mut b,
+                "output",
+                &[1, 4, 4, 2],
+                tensor_type::INT8,
+                0,
+                Some((&[0.25], &[-128])),
+            ),
+            tensor(
+                &mut b,
+                "probs",
+                &[1, 4, 4, 2],
+                tensor_type::FLOAT32,
+                0,
+                None,
+            ),
+        ];
+        let tensors = Field::Offset(b.create_vector(&tensors).as_union_value());
+
+        let options = table(
+            &mut b,
+            &[
+                (0, Field::I8(1)),
+                (1, Field::I32(1)),
+                (2, Field::I32(1)),
+                (3, Field::I8(activation::RELU)),
+            ],
+        );
+        let conv = (ints(&mut b, &[0, 1, 2]), ints(&mut b, &[3]));
+        let conv = table(
+            &mut b,
+            &[
+                (0, Field::U32(0)),
+                (1, conv.0),
+                (2, conv.1),
+                (3, Field::U8(options::CONV_2D)),
+                (4, Field::Offset(options)),
+            ],
+        );
+        let dequantize = (ints(&mut b, &[3]), ints(&mut b, &[4]));
+        let dequantize = table(
+            &mut b,
+            &[(0, Field::U32(1)), (1, dequantize.0), (2, dequantize.1)],
+        );
+        let operators = Field::Offset(b.create_vector(&[conv, dequantize]).as_union_value());
+
+        let subgraph_inputs = ints(&mut b, &[0]);
+        let subgraph_outputs = ints(&mut b, &[4]);
+        let name = Field::Offset(b.create_string("main").as_union_value());
+        let subgraph = table(
+            &mut b,
+            &[
+                (0, tensors),
+                (1, subgraph_inputs),
+                (2, subgraph_outputs),
+                (3, operators),
+                (4, name),
+            ],
+        );
+        let subgraphs = Field::Offset(b.create_vector(&[subgraph]).as_union_value());
+
+        let codes: Vec<_> = [builtin::CONV_2D, builtin::DEQUANTIZE]
+            .iter()
+            .map(|&code| table(&mut b, &[(0, Field::I8(code as i8)), (3, Field::I32(code))]))
+            .collect();
+        let codes = Field::Offset(b.create_vector(&codes).as_union_value());
+        let metadata_name = Field::Offset(b.create_string("min_runtime_version").as_union_value());
+        let metadata = table(&mut b, &[(0, metadata_name), (1, Field::U32(3))]);
+        let metadata = Field::Offset(b.create_vector(&[metadata]).as_union_value());
+        let description = Field::Offset(b.create_string("test").as_union_value());
+
+        let model = table(
+            &mut b,
+            &[
+                (0, Field::U32(3)),
+                (1, codes),
+                (2, subgraphs),
+                (3, description),
+                (4, buffers),
+                (6, metadata),
+            ],
+        );
+        b.finish(model, Some(schema::FILE_IDENTIFIER));
+        b.finished_data().to_vec()
+    }
+
+    #[test]
+    fn load_works() {
+        let bytes = conv_model();
+        assert!(is_tflite(&bytes));
+        let (graph, info) = load_from_bytes(&bytes, &Options::default()).unwrap();
+        assert_eq!(3, graph.operators().len());
+        assert_eq!(Some(ONNX_OPSET), graph.opset_version(ONNX_DOMAIN));
+        assert_eq!(1, graph.inputs().len());
+        assert_eq!(1, graph.outputs().len());
+
+        let conv = graph.get_operator("Conv_0").unwrap();
+        assert!(conv.since_version() > 0);
+        assert_eq!(vec![1, 1], conv.attr_ints("kernel_shape").unwrap());
+        assert_eq!(vec![1, 1], conv.attr_ints("strides").unwrap());
+        assert_eq!("VALID", conv.attr_string("auto_pad").unwrap());
+        assert_eq!(1, conv.attr_int("group").unwrap());
+        let filter = conv.get_input("1").unwrap();
+        assert_eq!(Type::Constant, filter.r#type());
+        assert_eq!(Format::NHWC, filter.format());
+        assert_eq!(Some(&[1u8, 2, 3, 4, 5, 6][..]), filter.as_bytes());
+        let q = filter.quantization().unwrap();
+        assert!(q.is_per_channel());
+        assert_eq!(vec![0.1, 0.2], q.scale);
+        assert_eq!(
+            vec![1, 2],
+            conv.get_input("2").unwrap().to_vec::<i32>().unwrap()
+        );
+        assert_eq!("output_preact", conv.get_output("0").unwrap().name());
+
+        let relu = graph.get_operator("Conv_0_Relu").unwrap();
+        let output = relu.get_output("0").unwrap();
+        assert_eq!("output", output.name());
+        assert_eq!(
+            Some(&Quantization::per_tensor(0.25, -128)),
+            output.quantization()
+        );
+
+        let dequantize = graph.get_operator("DequantizeLinear_1").unwrap();
+        let scale = dequantize.get_input("1").unwrap();
+        assert_eq!(vec![0.25], scale.to_vec::<f32>().unwrap());
+        let zero_point = dequantize.get_input("2").unwrap();
+        assert_eq!(vec![-128], zero_point.to_vec::<i8>().unwrap());
+
+        assert_eq!(3, info.model_version);
+        assert_eq!("test", info.doc_string);
+        assert_eq!(Some("1.14.0"), info.metadata("min_runtime_version"));
+        assert_eq!("input", info.inputs[0].name);
+        assert_eq!(DType::Int8, info.inputs[0].dtype);
+        assert_eq!(
+            Some(vec![
+                Dim::Value(1),
+                Dim::Value(4),
+                Dim::Value(4),
+                Dim::Value(3)
+            ]),
+            info.inputs[0].dims
+        );
+        assert_eq!(DType::Float32, info.outputs[0].dtype);
+    }
+
+    #[test]
+    fn pad_works() {
+        let mut b = FlatBufferBuilder::new();
+        let q = Some((&[0.5_f32][..], &[-1_i64][..]));
+        let tensors = [
+            tensor(&mut b, "input", &[1, 2, 2, 1], tensor_type::INT8, 0, q),
+            tensor(&mut b, "paddings", &[4, 2], tensor_type::INT32, 1, None),
+            tensor(&mut b, "output", &[1, 5, 9, 1], tensor_type::INT8, 0, q),
+        ];
+        let pad = operator(&mut b, 0, &[0, 1], &[2], None);
+        let paddings = le_bytes(&[0, 0, 1, 2, 3, 4, 0, 0]);
+        let bytes = single_graph(
+            b,
+            &[&paddings],
+            &tensors,
+            &[pad],
+            &[(builtin::PAD, None)],
+            &[0],
+            &[2],
+        );
+
+        let (graph, _) = load_from_bytes(&bytes, &Options::default()).unwrap();
+        let pad = graph.get_operator("Pad_0").unwrap();
+        // [rank, 2]转换为[begins..., ends...]
+        assert_eq!(
+            vec![0, 1, 3, 0, 0, 2, 4, 0],
+            pad.get_input("1").unwrap().to_vec::<i64>().unwrap()
+        );
+        // 量化张量按零点填充
+        let value = pad.get_input("2").unwrap();
+        assert_eq!(vec![-1], value.to_vec::<i8>().unwrap());
+        assert_eq!(
+            Some(&Quantization::per_tensor(0.5, -1)),
+            value.quantization()
+        );
+    }
+
+    #[test]
+    fn reshape_works() {
+        let mut b = FlatBufferBuilder::new();
+        let tensors = [
+            tensor(&mut b, "input", &[1, 2, 3], tensor_type::FLOAT32, 0, None),
+            tensor(&mut b, "shape", &[2], tensor_type::INT32, 1, None),
+            tensor(&mut b, "mid", &[3, 2], tensor_type::FLOAT32, 0, None),
+            tensor(&mut b, "output", &[6], tensor_type::FLOAT32, 0, None),
+        ];
+        // 第一个Reshape的形状来自常量输入，第二个来自选项
+        let first = operator(&mut b, 0, &[0, 1], &[2], None);
+        let new_shape = ints(&mut b, &[6]);
+        let o = table(&mut b, &[(0, new_shape)]);
+        let second = operator(&mut b, 0, &[2], &[3], Some((options::RESHAPE, o)));
+        let shape = le_bytes(&[3, 2]);
+        let bytes = single_graph(
+            b,
+            &[&shape],
+            &tensors,
+            &[first, second],
+            &[(builtin::RESHAPE, None)],
+            &[0],
+            &[3],
+        );
+
+        let (graph, _) = load_from_bytes(&bytes, &Options::default()).unwrap();
+        let shape = |name: &str| {
+            let op = graph.get_operator(name).unwrap();
+            assert_eq!(DType::Int64, op.get_input("1").unwrap().dtype());
+            op.get_input("1").unwrap().to_vec::<i64>().unwrap()
+        };
+        assert_eq!(vec![3, 2], shape("Reshape_0"));
+        assert_eq!(vec![6], shape("Reshape_1"));
+    }
+
+    #[test]
+    fn fp16_dequantize_cast() {
+        let mut b = FlatBufferBuilder::new();
+        let tensors = [
+            tensor(&mut b, "weight", &[2], tensor_type::FLOAT16, 1, None),
+            tensor(&mut b, "output", &[2], tensor_type::FLOAT32, 0, None),
+            tensor(&mut b, "input", &[2], tensor_type::FLOAT32, 0, None),
+            tensor(&mut b, "sum", &[2], tensor_type::FLOAT32, 0, None),
+        ];
+        let dequantize = operator(&mut b, 0, &[0], &[1], None);
+        let o = table(&mut b, &[]);
+        let add = operator(&mut b, 1, &[2, 1], &[3], Some((options::ADD, o)));
+        // float16的1.0和2.0
+        let weight = [0x00, 0x3c, 0x00, 0x40];
+        let bytes = single_graph(
+            b,
+            &[&weight],
+            &tensors,
+            &[dequantize, add],
+            &[(builtin::DEQUANTIZE, None), (builtin::ADD, None)],
+            &[2],
+            &[3],
+        );
+
+        let (graph, _) = load_from_bytes(&bytes, &Options::default()).unwrap();
+        let cast = graph.get_operator("Cast_0").unwrap();
+        assert_eq!("Cast", cast.r#type());
+        assert_eq!(
+            DType::Float32.get_code() as i64,
+            cast.attr_int("to").unwrap()
+        );
+        assert_eq!(DType::Float16, cast.get_input("0").unwrap().dtype());
+        assert!(graph.get_operator("DequantizeLinear_0").is_none());
+    }
+
+    #[test]
+    fn custom_works() {
+        let mut b = FlatBufferBuilder::new();
+        let tensors = [
+            tensor(&mut b, "input", &[2], tensor_type::FLOAT32, 0, None),
+            tensor(&mut b, "output", &[2], tensor_type::FLOAT32, 0, None),
+        ];
+        let (inputs, outputs) = (ints(&mut b, &[0]), ints(&mut b, &[1]));
+        let custom_options = Field::Offset(b.create_vector(&[1u8, 2, 3]).as_union_value());
+        let custom = table(
+            &mut b,
+            &[
+                (0, Field::U32(0)),
+                (1, inputs),
+                (2, outputs),
+                (5, custom_options),
+            ],
+        );
+        let bytes = single_graph(
+            b,
+            &[],
+            &tensors,
+            &[custom],
+            &[(builtin::CUSTOM, Some("MyOp"))],
+            &[0],
+            &[1],
+        );
+
+        let (graph, _) = load_from_bytes(&bytes, &Options::default()).unwrap();
+        assert_eq!(Some(1), graph.opset_version(CUSTOM_DOMAIN));
+        let op = graph.get_operator("MyOp_0").unwrap();
+        assert_eq!(CUSTOM_DOMAIN, op.domain());
+        assert_eq!("MyOp", op.r#type());
+        assert!(matches!(
+            op.get_attribute("custom_options"),
+            Some(Attribute::String(v)) if v == &[1, 2, 3]
+        ));
+    }
+
+    #[test]
+    fn load_rejects_invalid() {
+        let mut bytes = conv_model();
+        // 根表偏移越界
+        bytes[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_tflite(&bytes));
+        assert!(matches!(
+            load_from_bytes(&bytes, &Options::default()),
+            Err(LoadError::InvalidModel(_))
+        ));
+        assert!(!is_tflite(b"\x08\x01\x12\x00"));
+    }
+}
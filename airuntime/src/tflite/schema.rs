@@ -0,0 +1,520 @@
+//! TFLite模型格式中加载需要的部分
+//!
+//! 按`tensorflow/lite/schema/schema.fbs`手写的只读访问，字段编号与schema一致。
+//! 访问前必须先通过[`root`]校验整个buffer
+
+use flatbuffers::{
+    Follow, ForwardsUOffset, InvalidFlatbuffer, Table, VOffsetT, Vector, Verifiable, Verifier,
+};
+
+/// 文件标识，位于第4到8字节
+pub const FILE_IDENTIFIER: &str = "TFL3";
+
+/// 字段编号对应的vtable偏移
+const fn vt(field: u16) -> VOffsetT {
+    4 + 2 * field
+}
+
+/// 校验buffer并返回模型
+pub fn root(buf: &[u8]) -> Result<Model<'_>, InvalidFlatbuffer> {
+    flatbuffers::root::<Model>(buf)
+}
+
+macro_rules! table {
+    ($name:ident) => {
+        #[derive(Copy, Clone)]
+        pub struct $name<'a> {
+            tab: Table<'a>,
+        }
+
+        impl<'a> Follow<'a> for $name<'a> {
+            type Inner = $name<'a>;
+
+            unsafe fn follow(buf: &'a [u8], loc: usize) -> Self::Inner {
+                Self {
+                    tab: Table::new(buf, loc),
+                }
+            }
+        }
+    };
+}
+
+// 以下访问函数只用于校验过的表，字段类型与校验时一致
+fn scalar<'a, T>(tab: &Table<'a>, field: u16, default: T) -> T
+where
+    T: Follow<'a, Inner = T> + Copy + 'a,
+{
+    unsafe { tab.get::<T>(vt(field), Some(default)) }.unwrap_or(default)
+}
+
+fn vector<'a, T: Follow<'a> + 'a>(tab: &Table<'a>, field: u16) -> Option<Vector<'a, T>> {
+    unsafe { tab.get::<ForwardsUOffset<Vector<'a, T>>>(vt(field), None) }
+}
+
+fn string<'a>(tab: &Table<'a>, field: u16) -> Option<&'a str> {
+    unsafe { tab.get::<ForwardsUOffset<&str>>(vt(field), None) }
+}
+
+fn table<'a, T: Follow<'a> + 'a>(tab: &Table<'a>, field: u16) -> Option<T::Inner> {
+    unsafe { tab.get::<ForwardsUOffset<T>>(vt(field), None) }
+}
+
+type Tables<'a, T> = ForwardsUOffset<Vector<'a, ForwardsUOffset<T>>>;
+type Scalars<'a, T> = ForwardsUOffset<Vector<'a, T>>;
+
+table!(Model);
+
+impl<'a> Model<'a> {
+    pub fn version(&self) -> u32 {
+        scalar(&self.tab, 0, 0)
+    }
+
+    pub fn operator_codes(&self) -> Option<Vector<'a, ForwardsUOffset<OperatorCode<'a>>>> {
+        vector(&self.tab, 1)
+    }
+
+    pub fn subgraphs(&self) -> Option<Vector<'a, ForwardsUOffset<SubGraph<'a>>>> {
+        vector(&self.tab, 2)
+    }
+
+    pub fn description(&self) -> Option<&'a str> {
+        string(&self.tab, 3)
+    }
+
+    pub fn buffers(&self) -> Option<Vector<'a, ForwardsUOffset<Buffer<'a>>>> {
+        vector(&self.tab, 4)
+    }
+
+    pub fn metadata(&self) -> Option<Vector<'a, ForwardsUOffset<Metadata<'a>>>> {
+        vector(&self.tab, 6)
+    }
+}
+
+impl Verifiable for Model<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<u32>("version", vt(0), false)?
+            .visit_field::<Tables<OperatorCode>>("operator_codes", vt(1), false)?
+            .visit_field::<Tables<SubGraph>>("subgraphs", vt(2), false)?
+            .visit_field::<ForwardsUOffset<&str>>("description", vt(3), false)?
+            .visit_field::<Tables<Buffer>>("buffers", vt(4), false)?
+            .visit_field::<Tables<Metadata>>("metadata", vt(6), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+table!(OperatorCode);
+
+impl<'a> OperatorCode<'a> {
+    /// 算子编号，兼容只有deprecated_builtin_code的旧模型
+    pub fn builtin_code(&self) -> i32 {
+        let deprecated: i8 = scalar(&self.tab, 0, 0);
+        let code: i32 = scalar(&self.tab, 3, 0);
+        code.max(deprecated as i32)
+    }
+
+    pub fn custom_code(&self) -> Option<&'a str> {
+        string(&self.tab, 1)
+    }
+}
+
+impl Verifiable for OperatorCode<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<i8>("deprecated_builtin_code", vt(0), false)?
+            .visit_field::<ForwardsUOffset<&str>>("custom_code", vt(1), false)?
+            .visit_field::<i32>("version", vt(2), false)?
+            .visit_field::<i32>("builtin_code", vt(3), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+table!(SubGraph);
+
+impl<'a> SubGraph<'a> {
+    pub fn tensors(&self) -> Option<Vector<'a, ForwardsUOffset<Tensor<'a>>>> {
+        vector(&self.tab, 0)
+    }
+
+    pub fn inputs(&self) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, 1)
+    }
+
+    pub fn outputs(&self) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, 2)
+    }
+
+    pub fn operators(&self) -> Option<Vector<'a, ForwardsUOffset<Operator<'a>>>> {
+        vector(&self.tab, 3)
+    }
+
+    pub fn name(&self) -> Option<&'a str> {
+        string(&self.tab, 4)
+    }
+}
+
+impl Verifiable for SubGraph<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<Tables<Tensor>>("tensors", vt(0), false)?
+            .visit_field::<Scalars<i32>>("inputs", vt(1), false)?
+            .visit_field::<Scalars<i32>>("outputs", vt(2), false)?
+            .visit_field::<Tables<Operator>>("operators", vt(3), false)?
+            .visit_field::<ForwardsUOffset<&str>>("name", vt(4), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+/// TensorType枚举
+pub mod tensor_type {
+    pub const FLOAT32: i8 = 0;
+    pub const FLOAT16: i8 = 1;
+    pub const INT32: i8 = 2;
+    pub const UINT8: i8 = 3;
+    pub const INT64: i8 = 4;
+    pub const STRING: i8 = 5;
+    pub const BOOL: i8 = 6;
+    pub const INT16: i8 = 7;
+    pub const COMPLEX64: i8 = 8;
+    pub const INT8: i8 = 9;
+    pub const FLOAT64: i8 = 10;
+    pub const COMPLEX128: i8 = 11;
+    pub const UINT64: i8 = 12;
+    pub const UINT32: i8 = 15;
+    pub const UINT16: i8 = 16;
+}
+
+table!(Tensor);
+
+impl<'a> Tensor<'a> {
+    pub fn shape(&self) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, 0)
+    }
+
+    pub fn r#type(&self) -> i8 {
+        scalar(&self.tab, 1, tensor_type::FLOAT32)
+    }
+
+    pub fn buffer(&self) -> u32 {
+        scalar(&self.tab, 2, 0)
+    }
+
+    pub fn name(&self) -> Option<&'a str> {
+        string(&self.tab, 3)
+    }
+
+    pub fn quantization(&self) -> Option<QuantizationParameters<'a>> {
+        table::<QuantizationParameters>(&self.tab, 4)
+    }
+
+    pub fn is_variable(&self) -> bool {
+        scalar(&self.tab, 5, false)
+    }
+
+    /// 带有动态维度的形状，动态维度为-1
+    pub fn shape_signature(&self) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, 7)
+    }
+}
+
+impl Verifiable for Tensor<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<Scalars<i32>>("shape", vt(0), false)?
+            .visit_field::<i8>("type", vt(1), false)?
+            .visit_field::<u32>("buffer", vt(2), false)?
+            .visit_field::<ForwardsUOffset<&str>>("name", vt(3), false)?
+            .visit_field::<ForwardsUOffset<QuantizationParameters>>("quantization", vt(4), false)?
+            .visit_field::<bool>("is_variable", vt(5), false)?
+            .visit_field::<Scalars<i32>>("shape_signature", vt(7), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+table!(QuantizationParameters);
+
+impl<'a> QuantizationParameters<'a> {
+    pub fn scale(&self) -> Option<Vector<'a, f32>> {
+        vector(&self.tab, 2)
+    }
+
+    pub fn zero_point(&self) -> Option<Vector<'a, i64>> {
+        vector(&self.tab, 3)
+    }
+
+    pub fn quantized_dimension(&self) -> i32 {
+        scalar(&self.tab, 6, 0)
+    }
+}
+
+impl Verifiable for QuantizationParameters<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<Scalars<f32>>("min", vt(0), false)?
+            .visit_field::<Scalars<f32>>("max", vt(1), false)?
+            .visit_field::<Scalars<f32>>("scale", vt(2), false)?
+            .visit_field::<Scalars<i64>>("zero_point", vt(3), false)?
+            .visit_field::<i32>("quantized_dimension", vt(6), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+table!(Buffer);
+
+impl<'a> Buffer<'a> {
+    pub fn data(&self) -> Option<Vector<'a, u8>> {
+        vector(&self.tab, 0)
+    }
+
+    /// 超过2GB的模型中，数据保存在flatbuffer之后，offset为相对文件开始的位置
+    pub fn offset(&self) -> u64 {
+        scalar(&self.tab, 1, 0)
+    }
+
+    pub fn size(&self) -> u64 {
+        scalar(&self.tab, 2, 0)
+    }
+}
+
+impl Verifiable for Buffer<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<Scalars<u8>>("data", vt(0), false)?
+            .visit_field::<u64>("offset", vt(1), false)?
+            .visit_field::<u64>("size", vt(2), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+table!(Metadata);
+
+impl<'a> Metadata<'a> {
+    pub fn name(&self) -> Option<&'a str> {
+        string(&self.tab, 0)
+    }
+
+    pub fn buffer(&self) -> u32 {
+        scalar(&self.tab, 1, 0)
+    }
+}
+
+impl Verifiable for Metadata<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<ForwardsUOffset<&str>>("name", vt(0), false)?
+            .visit_field::<u32>("buffer", vt(1), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+table!(Operator);
+
+impl<'a> Operator<'a> {
+    pub fn opcode_index(&self) -> u32 {
+        scalar(&self.tab, 0, 0)
+    }
+
+    pub fn inputs(&self) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, 1)
+    }
+
+    pub fn outputs(&self) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, 2)
+    }
+
+    pub fn builtin_options_type(&self) -> u8 {
+        scalar(&self.tab, 3, 0)
+    }
+
+    /// 类型为`kind`的算子选项，类型不符或没有校验过的类型返回None
+    pub fn builtin_options(&self, kind: u8) -> Option<Options<'a>> {
+        if self.builtin_options_type() != kind || options::layout(kind).is_none() {
+            return None;
+        }
+        table::<Table>(&self.tab, 4).map(|tab| Options { tab })
+    }
+
+    pub fn custom_options(&self) -> Option<Vector<'a, u8>> {
+        vector(&self.tab, 5)
+    }
+}
+
+impl Verifiable for Operator<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<u32>("opcode_index", vt(0), false)?
+            .visit_field::<Scalars<i32>>("inputs", vt(1), false)?
+            .visit_field::<Scalars<i32>>("outputs", vt(2), false)?
+            .visit_union::<u8, _>(
+                "builtin_options_type",
+                vt(3),
+                "builtin_options",
+                vt(4),
+                false,
+                options::verify,
+            )?
+            .visit_field::<Scalars<u8>>("custom_options", vt(5), false)?
+            .finish();
+        Ok(())
+    }
+}
+
+/// 字段类型
+#[derive(Clone, Copy)]
+pub enum Field {
+    I8,
+    I32,
+    F32,
+    Bool,
+    I32s,
+}
+
+/// BuiltinOptions中使用到的选项，按union类型编号和字段顺序描述
+pub mod options {
+    use super::Field::{self, *};
+    use super::Options;
+    use flatbuffers::{ForwardsUOffset, InvalidFlatbuffer, Verifiable, Verifier};
+
+    pub const CONV_2D: u8 = 1;
+    pub const DEPTHWISE_CONV_2D: u8 = 2;
+    pub const POOL_2D: u8 = 5;
+    pub const FULLY_CONNECTED: u8 = 8;
+    pub const SOFTMAX: u8 = 9;
+    pub const CONCATENATION: u8 = 10;
+    pub const ADD: u8 = 11;
+    pub const RESHAPE: u8 = 17;
+    pub const MUL: u8 = 21;
+    pub const GATHER: u8 = 23;
+    pub const REDUCER: u8 = 27;
+    pub const SUB: u8 = 28;
+    pub const DIV: u8 = 29;
+    pub const SQUEEZE: u8 = 30;
+    pub const LEAKY_RELU: u8 = 75;
+
+    /// 按union类型校验选项，不使用的选项不需要校验
+    pub(super) fn verify(kind: u8, v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        macro_rules! verify {
+            ($($kind:ident),*) => {
+                match kind {
+                    $($kind => v.verify_union_variant::<ForwardsUOffset<Layout<$kind>>>(
+                        stringify!($kind),
+                        pos,
+                    ),)*
+                    _ => Ok(()),
+                }
+            };
+        }
+        verify!(
+            CONV_2D,
+            DEPTHWISE_CONV_2D,
+            POOL_2D,
+            FULLY_CONNECTED,
+            SOFTMAX,
+            CONCATENATION,
+            ADD,
+            RESHAPE,
+            MUL,
+            GATHER,
+            REDUCER,
+            SUB,
+            DIV,
+            SQUEEZE,
+            LEAKY_RELU
+        )
+    }
+
+    /// 类型为`KIND`的选项表
+    struct Layout<const KIND: u8>;
+
+    impl<const KIND: u8> Verifiable for Layout<KIND> {
+        fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+            Options::verify(v, pos, layout(KIND).unwrap_or(&[]))
+        }
+    }
+
+    pub fn layout(kind: u8) -> Option<&'static [Field]> {
+        let layout: &'static [Field] = match kind {
+            // padding, stride_w, stride_h, fused_activation_function,
+            // dilation_w_factor, dilation_h_factor
+            CONV_2D => &[I8, I32, I32, I8, I32, I32],
+            // padding, stride_w, stride_h, depth_multiplier, fused_activation_function,
+            // dilation_w_factor, dilation_h_factor
+            DEPTHWISE_CONV_2D => &[I8, I32, I32, I32, I8, I32, I32],
+            // padding, stride_w, stride_h, filter_width, filter_height, fused_activation_function
+            POOL_2D => &[I8, I32, I32, I32, I32, I8],
+            // fused_activation_function, weights_format, keep_num_dims, asymmetric_quantize_inputs
+            FULLY_CONNECTED => &[I8, I8, Bool, Bool],
+            // beta
+            SOFTMAX => &[F32],
+            // axis, fused_activation_function
+            CONCATENATION => &[I32, I8],
+            // fused_activation_function, pot_scale_int16
+            ADD | SUB => &[I8, Bool],
+            // new_shape
+            RESHAPE => &[I32s],
+            // fused_activation_function
+            MUL | DIV => &[I8],
+            // axis, batch_dims
+            GATHER => &[I32, I32],
+            // keep_dims
+            REDUCER => &[Bool],
+            // squeeze_dims
+            SQUEEZE => &[I32s],
+            // alpha
+            LEAKY_RELU => &[F32],
+            _ => return None,
+        };
+        Some(layout)
+    }
+}
+
+/// 算子选项，按字段编号读取
+#[derive(Copy, Clone)]
+pub struct Options<'a> {
+    tab: Table<'a>,
+}
+
+impl<'a> Options<'a> {
+    fn verify(v: &mut Verifier, pos: usize, layout: &[Field]) -> Result<(), InvalidFlatbuffer> {
+        let mut t = v.visit_table(pos)?;
+        for (i, field) in layout.iter().enumerate() {
+            let f = vt(i as u16);
+            t = match field {
+                Field::I8 => t.visit_field::<i8>("option", f, false)?,
+                Field::I32 => t.visit_field::<i32>("option", f, false)?,
+                Field::F32 => t.visit_field::<f32>("option", f, false)?,
+                Field::Bool => t.visit_field::<bool>("option", f, false)?,
+                Field::I32s => t.visit_field::<Scalars<i32>>("option", f, false)?,
+            };
+        }
+        t.finish();
+        Ok(())
+    }
+
+    pub fn i8(&self, field: u16, default: i8) -> i8 {
+        scalar(&self.tab, field, default)
+    }
+
+    pub fn i32(&self, field: u16, default: i32) -> i32 {
+        scalar(&self.tab, field, default)
+    }
+
+    pub fn f32(&self, field: u16, default: f32) -> f32 {
+        scalar(&self.tab, field, default)
+    }
+
+    pub fn bool(&self, field: u16, default: bool) -> bool {
+        scalar(&self.tab, field, default)
+    }
+
+    pub fn i32s(&self, field: u16) -> Option<Vector<'a, i32>> {
+        vector(&self.tab, field)
+    }
+}
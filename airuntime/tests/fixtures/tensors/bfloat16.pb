@@ -0,0 +1 @@
+*Bbfloat16
\ No newline at end of file
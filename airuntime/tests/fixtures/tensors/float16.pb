@@ -0,0 +1,2 @@
+
+*xBfloat16
\ No newline at end of file
@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+"""生成TensorProto测试数据
+
+按onnx.proto的定义直接编码protobuf，不依赖onnx和protobuf库：
+dims不打包，typed数据字段按packed编码，与onnx.helper.make_tensor的输出一致。
+"""
+import os
+import struct
+
+# TensorProto.DataType
+FLOAT, UINT8, INT8, UINT16, INT16, INT32, INT64, STRING, BOOL, FLOAT16, \
+    DOUBLE, UINT32, UINT64, COMPLEX64, COMPLEX128, BFLOAT16 = range(1, 17)
+
+
+def varint(v):
+    # 负数按64位补码编码
+    v &= (1 << 64) - 1
+    out = bytearray()
+    while True:
+        b = v & 0x7F
+        v >>= 7
+        if v:
+            out.append(b | 0x80)
+        else:
+            out.append(b)
+            return bytes(out)
+
+
+def key(field, wire):
+    return varint(field << 3 | wire)
+
+
+def length_delimited(field, data):
+    return key(field, 2) + varint(len(data)) + data
+
+
+def tensor(name, dims, data_type, float_data=None, int32_data=None,
+           string_data=None, int64_data=None, double_data=None,
+           uint64_data=None, raw_data=None):
+    out = bytearray()
+    for d in dims:
+        out += key(1, 0) + varint(d)
+    out += key(2, 0) + varint(data_type)
+    if float_data is not None:
+        out += length_delimited(4, b"".join(struct.pack("<f", v) for v in float_data))
+    if int32_data is not None:
+        out += length_delimited(5, b"".join(varint(v) for v in int32_data))
+    if string_data is not None:
+        for s in string_data:
+            out += length_delimited(6, s)
+    if int64_data is not None:
+        out += length_delimited(7, b"".join(varint(v) for v in int64_data))
+    out += length_delimited(8, name.encode())
+    if raw_data is not None:
+        out += length_delimited(9, raw_data)
+    if double_data is not None:
+        out += length_delimited(10, b"".join(struct.pack("<d", v) for v in double_data))
+    if uint64_data is not None:
+        out += length_delimited(11, b"".join(varint(v) for v in uint64_data))
+    return bytes(out)
+
+
+FIXTURES = {
+    "float": tensor("float", [2, 2], FLOAT, float_data=[1.5, -2.0, 3.0, 4.0]),
+    "scalar": tensor("scalar", [], FLOAT, float_data=[3.0]),
+    "uint8": tensor("uint8", [3], UINT8, int32_data=[0, 128, 255]),
+    "int8": tensor("int8", [3], INT8, int32_data=[-128, 0, 127]),
+    "uint16": tensor("uint16", [2], UINT16, int32_data=[0, 65535]),
+    "int16": tensor("int16", [2], INT16, int32_data=[-32768, 32767]),
+    "int32": tensor("int32", [2], INT32, int32_data=[-1, 2147483647]),
+    "int64": tensor("int64", [2], INT64, int64_data=[-1, 1 << 62]),
+    "string": tensor("string", [2], STRING, string_data=[b"hello", b"\xff"]),
+    "bool": tensor("bool", [3], BOOL, int32_data=[1, 0, 1]),
+    # float16和bfloat16按位保存在int32_data中
+    "float16": tensor("float16", [2], FLOAT16, int32_data=[0x3C00, 0xC000]),
+    "bfloat16": tensor("bfloat16", [2], BFLOAT16, int32_data=[0x3F80, 0xC000]),
+    "double": tensor("double", [2], DOUBLE, double_data=[1.25, -2.5]),
+    "uint32": tensor("uint32", [2], UINT32, uint64_data=[0, 4294967295]),
+    "uint64": tensor("uint64", [2], UINT64, uint64_data=[0, (1 << 64) - 1]),
+    # 复数按实部、虚部交替保存
+    "complex64": tensor("complex64", [2], COMPLEX64, float_data=[1.0, 2.0, 3.0, 4.0]),
+    "complex128": tensor("complex128", [1], COMPLEX128, double_data=[1.5, -0.5]),
+    "raw_int16": tensor("raw_int16", [2], INT16, raw_data=struct.pack("<hh", -2, 3)),
+}
+
+if __name__ == "__main__":
+    here = os.path.dirname(os.path.abspath(__file__))
+    for name, data in FIXTURES.items():
+        with open(os.path.join(here, name + ".pb"), "wb") as f:
+            f.write(data)
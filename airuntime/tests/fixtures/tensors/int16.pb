@@ -0,0 +1 @@
+*Bint16
\ No newline at end of file
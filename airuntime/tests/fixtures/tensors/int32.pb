@@ -0,0 +1 @@
+*Bint32
\ No newline at end of file
@@ -0,0 +1 @@
+:@Bint64
\ No newline at end of file
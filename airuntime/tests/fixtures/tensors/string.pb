@@ -0,0 +1 @@
+2hello2Bstring
\ No newline at end of file
@@ -1,7 +1,8 @@
-use std::{collections::HashMap, ffi::*, mem::forget, ptr::null, ptr::null_mut};
+use std::{collections::HashMap, ffi::*, mem::forget, ptr::null, ptr::null_mut, rc::Rc};
 
 use derive::{FromCode, GetCode};
-use model::tensor;
+use airuntime::info::Dim;
+use model::tensor::{self, SharedBytes};
 
 // 对外暴露的类型
 /// 配置信息
@@ -12,8 +13,15 @@ pub type Context = airuntime::Context;
 pub type Tensor = model::tensor::Tensor;
 /// 模型加载回调
 pub type LoadCallback = unsafe extern "C" fn(AiruntimeErrCode, *mut c_void);
+/// 外部数据回调，按`location`返回数据的地址和长度，成功时返回0
+///
+/// 返回的数据在上下文销毁前必须保持有效
+pub type ExternalDataCallback =
+    unsafe extern "C" fn(*const c_char, *mut *const u8, *mut usize, *mut c_void) -> c_int;
 /// 模型推理回调
 pub type RunCallback = unsafe extern "C" fn(*mut TensorVec, c_uint, AiruntimeErrCode, *mut c_void);
+/// 模型输入输出的描述
+pub type Signature = airuntime::info::Signature;
 /// \0结尾的String
 pub type CString = std::ffi::CString;
 
@@ -22,6 +30,26 @@ pub enum AiruntimeErrCode {
     Ok = 0,
     Error = -1,
     InvalidParam = -2,
+    /// 模型文件不存在
+    FileNotFound = -3,
+    /// 模型protobuf解析失败
+    DecodeFailed = -4,
+    /// 不支持的数据类型
+    UnsupportedDType = -5,
+    /// 不支持的属性
+    UnsupportedAttribute = -6,
+    /// 使用了未定义的张量
+    UndefinedTensor = -7,
+    /// 外部数据加载失败
+    ExternalDataFailed = -8,
+    /// 张量数据无效
+    InvalidTensor = -9,
+    /// 算子与定义不符
+    InvalidOperator = -10,
+    /// 模型无效
+    InvalidModel = -11,
+    /// 模型或外部数据的摘要、签名校验失败
+    IntegrityFailed = -12,
 }
 
 /// 数据布局
@@ -42,6 +70,14 @@ pub enum TensorFormat {
     NCDHW = 6,
 }
 
+/// 计算精度
+#[repr(C)]
+pub enum PrecisionType {
+    Float32 = 0,
+    /// 常量和中间张量转换为float16
+    Float16 = 1,
+}
+
 /// Tensor数据类型
 #[repr(C)]
 #[derive(GetCode, FromCode)]
@@ -82,6 +118,16 @@ pub enum TensorDType {
     Bfloat16 = 16,
 }
 
+/// Tensor数据位置
+#[repr(C)]
+#[derive(GetCode, FromCode)]
+pub enum TensorLocation {
+    #[code(0)]
+    Host = 0,
+    #[code(1)]
+    Device = 1,
+}
+
 /// 候选后端
 pub struct Backends {
     pub backends: Vec<String>,
@@ -128,7 +174,108 @@ pub extern "C" fn airuntime_load(
     let mut config = unsafe { Box::from_raw(config) };
     config.model_dir = String::from(c_char_to_str(path));
 
-    let code = match airuntime::load(&config, move |r| {
+    let code = match airuntime::load(&config, load_callback(cb, user_data)) {
+        Ok(c) => {
+            let c = Box::new(c);
+            unsafe { *ctx = Box::into_raw(c) };
+            AiruntimeErrCode::Ok
+        }
+        Err(e) => {
+            println!("[E][AiRuntime] -> Load model failed! {}", e);
+            load_error(&e)
+        }
+    };
+    // 确保config不被rust释放
+    forget(config);
+    code
+}
+
+/// 从内存中加载模型
+///
+/// `buffer`只在调用期间使用；模型的外部数据通过`resolver`按location获取，
+/// 没有外部数据时`resolver`可以为NULL
+#[no_mangle]
+pub extern "C" fn airuntime_load_from_buffer(
+    ctx: *mut *mut Context,
+    buffer: *const u8,
+    len: usize,
+    config: *mut Config,
+    resolver: Option<ExternalDataCallback>,
+    resolver_data: *mut c_void,
+    cb: LoadCallback,
+    user_data: *mut c_void,
+) -> AiruntimeErrCode {
+    if ctx.is_null() || config.is_null() || buffer.is_null() {
+        return AiruntimeErrCode::InvalidParam;
+    }
+
+    let config = unsafe { Box::from_raw(config) };
+    let bytes = unsafe { std::slice::from_raw_parts(buffer, len) };
+    let resolver = resolver.map(|f| ExternalResolver {
+        f,
+        user_data: resolver_data,
+    });
+
+    let code = match airuntime::load_from_bytes(
+        bytes,
+        resolver.as_ref().map(|r| r as &dyn airuntime::ExternalDataResolver),
+        &config,
+        load_callback(cb, user_data),
+    ) {
+        Ok(c) => {
+            let c = Box::new(c);
+            unsafe { *ctx = Box::into_raw(c) };
+            AiruntimeErrCode::Ok
+        }
+        Err(e) => {
+            println!("[E][AiRuntime] -> Load model from buffer failed! {}", e);
+            load_error(&e)
+        }
+    };
+    // 确保config不被rust释放
+    forget(config);
+    code
+}
+
+thread_local! {
+    // 当前线程最近一次加载失败的错误信息
+    static LAST_ERROR: std::cell::RefCell<Option<CString>> = const { std::cell::RefCell::new(None) };
+}
+
+/// 记录错误信息，并按加载错误的类型返回错误码
+fn load_error(e: &airuntime::Error) -> AiruntimeErrCode {
+    use airuntime::LoadError;
+
+    let message = CString::new(format!("{:#}", e).replace('\0', "")).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    match e.downcast_ref::<LoadError>() {
+        Some(LoadError::FileNotFound { .. }) => AiruntimeErrCode::FileNotFound,
+        Some(LoadError::Decode { .. }) => AiruntimeErrCode::DecodeFailed,
+        Some(LoadError::UnsupportedDType { .. }) => AiruntimeErrCode::UnsupportedDType,
+        Some(LoadError::UnsupportedAttribute { .. }) => AiruntimeErrCode::UnsupportedAttribute,
+        Some(LoadError::UndefinedTensor { .. }) => AiruntimeErrCode::UndefinedTensor,
+        Some(LoadError::ExternalData { .. }) => AiruntimeErrCode::ExternalDataFailed,
+        Some(LoadError::InvalidTensor { .. }) => AiruntimeErrCode::InvalidTensor,
+        Some(LoadError::InvalidOperator(_)) | Some(LoadError::ShapeInference { .. }) => {
+            AiruntimeErrCode::InvalidOperator
+        }
+        Some(LoadError::InvalidModel(_)) => AiruntimeErrCode::InvalidModel,
+        Some(LoadError::Integrity { .. }) => AiruntimeErrCode::IntegrityFailed,
+        None => AiruntimeErrCode::Error,
+    }
+}
+
+/// 获取当前线程最近一次加载失败的错误信息，没有时返回NULL
+#[no_mangle]
+pub extern "C" fn airuntime_get_last_error() -> *mut CString {
+    LAST_ERROR.with(|last| match last.borrow().as_ref() {
+        Some(message) => Box::into_raw(Box::new(message.clone())),
+        None => null_mut(),
+    })
+}
+
+fn load_callback(cb: LoadCallback, user_data: *mut c_void) -> impl FnOnce(airuntime::Result<()>) {
+    move |r| {
         let code = match r {
             Ok(_) => AiruntimeErrCode::Ok,
             Err(e) => {
@@ -142,20 +289,39 @@ pub extern "C" fn airuntime_load(
         unsafe {
             cb(code, user_data);
         }
-    }) {
-        Ok(c) => {
-            let c = Box::new(c);
-            unsafe { *ctx = Box::into_raw(c) };
-            AiruntimeErrCode::Ok
-        }
-        Err(e) => {
-            println!("[E][AiRuntime] -> Load model failed! {}", e);
-            AiruntimeErrCode::Error
+    }
+}
+
+/// 通过C回调获取外部数据
+struct ExternalResolver {
+    f: ExternalDataCallback,
+    user_data: *mut c_void,
+}
+
+impl airuntime::ExternalDataResolver for ExternalResolver {
+    fn resolve(&self, location: &str) -> airuntime::Result<Rc<dyn SharedBytes>> {
+        let name = CString::new(location)?;
+        let mut ptr: *const u8 = null();
+        let mut len: usize = 0;
+        let ret = unsafe { (self.f)(name.as_ptr(), &mut ptr, &mut len, self.user_data) };
+        if ret != 0 || ptr.is_null() {
+            airuntime::bail!("resolve external data {} failed, code {}", location, ret);
         }
-    };
-    // 确保config不被rust释放
-    forget(config);
-    code
+        Ok(Rc::new(ExternalBuffer { ptr, len }))
+    }
+}
+
+/// 调用方提供的外部数据，由调用方保证在上下文销毁前有效
+#[derive(Debug)]
+struct ExternalBuffer {
+    ptr: *const u8,
+    len: usize,
+}
+
+impl SharedBytes for ExternalBuffer {
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
 }
 
 /// 执行推理
@@ -272,6 +438,9 @@ pub extern "C" fn airuntime_config_new(backend_id: *const c_char, dev_id: c_int)
         backend: String::from(backend_id),
         device_id: dev_id,
         ops: HashMap::new(),
+        keep_sparse: false,
+        layout: None,
+        precision: airuntime::PrecisionType::Float32,
     };
     let config = Box::new(config);
     Box::into_raw(config)
@@ -299,6 +468,36 @@ pub extern "C" fn airuntime_config_add_option(
     0
 }
 
+/// 设置稀疏权重是否保留COO格式，`keep`非0时保留
+#[no_mangle]
+pub extern "C" fn airuntime_config_set_keep_sparse(config: *mut Config, keep: c_int) {
+    let mut config = unsafe { Box::from_raw(config) };
+    config.keep_sparse = keep != 0;
+    // 确保config不被rust释放
+    forget(config);
+}
+
+/// 设置后端要求的数据布局，模型在编译前转换为该布局
+#[no_mangle]
+pub extern "C" fn airuntime_config_set_layout(config: *mut Config, format: TensorFormat) {
+    let mut config = unsafe { Box::from_raw(config) };
+    config.layout = Some(tensor::Format::from_code(format.get_code()));
+    // 确保config不被rust释放
+    forget(config);
+}
+
+/// 设置计算精度
+#[no_mangle]
+pub extern "C" fn airuntime_config_set_precision(config: *mut Config, precision: PrecisionType) {
+    let mut config = unsafe { Box::from_raw(config) };
+    config.precision = match precision {
+        PrecisionType::Float32 => airuntime::PrecisionType::Float32,
+        PrecisionType::Float16 => airuntime::PrecisionType::Float16,
+    };
+    // 确保config不被rust释放
+    forget(config);
+}
+
 /// 获取配置对象的Option值
 #[no_mangle]
 pub extern "C" fn airuntime_config_get_option(
@@ -456,6 +655,51 @@ pub extern "C" fn airuntime_tensor_get_data(tensor: *mut Tensor, data: *mut *mut
     len as c_uint
 }
 
+#[no_mangle]
+pub extern "C" fn airuntime_tensor_get_location(tensor: *mut Tensor) -> TensorLocation {
+    let tensor = unsafe { Box::from_raw(tensor) };
+    let location = TensorLocation::from_code(tensor.location().get_code());
+    // 确保不被rust释放
+    forget(tensor);
+    location
+}
+
+/// 将Tensor复制到设备上，返回新的Tensor，失败时返回NULL
+///
+/// 返回的Tensor需要在销毁上下文前调用airuntime_tensor_destory释放
+#[no_mangle]
+pub extern "C" fn airuntime_tensor_to_device(ctx: *mut Context, tensor: *mut Tensor) -> *mut Tensor {
+    transfer(ctx, tensor, airuntime::device::to_device)
+}
+
+/// 将设备上的Tensor复制回host，返回新的Tensor，失败时返回NULL
+#[no_mangle]
+pub extern "C" fn airuntime_tensor_to_host(ctx: *mut Context, tensor: *mut Tensor) -> *mut Tensor {
+    transfer(ctx, tensor, airuntime::device::to_host)
+}
+
+fn transfer<F>(ctx: *mut Context, tensor: *mut Tensor, f: F) -> *mut Tensor
+where
+    F: FnOnce(&Context, &Tensor) -> airuntime::Result<Tensor>,
+{
+    if ctx.is_null() || tensor.is_null() {
+        return null_mut();
+    }
+    let ctx = unsafe { Box::from_raw(ctx) };
+    let tensor = unsafe { Box::from_raw(tensor) };
+    let result = match f(&ctx, &tensor) {
+        Ok(t) => Box::into_raw(Box::new(t)),
+        Err(e) => {
+            println!("[E][AiRuntime] -> Transfer tensor failed! {}", e);
+            null_mut()
+        }
+    };
+    // 确保Context和Tensor不被rust释放
+    forget(ctx);
+    forget(tensor);
+    result
+}
+
 /// 销毁Backends
 #[no_mangle]
 pub extern "C" fn airuntime_backends_destory(backends: *mut Backends) {
@@ -527,6 +771,176 @@ pub extern "C" fn airuntime_tensorvec_get(vec: *mut TensorVec, index: c_uint) ->
     result
 }
 
+/// 获取模型的ONNX IR版本
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_ir_version(ctx: *mut Context) -> i64 {
+    with_context(ctx, 0, |ctx| ctx.info.ir_version)
+}
+
+/// 获取模型版本
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_version(ctx: *mut Context) -> i64 {
+    with_context(ctx, 0, |ctx| ctx.info.model_version)
+}
+
+/// 获取生成模型的工具名
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_producer_name(ctx: *mut Context) -> *mut CString {
+    with_context(ctx, null_mut(), |ctx| new_cstring(&ctx.info.producer_name))
+}
+
+/// 获取生成模型的工具版本
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_producer_version(ctx: *mut Context) -> *mut CString {
+    with_context(ctx, null_mut(), |ctx| new_cstring(&ctx.info.producer_version))
+}
+
+/// 获取模型的命名空间
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_domain(ctx: *mut Context) -> *mut CString {
+    with_context(ctx, null_mut(), |ctx| new_cstring(&ctx.info.domain))
+}
+
+/// 获取模型的文档说明
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_doc_string(ctx: *mut Context) -> *mut CString {
+    with_context(ctx, null_mut(), |ctx| new_cstring(&ctx.info.doc_string))
+}
+
+/// 获取模型附加信息的数量
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_metadata_len(ctx: *mut Context) -> c_uint {
+    with_context(ctx, 0, |ctx| ctx.info.metadata_props.len() as c_uint)
+}
+
+/// 获取第`index`个附加信息的key，越界时返回NULL
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_metadata_key(ctx: *mut Context, index: c_uint) -> *mut CString {
+    with_context(ctx, null_mut(), |ctx| match ctx.info.metadata_props.get(index as usize) {
+        Some((key, _)) => new_cstring(key),
+        None => null_mut(),
+    })
+}
+
+/// 获取附加信息的值，不存在时返回NULL
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_metadata(ctx: *mut Context, key: *const c_char) -> *mut CString {
+    if key.is_null() {
+        return null_mut();
+    }
+    let key = c_char_to_str(key);
+    with_context(ctx, null_mut(), |ctx| match ctx.info.metadata(key) {
+        Some(value) => new_cstring(value),
+        None => null_mut(),
+    })
+}
+
+/// 获取模型输入的数量
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_input_len(ctx: *mut Context) -> c_uint {
+    with_context(ctx, 0, |ctx| ctx.info.inputs.len() as c_uint)
+}
+
+/// 获取第`index`个模型输入，越界时返回NULL，生命周期与上下文相同
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_input(ctx: *mut Context, index: c_uint) -> *const Signature {
+    with_context(ctx, null(), |ctx| match ctx.info.inputs.get(index as usize) {
+        Some(s) => s as *const Signature,
+        None => null(),
+    })
+}
+
+/// 获取模型输出的数量
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_output_len(ctx: *mut Context) -> c_uint {
+    with_context(ctx, 0, |ctx| ctx.info.outputs.len() as c_uint)
+}
+
+/// 获取第`index`个模型输出，越界时返回NULL，生命周期与上下文相同
+#[no_mangle]
+pub extern "C" fn airuntime_model_get_output(ctx: *mut Context, index: c_uint) -> *const Signature {
+    with_context(ctx, null(), |ctx| match ctx.info.outputs.get(index as usize) {
+        Some(s) => s as *const Signature,
+        None => null(),
+    })
+}
+
+/// 获取输入输出的名字
+#[no_mangle]
+pub extern "C" fn airuntime_signature_get_name(signature: *const Signature) -> *mut CString {
+    if signature.is_null() {
+        return null_mut();
+    }
+    let signature = unsafe { &*signature };
+    new_cstring(&signature.name)
+}
+
+/// 获取输入输出的数据类型
+#[no_mangle]
+pub extern "C" fn airuntime_signature_get_dtype(signature: *const Signature) -> TensorDType {
+    if signature.is_null() {
+        return TensorDType::Undefined;
+    }
+    let signature = unsafe { &*signature };
+    TensorDType::from_code(signature.dtype.get_code())
+}
+
+/// 获取输入输出的维数，维数未知时返回-1
+#[no_mangle]
+pub extern "C" fn airuntime_signature_get_shape_len(signature: *const Signature) -> c_int {
+    if signature.is_null() {
+        return -1;
+    }
+    let signature = unsafe { &*signature };
+    match &signature.dims {
+        Some(dims) => dims.len() as c_int,
+        None => -1,
+    }
+}
+
+/// 获取第`index`维的大小，符号维度或未知大小时返回-1
+#[no_mangle]
+pub extern "C" fn airuntime_signature_get_dim(signature: *const Signature, index: c_uint) -> i64 {
+    if signature.is_null() {
+        return -1;
+    }
+    let signature = unsafe { &*signature };
+    match signature.dims.as_ref().and_then(|d| d.get(index as usize)) {
+        Some(Dim::Value(v)) => *v,
+        _ => -1,
+    }
+}
+
+/// 获取第`index`维的符号名，不是符号维度时返回NULL
+#[no_mangle]
+pub extern "C" fn airuntime_signature_get_dim_param(
+    signature: *const Signature,
+    index: c_uint,
+) -> *mut CString {
+    if signature.is_null() {
+        return null_mut();
+    }
+    let signature = unsafe { &*signature };
+    match signature.dims.as_ref().and_then(|d| d.get(index as usize)) {
+        Some(Dim::Param(p)) => new_cstring(p),
+        _ => null_mut(),
+    }
+}
+
+/// 借用上下文调用`f`，上下文为NULL时返回`default`
+fn with_context<T>(ctx: *mut Context, default: T, f: impl FnOnce(&Context) -> T) -> T {
+    if ctx.is_null() {
+        return default;
+    }
+    f(unsafe { &*ctx })
+}
+
+fn new_cstring(s: &str) -> *mut CString {
+    // 去掉内部的\0，避免创建失败
+    let s = CString::new(s.replace('\0', "")).unwrap();
+    Box::into_raw(Box::new(s))
+}
+
 /// 销毁CString
 #[no_mangle]
 pub extern "C" fn airuntime_cstring_destory(cstr: *mut CString) {
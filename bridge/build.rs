@@ -8,10 +8,15 @@ fn main() {
     cxx_build::bridge("src/nndevice/ffi.rs");
 
     // 使用cmake编译
-    let dst = Config::new("cxx")
+    let mut config = Config::new("cxx");
+    config
     .define("FROM_CARGO", "ON")
-    .define("CARGO_BUILD_TARGET", get_env_value("CARGO_BUILD_TARGET"))
-    .build();
+    .define("CARGO_BUILD_TARGET", get_env_value("CARGO_BUILD_TARGET"));
+    // SDK提供GetSupportedOps和设备内存接口时才编译对应的调用
+    if env::var("CARGO_FEATURE_ENGINE_EXT").is_ok() {
+        config.define("BRIDGE_ENGINE_EXT", "ON");
+    }
+    let dst = config.build();
 
     println!("cargo:rustc-link-search=native={}", dst.display());
     println!("cargo:rustc-link-lib=static=cxxbridge-cxx");
@@ -33,6 +33,8 @@ std::unique_ptr<Context> CreateContext(rust::Str backend_id, DevId dev_id,
 
 void DestoryContext(std::unique_ptr<Context> ctx);
 
+rust::Vec<RustOpSupport> GetSupportedOps(const std::unique_ptr<Context>& ctx);
+
 void CompileGraph(const std::unique_ptr<Context>& ctx,
                   const bridge::GraphWrapper& wrapper,
                   bridge::CompileCallback cb,
@@ -40,9 +42,19 @@ void CompileGraph(const std::unique_ptr<Context>& ctx,
 
 void Execute(const std::unique_ptr<Context>& ctx,
              const rust::Vec<bridge::TensorWrapper>& inputs_wrapper,
-             bridge::ExecuteCallback cb,
+             bool outputs_on_device, bridge::ExecuteCallback cb,
              rust::Box<bridge::RustExecuteCallback> rust_cb);
 
+uint8_t* AllocDeviceMemory(const std::unique_ptr<Context>& ctx, size_t len);
+
+void FreeDeviceMemory(const std::unique_ptr<Context>& ctx, uint8_t* ptr);
+
+void CopyToDevice(const std::unique_ptr<Context>& ctx, uint8_t* dst,
+                  rust::Slice<const uint8_t> src);
+
+void CopyToHost(const std::unique_ptr<Context>& ctx, rust::Slice<uint8_t> dst,
+                const uint8_t* src);
+
 }  // namespace nndevice
 }  // namespace aichip
 }  // namespace inos
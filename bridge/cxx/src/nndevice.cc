@@ -28,6 +28,12 @@ class nndevice_error : public std::exception {
   std::string str_code_;
 };
 
+// SDK没有提供的接口，what()与rust侧的UNSUPPORTED一致
+class unsupported_error : public std::exception {
+ public:
+  const char* what() const noexcept override { return "unsupported"; }
+};
+
 static std::shared_ptr<Tensor> FromWrapper(
     const bridge::TensorWrapper& wrapper) {
   auto name = std::string(wrapper.Name());
@@ -49,6 +55,23 @@ static std::shared_ptr<Tensor> FromWrapper(
     auto data = wrapper.Data();
     auto tensor_data = std::make_unique<TensorData>();
     tensor_data->set_data_nocopy(data, data_len);
+#ifdef BRIDGE_ENGINE_EXT
+    tensor_data->set_location(
+        static_cast<TensorData::Location>(wrapper.Location()));
+    auto sparse_indices = wrapper.SparseIndices();
+    if (!sparse_indices.empty()) {
+      // data中只有非零值，按COO格式的线性下标展开
+      tensor_data->set_sparse_indices(std::vector<int64_t>(
+          sparse_indices.begin(), sparse_indices.end()));
+    }
+#else
+    // 没有设备内存接口时数据只能在host上，稀疏张量由rust侧转换为稠密张量
+    if (wrapper.Location() != 0 || !wrapper.SparseIndices().empty()) {
+      LOG(ERROR) << "[bridge] Tensor " << name
+                 << " is on device or sparse, not supported by the SDK.";
+      throw unsupported_error();
+    }
+#endif
     tensor->set_data(std::move(tensor_data));
   }
   return tensor;
@@ -104,7 +127,16 @@ static std::shared_ptr<Operator> FromWrapper(
     const bridge::OperatorWrapper& wrapper) {
   auto op_name = std::string(wrapper.Name());
   auto op_type = std::string(wrapper.Type());
+#ifdef BRIDGE_ENGINE_EXT
+  auto domain = std::string(wrapper.Domain());
+  auto since_version = wrapper.SinceVersion();
+  auto op = Operator::Builder()
+                .Create(op_name, op_type)
+                .SetDomain(domain, since_version)
+                .Build();
+#else
   auto op = Operator::Builder().Create(op_name, op_type).Build();
+#endif
 
   auto inputs_wrapper = wrapper.Inputs();
   for (auto& input_wrapper : inputs_wrapper) {
@@ -135,6 +167,14 @@ static std::shared_ptr<Graph> FromWrapper(const bridge::GraphWrapper& wrapper) {
 
   LOG(INFO) << "[bridge] grap name: " << graph_name;
 
+#ifdef BRIDGE_ENGINE_EXT
+  for (auto& opset : wrapper.OpsetImports()) {
+    auto domain = std::string(opset.domain);
+    VLOG(1) << "[bridge] opset import: " << domain << " v" << opset.version;
+    graph->AddOpsetImport(domain, opset.version);
+  }
+#endif
+
   auto op_wrappers = wrapper.GraphAllOperators();
   for (auto& op_wrapper : op_wrappers) {
     auto op = FromWrapper(op_wrapper);
@@ -156,6 +196,12 @@ static RustTensor ToRustTensor(std::shared_ptr<Tensor> tensor) {
 
   rust_tensor.data = (uint8_t*)tensor->data()->data();
   rust_tensor.len = tensor->data()->length();
+#ifdef BRIDGE_ENGINE_EXT
+  rust_tensor.location = static_cast<uint32_t>(tensor->data()->location());
+#else
+  // 没有设备内存接口时输出都在host上
+  rust_tensor.location = 0;
+#endif
 
   return rust_tensor;
 }
@@ -212,6 +258,39 @@ void DestoryContext(std::unique_ptr<Context> ctx) {
   VLOG(1) << "[bridge] Call engine DestoryContext success.";
 }
 
+// GetSupportedOps和设备内存接口需要较新的ai_chip_client，
+// 由bridge的engine-ext feature定义BRIDGE_ENGINE_EXT后启用
+#ifdef BRIDGE_ENGINE_EXT
+rust::Vec<RustOpSupport> GetSupportedOps(const std::unique_ptr<Context>& ctx) {
+  VLOG(1) << "[bridge] Call GetSupportedOps in bridge cxx.";
+  auto result = ENGINE.GetSupportedOps(ctx);
+  if (!result.IsOK()) {
+    LOG(ERROR) << "[bridge] Call engine GetSupportedOps failed, "
+               << result.GetError();
+    throw nndevice_error(result.GetError());
+  }
+  rust::Vec<RustOpSupport> ops;
+  for (auto& support : result.Get()) {
+    RustOpSupport op;
+    op.domain = std::string(support.domain);
+    op.op_type = std::string(support.op_type);
+    for (auto dtype : support.dtypes) {
+      op.dtypes.push_back(static_cast<uint32_t>(dtype));
+    }
+    ops.push_back(std::move(op));
+  }
+  VLOG(1) << "[bridge] Call engine GetSupportedOps success, " << ops.size()
+          << " ops.";
+
+  return ops;
+}
+#else
+rust::Vec<RustOpSupport> GetSupportedOps(const std::unique_ptr<Context>& ctx) {
+  VLOG(1) << "[bridge] GetSupportedOps is not supported by the SDK.";
+  throw unsupported_error();
+}
+#endif
+
 void CompileGraph(const std::unique_ptr<Context>& ctx,
                   const bridge::GraphWrapper& wrapper,
                   bridge::CompileCallback cb,
@@ -232,15 +311,14 @@ void CompileGraph(const std::unique_ptr<Context>& ctx,
 
 void Execute(const std::unique_ptr<Context>& ctx,
              const rust::Vec<bridge::TensorWrapper>& inputs_wrapper,
-             bridge::ExecuteCallback cb,
+             bool outputs_on_device, bridge::ExecuteCallback cb,
              rust::Box<bridge::RustExecuteCallback> rust_cb) {
   VLOG(1) << "[bridge] Call Execute in bridge cxx.";
   std::vector<std::shared_ptr<Tensor>> inputs;
   for (auto& wrapper : inputs_wrapper) {
     inputs.push_back(FromWrapper(wrapper));
   }
-  auto result = ENGINE.Execute(
-      ctx, inputs,
+  auto on_outputs =
       [cb, &rust_cb](Result<std::vector<std::shared_ptr<Tensor>>> outputs) {
         if (!outputs.IsOK()) {
           cb(std::move(rust_cb), {}, static_cast<int>(outputs.GetError()));
@@ -252,7 +330,17 @@ void Execute(const std::unique_ptr<Context>& ctx,
           rust_outputs.push_back(ToRustTensor(tensor));
         }
         cb(std::move(rust_cb), rust_outputs, 0);
-      });
+      };
+#ifdef BRIDGE_ENGINE_EXT
+  auto result = ENGINE.Execute(ctx, inputs, outputs_on_device, on_outputs);
+#else
+  // SDK总是把输出复制到host
+  if (outputs_on_device) {
+    VLOG(1) << "[bridge] outputs_on_device is not supported by the SDK, "
+               "outputs are copied to host.";
+  }
+  auto result = ENGINE.Execute(ctx, inputs, on_outputs);
+#endif
 
   if (!result.IsOK()) {
     LOG(ERROR) << "[bridge] Call engine Execute failed, " << result.GetError();
@@ -261,6 +349,70 @@ void Execute(const std::unique_ptr<Context>& ctx,
   VLOG(1) << "[bridge] Call engine Execute success.";
 }
 
+#ifdef BRIDGE_ENGINE_EXT
+uint8_t* AllocDeviceMemory(const std::unique_ptr<Context>& ctx, size_t len) {
+  VLOG(1) << "[bridge] Call AllocDeviceMemory in bridge cxx, len: " << len;
+  auto result = ENGINE.AllocDeviceMemory(ctx, len);
+  if (!result.IsOK()) {
+    LOG(ERROR) << "[bridge] Call engine AllocDeviceMemory failed, "
+               << result.GetError();
+    throw nndevice_error(result.GetError());
+  }
+  return static_cast<uint8_t*>(result.Get());
+}
+
+void FreeDeviceMemory(const std::unique_ptr<Context>& ctx, uint8_t* ptr) {
+  VLOG(1) << "[bridge] Call FreeDeviceMemory in bridge cxx.";
+  auto result = ENGINE.FreeDeviceMemory(ctx, ptr);
+  if (!result.IsOK()) {
+    LOG(ERROR) << "[bridge] Call engine FreeDeviceMemory failed, "
+               << result.GetError();
+    throw nndevice_error(result.GetError());
+  }
+}
+
+void CopyToDevice(const std::unique_ptr<Context>& ctx, uint8_t* dst,
+                  rust::Slice<const uint8_t> src) {
+  VLOG(1) << "[bridge] Call CopyToDevice in bridge cxx, len: " << src.size();
+  auto result = ENGINE.CopyToDevice(ctx, dst, src.data(), src.size());
+  if (!result.IsOK()) {
+    LOG(ERROR) << "[bridge] Call engine CopyToDevice failed, "
+               << result.GetError();
+    throw nndevice_error(result.GetError());
+  }
+}
+
+void CopyToHost(const std::unique_ptr<Context>& ctx, rust::Slice<uint8_t> dst,
+                const uint8_t* src) {
+  VLOG(1) << "[bridge] Call CopyToHost in bridge cxx, len: " << dst.size();
+  auto result = ENGINE.CopyToHost(ctx, dst.data(), src, dst.size());
+  if (!result.IsOK()) {
+    LOG(ERROR) << "[bridge] Call engine CopyToHost failed, "
+               << result.GetError();
+    throw nndevice_error(result.GetError());
+  }
+}
+#else
+uint8_t* AllocDeviceMemory(const std::unique_ptr<Context>& ctx, size_t len) {
+  VLOG(1) << "[bridge] AllocDeviceMemory is not supported by the SDK.";
+  throw unsupported_error();
+}
+
+void FreeDeviceMemory(const std::unique_ptr<Context>& ctx, uint8_t* ptr) {
+  throw unsupported_error();
+}
+
+void CopyToDevice(const std::unique_ptr<Context>& ctx, uint8_t* dst,
+                  rust::Slice<const uint8_t> src) {
+  throw unsupported_error();
+}
+
+void CopyToHost(const std::unique_ptr<Context>& ctx, rust::Slice<uint8_t> dst,
+                const uint8_t* src) {
+  throw unsupported_error();
+}
+#endif
+
 #undef CLIENT
 
 // }  // namespace bridge
@@ -226,18 +226,10 @@ pub fn main() {
         }
     };
 
-    let mut data1 = Tensor::new("data1", Format::NCHW, DType::Int32, Type::Variable);
-    data1.set_array2(&[[-1, 2], [3, 4]]);
-
-    let mut data2 = Tensor::new("data2", Format::NCHW, DType::Int32, Type::Variable);
-    data2.set_array2(&[[1, -2], [3, 4]]);
-
-    let mut data3 =
-        Tensor::new_with_shape("data3", &[2, 3], Format::NCHW, DType::Int32, Type::Variable);
-    data3.set_array2(&[[1, 2, -3], [4, 5, 6]]);
-
-    let mut data4 = Tensor::new("data4", Format::NCHW, DType::Int32, Type::Variable);
-    data4.set_array2(&[[1, 2, 3], [-4, 5, 6]]);
+    let data1 = Tensor::from_vec("data1", &[2, 2], vec![-1, 2, 3, 4]).unwrap();
+    let data2 = Tensor::from_vec("data2", &[2, 2], vec![1, -2, 3, 4]).unwrap();
+    let data3 = Tensor::from_vec("data3", &[2, 3], vec![1, 2, -3, 4, 5, 6]).unwrap();
+    let data4 = Tensor::from_vec("data4", &[2, 3], vec![1, 2, 3, -4, 5, 6]).unwrap();
     let inputs = [&data1, &data2, &data3, &data4];
 
     match nndevice::engine::excute(&ctx, &inputs, |result| {
@@ -1,5 +1,6 @@
 pub mod engine;
 mod ffi;
+pub mod memory;
 
 use cxx::Exception;
 use ffi as nndevice;
@@ -21,12 +22,20 @@ pub enum Error {
     NnDeviceCloseErr,
     #[error("后端调用错误")]
     NnDeviceDriverErr,
+    #[error("后端SDK不支持该接口, 需要启用engine-ext feature")]
+    Unsupported,
 }
 
+/// SDK没有提供的接口抛出的异常，与nndevice.cc中的unsupported_error一致
+const UNSUPPORTED: &str = "unsupported";
+
 /**
  * 要与inos/ai_chip/result.h中的定义匹配
  */
 pub fn parser_error(e: Exception) -> Option<Error> {
+    if e.what() == UNSUPPORTED {
+        return Some(Error::Unsupported);
+    }
     let rc = match  e.what().parse::<i32>() {
         Ok(rc) => rc,
         Err(_) => {
@@ -41,6 +41,33 @@ pub fn destory_context(ctx: Context) -> Result<(), Error> {
     }
 }
 
+/// 后端支持的算子
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpSupport {
+    pub domain: String,
+    pub op_type: String,
+    /// 支持的数据类型，为空时不限制
+    pub dtypes: Vec<tensor::DType>,
+}
+
+/// 查询后端支持的算子和数据类型
+pub fn supported_ops(ctx: &Context) -> Result<Vec<OpSupport>, Error> {
+    match ffi::GetSupportedOps(ctx) {
+        Ok(ops) => Ok(ops
+            .into_iter()
+            .map(|op| OpSupport {
+                domain: op.domain,
+                op_type: op.op_type,
+                dtypes: op.dtypes.into_iter().map(tensor::DType::from_code).collect(),
+            })
+            .collect()),
+        Err(e) => match parser_error(e) {
+            Some(e) => Err(e),
+            None => panic!("Not reachable!"),
+        },
+    }
+}
+
 pub fn compile_graph<C>(ctx: &Context, graph: &Graph, cb: C) -> Result<(), Error>
 where
     C: FnOnce(Result<(), Error>) + 'static,
@@ -69,6 +96,21 @@ where
     }
 }
 pub fn excute<C>(ctx: &Context, inputs: &[&Tensor], cb: C) -> Result<(), Error>
+where
+    C: FnOnce(Result<Vec<Tensor>, Error>) + 'static,
+{
+    excute_with_options(ctx, inputs, false, cb)
+}
+
+/// 执行推理，`outputs_on_device`为true时请求后端将输出保留在设备上
+///
+/// 后端返回的输出数据由后端管理，Location为Device时数据指针为设备地址
+pub fn excute_with_options<C>(
+    ctx: &Context,
+    inputs: &[&Tensor],
+    outputs_on_device: bool,
+    cb: C,
+) -> Result<(), Error>
 where
     C: FnOnce(Result<Vec<Tensor>, Error>) + 'static,
 {
@@ -82,6 +124,7 @@ where
     match ffi::Execute(
         ctx,
         &inputs,
+        outputs_on_device,
         |rust_cb, outputs, rc| {
             let cb = rust_cb.cb;
             match parser_error_from_code(rc) {
@@ -99,7 +142,7 @@ where
                             tensor.set_data(
                                 rust_tensor.data,
                                 rust_tensor.len,
-                                tensor::Location::Host,
+                                tensor::Location::from_code(rust_tensor.location),
                             );
                             tensor
                         })
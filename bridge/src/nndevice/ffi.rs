@@ -1,5 +1,8 @@
+use std::borrow::Cow;
+
 use super::Error;
-use model::attribute::Attribute;
+use log::*;
+use model::attribute::{AttType, Attribute};
 use model::graph::Graph;
 use model::operator::Operator;
 use model::tensor::Tensor;
@@ -18,6 +21,19 @@ pub mod ffi {
         layout: u32,
         data: *mut u8,
         len: usize,
+        location: u32,
+    }
+
+    /// 后端支持的算子，dtypes为空时不限制数据类型
+    struct RustOpSupport {
+        domain: String,
+        op_type: String,
+        dtypes: Vec<u32>,
+    }
+
+    struct RustOpsetImport {
+        domain: String,
+        version: i64,
     }
 
     // 暴露Rust接口到C++
@@ -32,10 +48,16 @@ pub mod ffi {
         unsafe fn GraphName<'a>(self: &'a GraphWrapper) -> &'a String;
         #[rust_name = "graph_all_operators"]
         unsafe fn GraphAllOperators<'a>(self: &'a GraphWrapper) -> Vec<OperatorWrapper<'a>>;
+        #[rust_name = "opset_imports"]
+        unsafe fn OpsetImports<'a>(self: &'a GraphWrapper) -> Vec<RustOpsetImport>;
         #[rust_name = "name"]
         unsafe fn Name<'a>(self: &'a OperatorWrapper) -> &'a String;
         #[rust_name = "type1"]
         unsafe fn Type<'a>(self: &'a OperatorWrapper) -> &'a String;
+        #[rust_name = "domain"]
+        unsafe fn Domain<'a>(self: &'a OperatorWrapper) -> &'a String;
+        #[rust_name = "since_version"]
+        unsafe fn SinceVersion<'a>(self: &'a OperatorWrapper) -> i64;
         #[rust_name = "inputs"]
         unsafe fn Inputs<'a>(self: &'a OperatorWrapper) -> Vec<TensorWrapper<'a>>;
         #[rust_name = "outputs"]
@@ -56,6 +78,10 @@ pub mod ffi {
         unsafe fn Data<'a>(self: &'a TensorWrapper) -> *const u8;
         #[rust_name = "data_len"]
         unsafe fn DataLen<'a>(self: &'a TensorWrapper) -> usize;
+        #[rust_name = "location"]
+        unsafe fn Location<'a>(self: &'a TensorWrapper) -> u32;
+        #[rust_name = "sparse_indices"]
+        unsafe fn SparseIndices<'a>(self: &'a TensorWrapper) -> &'a [i64];
         #[rust_name = "name"]
         unsafe fn Name<'a>(self: &'a AttributeWrapper) -> &'a String;
         #[rust_name = "type1"]
@@ -94,6 +120,7 @@ pub mod ffi {
             opts: &RustOptions,
         ) -> Result<UniquePtr<CxxContext>>;
         pub fn DestoryContext(ctx: UniquePtr<CxxContext>) -> Result<()>;
+        pub fn GetSupportedOps(ctx: &UniquePtr<CxxContext>) -> Result<Vec<RustOpSupport>>;
         pub fn CompileGraph(
             ctx: &UniquePtr<CxxContext>,
             graph: &GraphWrapper,
@@ -103,9 +130,22 @@ pub mod ffi {
         pub fn Execute(
             ctx: &UniquePtr<CxxContext>,
             inputs: &Vec<TensorWrapper>,
+            outputs_on_device: bool,
             cb: fn(Box<ExecuteCallback>, &Vec<RustTensor>, i32),
             rust_cb: Box<ExecuteCallback>,
         ) -> Result<()>;
+        pub unsafe fn AllocDeviceMemory(ctx: &UniquePtr<CxxContext>, len: usize) -> Result<*mut u8>;
+        pub unsafe fn FreeDeviceMemory(ctx: &UniquePtr<CxxContext>, ptr: *mut u8) -> Result<()>;
+        pub unsafe fn CopyToDevice(
+            ctx: &UniquePtr<CxxContext>,
+            dst: *mut u8,
+            src: &[u8],
+        ) -> Result<()>;
+        pub unsafe fn CopyToHost(
+            ctx: &UniquePtr<CxxContext>,
+            dst: &mut [u8],
+            src: *const u8,
+        ) -> Result<()>;
     }
 }
 
@@ -146,15 +186,39 @@ impl<'a> GraphWrapper<'a> {
             .map(|op| OperatorWrapper::new(op))
             .collect()
     }
+
+    fn opset_imports(&'a self) -> Vec<ffi::RustOpsetImport> {
+        self.graph
+            .opset_imports()
+            .iter()
+            .map(|(domain, version)| ffi::RustOpsetImport {
+                domain: domain.clone(),
+                version: *version,
+            })
+            .collect()
+    }
 }
 
 pub struct TensorWrapper<'a> {
     pub tag: &'a String,
-    pub tensor: &'a Tensor,
+    pub tensor: Cow<'a, Tensor>,
 }
 impl<'a> TensorWrapper<'a> {
     pub fn new(tag: &'a String, tensor: &'a Tensor) -> Self {
-        Self { tag, tensor }
+        // SDK不支持稀疏张量时转换为稠密张量，数据不在host上的保持稀疏，由C++侧报错
+        #[cfg(not(feature = "engine-ext"))]
+        if tensor.is_sparse() {
+            if let Ok(dense) = tensor.to_dense() {
+                return Self {
+                    tag,
+                    tensor: Cow::Owned(dense),
+                };
+            }
+        }
+        Self {
+            tag,
+            tensor: Cow::Borrowed(tensor),
+        }
     }
 
     fn name(&'a self) -> &'a String {
@@ -184,6 +248,15 @@ impl<'a> TensorWrapper<'a> {
     fn data_len(&'a self) -> usize {
         self.tensor.data_len()
     }
+
+    fn location(&'a self) -> u32 {
+        self.tensor.location().get_code()
+    }
+
+    // 稠密张量返回空数组
+    fn sparse_indices(&'a self) -> &'a [i64] {
+        self.tensor.sparse_indices().unwrap_or_default()
+    }
 }
 
 pub struct OperatorWrapper<'a> {
@@ -202,6 +275,14 @@ impl<'a> OperatorWrapper<'a> {
         self.op.r#type()
     }
 
+    fn domain(&'a self) -> &'a String {
+        self.op.domain()
+    }
+
+    fn since_version(&'a self) -> i64 {
+        self.op.since_version()
+    }
+
     fn inputs(&'a self) -> Vec<TensorWrapper<'a>> {
         self.op
             .inputs()
@@ -222,6 +303,27 @@ impl<'a> OperatorWrapper<'a> {
         self.op
             .attributes()
             .into_iter()
+            .filter(|v| {
+                // 后端只支持标量、列表和字符串类型的属性
+                let supported = matches!(
+                    v.1.r#type(),
+                    AttType::Int
+                        | AttType::Ints
+                        | AttType::Float
+                        | AttType::Floats
+                        | AttType::String
+                        | AttType::Strings
+                );
+                if !supported {
+                    warn!(
+                        "op {} attribute {} type {:?} is not supported by backend, skip it",
+                        self.op.name(),
+                        v.0,
+                        v.1.r#type()
+                    );
+                }
+                supported
+            })
             .map(|v| AttributeWrapper::new(v.0, v.1))
             .collect()
     }
@@ -246,32 +348,44 @@ impl<'a> AttributeWrapper<'a> {
     }
 
     fn as_int(&'a self) -> i64 {
-        let attr = self.attr.clone();
-        attr.into()
+        self.attr.try_into().unwrap_or_else(|e| {
+            error!("属性{}转换失败, {}", self.name, e);
+            Default::default()
+        })
     }
 
     fn as_ints(&'a self) -> Vec<i64> {
-        let attr = self.attr.clone();
-        attr.into()
+        self.attr.try_into().unwrap_or_else(|e| {
+            error!("属性{}转换失败, {}", self.name, e);
+            Default::default()
+        })
     }
 
     fn as_float(&'a self) -> f32 {
-        let attr = self.attr.clone();
-        attr.into()
+        self.attr.try_into().unwrap_or_else(|e| {
+            error!("属性{}转换失败, {}", self.name, e);
+            Default::default()
+        })
     }
 
     fn as_floats(&'a self) -> Vec<f32> {
-        let attr = self.attr.clone();
-        attr.into()
+        self.attr.try_into().unwrap_or_else(|e| {
+            error!("属性{}转换失败, {}", self.name, e);
+            Default::default()
+        })
     }
 
     fn as_string(&'a self) -> String {
-        let attr = self.attr.clone();
-        attr.into()
+        self.attr.try_into().unwrap_or_else(|e| {
+            error!("属性{}转换失败, {}", self.name, e);
+            Default::default()
+        })
     }
 
     fn as_strings(&'a self) -> Vec<String> {
-        let attr = self.attr.clone();
-        attr.into()
+        self.attr.try_into().unwrap_or_else(|e| {
+            error!("属性{}转换失败, {}", self.name, e);
+            Default::default()
+        })
     }
 }
@@ -0,0 +1,168 @@
+use super::engine::Context;
+use super::ffi::ffi;
+use super::{parser_error, Error};
+use cxx::Exception;
+use log::*;
+use model::tensor::DeviceBuffer;
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// 设备内存操作
+pub trait DeviceMemory {
+    /// 分配len字节的设备内存
+    fn alloc(&self, len: usize) -> Result<*mut u8, Error>;
+    /// 释放alloc分配的设备内存
+    ///
+    /// # Safety
+    /// `ptr`必须由同一个DeviceMemory的alloc返回，且只能释放一次
+    unsafe fn free(&self, ptr: *mut u8) -> Result<(), Error>;
+    /// 将host数据复制到设备内存
+    ///
+    /// # Safety
+    /// `dst`必须指向至少`src.len()`字节的有效设备内存
+    unsafe fn copy_to_device(&self, dst: *mut u8, src: &[u8]) -> Result<(), Error>;
+    /// 将设备内存复制到host
+    ///
+    /// # Safety
+    /// `src`必须指向至少`dst.len()`字节的有效设备内存
+    unsafe fn copy_to_host(&self, dst: &mut [u8], src: *const u8) -> Result<(), Error>;
+}
+
+fn to_error(e: Exception) -> Error {
+    parser_error(e).unwrap_or(Error::UnknowErr)
+}
+
+impl DeviceMemory for Context {
+    fn alloc(&self, len: usize) -> Result<*mut u8, Error> {
+        unsafe { ffi::AllocDeviceMemory(self, len) }.map_err(to_error)
+    }
+
+    unsafe fn free(&self, ptr: *mut u8) -> Result<(), Error> {
+        ffi::FreeDeviceMemory(self, ptr).map_err(to_error)
+    }
+
+    unsafe fn copy_to_device(&self, dst: *mut u8, src: &[u8]) -> Result<(), Error> {
+        ffi::CopyToDevice(self, dst, src).map_err(to_error)
+    }
+
+    unsafe fn copy_to_host(&self, dst: &mut [u8], src: *const u8) -> Result<(), Error> {
+        ffi::CopyToHost(self, dst, src).map_err(to_error)
+    }
+}
+
+/// 用host内存模拟设备内存，用于没有真实后端时测试
+#[derive(Default)]
+pub struct HostMemory {
+    // 已分配的内存及其布局
+    allocated: RefCell<HashMap<usize, Layout>>,
+}
+
+impl HostMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 尚未释放的内存块数量
+    pub fn allocated(&self) -> usize {
+        self.allocated.borrow().len()
+    }
+}
+
+impl DeviceMemory for HostMemory {
+    fn alloc(&self, len: usize) -> Result<*mut u8, Error> {
+        let layout = Layout::from_size_align(len.max(1), 16).map_err(|_| Error::InvalidParam)?;
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            return Err(Error::NnDeviceDriverErr);
+        }
+        self.allocated.borrow_mut().insert(ptr as usize, layout);
+        Ok(ptr)
+    }
+
+    unsafe fn free(&self, ptr: *mut u8) -> Result<(), Error> {
+        match self.allocated.borrow_mut().remove(&(ptr as usize)) {
+            Some(layout) => {
+                dealloc(ptr, layout);
+                Ok(())
+            }
+            None => Err(Error::InvalidParam),
+        }
+    }
+
+    unsafe fn copy_to_device(&self, dst: *mut u8, src: &[u8]) -> Result<(), Error> {
+        std::ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len());
+        Ok(())
+    }
+
+    unsafe fn copy_to_host(&self, dst: &mut [u8], src: *const u8) -> Result<(), Error> {
+        std::ptr::copy_nonoverlapping(src, dst.as_mut_ptr(), dst.len());
+        Ok(())
+    }
+}
+
+/// 一块设备内存，释放时归还给分配它的DeviceMemory
+pub struct Buffer {
+    ptr: *mut u8,
+    len: usize,
+    memory: Rc<dyn DeviceMemory>,
+}
+
+impl Buffer {
+    pub fn alloc(memory: &Rc<dyn DeviceMemory>, len: usize) -> Result<Self, Error> {
+        let ptr = memory.alloc(len)?;
+        Ok(Self {
+            ptr,
+            len,
+            memory: memory.clone(),
+        })
+    }
+}
+
+impl Debug for Buffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Buffer {{ ptr: {:p}, len: {} }}", self.ptr, self.len)
+    }
+}
+
+impl DeviceBuffer for Buffer {
+    fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        // ptr由memory分配，Buffer释放时只归还一次
+        if let Err(e) = unsafe { self.memory.free(self.ptr) } {
+            error!("释放设备内存{:p}失败, {}", self.ptr, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_memory_works() {
+        let host = Rc::new(HostMemory::new());
+        let memory: Rc<dyn DeviceMemory> = host.clone();
+        let buffer = Buffer::alloc(&memory, 4).unwrap();
+        let mut dst = [0; 4];
+        unsafe {
+            memory.copy_to_device(buffer.ptr(), &[1, 2, 3, 4]).unwrap();
+            memory.copy_to_host(&mut dst, buffer.ptr()).unwrap();
+        }
+        assert_eq!([1, 2, 3, 4], dst);
+        assert_eq!(1, host.allocated());
+        drop(buffer);
+        assert_eq!(0, host.allocated());
+    }
+}
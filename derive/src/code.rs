@@ -90,10 +90,18 @@ pub fn parse_from_code_stream(input: proc_macro::TokenStream) -> proc_macro::Tok
 
     let output = quote! {
         impl #name {
-            pub fn from_code(code: u32) -> Self {
-                match code {
+            pub fn try_from_code(code: u32) -> Option<Self> {
+                let value = match code {
                     #(#setters),*
-                    ,_ => panic!("Invalid code value, which is {}", code),
+                    ,_ => return None,
+                };
+                Some(value)
+            }
+
+            pub fn from_code(code: u32) -> Self {
+                match Self::try_from_code(code) {
+                    Some(value) => value,
+                    None => panic!("Invalid code value, which is {}", code),
                 }
             }
         }
@@ -1,10 +1,10 @@
 use derive::{FromCode, GetCode};
-use std::any::type_name;
-use std::mem::forget;
+use std::string::FromUtf8Error;
 
-use super::data::Data;
+use super::graph::Graph;
+use super::tensor::Tensor;
 
-/// 属性类型
+/// 属性类型，编码与ONNX AttributeProto.AttributeType一致
 #[derive(Debug, Clone, Copy, PartialEq, GetCode, FromCode)]
 pub enum AttType {
     #[code(0)]
@@ -16,253 +16,331 @@ pub enum AttType {
     #[code(3)]
     String,
     #[code(4)]
+    Tensor,
+    #[code(5)]
+    Graph,
+    #[code(6)]
     Floats,
     #[code(7)]
     Ints,
     #[code(8)]
     Strings,
+    #[code(9)]
+    Tensors,
+    #[code(10)]
+    Graphs,
+    #[code(11)]
+    SparseTensor,
+    #[code(12)]
+    SparseTensors,
+    #[code(13)]
+    TypeProto,
+    #[code(14)]
+    TypeProtos,
 }
 
-impl AttType {
-    pub fn from_type_name(name: &str) -> Self {
-        match name {
-            "f32" => Self::Float,
-            "&[f32]" => Self::Floats,
-            "i64" => Self::Int,
-            "&[i64]" => Self::Ints,
-            "alloc::string::String" => Self::String,
-            "&[alloc::string::String]" => Self::Strings,
-            _ => panic!("Not support type name"),
-        }
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("属性类型不匹配, 需要{expected:?}, 实际为{actual:?}")]
+    TypeMismatch { expected: AttType, actual: AttType },
+    #[error("属性不是合法的UTF-8字符串, {0}")]
+    InvalidUtf8(#[from] FromUtf8Error),
+}
+
+/// 算子属性
+///
+/// 每种ONNX属性类型对应一个变体，数据以拥有所有权的方式保存，
+/// 字符串保留原始字节，保证与模型中的内容完全一致
+#[derive(Debug, Clone, GetCode)]
+pub enum Attribute {
+    #[code(1)]
+    Float(f32),
+    #[code(2)]
+    Int(i64),
+    #[code(3)]
+    String(Vec<u8>),
+    #[code(4)]
+    Tensor(Tensor),
+    #[code(5)]
+    Graph(Graph),
+    #[code(6)]
+    Floats(Vec<f32>),
+    #[code(7)]
+    Ints(Vec<i64>),
+    #[code(8)]
+    Strings(Vec<Vec<u8>>),
+    #[code(9)]
+    Tensors(Vec<Tensor>),
+    #[code(10)]
+    Graphs(Vec<Graph>),
+    /// 稀疏张量，加载时默认转换为稠密张量
+    #[code(11)]
+    SparseTensor(Tensor),
+    #[code(12)]
+    SparseTensors(Vec<Tensor>),
+    /// 类型描述，以不带数据的Tensor表示dtype和shape
+    #[code(13)]
+    TypeProto(Tensor),
+    #[code(14)]
+    TypeProtos(Vec<Tensor>),
+}
+
+impl Attribute {
+    pub fn r#type(&self) -> AttType {
+        AttType::from_code(self.get_code())
     }
-    pub fn type_name(&self) -> &str {
-        match self {
-            Self::Float => type_name::<f32>(),
-            Self::Floats => type_name::<&[f32]>(),
-            Self::Int => type_name::<i64>(),
-            Self::Ints => type_name::<&[i64]>(),
-            Self::String => type_name::<String>(),
-            Self::Strings => type_name::<&[String]>(),
-            _ => panic!("Not support att_type now"),
-        }
+
+    pub fn from_vec_u8_as_string(v: Vec<u8>) -> Self {
+        Self::String(v)
+    }
+
+    pub fn from_vec_u8_as_strings(v: Vec<Vec<u8>>) -> Self {
+        Self::Strings(v)
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Attribute {
-    /// 类型
-    r#type: AttType,
-    /// 数据
-    data: Data,
+    fn mismatch(&self, expected: AttType) -> Error {
+        Error::TypeMismatch {
+            expected,
+            actual: self.r#type(),
+        }
+    }
 }
 
 impl From<i64> for Attribute {
     fn from(value: i64) -> Self {
-        Self {
-            r#type: AttType::Int,
-            data: Data::from_array(&[value]),
-        }
+        Self::Int(value)
     }
 }
 
 impl From<&[i64]> for Attribute {
     fn from(value: &[i64]) -> Self {
-        Self {
-            r#type: AttType::Ints,
-            data: Data::from_array(value),
-        }
+        Self::Ints(value.to_vec())
+    }
+}
+
+impl From<Vec<i64>> for Attribute {
+    fn from(value: Vec<i64>) -> Self {
+        Self::Ints(value)
     }
 }
 
 impl From<f32> for Attribute {
     fn from(value: f32) -> Self {
-        Self {
-            r#type: AttType::Float,
-            data: Data::from_array(&[value]),
-        }
+        Self::Float(value)
     }
 }
 
 impl From<&[f32]> for Attribute {
     fn from(value: &[f32]) -> Self {
-        Self {
-            r#type: AttType::Floats,
-            data: Data::from_array(value),
-        }
+        Self::Floats(value.to_vec())
+    }
+}
+
+impl From<Vec<f32>> for Attribute {
+    fn from(value: Vec<f32>) -> Self {
+        Self::Floats(value)
     }
 }
 
 impl From<&str> for Attribute {
     fn from(v: &str) -> Self {
-        let v = cast_to_u8_vec_unsafe(String::from(v));
+        Self::String(v.as_bytes().to_vec())
+    }
+}
 
-        Self {
-            r#type: AttType::String,
-            data: Data::from(v),
-        }
+impl From<String> for Attribute {
+    fn from(v: String) -> Self {
+        Self::String(v.into_bytes())
     }
 }
 
 impl From<&[String]> for Attribute {
     fn from(v: &[String]) -> Self {
-        Self {
-            r#type: AttType::Strings,
-            data: Data::from_array(v),
-        }
+        Self::Strings(v.iter().map(|s| s.as_bytes().to_vec()).collect())
     }
 }
 
-impl Into<i64> for Attribute {
-    fn into(self) -> i64 {
-        debug_assert_eq!(self.r#type, AttType::Int, "type is not Int");
-        match self.data.try_into::<i64>() {
-            Some(v) => v[0],
-            None => unreachable!("guarantee not None, because of from implement"),
-        }
+impl From<Vec<String>> for Attribute {
+    fn from(v: Vec<String>) -> Self {
+        Self::Strings(v.into_iter().map(String::into_bytes).collect())
     }
 }
 
-impl Into<Vec<i64>> for Attribute {
-    fn into(self) -> Vec<i64> {
-        debug_assert_eq!(self.r#type, AttType::Ints, "type is not Ints");
-        match self.data.try_into::<i64>() {
-            Some(v) => v,
-            None => unreachable!("guarantee not None, because of from implement"),
-        }
+impl From<Tensor> for Attribute {
+    fn from(v: Tensor) -> Self {
+        Self::Tensor(v)
     }
 }
 
-impl Into<f32> for Attribute {
-    fn into(self) -> f32 {
-        debug_assert_eq!(self.r#type, AttType::Float, "type is not Float");
-        match self.data.try_into::<f32>() {
-            Some(v) => v[0],
-            None => unreachable!("guarantee not None, because of from implement"),
-        }
+impl From<Vec<Tensor>> for Attribute {
+    fn from(v: Vec<Tensor>) -> Self {
+        Self::Tensors(v)
     }
 }
 
-impl Into<Vec<f32>> for Attribute {
-    fn into(self) -> Vec<f32> {
-        debug_assert_eq!(self.r#type, AttType::Floats, "type is not Floats");
-        match self.data.try_into::<f32>() {
-            Some(v) => v,
-            None => unreachable!("guarantee not None, because of from implement"),
-        }
+impl From<Graph> for Attribute {
+    fn from(v: Graph) -> Self {
+        Self::Graph(v)
     }
 }
 
-impl Into<String> for Attribute {
-    fn into(self) -> String {
-        debug_assert_eq!(self.r#type, AttType::String, "type is not String");
-        match self.data.try_into_own_data() {
-            Some(v) => cast_to_string_unsafe(v),
-            None => unreachable!("guarantee not None, because of from implement"),
-        }
+impl From<Vec<Graph>> for Attribute {
+    fn from(v: Vec<Graph>) -> Self {
+        Self::Graphs(v)
     }
 }
 
-impl Into<Vec<String>> for Attribute {
-    fn into(self) -> Vec<String> {
-        debug_assert_eq!(self.r#type, AttType::Strings, "type is not Strings");
-        match self.data.try_into::<String>() {
-            Some(v) => v,
-            None => unreachable!("guarantee not None, because of from implement"),
+// 为拥有所有权和借用的属性同时实现TryFrom，借用时复制数据
+macro_rules! impl_try_from {
+    ($t:ty, $variant:ident) => {
+        impl TryFrom<Attribute> for $t {
+            type Error = Error;
+
+            fn try_from(attr: Attribute) -> Result<Self, Self::Error> {
+                match attr {
+                    Attribute::$variant(v) => Ok(v),
+                    _ => Err(attr.mismatch(AttType::$variant)),
+                }
+            }
         }
-    }
-}
 
-impl Attribute {
-    pub fn r#type(&self) -> AttType {
-        self.r#type
-    }
+        impl TryFrom<&Attribute> for $t {
+            type Error = Error;
 
-    pub fn from_vec_u8_as_string(v: Vec<u8>) -> Self {
-        Self {
-            r#type: AttType::String,
-            data: Data::from(v),
+            fn try_from(attr: &Attribute) -> Result<Self, Self::Error> {
+                match attr {
+                    Attribute::$variant(v) => Ok(v.clone()),
+                    _ => Err(attr.mismatch(AttType::$variant)),
+                }
+            }
         }
+    };
+}
+
+impl_try_from!(i64, Int);
+impl_try_from!(Vec<i64>, Ints);
+impl_try_from!(f32, Float);
+impl_try_from!(Vec<f32>, Floats);
+impl_try_from!(Vec<u8>, String);
+impl_try_from!(Vec<Vec<u8>>, Strings);
+impl_try_from!(Tensor, Tensor);
+impl_try_from!(Vec<Tensor>, Tensors);
+impl_try_from!(Graph, Graph);
+impl_try_from!(Vec<Graph>, Graphs);
+
+impl TryFrom<Attribute> for String {
+    type Error = Error;
+
+    fn try_from(attr: Attribute) -> Result<Self, Self::Error> {
+        let v: Vec<u8> = attr.try_into()?;
+        Ok(String::from_utf8(v)?)
     }
+}
 
-    pub fn from_vec_u8_as_strings(v: Vec<Vec<u8>>) -> Self {
-        let value = v.into_iter().flatten().collect();
-        Self {
-            r#type: AttType::Strings,
-            data: Data::from(value),
-        }
+impl TryFrom<&Attribute> for String {
+    type Error = Error;
+
+    fn try_from(attr: &Attribute) -> Result<Self, Self::Error> {
+        let v: Vec<u8> = attr.try_into()?;
+        Ok(String::from_utf8(v)?)
     }
 }
 
-// 将String转为u8数组，用于存储
-fn cast_to_u8_vec_unsafe(mut v: String) -> Vec<u8> {
-    let length = v.len();
-    let capacity = v.capacity();
-    let ptr = v.as_mut_ptr();
+impl TryFrom<Attribute> for Vec<String> {
+    type Error = Error;
 
-    // 不再执行v的析构
-    forget(v);
-    // 重新创建Vec<u8>数组
-    unsafe { Vec::from_raw_parts(ptr, length, capacity) }
+    fn try_from(attr: Attribute) -> Result<Self, Self::Error> {
+        let vs: Vec<Vec<u8>> = attr.try_into()?;
+        vs.into_iter()
+            .map(|v| String::from_utf8(v).map_err(Error::from))
+            .collect()
+    }
 }
 
-fn cast_to_string_unsafe(mut v: Vec<u8>) -> String {
-    let length = v.len();
-    let capacity = v.capacity();
-    let ptr = v.as_mut_ptr();
+impl TryFrom<&Attribute> for Vec<String> {
+    type Error = Error;
 
-    // 不再执行v的析构
-    forget(v);
-    // 重新创建Vec<u8>数组
-    unsafe { String::from_raw_parts(ptr, length, capacity) }
+    fn try_from(attr: &Attribute) -> Result<Self, Self::Error> {
+        let vs: Vec<Vec<u8>> = attr.try_into()?;
+        vs.into_iter()
+            .map(|v| String::from_utf8(v).map_err(Error::from))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::u8;
-
     use super::*;
+    use crate::tensor::*;
 
     #[test]
     fn it_works() {
         let attr = Attribute::from(-1);
-        let v: i64 = attr.into();
+        assert_eq!(AttType::Int, attr.r#type());
+        let v: i64 = attr.try_into().unwrap();
         assert_eq!(-1, v);
 
         let attr = Attribute::from(&[-1_i64, 1_i64] as &[i64]);
-        let v: Vec<i64> = attr.into();
+        let v: Vec<i64> = attr.try_into().unwrap();
         assert_eq!(&[-1, 1], v.as_slice());
 
         let attr = Attribute::from(-1.0);
-        let v: f32 = attr.into();
+        let v: f32 = attr.try_into().unwrap();
         assert_eq!(-1.0, v);
 
         let a: &[f32] = &[1.0, 2.0];
         let attr = Attribute::from(a);
-        let v: Vec<f32> = attr.into();
+        let v: Vec<f32> = attr.try_into().unwrap();
         assert_eq!(a, v.as_slice());
 
         let attr = Attribute::from("test");
-        let v: String = attr.into();
+        let v: String = attr.try_into().unwrap();
         assert_eq!("test", v);
 
-        let str = String::from("value");
-        let attr = Attribute::from_vec_u8_as_string(cast_to_u8_vec_unsafe(str));
-        let v: String = attr.into();
+        let attr = Attribute::from_vec_u8_as_string(b"value".to_vec());
+        let v: String = (&attr).try_into().unwrap();
         assert_eq!("value", v);
 
         let attr = Attribute::from(&[String::from("1234"), String::from("123")] as &[String]);
-        let v: Vec<String> = attr.into();
+        let v: Vec<String> = attr.try_into().unwrap();
         assert_eq!(&[String::from("1234"), String::from("123")], v.as_slice());
 
-        let v1: Vec<u8> = cast_to_u8_vec_unsafe(String::from("value1"));
-        let v2: Vec<u8> = cast_to_u8_vec_unsafe(String::from("value2"));
-        let mut v: Vec<Vec<u8>> = vec![];
-        v.push(v1);
-        v.push(v2);
-        let attr = Attribute::from_vec_u8_as_strings(v);
-        if let Some(u) = attr.data.try_into_own_data() {
-            let vs = cast_to_string_unsafe(u);
-            assert_eq!(String::from("value1value2"), vs);
-        };
+        let tensor = Tensor::new_with_shape("t", &[2], Format::NCHW, DType::Int32, Type::Constant);
+        let attr = Attribute::from(tensor);
+        assert_eq!(AttType::Tensor, attr.r#type());
+        let v: Tensor = attr.try_into().unwrap();
+        assert_eq!("t", v.name());
+    }
+
+    #[test]
+    fn strings_keep_boundaries() {
+        let v = vec![b"value1".to_vec(), b"value2".to_vec(), vec![]];
+        let attr = Attribute::from_vec_u8_as_strings(v.clone());
+        let raw: Vec<Vec<u8>> = (&attr).try_into().unwrap();
+        assert_eq!(v, raw);
+        let vs: Vec<String> = attr.try_into().unwrap();
+        assert_eq!(vec!["value1", "value2", ""], vs);
+
+        // 非UTF-8字节原样保留，只有转换为String时报错
+        let attr = Attribute::from_vec_u8_as_string(vec![0xff, 0xfe]);
+        let raw: Vec<u8> = (&attr).try_into().unwrap();
+        assert_eq!(vec![0xff, 0xfe], raw);
+        let r: Result<String, Error> = attr.try_into();
+        assert!(matches!(r, Err(Error::InvalidUtf8(_))));
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let attr = Attribute::from(1.0);
+        let r: Result<i64, Error> = (&attr).try_into();
+        assert!(matches!(
+            r,
+            Err(Error::TypeMismatch {
+                expected: AttType::Int,
+                actual: AttType::Float
+            })
+        ));
+        let r: Result<Vec<String>, Error> = attr.try_into();
+        assert!(r.is_err());
     }
 }
@@ -1,34 +1,9 @@
-use std::mem::{forget, size_of};
 use std::fmt::Display;
 use std::ptr::null_mut;
-use super::tensor::Location;
-
-// 将其他类型的数据转为u8数组，转换后的u8数组不能直接使用，需要转回到原来的数据才可以使用
-fn cast_to_u8_vec_unsafe<T>(mut v: Vec<T>) -> Vec<u8> {
-    let ratio = size_of::<T>() / size_of::<u8>();
-    let length = v.len() * ratio;
-    let capacity = v.capacity() * ratio;
-    let ptr = v.as_mut_ptr() as *mut u8;
-
-    // 不再执行v的析构
-    forget(v);
-    // 重新创建Vec<u8>数组
-    unsafe { Vec::from_raw_parts(ptr, length, capacity) }
-}
-
-fn cast_to_t_vec_unsafe<T>(mut v: Vec<u8>) -> Vec<T> {
-    let ratio = size_of::<T>() / size_of::<u8>();
-    let length = v.len() / ratio;
-    let capacity = v.capacity() / ratio;
-    let ptr = v.as_mut_ptr() as *mut T;
+use std::rc::Rc;
+use super::tensor::{DeviceBuffer, Location, SharedBytes};
 
-    // 不再执行v的析构
-    forget(v);
-    // 重新创建Vec<T>数组
-    unsafe { Vec::from_raw_parts(ptr, length, capacity) }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Data {
     location: Location,
     length: usize,
@@ -37,6 +12,27 @@ pub struct Data {
     // 持有所有权，保证raw指针有效
     own_data: Vec<u8>,
     owned: bool,
+    // 设备内存，持有引用保证设备指针有效
+    device: Option<Rc<dyn DeviceBuffer>>,
+    // 共享的host数据，持有引用保证ptr有效
+    shared: Option<Rc<dyn SharedBytes>>,
+}
+
+impl Clone for Data {
+    // 持有所有权时复制数据，并指向新的内存；设备内存共享；否则只复制指针
+    fn clone(&self) -> Self {
+        if self.owned {
+            Self::from_location(self.own_data.clone(), self.location)
+        } else if let Some(buffer) = &self.device {
+            Self::from_device(buffer.clone())
+        } else if let Some(bytes) = &self.shared {
+            let mut data = Self::from_ptr(self.ptr, self.length, self.location);
+            data.shared = Some(bytes.clone());
+            data
+        } else {
+            Self::from_ptr(self.ptr, self.length, self.location)
+        }
+    }
 }
 
 impl Display for Data {
@@ -53,6 +49,8 @@ impl Data {
             ptr: null_mut(),
             own_data: vec![],
             owned: false,
+            device: None,
+            shared: None,
         }
     }
 
@@ -60,18 +58,28 @@ impl Data {
         Self::from_location(vs, Location::Host)
     }
 
-    pub fn from_array<T: Clone>(xs: &[T]) -> Data {
-        let v = xs.to_vec();
-        Self::from_vec(v)
+    pub fn from_ptr(ptr: *mut u8, length: usize, location: Location) -> Self {
+        Self { location, length, ptr, own_data: vec![], owned: false, device: None, shared: None }
     }
 
-    pub fn from_vec<T>(v: Vec<T>) -> Data {
-        let data = cast_to_u8_vec_unsafe(v);
-        Data::from(data)
+    /// 引用共享数据中`offset`开始的`length`字节，不复制数据
+    pub fn from_shared(bytes: Rc<dyn SharedBytes>, offset: usize, length: usize) -> Self {
+        let ptr = bytes.bytes()[offset..offset + length].as_ptr() as *mut u8;
+        let mut data = Self::from_ptr(ptr, length, Location::Host);
+        data.shared = Some(bytes);
+        data
     }
 
-    pub fn from_ptr(ptr: *mut u8, length: usize, location: Location) -> Self {
-        Self { location, length, ptr, own_data: vec![], owned: false }
+    pub fn from_device(buffer: Rc<dyn DeviceBuffer>) -> Self {
+        Self {
+            location: Location::Device,
+            length: buffer.len(),
+            ptr: buffer.ptr(),
+            own_data: vec![],
+            owned: false,
+            device: Some(buffer),
+            shared: None,
+        }
     }
 
     pub fn from_location(vs: Vec<u8>, location: Location) -> Self {
@@ -82,16 +90,31 @@ impl Data {
         data
     }
 
-    /// 如果原生存储的跟实际转换的类型不一致，将导致不可预知的错误
-   pub fn try_into<T>(mut self) -> Option<Vec<T>> {
+    /// 移交数据所有权，不持有所有权时返回None
+    pub fn try_into_bytes(mut self) -> Option<Vec<u8>> {
         if self.owned {
             self.reset();
-            Some(cast_to_t_vec_unsafe(self.own_data))
+            Some(self.own_data)
         } else {
             None
         }
     }
 
+    /// Host上的数据，未设置数据或数据在设备上时返回None
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        if self.location != Location::Host {
+            return None;
+        }
+        if self.owned {
+            Some(&self.own_data)
+        } else if self.ptr.is_null() {
+            None
+        } else {
+            // 不持有所有权时，由调用set_data的一方保证ptr有效
+            Some(unsafe { std::slice::from_raw_parts(self.ptr, self.length) })
+        }
+    }
+
     pub fn set_data(&mut self, mut vs: Vec<u8>) {
         self.length = vs.len();
         self.ptr = vs.as_mut_ptr();
@@ -115,15 +138,6 @@ impl Data {
         self.ptr
     }
 
-    pub fn try_into_own_data(mut self) -> Option<Vec<u8>> {
-        if self.owned {
-            self.reset();
-            Some(self.own_data)
-        } else {
-            None
-        }
-    }
-
     fn reset(&mut self) {
         self.ptr = null_mut();
         self.length = 0;
@@ -142,4 +156,3 @@ impl Data {
 //         str.as_mut_ptr()
 //     }
 // }
-
@@ -0,0 +1,86 @@
+use super::tensor::DType;
+use half::{bf16, f16};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// 可以作为Tensor元素的Rust标量类型
+///
+/// 该trait是封闭的，只为与DType一一对应的类型实现
+pub trait Element: private::Sealed + Copy + Default + PartialEq + std::fmt::Debug + 'static {
+    /// 对应的Tensor数据类型
+    const DTYPE: DType;
+
+    /// 按小端序写入字节数组
+    fn write_le(self, out: &mut Vec<u8>);
+
+    /// 从小端序字节读取，`bytes`的长度等于`DTYPE.size_of()`
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_element {
+    ($($t:ty => $dtype:ident),* $(,)?) => {
+        $(
+            impl private::Sealed for $t {}
+
+            impl Element for $t {
+                const DTYPE: DType = DType::$dtype;
+
+                fn write_le(self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    let mut buf = [0u8; std::mem::size_of::<$t>()];
+                    buf.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(buf)
+                }
+            }
+        )*
+    };
+}
+
+impl_element!(
+    f32 => Float32,
+    f64 => Float64,
+    f16 => Float16,
+    bf16 => Bfloat16,
+    u8 => Uint8,
+    u16 => Uint16,
+    u32 => Uint32,
+    u64 => Uint64,
+    i8 => Int8,
+    i16 => Int16,
+    i32 => Int32,
+    i64 => Int64,
+);
+
+impl private::Sealed for bool {}
+
+impl Element for bool {
+    const DTYPE: DType = DType::Bool;
+
+    fn write_le(self, out: &mut Vec<u8>) {
+        out.push(self as u8);
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+/// 将元素序列转换为小端序字节
+pub fn to_bytes<T: Element>(vs: &[T]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(vs.len() * T::DTYPE.size_of());
+    vs.iter().for_each(|v| v.write_le(&mut out));
+    out
+}
+
+/// 将小端序字节转换为元素序列，末尾不足一个元素的字节被忽略
+pub fn from_bytes<T: Element>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(T::DTYPE.size_of())
+        .map(T::read_le)
+        .collect()
+}
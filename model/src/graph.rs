@@ -1,14 +1,21 @@
 use super::operator::Operator;
+use super::tensor::Tensor;
 use std::collections::HashMap;
 
 use anyhow::{Result, Ok};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Graph {
     /// 图的名字
     name: String,
     /// 模型所有的节点
     operators: HashMap<String, Operator>,
+    /// 模型导入的算子集，domain到版本
+    opset_imports: HashMap<String, i64>,
+    /// 图的输入，不包含有初始值的输入
+    inputs: Vec<Tensor>,
+    /// 图的输出
+    outputs: Vec<Tensor>,
 }
 
 impl Graph {
@@ -16,6 +23,9 @@ impl Graph {
         Self {
             name: String::from(name),
             operators: HashMap::new(),
+            opset_imports: HashMap::new(),
+            inputs: vec![],
+            outputs: vec![],
         }
     }
 
@@ -25,6 +35,24 @@ impl Graph {
         Ok(self)
     }
 
+    pub fn add_opset_import(mut self, domain: &str, version: i64) -> Result<Self> {
+        self.opset_imports.insert(String::from(domain), version);
+
+        Ok(self)
+    }
+
+    pub fn add_input(mut self, input: Tensor) -> Result<Self> {
+        self.inputs.push(input);
+
+        Ok(self)
+    }
+
+    pub fn add_output(mut self, output: Tensor) -> Result<Self> {
+        self.outputs.push(output);
+
+        Ok(self)
+    }
+
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -36,6 +64,45 @@ impl Graph {
     pub fn get_operator(&self, name: &str) -> Option<&Operator> {
         self.operators.get(name)
     }
+
+    pub fn operators_mut(&mut self) -> Vec<&mut Operator> {
+        self.operators.values_mut().collect()
+    }
+
+    pub fn get_operator_mut(&mut self, name: &str) -> Option<&mut Operator> {
+        self.operators.get_mut(name)
+    }
+
+    pub fn remove_operator(&mut self, name: &str) -> Option<Operator> {
+        self.operators.remove(name)
+    }
+
+    pub fn inputs(&self) -> &[Tensor] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Tensor] {
+        &self.outputs
+    }
+
+    /// 用于图变换中更新输入描述
+    pub fn inputs_mut(&mut self) -> &mut Vec<Tensor> {
+        &mut self.inputs
+    }
+
+    /// 用于图变换中更新输出描述
+    pub fn outputs_mut(&mut self) -> &mut Vec<Tensor> {
+        &mut self.outputs
+    }
+
+    pub fn opset_imports(&self) -> &HashMap<String, i64> {
+        &self.opset_imports
+    }
+
+    /// 指定domain导入的算子集版本，未导入时返回None
+    pub fn opset_version(&self, domain: &str) -> Option<i64> {
+        self.opset_imports.get(domain).copied()
+    }
 }
 
 #[cfg(test)]
@@ -80,9 +147,20 @@ mod tests {
                             Type::Variable,
                         ),
                     ).unwrap()
-                    .add_attribute("B", Attribute::from("test")).unwrap(),
-            ).unwrap();
+                    .add_attribute("B", Attribute::from("test")).unwrap()
+                    .set_opset(ONNX_DOMAIN, 14).unwrap(),
+            ).unwrap()
+            .add_opset_import(ONNX_DOMAIN, 17).unwrap()
+            .add_input(Tensor::new("add1", Format::CHWN, DType::Int32, Type::Variable)).unwrap()
+            .add_output(Tensor::new("sum", Format::CHWN, DType::Int32, Type::Variable)).unwrap();
 
+        assert_eq!(Some(17), graph.opset_version(ONNX_DOMAIN));
+        assert_eq!(None, graph.opset_version("com.example"));
+        let op = graph.get_operator("name").unwrap();
+        assert_eq!(ONNX_DOMAIN, op.domain());
+        assert_eq!(14, op.since_version());
+        assert_eq!("add1", graph.inputs()[0].name());
+        assert_eq!("sum", graph.outputs()[0].name());
         println!("{:?}", graph);
     }
 }
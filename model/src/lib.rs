@@ -2,5 +2,8 @@ pub mod graph;
 pub mod operator;
 pub mod tensor;
 pub mod attribute;
+pub mod element;
+
+pub use half;
 
 mod data;
@@ -1,21 +1,49 @@
 use std::collections::HashMap;
 
+use super::attribute::{self, Attribute};
+use super::graph::Graph;
 use super::tensor::Tensor;
-use super::attribute::Attribute;
 use anyhow::Result;
 
-#[derive(Debug)]
+/// ONNX标准算子的domain，模型中空domain等价于该值
+pub const ONNX_DOMAIN: &str = "ai.onnx";
+
+/// 读取算子属性的错误
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("算子{op}({op_type})缺少属性{attr}")]
+    MissingAttribute {
+        op: String,
+        op_type: String,
+        attr: String,
+    },
+    #[error("算子{op}({op_type})的属性{attr}读取失败, {source}")]
+    InvalidAttribute {
+        op: String,
+        op_type: String,
+        attr: String,
+        source: attribute::Error,
+    },
+}
+
+#[derive(Debug, Clone)]
 pub struct Operator {
     /// 名字
     name: String,
     /// 类型
     r#type: String,
+    /// 算子所属的domain
+    domain: String,
+    /// 生效的算子定义版本，0表示未找到定义
+    since_version: i64,
     /// 输入Tensor
     inputs: HashMap<String, Tensor>,
     /// 输出Tensor
     outputs: HashMap<String, Tensor>,
     /// 属性
     attributes: HashMap<String, Attribute>,
+    /// 算子定义中声明的属性默认值，模型中未设置属性时使用
+    defaults: HashMap<String, Attribute>,
 }
 
 impl Operator {
@@ -23,9 +51,12 @@ impl Operator {
         Self {
             name: String::from(name),
             r#type: String::from(r#type),
+            domain: String::from(ONNX_DOMAIN),
+            since_version: 0,
             inputs: HashMap::new(),
             outputs: HashMap::new(),
             attributes: HashMap::new(),
+            defaults: HashMap::new(),
         }
     }
 
@@ -47,6 +78,19 @@ impl Operator {
         Ok(self)
     }
 
+    pub fn add_default_attribute(mut self, tag: &str, attr: Attribute) -> Result<Self> {
+        self.defaults.insert(String::from(tag), attr);
+
+        Ok(self)
+    }
+
+    pub fn set_opset(mut self, domain: &str, since_version: i64) -> Result<Self> {
+        self.domain = String::from(domain);
+        self.since_version = since_version;
+
+        Ok(self)
+    }
+
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -55,6 +99,14 @@ impl Operator {
         &self.r#type
     }
 
+    pub fn domain(&self) -> &String {
+        &self.domain
+    }
+
+    pub fn since_version(&self) -> i64 {
+        self.since_version
+    }
+
     pub fn inputs(&self) -> &HashMap<String, Tensor> {
         &self.inputs
     }
@@ -63,6 +115,11 @@ impl Operator {
         self.inputs.get(tag)
     }
 
+    /// 用于图变换中替换输入
+    pub fn inputs_mut(&mut self) -> &mut HashMap<String, Tensor> {
+        &mut self.inputs
+    }
+
     pub fn outputs(&self) -> &HashMap<String, Tensor> {
         &self.outputs
     }
@@ -71,6 +128,11 @@ impl Operator {
         self.outputs.get(tag)
     }
 
+    /// 用于图变换中替换输出
+    pub fn outputs_mut(&mut self) -> &mut HashMap<String, Tensor> {
+        &mut self.outputs
+    }
+
     pub fn attributes(&self) -> &HashMap<String, Attribute> {
         &self.attributes
     }
@@ -78,6 +140,105 @@ impl Operator {
     pub fn get_attribute(&self, tag: &str) -> Option<&Attribute> {
         self.attributes.get(tag)
     }
+
+    pub fn defaults(&self) -> &HashMap<String, Attribute> {
+        &self.defaults
+    }
+
+    /// 获取属性，模型中未设置时返回算子定义中的默认值
+    pub fn get_attribute_or_default(&self, tag: &str) -> Option<&Attribute> {
+        self.attributes.get(tag).or_else(|| self.defaults.get(tag))
+    }
+
+    /// 按类型读取属性，属性不存在时返回None，类型不匹配时返回错误
+    pub fn try_attr<T>(&self, tag: &str) -> Result<Option<T>, Error>
+    where
+        T: for<'a> TryFrom<&'a Attribute, Error = attribute::Error>,
+    {
+        match self.get_attribute_or_default(tag) {
+            None => Ok(None),
+            Some(attr) => T::try_from(attr)
+                .map(Some)
+                .map_err(|source| Error::InvalidAttribute {
+                    op: self.name.clone(),
+                    op_type: self.r#type.clone(),
+                    attr: String::from(tag),
+                    source,
+                }),
+        }
+    }
+
+    /// 按类型读取属性，属性不存在时返回错误
+    pub fn attr<T>(&self, tag: &str) -> Result<T, Error>
+    where
+        T: for<'a> TryFrom<&'a Attribute, Error = attribute::Error>,
+    {
+        self.try_attr(tag)?.ok_or_else(|| Error::MissingAttribute {
+            op: self.name.clone(),
+            op_type: self.r#type.clone(),
+            attr: String::from(tag),
+        })
+    }
+
+    /// 按类型读取属性，属性不存在时返回`default`
+    pub fn attr_or<T>(&self, tag: &str, default: T) -> Result<T, Error>
+    where
+        T: for<'a> TryFrom<&'a Attribute, Error = attribute::Error>,
+    {
+        Ok(self.try_attr(tag)?.unwrap_or(default))
+    }
+
+    pub fn attr_int(&self, tag: &str) -> Result<i64, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_int_or(&self, tag: &str, default: i64) -> Result<i64, Error> {
+        self.attr_or(tag, default)
+    }
+
+    pub fn attr_ints(&self, tag: &str) -> Result<Vec<i64>, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_ints_or(&self, tag: &str, default: &[i64]) -> Result<Vec<i64>, Error> {
+        self.attr_or(tag, default.to_vec())
+    }
+
+    pub fn attr_float(&self, tag: &str) -> Result<f32, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_float_or(&self, tag: &str, default: f32) -> Result<f32, Error> {
+        self.attr_or(tag, default)
+    }
+
+    pub fn attr_floats(&self, tag: &str) -> Result<Vec<f32>, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_floats_or(&self, tag: &str, default: &[f32]) -> Result<Vec<f32>, Error> {
+        self.attr_or(tag, default.to_vec())
+    }
+
+    pub fn attr_string(&self, tag: &str) -> Result<String, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_string_or(&self, tag: &str, default: &str) -> Result<String, Error> {
+        self.attr_or(tag, String::from(default))
+    }
+
+    pub fn attr_strings(&self, tag: &str) -> Result<Vec<String>, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_tensor(&self, tag: &str) -> Result<Tensor, Error> {
+        self.attr(tag)
+    }
+
+    pub fn attr_graph(&self, tag: &str) -> Result<Graph, Error> {
+        self.attr(tag)
+    }
 }
 
 
@@ -106,4 +267,34 @@ mod tests {
 
         println!("{:?}", op);
     }
+
+    #[test]
+    fn attr_works() {
+        let op = Operator::new("conv", "Conv")
+            .add_attribute("pads", Attribute::from(&[1_i64, 1, 1, 1] as &[i64]))
+            .unwrap()
+            .add_attribute("auto_pad", Attribute::from("NOTSET"))
+            .unwrap()
+            .add_default_attribute("group", Attribute::from(1))
+            .unwrap()
+            .add_default_attribute("auto_pad", Attribute::from("VALID"))
+            .unwrap();
+
+        assert_eq!(vec![1, 1, 1, 1], op.attr_ints("pads").unwrap());
+        // 模型中的值优先于默认值
+        assert_eq!("NOTSET", op.attr_string("auto_pad").unwrap());
+        assert_eq!(1, op.attr_int("group").unwrap());
+        assert_eq!(1, op.attr_int_or("group", 2).unwrap());
+        assert_eq!(vec![1, 1], op.attr_ints_or("strides", &[1, 1]).unwrap());
+        assert!(op.get_attribute("group").is_none());
+
+        let e = op.attr_ints("strides").unwrap_err();
+        assert!(matches!(e, Error::MissingAttribute { .. }));
+        assert!(e.to_string().contains("conv"));
+        assert!(e.to_string().contains("strides"));
+
+        let e = op.attr_int("pads").unwrap_err();
+        assert!(matches!(e, Error::InvalidAttribute { .. }));
+        assert!(e.to_string().contains("pads"));
+    }
 }
@@ -1,7 +1,9 @@
 use super::data::Data;
+use super::element::{self, Element};
+use anyhow::{bail, Result};
 use derive::{FromCode, GetCode};
-use std::any::type_name;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub struct Tensor {
@@ -17,6 +19,16 @@ pub struct Tensor {
     data: Data,
     /// 类型
     r#type: Type,
+    /// COO格式稀疏张量中非零值的线性下标，此时data只保存非零值
+    sparse: Option<Vec<i64>>,
+    /// 字符串张量的元素，保留原始字节，此时data为空
+    strings: Option<Vec<Vec<u8>>>,
+    /// 量化参数
+    quantization: Option<Quantization>,
+    /// 动态维度的符号名，按维度保存，静态维度为None
+    dim_params: Vec<Option<String>>,
+    /// 是否为0维标量，标量的shape按一维`[1]`保存
+    scalar: bool,
 }
 
 impl Display for Tensor {
@@ -38,6 +50,11 @@ impl Tensor {
             dtype,
             data: Data::new(),
             r#type,
+            sparse: None,
+            strings: None,
+            quantization: None,
+            dim_params: vec![],
+            scalar: false,
         }
     }
 
@@ -55,126 +72,159 @@ impl Tensor {
             dtype,
             data: Data::new(),
             r#type,
+            sparse: None,
+            strings: None,
+            quantization: None,
+            dim_params: vec![],
+            scalar: false,
         }
     }
 
-    pub fn set_array1<T: Clone>(&mut self, vs: &[T]) {
-        debug_assert!(
-            self.type_match::<T>(),
-            "Type not match, need {}, but {}",
-            self.dtype(),
-            type_name::<T>()
-        );
-        self.shape = Shape::from(&[vs.len() as u32]);
+    /// 由元素数组创建Tensor，数据类型由元素类型确定
+    pub fn from_vec<T: Element>(name: &str, shape: &[u32], vs: Vec<T>) -> Result<Self> {
+        Self::from_slice(name, shape, &vs)
+    }
 
-        self.data = Data::from_array(vs);
+    pub fn from_slice<T: Element>(name: &str, shape: &[u32], vs: &[T]) -> Result<Self> {
+        let shape = Shape::from(shape);
+        if shape.len() != vs.len() {
+            bail!(
+                "Tensor {} data length({}) not match shape {}",
+                name,
+                vs.len(),
+                shape
+            );
+        }
+        let mut tensor = Self::new(name, Format::default(), T::DTYPE, Type::Variable);
+        tensor.shape = shape;
+        tensor.data = Data::from(element::to_bytes(vs));
+
+        Ok(tensor)
     }
 
-    pub fn set_array2<T, A, B>(&mut self, vs: A)
-    where
-        A: AsRef<[B]>,
-        B: AsRef<[T]> + Clone,
-    {
-        debug_assert!(
-            self.type_match::<T>(),
-            "Type not match, need {}, but {}",
-            self.dtype(),
-            type_name::<T>()
-        );
-        let a = vs.as_ref();
-        let b = a[0].as_ref();
-        self.shape = Shape::from(&[a.len() as u32, b.len() as u32]);
+    /// 由COO格式的非零值和线性下标创建稀疏Tensor，下标需要升序且不重复
+    pub fn from_coo(
+        name: &str,
+        shape: &[u32],
+        dtype: DType,
+        values: Vec<u8>,
+        indices: Vec<i64>,
+    ) -> Result<Self> {
+        if matches!(dtype, DType::Undefined | DType::String) {
+            bail!("Sparse tensor {} dtype {:?} not supported", name, dtype);
+        }
+        let shape = Shape::from(shape);
+        if values.len() != indices.len() * dtype.size_of() {
+            bail!(
+                "Sparse tensor {} values length({}) not match {} indices",
+                name,
+                values.len(),
+                indices.len()
+            );
+        }
+        if indices.iter().any(|&i| i < 0 || i as usize >= shape.len()) {
+            bail!("Sparse tensor {} index out of shape {}", name, shape);
+        }
+        if indices.windows(2).any(|w| w[0] >= w[1]) {
+            bail!("Sparse tensor {} indices must be ascending without duplication", name);
+        }
+        let mut tensor = Self::new(name, Format::default(), dtype, Type::Constant);
+        tensor.shape = shape;
+        tensor.data = Data::from(values);
+        tensor.sparse = Some(indices);
+
+        Ok(tensor)
+    }
+
+    /// 创建字符串Tensor
+    pub fn from_strings(name: &str, shape: &[u32], vs: Vec<Vec<u8>>) -> Result<Self> {
+        let shape = Shape::from(shape);
+        if shape.len() != vs.len() {
+            bail!(
+                "Tensor {} data length({}) not match shape {}",
+                name,
+                vs.len(),
+                shape
+            );
+        }
+        let mut tensor = Self::new(name, Format::default(), DType::String, Type::Variable);
+        tensor.shape = shape;
+        tensor.strings = Some(vs);
 
-        self.data = Data::from_array(a);
+        Ok(tensor)
     }
 
-    pub fn set_array3<T, A, B, C>(&mut self, vs: A)
-    where
-        A: AsRef<[B]>,
-        B: AsRef<[C]> + Clone,
-        C: AsRef<[T]>,
-    {
-        debug_assert!(
-            self.type_match::<T>(),
-            "Type not match, need {}, but {}",
-            self.dtype(),
-            type_name::<T>()
-        );
-        let a = vs.as_ref();
-        let b = a[0].as_ref();
-        let c = b[0].as_ref();
-        self.shape = Shape::from(&[a.len() as u32, b.len() as u32, c.len() as u32]);
-
-        self.data = Data::from_array(a);
-    }
-
-    pub fn set_array4<T, A, B, C, D>(&mut self, vs: A)
-    where
-        A: AsRef<[B]>,
-        B: AsRef<[C]> + Clone,
-        C: AsRef<[D]>,
-        D: AsRef<[T]>,
-    {
-        debug_assert!(
-            self.type_match::<T>(),
-            "Type not match, need {}, but {}",
-            self.dtype(),
-            type_name::<T>()
-        );
-        let a = vs.as_ref();
-        let b = a[0].as_ref();
-        let c = b[0].as_ref();
-        let d = c[0].as_ref();
-        self.shape = Shape::from(&[
-            a.len() as u32,
-            b.len() as u32,
-            c.len() as u32,
-            d.len() as u32,
-        ]);
-
-        self.data = Data::from_array(a);
-    }
-
-    pub fn set_array5<T, A, B, C, D, E>(&mut self, vs: A)
-    where
-        A: AsRef<[B]>,
-        B: AsRef<[C]> + Clone,
-        C: AsRef<[D]>,
-        D: AsRef<[E]>,
-        E: AsRef<[T]>,
-    {
-        debug_assert!(
-            self.type_match::<T>(),
-            "Type not match, need {}, but {}",
+    /// 创建数据全为0的Tensor
+    pub fn zeros<T: Element>(name: &str, shape: &[u32]) -> Self {
+        let shape = Shape::from(shape);
+        let mut tensor = Self::new(name, Format::default(), T::DTYPE, Type::Variable);
+        tensor.data = Data::from(vec![0; shape.len() * T::DTYPE.size_of()]);
+        tensor.shape = shape;
+
+        tensor
+    }
+
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = Some(quantization);
+        self
+    }
+
+    pub fn with_type(mut self, r#type: Type) -> Self {
+        self.r#type = r#type;
+        self
+    }
+
+    /// 设置动态维度，`params[i]`为Some时第i维在shape中为0，符号名为空表示大小未知
+    pub fn with_dim_params(mut self, params: Vec<Option<String>>) -> Self {
+        self.dim_params = if params.iter().any(Option::is_some) {
+            params
+        } else {
+            vec![]
+        };
+        self
+    }
+
+    /// 标记为0维标量，shape设为`[1]`
+    pub fn with_scalar(mut self) -> Self {
+        self.shape = Shape::from(&[1]);
+        self.dim_params = vec![];
+        self.scalar = true;
+        self
+    }
+
+    /// 是否为0维标量
+    pub fn is_scalar(&self) -> bool {
+        self.scalar
+    }
+
+    /// 第`i`维的符号名，静态维度返回None
+    pub fn dim_param(&self, i: usize) -> Option<&str> {
+        self.dim_params.get(i).and_then(|p| p.as_deref())
+    }
+
+    /// 是否有动态维度
+    pub fn is_dynamic(&self) -> bool {
+        !self.dim_params.is_empty()
+    }
+
+    pub fn set_vec<T: Element>(&mut self, data: Vec<T>) {
+        debug_assert_eq!(
+            T::DTYPE,
             self.dtype(),
-            type_name::<T>()
-        );
-        let a = vs.as_ref();
-        let b = a[0].as_ref();
-        let c = b[0].as_ref();
-        let d = c[0].as_ref();
-        let e = d[0].as_ref();
-        self.shape = Shape::from(&[
-            a.len() as u32,
-            b.len() as u32,
-            c.len() as u32,
-            d.len() as u32,
-            e.len() as u32,
-        ]);
-
-        self.data = Data::from_array(a);
-    }
-
-    pub fn set_vec<T>(&mut self, data: Vec<T>) {
-        debug_assert!(
-            self.type_match::<T>(),
             "Type not match, need {}, but {}",
             self.dtype(),
-            type_name::<T>()
+            T::DTYPE
         );
         debug_assert_eq!(data.len(), self.shape.len());
 
-        self.data = Data::from_vec(data);
+        self.data = Data::from(element::to_bytes(&data));
+        self.sparse = None;
+        self.strings = None;
     }
 
     pub fn set_vec_u8(&mut self, data: Vec<u8>, dtype: DType) {
@@ -186,6 +236,8 @@ impl Tensor {
             dtype
         );
         self.data = Data::from(data);
+        self.sparse = None;
+        self.strings = None;
     }
 
     // 不持有ptr的所有权，需要自己确保在使用时ptr指向的内容是有效的
@@ -197,11 +249,115 @@ impl Tensor {
             self.shape.len() * self.dtype.size_of()
         );
         self.data = Data::from_ptr(ptr, length, location);
+        self.sparse = None;
+        self.strings = None;
+    }
+
+    /// 设置设备上的数据，Tensor持有buffer的引用直到被释放
+    pub fn set_device_buffer(&mut self, buffer: Rc<dyn DeviceBuffer>) {
+        debug_assert_eq!(
+            self.shape.len() * self.dtype.size_of(),
+            buffer.len(),
+            "Data length({}) not match shape len({}).",
+            buffer.len(),
+            self.shape.len() * self.dtype.size_of()
+        );
+        self.data = Data::from_device(buffer);
+        self.sparse = None;
+        self.strings = None;
+    }
+
+    /// 引用共享数据中的一段作为Tensor的数据，不复制数据
+    pub fn set_shared_data(
+        &mut self,
+        bytes: Rc<dyn SharedBytes>,
+        offset: usize,
+        length: usize,
+    ) -> Result<()> {
+        let valid = matches!(offset.checked_add(length), Some(end) if end <= bytes.bytes().len());
+        if !valid {
+            bail!(
+                "Tensor {} data range {}+{} out of {} bytes",
+                self.name,
+                offset,
+                length,
+                bytes.bytes().len()
+            );
+        }
+        self.data = Data::from_shared(bytes, offset, length);
+        self.sparse = None;
+        self.strings = None;
+
+        Ok(())
+    }
+
+    /// COO格式稀疏张量中非零值的线性下标，稠密张量返回None
+    pub fn sparse_indices(&self) -> Option<&[i64]> {
+        self.sparse.as_deref()
+    }
+
+    /// 字符串张量的元素，其他类型返回None
+    pub fn strings(&self) -> Option<&[Vec<u8>]> {
+        self.strings.as_deref()
+    }
+
+    pub fn quantization(&self) -> Option<&Quantization> {
+        self.quantization.as_ref()
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        self.sparse.is_some()
+    }
+
+    /// 转换为稠密张量，未设置的元素为0，稠密张量直接复制
+    pub fn to_dense(&self) -> Result<Tensor> {
+        let indices = match &self.sparse {
+            Some(indices) => indices,
+            None => return Ok(self.clone()),
+        };
+        let values = match self.data.as_bytes() {
+            Some(values) => values,
+            None => bail!("Tensor {} has no data on host", self.name),
+        };
+        let size = self.dtype.size_of();
+        let mut dense = vec![0_u8; self.shape.len() * size];
+        for (i, &index) in indices.iter().enumerate() {
+            let index = index as usize;
+            dense[index * size..(index + 1) * size]
+                .copy_from_slice(&values[i * size..(i + 1) * size]);
+        }
+        let mut tensor = Self::new(&self.name, self.format, self.dtype, self.r#type);
+        tensor.shape = self.shape.clone();
+        tensor.data = Data::from(dense);
+        tensor.scalar = self.scalar;
+
+        Ok(tensor)
     }
 
     // 移交data所有权
     pub fn into_vec(self) -> Option<Vec<u8>> {
-        self.data.try_into::<u8>()
+        self.data.try_into_bytes()
+    }
+
+    /// 按元素类型复制Host上的数据，稀疏张量只包含非零值
+    pub fn to_vec<T: Element>(&self) -> Result<Vec<T>> {
+        if T::DTYPE != self.dtype {
+            bail!(
+                "Tensor {} type not match, need {}, but {}",
+                self.name,
+                self.dtype,
+                T::DTYPE
+            );
+        }
+        match self.data.as_bytes() {
+            Some(bytes) => Ok(element::from_bytes(bytes)),
+            None => bail!("Tensor {} has no data on host", self.name),
+        }
+    }
+
+    /// Host上的原始数据
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.data.as_bytes()
     }
 
     pub fn name(&self) -> &String {
@@ -235,10 +391,6 @@ impl Tensor {
     pub fn location(&self) -> Location {
         self.data.location()
     }
-
-    fn type_match<T>(&self) -> bool {
-        self.dtype.type_name() == type_name::<T>()
-    }
 }
 
 #[derive(Debug, Clone)]
@@ -361,6 +513,10 @@ impl Display for DType {
 impl DType {
     pub const fn size_of(&self) -> usize {
         match self {
+            Self::Bool => 1,
+            Self::Bfloat16 => 2,
+            Self::Complex64 => 8,
+            Self::Complex128 => 16,
             Self::Float16 => 2,
             Self::Float32 => 4,
             Self::Float64 => 8,
@@ -378,18 +534,23 @@ impl DType {
 
     pub fn type_name(&self) -> &str {
         match self {
+            Self::Undefined => "undefined",
+            Self::Float32 => "f32",
+            Self::Uint8 => "u8",
+            Self::Int8 => "i8",
+            Self::Uint16 => "u16",
+            Self::Int16 => "i16",
+            Self::Int32 => "i32",
+            Self::Int64 => "i64",
+            Self::String => "string",
+            Self::Bool => "bool",
             Self::Float16 => "f16",
-            Self::Float32 => type_name::<f32>(),
-            Self::Float64 => type_name::<f64>(),
-            Self::Uint8 => type_name::<u8>(),
-            Self::Uint16 => type_name::<u16>(),
-            Self::Uint32 => type_name::<u32>(),
-            Self::Uint64 => type_name::<u64>(),
-            Self::Int8 => type_name::<i8>(),
-            Self::Int16 => type_name::<i16>(),
-            Self::Int32 => type_name::<i32>(),
-            Self::Int64 => type_name::<i64>(),
-            _ => panic!("Not support dtype now"),
+            Self::Float64 => "f64",
+            Self::Uint32 => "u32",
+            Self::Uint64 => "u64",
+            Self::Complex64 => "complex64",
+            Self::Complex128 => "complex128",
+            Self::Bfloat16 => "bf16",
         }
     }
 }
@@ -403,6 +564,53 @@ pub enum Location {
     Device,
 }
 
+/// 后端分配的设备内存，释放最后一个引用时归还给后端
+pub trait DeviceBuffer: Debug {
+    fn ptr(&self) -> *mut u8;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// 由多个Tensor共享的只读host数据，如模型外部数据文件的内存映射
+pub trait SharedBytes: Debug {
+    fn bytes(&self) -> &[u8];
+}
+
+impl SharedBytes for Vec<u8> {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+/// 量化参数，实际值为`(q - zero_point) * scale`
+///
+/// 逐通道量化时scale和zero_point按`axis`维的每个通道各有一个
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Quantization {
+    pub scale: Vec<f32>,
+    pub zero_point: Vec<i64>,
+    /// 逐通道量化的维度
+    pub axis: i32,
+}
+
+impl Quantization {
+    /// 按tensor量化的参数
+    pub fn per_tensor(scale: f32, zero_point: i64) -> Self {
+        Self {
+            scale: vec![scale],
+            zero_point: vec![zero_point],
+            axis: 0,
+        }
+    }
+
+    pub fn is_per_channel(&self) -> bool {
+        self.scale.len() > 1
+    }
+}
+
 /// Tensor类型
 #[derive(Debug, Clone, Copy, PartialEq, GetCode, FromCode)]
 pub enum Type {
@@ -438,29 +646,125 @@ mod tests {
 
     #[test]
     fn tensor_works() {
-        let mut tensor = Tensor::new("name", Format::CHWN, DType::Int32, Type::Constant);
-        tensor.set_array1(&[1]);
+        let tensor = Tensor::from_vec("name", &[1], vec![1]).unwrap();
         assert_eq!(1, tensor.shape().dim());
+        assert_eq!(DType::Int32, tensor.dtype());
 
-        let mut tensor = Tensor::new("name", Format::CHWN, DType::Int32, Type::Constant);
-        tensor.set_array2(&[[1]]);
-        assert_eq!(2, tensor.shape().dim());
+        let tensor = Tensor::from_vec("name", &[2, 2], vec![1.0_f32, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(&[2, 2], tensor.shape().data());
+        assert_eq!(DType::Float32, tensor.dtype());
+        assert_eq!(16, tensor.data_len());
+        assert_eq!(vec![1.0_f32, 2.0, 3.0, 4.0], tensor.to_vec::<f32>().unwrap());
+        assert!(tensor.to_vec::<i32>().is_err());
+
+        let tensor = Tensor::from_vec("name", &[1, 1, 1, 1, 1], vec![half::f16::ONE]).unwrap();
+        assert_eq!(5, tensor.shape().dim());
+        assert_eq!(DType::Float16, tensor.dtype());
 
-        let mut tensor = Tensor::new("name", Format::CHWN, DType::Int32, Type::Constant);
-        tensor.set_array3(&[[[1]]]);
-        assert_eq!(3, tensor.shape().dim());
+        let tensor = Tensor::from_vec("name", &[3], vec![true, false, true]).unwrap();
+        assert_eq!(vec![true, false, true], tensor.to_vec::<bool>().unwrap());
 
-        let mut tensor = Tensor::new("name", Format::CHWN, DType::Int32, Type::Constant);
-        tensor.set_array4(&[[[[1]]]]);
-        assert_eq!(4, tensor.shape().dim());
+        assert!(Tensor::from_vec("name", &[2, 3], vec![1_i64, 2]).is_err());
 
-        let mut tensor = Tensor::new("name", Format::CHWN, DType::Int32, Type::Constant);
-        tensor.set_array5(&[[[[[1]]]]]);
-        assert_eq!(5, tensor.shape().dim());
+        let tensor = Tensor::from_strings("name", &[2], vec![b"a".to_vec(), vec![0xff]]).unwrap();
+        assert_eq!(DType::String, tensor.dtype());
+        assert_eq!(Some(&[b"a".to_vec(), vec![0xff]] as &[Vec<u8>]), tensor.strings());
+        assert!(Tensor::from_strings("name", &[3], vec![]).is_err());
+
+        let tensor = Tensor::zeros::<u16>("name", &[2, 3]);
+        assert_eq!(DType::Uint16, tensor.dtype());
+        assert_eq!(vec![0_u16; 6], tensor.to_vec::<u16>().unwrap());
 
         let mut tensor =
             Tensor::new_with_shape("name", &[2, 2], Format::CHWN, DType::Int32, Type::Constant);
         tensor.set_vec(vec![1, 2, 3, 4]);
         assert_eq!(2, tensor.shape().dim());
+
+        // clone后数据独立
+        let cloned = tensor.clone();
+        drop(tensor);
+        assert_eq!(vec![1, 2, 3, 4], cloned.to_vec::<i32>().unwrap());
+    }
+
+    #[derive(Debug)]
+    struct Bytes(Vec<u8>);
+
+    impl SharedBytes for Bytes {
+        fn bytes(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn shared_works() {
+        let bytes: Rc<dyn SharedBytes> = Rc::new(Bytes(element::to_bytes(&[1_i32, 2, 3, 4])));
+        let mut tensor = Tensor::new_with_shape("t", &[2], Format::NCHW, DType::Int32, Type::Constant);
+        tensor.set_shared_data(bytes.clone(), 8, 8).unwrap();
+        assert_eq!(vec![3, 4], tensor.to_vec::<i32>().unwrap());
+        // clone后共享同一块数据
+        let cloned = tensor.clone();
+        assert_eq!(tensor.data_ptr(), cloned.data_ptr());
+        drop(tensor);
+        assert_eq!(vec![3, 4], cloned.to_vec::<i32>().unwrap());
+        assert_eq!(2, Rc::strong_count(&bytes));
+
+        let mut tensor = Tensor::new_with_shape("t", &[2], Format::NCHW, DType::Int32, Type::Constant);
+        assert!(tensor.set_shared_data(bytes, 12, 8).is_err());
+    }
+
+    #[test]
+    fn sparse_works() {
+        let values = element::to_bytes(&[5.0_f32, 7.0]);
+        let tensor = Tensor::from_coo("w", &[2, 3], DType::Float32, values, vec![1, 5]).unwrap();
+        assert!(tensor.is_sparse());
+        assert_eq!(Some(&[1_i64, 5] as &[i64]), tensor.sparse_indices());
+        assert_eq!(8, tensor.data_len());
+
+        let dense = tensor.to_dense().unwrap();
+        assert!(!dense.is_sparse());
+        assert_eq!(Type::Constant, dense.r#type());
+        assert_eq!(
+            vec![0.0_f32, 5.0, 0.0, 0.0, 0.0, 7.0],
+            dense.to_vec::<f32>().unwrap()
+        );
+
+        let values = element::to_bytes(&[1_i32, 2]);
+        assert!(Tensor::from_coo("w", &[2, 3], DType::Int32, values.clone(), vec![1]).is_err());
+        assert!(Tensor::from_coo("w", &[2, 3], DType::Int32, values.clone(), vec![1, 6]).is_err());
+        assert!(Tensor::from_coo("w", &[2, 3], DType::Int32, values, vec![3, 1]).is_err());
+    }
+
+    #[test]
+    fn dim_params_works() {
+        let tensor =
+            Tensor::new_with_shape("x", &[0, 3], Format::NCHW, DType::Float32, Type::Variable)
+                .with_dim_params(vec![Some(String::from("batch")), None]);
+        assert!(tensor.is_dynamic());
+        assert_eq!(Some("batch"), tensor.dim_param(0));
+        assert_eq!(None, tensor.dim_param(1));
+        let tensor = tensor.with_dim_params(vec![None, None]);
+        assert!(!tensor.is_dynamic());
+    }
+
+    #[test]
+    fn scalar_works() {
+        let tensor = Tensor::from_vec("s", &[1], vec![3_i64]).unwrap();
+        assert!(!tensor.is_scalar());
+        let tensor = tensor.with_scalar();
+        assert!(tensor.is_scalar());
+        assert_eq!(1, tensor.shape().len());
+        assert!(tensor.to_dense().unwrap().is_scalar());
+    }
+
+    #[test]
+    fn quantization_works() {
+        let tensor = Tensor::from_vec("q", &[2], vec![-3_i8, 5])
+            .unwrap()
+            .with_quantization(Quantization::per_tensor(0.5, -1));
+        let q = tensor.quantization().unwrap();
+        assert!(!q.is_per_channel());
+        assert_eq!(vec![0.5_f32], q.scale);
+        // 复制数据时保留量化参数
+        assert_eq!(Some(q), tensor.clone().quantization());
     }
 }